@@ -0,0 +1,47 @@
+//! Per-call handling of large tool arguments
+//!
+//! Times what a call to `import_prices` with a 1 MiB CSV, auditing on and
+//! an idempotency key does with its arguments outside the database, through
+//! the plugin's own functions: parsing them, sharing them between dispatch
+//! and audit, deserializing them, binding them to the key and writing the
+//! audit entry without `_auth`.
+//!
+//! ```bash
+//! cargo bench --bench arguments
+//! ```
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use plug_pricing::bench;
+use serde_json::json;
+use std::hint::black_box;
+
+/// Size of the CSV argument
+const CSV_BYTES: usize = 1024 * 1024;
+
+/// The arguments of the call as the host passes them
+fn arguments() -> Vec<u8> {
+    let mut csv = String::from("sku,price\n");
+    let mut row = 0;
+    while csv.len() < CSV_BYTES {
+        csv.push_str(&format!("SKU-{row:08},{}.{:02}\n", row % 1000, row % 100));
+        row += 1;
+    }
+    let args = json!({
+        "csv": csv,
+        "idempotency_key": "import-2024-05-01",
+        "_auth": {"user_id": "u-42", "roles": ["pricing"]},
+        "_meta": {"progressToken": 7}
+    });
+    serde_json::to_vec(&args).unwrap()
+}
+
+fn import_arguments(c: &mut Criterion) {
+    let json = arguments();
+    let mut group = c.benchmark_group("arguments");
+    group.throughput(Throughput::Bytes(json.len() as u64));
+    group.bench_function("import_prices", |b| b.iter(|| bench::import_arguments(black_box(&json)).unwrap()));
+    group.finish();
+}
+
+criterion_group!(benches, import_arguments);
+criterion_main!(benches);
@@ -0,0 +1,11 @@
+-- Changes made by update_product_price and import_prices
+CREATE TABLE IF NOT EXISTS price_audit (
+    id BIGSERIAL PRIMARY KEY,
+    product_id INTEGER NOT NULL,
+    old_price DECIMAL(10,2) NOT NULL,
+    new_price DECIMAL(10,2) NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    changed_by TEXT,
+    reason TEXT
+);
+CREATE INDEX IF NOT EXISTS price_audit_product_idx ON price_audit (product_id, changed_at);
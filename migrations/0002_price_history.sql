@@ -0,0 +1,8 @@
+-- Prices over time, read by get_price_history and get_recent_price_changes
+CREATE TABLE IF NOT EXISTS price_history (
+    product_id INTEGER NOT NULL,
+    price DECIMAL(10,2) NOT NULL,
+    effective_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX IF NOT EXISTS price_history_product_idx ON price_history (product_id, effective_at);
+CREATE INDEX IF NOT EXISTS price_history_effective_idx ON price_history (effective_at);
@@ -0,0 +1,11 @@
+-- Tool call audit log (audit_log: "table")
+CREATE TABLE IF NOT EXISTS plugin_audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    called_at TIMESTAMPTZ NOT NULL,
+    tool TEXT NOT NULL,
+    arguments JSONB NOT NULL,
+    latency_ms DOUBLE PRECISION NOT NULL,
+    status TEXT NOT NULL,
+    error_code TEXT
+);
+CREATE INDEX IF NOT EXISTS plugin_audit_log_called_at_idx ON plugin_audit_log (called_at);
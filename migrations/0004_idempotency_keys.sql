@@ -0,0 +1,9 @@
+-- Results of write tool calls with an idempotency_key
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    tool TEXT NOT NULL,
+    key TEXT NOT NULL,
+    arguments JSONB NOT NULL,
+    response JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (tool, key)
+);
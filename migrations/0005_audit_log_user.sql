@@ -0,0 +1,2 @@
+-- User of the _auth context of each audited call
+ALTER TABLE plugin_audit_log ADD COLUMN IF NOT EXISTS user_id TEXT;
@@ -0,0 +1,24 @@
+-- Price alert rules and the alerts they triggered (price_alerts_enabled)
+CREATE TABLE IF NOT EXISTS price_alerts (
+    id BIGSERIAL PRIMARY KEY,
+    product_id INTEGER,
+    category TEXT,
+    threshold NUMERIC(10,2) NOT NULL,
+    direction TEXT NOT NULL CHECK (direction IN ('above', 'below')),
+    created_by TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CHECK ((product_id IS NULL) <> (category IS NULL))
+);
+
+CREATE TABLE IF NOT EXISTS triggered_price_alerts (
+    id BIGSERIAL PRIMARY KEY,
+    alert_id BIGINT NOT NULL REFERENCES price_alerts(id) ON DELETE CASCADE,
+    product_id INTEGER NOT NULL,
+    price NUMERIC(10,2) NOT NULL,
+    triggered_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    resolved_at TIMESTAMPTZ
+);
+-- One open alert per rule and product, also across plugin instances
+CREATE UNIQUE INDEX IF NOT EXISTS triggered_price_alerts_open_idx
+    ON triggered_price_alerts (alert_id, product_id) WHERE resolved_at IS NULL;
+CREATE INDEX IF NOT EXISTS triggered_price_alerts_triggered_at_idx ON triggered_price_alerts (triggered_at);
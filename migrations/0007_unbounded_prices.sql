@@ -0,0 +1,6 @@
+-- Prices of any scale and precision, as the plugin computes them with
+-- exact decimals and rounds only for output (price_decimal_places)
+ALTER TABLE price_audit ALTER COLUMN old_price TYPE NUMERIC, ALTER COLUMN new_price TYPE NUMERIC;
+ALTER TABLE price_history ALTER COLUMN price TYPE NUMERIC;
+ALTER TABLE price_alerts ALTER COLUMN threshold TYPE NUMERIC;
+ALTER TABLE triggered_price_alerts ALTER COLUMN price TYPE NUMERIC;
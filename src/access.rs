@@ -0,0 +1,179 @@
+//! Row-level access policy
+//!
+//! The `access_policy` config restricts what one deployment sees of a
+//! catalogue shared with others, without separate database roles:
+//!
+//! - `allowed_categories` / `denied_categories` and `allowed_brands` /
+//!   `denied_brands` limit the visible products. They become a condition
+//!   of the `products` relation itself, so every query reading from it,
+//!   searches, lookups, joins and statistics alike, only sees those rows;
+//!   the write tools cannot change other products either. Products
+//!   without a category or brand pass the denied lists but not the allowed
+//!   ones. Brands need `schema_mapping.brand_column`.
+//! - `redacted_fields` names result fields, e.g. `price` or
+//!   `converted_price`, that are removed from every tool result, wherever
+//!   they occur, and left out of exports. Callers whose `_auth` context
+//!   has one of `unredacted_roles` get them.
+//!
+//! `query_products_sql` reads the tables directly and is refused while a
+//! row restriction is configured.
+
+use crate::auth::{self, AuthContext};
+use crate::backend::BackendKind;
+use crate::error::PluginError;
+use crate::get_config;
+use crate::mapping::SchemaMapping;
+use crate::PluginConfig;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// The `access_policy` config field
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+pub(crate) struct AccessPolicy {
+    /// Categories whose products are visible, all of them if absent
+    #[serde(default)]
+    allowed_categories: Option<Vec<String>>,
+
+    /// Categories whose products are hidden
+    #[serde(default)]
+    denied_categories: Vec<String>,
+
+    /// Brands whose products are visible, all of them if absent
+    #[serde(default)]
+    allowed_brands: Option<Vec<String>>,
+
+    /// Brands whose products are hidden
+    #[serde(default)]
+    denied_brands: Vec<String>,
+
+    /// Result fields removed from every tool result, e.g. ["cost"]
+    #[serde(default)]
+    pub(crate) redacted_fields: Vec<String>,
+
+    /// Roles of the caller's `_auth` context that see the redacted fields
+    #[serde(default)]
+    unredacted_roles: Vec<String>,
+}
+
+impl AccessPolicy {
+    fn restricts_categories(&self) -> bool {
+        self.allowed_categories.is_some() || !self.denied_categories.is_empty()
+    }
+
+    fn restricts_brands(&self) -> bool {
+        self.allowed_brands.is_some() || !self.denied_brands.is_empty()
+    }
+
+    /// Fields removed from the results of the caller
+    fn redacted_for(&self, auth: Option<&AuthContext>) -> &[String] {
+        match auth {
+            Some(auth) if auth.has_any_role(&self.unredacted_roles) => &[],
+            _ => &self.redacted_fields,
+        }
+    }
+
+    /// Whether the policy hides any rows
+    pub(crate) fn restricts_rows(&self) -> bool {
+        self.restricts_categories() || self.restricts_brands()
+    }
+
+    /// Condition on the mapped table selecting the visible rows, `None`
+    /// without a row restriction
+    pub(crate) fn row_filter(&self, mapping: &SchemaMapping) -> Option<String> {
+        let mut conditions = Vec::new();
+        let mut restrict = |column: &Option<String>, allowed: &Option<Vec<String>>, denied: &[String]| {
+            let Some(column) = column else {
+                return;
+            };
+            let column = mapping.column(column);
+            if let Some(allowed) = allowed {
+                conditions.push(format!("{column}::text = ANY({})", text_array(allowed)));
+            }
+            if !denied.is_empty() {
+                conditions.push(format!(
+                    "({column} IS NULL OR {column}::text <> ALL({}))",
+                    text_array(denied)
+                ));
+            }
+        };
+        restrict(&mapping.category_column, &self.allowed_categories, &self.denied_categories);
+        restrict(&mapping.brand_column, &self.allowed_brands, &self.denied_brands);
+        (!conditions.is_empty()).then(|| conditions.join(" AND "))
+    }
+}
+
+/// SQL string literal; the values come from the configuration, never from
+/// callers, but may still contain quotes
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+fn text_array(values: &[String]) -> String {
+    let values: Vec<String> = values.iter().map(|value| quote_literal(value)).collect();
+    format!("ARRAY[{}]::text[]", values.join(", "))
+}
+
+/// Validate the access policy, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    let policy = &config.access_policy;
+    let mapping = &config.schema_mapping;
+    if policy.restricts_categories() && mapping.category_column.is_none() {
+        return Err("access_policy category lists require schema_mapping.category_column".to_string());
+    }
+    if policy.restricts_brands() && mapping.brand_column.is_none() {
+        return Err("access_policy brand lists require schema_mapping.brand_column".to_string());
+    }
+    if policy.restricts_rows() && BackendKind::parse(&config.backend)? != BackendKind::Postgres {
+        return Err("access_policy row restrictions require the postgres backend".to_string());
+    }
+    Ok(())
+}
+
+/// Fail for tools that cannot apply the row restriction
+pub(crate) fn ensure_unrestricted(tool: &str) -> Result<(), PluginError> {
+    if get_config().access_policy.restricts_rows() {
+        Err(PluginError::PermissionDenied(format!(
+            "{tool} is not available while access_policy restricts the visible products"
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Whether results for the caller may carry the field `name`
+pub(crate) fn is_visible(name: &str, auth: Option<&AuthContext>) -> bool {
+    !get_config().access_policy.redacted_for(auth).iter().any(|field| field == name)
+}
+
+/// Remove the fields named `fields` from `value` at any depth
+pub(crate) fn remove_fields(value: &mut Value, fields: &[String]) {
+    match value {
+        Value::Object(object) => {
+            object.retain(|name, _| !fields.contains(name));
+            object.values_mut().for_each(|value| remove_fields(value, fields));
+        }
+        Value::Array(items) => items.iter_mut().for_each(|value| remove_fields(value, fields)),
+        _ => {}
+    }
+}
+
+/// Remove the fields redacted for the caller of `args` from a tool result
+pub(crate) fn redact_response(mut result: Value, args: &Value) -> Value {
+    let config = get_config();
+    // auth::check() refused malformed contexts before the call
+    let auth = auth::parse(args).ok().flatten();
+    let fields = config.access_policy.redacted_for(auth.as_ref());
+    if fields.is_empty() {
+        return result;
+    }
+    if let Some(content) = result["content"].as_array_mut() {
+        for block in content.iter_mut().filter(|block| block["type"] == "json") {
+            remove_fields(&mut block["json"], fields);
+        }
+    }
+    if let Some(structured) = result.get_mut("structuredContent") {
+        remove_fields(structured, fields);
+    }
+    result
+}
@@ -0,0 +1,427 @@
+//! Price alerts
+//!
+//! `create_price_alert` registers a rule for a product or a whole category:
+//! alert when the price is at or `above`, or at or `below`, a threshold.
+//! The rules and the alerts they triggered live in plugin-owned tables
+//! (names configurable through `price_alerts_table` and
+//! `triggered_alerts_table`, created by the migrations with
+//! `manage_schema`):
+//!
+//! ```sql
+//! CREATE TABLE price_alerts (
+//!     id         BIGSERIAL PRIMARY KEY,
+//!     product_id INTEGER,
+//!     category   TEXT,
+//!     threshold  NUMERIC NOT NULL,
+//!     direction  TEXT NOT NULL CHECK (direction IN ('above', 'below')),
+//!     created_by TEXT,
+//!     created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+//!     CHECK ((product_id IS NULL) <> (category IS NULL))
+//! );
+//!
+//! CREATE TABLE triggered_price_alerts (
+//!     id           BIGSERIAL PRIMARY KEY,
+//!     alert_id     BIGINT NOT NULL REFERENCES price_alerts(id) ON DELETE CASCADE,
+//!     product_id   INTEGER NOT NULL,
+//!     price        NUMERIC NOT NULL,
+//!     triggered_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+//!     resolved_at  TIMESTAMPTZ
+//! );
+//! CREATE UNIQUE INDEX triggered_price_alerts_open_idx
+//!     ON triggered_price_alerts (alert_id, product_id) WHERE resolved_at IS NULL;
+//! ```
+//!
+//! A background task evaluates the rules against the active products every
+//! `price_alert_interval_seconds`, and right away after a price change made
+//! through the write tools or announced on `cache_invalidation_channel`. A
+//! product meeting a rule triggers an alert once; the alert is resolved
+//! when the price no longer meets it, and a later crossing triggers a new
+//! one. The unique index keeps several plugin instances from triggering
+//! the same alert twice. With `price_alert_webhooks` set, triggered alerts
+//! are posted to `webhook_urls` as `price_alert.triggered` events.
+//! `get_triggered_alerts` lists them. Tenants' schemas are not evaluated.
+
+use crate::args::{self, DecimalArg};
+use crate::auth;
+use crate::backend::{BackendKind, Database, DatabaseBackend};
+use crate::error::PluginError;
+use crate::mapping;
+use crate::sql::{is_undefined_table, quote_identifier};
+use crate::{format_price, get_config, parse_timestamp, webhooks, PluginConfig};
+use arc_swap::ArcSwap;
+use chrono::{DateTime, Utc};
+use mcp_plugin_api::utils;
+use once_cell::sync::Lazy;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Page size of the listings without `limit`
+const DEFAULT_ALERT_LIMIT: i64 = 50;
+
+/// Upper bound of the `limit` argument
+const MAX_ALERT_LIMIT: i64 = 500;
+
+/// Shortest evaluation interval
+const MIN_INTERVAL_SECONDS: u64 = 5;
+
+/// Wakes the evaluation task for an immediate evaluation
+static WAKE: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Validate the price alert settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    quote_identifier(&config.price_alerts_table)?;
+    quote_identifier(&config.triggered_alerts_table)?;
+    if !config.price_alerts_enabled {
+        return Ok(());
+    }
+    if BackendKind::parse(&config.backend)? != BackendKind::Postgres {
+        return Err("price_alerts_enabled requires the postgres backend".to_string());
+    }
+    if config.read_only {
+        return Err("price_alerts_enabled records alerts and requires read_only to be set to false".to_string());
+    }
+    if config.price_alert_interval_seconds < MIN_INTERVAL_SECONDS {
+        return Err(format!("price_alert_interval_seconds must be at least {MIN_INTERVAL_SECONDS}"));
+    }
+    Ok(())
+}
+
+fn ensure_enabled() -> Result<(), PluginError> {
+    if get_config().price_alerts_enabled {
+        Ok(())
+    } else {
+        Err(PluginError::PermissionDenied(
+            "Price alerts are disabled, set price_alerts_enabled to use them".to_string(),
+        ))
+    }
+}
+
+/// Error for a statement on the alert tables
+fn alert_error(err: sqlx::Error) -> PluginError {
+    if is_undefined_table(&err) {
+        let config = get_config();
+        PluginError::Unsupported(format!(
+            "Price alerts need the {} and {} tables, see the alerts module or set manage_schema",
+            config.price_alerts_table, config.triggered_alerts_table
+        ))
+    } else {
+        err.into()
+    }
+}
+
+/// Side of the threshold that triggers an alert
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum Direction {
+    Above,
+    Below,
+}
+
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Above => "above",
+            Direction::Below => "below",
+        }
+    }
+}
+
+/// Arguments of create_price_alert
+#[derive(Debug, Deserialize, JsonSchema)]
+struct CreateAlertArgs {
+    product_id: Option<i32>,
+    #[schemars(length(min = 1))]
+    category: Option<String>,
+    threshold: DecimalArg,
+    direction: Direction,
+}
+
+/// Arguments of list_price_alerts
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ListAlertsArgs {
+    product_id: Option<i32>,
+    category: Option<String>,
+    #[schemars(range(min = 1, max = "MAX_ALERT_LIMIT"))]
+    limit: Option<i64>,
+    #[serde(default)]
+    #[schemars(range(min = 0))]
+    offset: i64,
+}
+
+/// Arguments of get_triggered_alerts
+#[derive(Debug, Deserialize, JsonSchema)]
+struct TriggeredArgs {
+    since: Option<String>,
+    alert_id: Option<i64>,
+    #[serde(default)]
+    open_only: bool,
+    #[schemars(range(min = 1, max = "MAX_ALERT_LIMIT"))]
+    limit: Option<i64>,
+}
+
+#[derive(sqlx::FromRow)]
+struct Alert {
+    id: i64,
+    product_id: Option<i32>,
+    category: Option<String>,
+    threshold: Decimal,
+    direction: String,
+    created_by: Option<String>,
+    created_at: DateTime<Utc>,
+    open_alerts: i64,
+    last_triggered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(sqlx::FromRow)]
+struct TriggeredAlert {
+    id: i64,
+    alert_id: i64,
+    product_id: i32,
+    name: String,
+    price: Decimal,
+    threshold: Decimal,
+    direction: String,
+    triggered_at: DateTime<Utc>,
+    resolved_at: Option<DateTime<Utc>>,
+}
+
+impl TriggeredAlert {
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "alert_id": self.alert_id,
+            "product_id": self.product_id,
+            "name": self.name,
+            "price": format_price(&self.price),
+            "threshold": format_price(&self.threshold),
+            "direction": self.direction,
+            "triggered_at": self.triggered_at.to_rfc3339(),
+            "resolved_at": self.resolved_at.map(|resolved_at| resolved_at.to_rfc3339()),
+            "open": self.resolved_at.is_none()
+        })
+    }
+}
+
+pub(crate) async fn handle_create_price_alert(db: &dyn DatabaseBackend, args: &Value) -> Result<Value, PluginError> {
+    ensure_enabled()?;
+    let CreateAlertArgs {
+        product_id,
+        category,
+        threshold,
+        direction,
+    } = args::parse(args)?;
+    let threshold = threshold.to_decimal("threshold")?;
+    if threshold < Decimal::ZERO {
+        return Err(PluginError::invalid_argument("threshold must not be negative"));
+    }
+    match (&product_id, &category) {
+        (Some(_), Some(_)) => {
+            return Err(PluginError::invalid_argument("Pass either product_id or category, not both"))
+        }
+        (None, None) => return Err(PluginError::invalid_argument("Missing product_id or category parameter")),
+        (Some(product_id), None) if db.fetch_product(*product_id).await?.is_none() => {
+            return Err(PluginError::not_found(format!("Product {product_id} not found")))
+        }
+        _ => {}
+    }
+
+    let table = quote_identifier(&get_config().price_alerts_table).map_err(PluginError::internal)?;
+    let created_by = auth::user_id(args);
+    let (id, created_at): (i64, DateTime<Utc>) = sqlx::query_as(&format!(
+        "INSERT INTO {table} (product_id, category, threshold, direction, created_by) \
+         VALUES ($1, $2, $3, $4, $5) RETURNING id, created_at"
+    ))
+    .bind(product_id)
+    .bind(&category)
+    .bind(threshold)
+    .bind(direction.as_str())
+    .bind(&created_by)
+    .fetch_one(db.primary()?)
+    .await
+    .map_err(alert_error)?;
+    // A price already past the threshold triggers without waiting
+    wake();
+
+    Ok(utils::json_content(json!({
+        "id": id,
+        "product_id": product_id,
+        "category": category,
+        "threshold": format_price(&threshold),
+        "direction": direction.as_str(),
+        "created_by": created_by,
+        "created_at": created_at.to_rfc3339()
+    })))
+}
+
+pub(crate) async fn handle_list_price_alerts(db: &dyn DatabaseBackend, args: &Value) -> Result<Value, PluginError> {
+    ensure_enabled()?;
+    let ListAlertsArgs {
+        product_id,
+        category,
+        limit,
+        offset,
+    } = args::parse(args)?;
+    let limit = limit.unwrap_or(DEFAULT_ALERT_LIMIT);
+    let config = get_config();
+    let alerts = quote_identifier(&config.price_alerts_table).map_err(PluginError::internal)?;
+    let triggered = quote_identifier(&config.triggered_alerts_table).map_err(PluginError::internal)?;
+
+    let rows = sqlx::query_as::<_, Alert>(&format!(
+        "SELECT a.id, a.product_id, a.category, a.threshold, a.direction, a.created_by, a.created_at, \
+                count(t.id) FILTER (WHERE t.resolved_at IS NULL) AS open_alerts, \
+                max(t.triggered_at) AS last_triggered_at \
+         FROM {alerts} a LEFT JOIN {triggered} t ON t.alert_id = a.id \
+         WHERE ($1::int IS NULL OR a.product_id = $1) AND ($2::text IS NULL OR a.category = $2) \
+         GROUP BY a.id ORDER BY a.id LIMIT $3 OFFSET $4"
+    ))
+    .bind(product_id)
+    .bind(&category)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(db.postgres()?)
+    .await
+    .map_err(alert_error)?;
+
+    let alerts: Vec<Value> = rows
+        .iter()
+        .map(|alert| {
+            json!({
+                "id": alert.id,
+                "product_id": alert.product_id,
+                "category": alert.category,
+                "threshold": format_price(&alert.threshold),
+                "direction": alert.direction,
+                "created_by": alert.created_by,
+                "created_at": alert.created_at.to_rfc3339(),
+                "open_alerts": alert.open_alerts,
+                "last_triggered_at": alert.last_triggered_at.map(|triggered_at| triggered_at.to_rfc3339())
+            })
+        })
+        .collect();
+    Ok(utils::json_content(json!({
+        "alerts": alerts,
+        "count": alerts.len(),
+        "offset": offset
+    })))
+}
+
+pub(crate) async fn handle_get_triggered_alerts(db: &dyn DatabaseBackend, args: &Value) -> Result<Value, PluginError> {
+    ensure_enabled()?;
+    let TriggeredArgs {
+        since,
+        alert_id,
+        open_only,
+        limit,
+    } = args::parse(args)?;
+    let since = since.map(|since| parse_timestamp(&since, "since")).transpose()?;
+    let limit = limit.unwrap_or(DEFAULT_ALERT_LIMIT);
+    let config = get_config();
+    let alerts = quote_identifier(&config.price_alerts_table).map_err(PluginError::internal)?;
+    let triggered = quote_identifier(&config.triggered_alerts_table).map_err(PluginError::internal)?;
+
+    let rows = sqlx::query_as::<_, TriggeredAlert>(&format!(
+        "SELECT t.id, t.alert_id, t.product_id, p.name, t.price, a.threshold, a.direction, \
+                t.triggered_at, t.resolved_at \
+         FROM {triggered} t JOIN {alerts} a ON a.id = t.alert_id JOIN {} p ON p.id = t.product_id \
+         WHERE ($1::timestamptz IS NULL OR t.triggered_at >= $1) AND ($2::bigint IS NULL OR t.alert_id = $2) \
+           AND (NOT $3 OR t.resolved_at IS NULL) \
+         ORDER BY t.triggered_at DESC, t.id DESC LIMIT $4",
+        mapping::products()
+    ))
+    .bind(since)
+    .bind(alert_id)
+    .bind(open_only)
+    .bind(limit)
+    .fetch_all(db.postgres()?)
+    .await
+    .map_err(alert_error)?;
+
+    let triggered: Vec<Value> = rows.iter().map(TriggeredAlert::to_json).collect();
+    Ok(utils::json_content(json!({
+        "triggered": triggered,
+        "count": triggered.len()
+    })))
+}
+
+/// Trigger the alerts of the rules the active products now meet and
+/// resolve those whose products no longer do
+async fn evaluate(db: &dyn DatabaseBackend) -> Result<Vec<TriggeredAlert>, PluginError> {
+    let config = get_config();
+    let alerts = quote_identifier(&config.price_alerts_table).map_err(PluginError::internal)?;
+    let triggered = quote_identifier(&config.triggered_alerts_table).map_err(PluginError::internal)?;
+    let rows = sqlx::query_as::<_, TriggeredAlert>(&format!(
+        "WITH matches AS ( \
+             SELECT a.id AS alert_id, p.id AS product_id, p.name, p.price, a.threshold, a.direction \
+             FROM {alerts} a JOIN {} p ON p.id = a.product_id OR p.category = a.category \
+             WHERE (p.status IS NULL OR p.status = ANY($1)) \
+               AND CASE a.direction WHEN 'above' THEN p.price >= a.threshold ELSE p.price <= a.threshold END \
+         ), resolved AS ( \
+             UPDATE {triggered} t SET resolved_at = now() \
+             WHERE t.resolved_at IS NULL AND NOT EXISTS ( \
+                 SELECT 1 FROM matches m WHERE m.alert_id = t.alert_id AND m.product_id = t.product_id) \
+         ), inserted AS ( \
+             INSERT INTO {triggered} (alert_id, product_id, price) \
+             SELECT alert_id, product_id, price FROM matches \
+             ON CONFLICT (alert_id, product_id) WHERE resolved_at IS NULL DO NOTHING \
+             RETURNING id, alert_id, product_id, triggered_at \
+         ) \
+         SELECT i.id, i.alert_id, i.product_id, m.name, m.price, m.threshold, m.direction, \
+                i.triggered_at, NULL::timestamptz AS resolved_at \
+         FROM inserted i JOIN matches m USING (alert_id, product_id) \
+         ORDER BY i.id",
+        mapping::products()
+    ))
+    .bind(&config.schema_mapping.active_statuses)
+    .fetch_all(db.primary()?)
+    .await
+    .map_err(alert_error)?;
+    Ok(rows)
+}
+
+/// Spawn the evaluation task on the current runtime
+pub(crate) fn start(db: Arc<ArcSwap<Database>>) -> JoinHandle<()> {
+    tokio::spawn(run(db))
+}
+
+/// Evaluate the rules now, called after price changes and once a
+/// configuration is applied
+pub(crate) fn wake() {
+    WAKE.notify_one();
+}
+
+async fn run(db: Arc<ArcSwap<Database>>) {
+    loop {
+        let config = get_config();
+        if !config.price_alerts_enabled {
+            WAKE.notified().await;
+            continue;
+        }
+
+        match evaluate(&**db.load_full()).await {
+            Ok(triggered) => {
+                if !triggered.is_empty() {
+                    tracing::info!(alerts = triggered.len(), "Price alerts triggered");
+                }
+                if config.price_alert_webhooks {
+                    for alert in &triggered {
+                        let mut event = alert.to_json();
+                        event["event"] = json!("price_alert.triggered");
+                        webhooks::notify("price_alert.triggered", event);
+                    }
+                }
+            }
+            Err(err) => tracing::warn!("Evaluating price alerts failed: {}", err.message()),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(config.price_alert_interval_seconds)) => {}
+            _ = WAKE.notified() => {}
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! Typed tool arguments
+//!
+//! Tools declare their arguments as a `#[derive(Deserialize, JsonSchema)]`
+//! struct and read them with `parse`. The call arguments are first checked
+//! against the struct's JSON Schema, so that a wrong argument is reported
+//! with its field, the expected type or range and the received value
+//! instead of a generic message, and then deserialized. Arguments the
+//! struct does not know, such as `tenant` and `_meta`, are left to the
+//! dispatcher.
+//!
+//! The check covers the schema keywords `schemars` derives from Rust types
+//! and `#[schemars(...)]` attributes: `type`, `enum`, `minimum`, `maximum`,
+//! `minLength`, `maxLength`, `items`, `minItems`, `maxItems`, `required`,
+//! `properties`, `anyOf` and local `$ref`s. Checks that depend on the
+//! configuration, like `max_batch_size`, are made by the dispatcher.
+
+use crate::error::{FieldError, PluginError};
+use once_cell::sync::Lazy;
+use rust_decimal::Decimal;
+use schemars::{schema_for, JsonSchema};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Argument schemas by type name, derived on first use
+static SCHEMAS: Lazy<RwLock<HashMap<&'static str, Arc<Value>>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// JSON Schema of an argument struct
+pub(crate) fn schema<T: JsonSchema>() -> Arc<Value> {
+    let name = std::any::type_name::<T>();
+    if let Some(schema) = SCHEMAS.read().unwrap().get(name) {
+        return schema.clone();
+    }
+    let schema = Arc::new(json!(schema_for!(T)));
+    SCHEMAS.write().unwrap().insert(name, schema.clone());
+    schema
+}
+
+/// Validate the call arguments against the schema of `T` and deserialize them
+pub(crate) fn parse<T: DeserializeOwned + JsonSchema>(args: &Value) -> Result<T, PluginError> {
+    let schema = schema::<T>();
+    let empty = json!({});
+    let args = if args.is_null() { &empty } else { args };
+    check(&schema, &schema, args, "").map_err(PluginError::InvalidField)?;
+    // Read in place instead of from a copy, as arguments can be large.
+    // Only fails for values the schema cannot express, e.g. an i32 overflow
+    T::deserialize(args).map_err(|err| PluginError::invalid_argument(format!("Invalid arguments: {err}")))
+}
+
+/// A decimal given as JSON number or string, e.g. a price
+///
+/// Numbers are converted through their JSON text, so `49.99` stays exact.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub(crate) enum DecimalArg {
+    Number(serde_json::Number),
+    Text(String),
+}
+
+impl DecimalArg {
+    /// Parse the decimal, naming the argument `name` on failure
+    pub(crate) fn to_decimal(&self, name: &str) -> Result<Decimal, PluginError> {
+        let text = match self {
+            DecimalArg::Number(number) => number.to_string(),
+            DecimalArg::Text(text) => text.clone(),
+        };
+        text.parse::<Decimal>()
+            .or_else(|_| Decimal::from_scientific(&text))
+            .map_err(|_| FieldError::new(name, "a decimal number", &Value::String(text)))
+            .map_err(PluginError::InvalidField)
+    }
+}
+
+/// Path of a nested field, e.g. `product_ids[2]`
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{path}.{field}")
+    }
+}
+
+/// Resolve a `#/definitions/...` reference against the root schema
+fn resolve<'a>(root: &'a Value, schema: &'a Value) -> &'a Value {
+    match schema["$ref"].as_str().and_then(|reference| reference.strip_prefix("#/")) {
+        Some(pointer) => root.pointer(&format!("/{pointer}")).unwrap_or(&Value::Bool(true)),
+        None => schema,
+    }
+}
+
+fn type_matches(name: &str, value: &Value) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+/// Human-readable description of what a schema accepts, without null
+fn describe(root: &Value, schema: &Value) -> String {
+    let schema = resolve(root, schema);
+    if let Some(values) = schema["enum"].as_array() {
+        let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+        return format!("one of {}", values.join(", "));
+    }
+    if let Some(alternatives) = schema["anyOf"].as_array() {
+        let alternatives: Vec<String> = alternatives
+            .iter()
+            .filter(|alternative| resolve(root, alternative)["type"] != "null")
+            .map(|alternative| describe(root, alternative))
+            .collect();
+        return alternatives.join(" or ");
+    }
+    let types: Vec<&str> = match &schema["type"] {
+        Value::String(name) => vec![name.as_str()],
+        Value::Array(names) => names.iter().filter_map(Value::as_str).filter(|name| *name != "null").collect(),
+        _ => return "any value".to_string(),
+    };
+    let types = types.join(" or ");
+    if types == "array" {
+        if let Some(items) = schema.get("items") {
+            return format!("array of {}", describe(root, items));
+        }
+    }
+    types
+}
+
+/// Check `value` at `path` against `schema`
+fn check(root: &Value, schema: &Value, value: &Value, path: &str) -> Result<(), FieldError> {
+    let schema = resolve(root, schema);
+    let field = if path.is_empty() { "arguments" } else { path };
+
+    if let Some(alternatives) = schema["anyOf"].as_array() {
+        if !alternatives.iter().any(|alternative| check(root, alternative, value, path).is_ok()) {
+            return Err(FieldError::new(field, describe(root, schema), value));
+        }
+    }
+
+    let type_ok = match &schema["type"] {
+        Value::String(name) => type_matches(name, value),
+        Value::Array(names) => names.iter().filter_map(Value::as_str).any(|name| type_matches(name, value)),
+        _ => true,
+    };
+    if !type_ok {
+        return Err(FieldError::new(field, describe(root, schema), value));
+    }
+
+    if let Some(values) = schema["enum"].as_array() {
+        if !values.contains(value) {
+            return Err(FieldError::new(field, describe(root, schema), value));
+        }
+    }
+
+    match value {
+        Value::Number(number) => {
+            if schema["format"] == "int32" && number.as_i64().and_then(|n| i32::try_from(n).ok()).is_none() {
+                return Err(FieldError::new(field, "32-bit integer", value));
+            }
+            let number = number.as_f64().unwrap_or_default();
+            let (minimum, maximum) = (schema["minimum"].as_f64(), schema["maximum"].as_f64());
+            if minimum.is_some_and(|minimum| number < minimum) || maximum.is_some_and(|maximum| number > maximum) {
+                let expected = match (minimum, maximum) {
+                    (Some(minimum), Some(maximum)) => format!("{minimum}..={maximum}"),
+                    (Some(minimum), None) => format!("at least {minimum}"),
+                    (None, Some(maximum)) => format!("at most {maximum}"),
+                    (None, None) => unreachable!(),
+                };
+                return Err(FieldError::new(field, expected, value));
+            }
+        }
+        Value::String(text) => {
+            let length = text.chars().count() as u64;
+            if schema["minLength"].as_u64().is_some_and(|minimum| length < minimum) {
+                return Err(FieldError::new(field, format!("at least {} characters", schema["minLength"]), value));
+            }
+            if schema["maxLength"].as_u64().is_some_and(|maximum| length > maximum) {
+                return Err(FieldError::new(field, format!("at most {} characters", schema["maxLength"]), value));
+            }
+        }
+        Value::Array(items) => {
+            let length = items.len() as u64;
+            if schema["minItems"].as_u64().is_some_and(|minimum| length < minimum) {
+                return Err(FieldError::new(field, format!("at least {} items", schema["minItems"]), value));
+            }
+            if schema["maxItems"].as_u64().is_some_and(|maximum| length > maximum) {
+                return Err(FieldError::new(field, format!("at most {} items", schema["maxItems"]), value));
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    check(root, item_schema, item, &format!("{field}[{index}]"))?;
+                }
+            }
+        }
+        Value::Object(object) => {
+            for required in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                if object.get(required).is_none_or(Value::is_null) {
+                    let expected = describe(root, &schema["properties"][required]);
+                    return Err(FieldError::new(join(path, required), expected, &Value::Null));
+                }
+            }
+            if let Some(properties) = schema["properties"].as_object() {
+                for (name, property) in properties {
+                    if let Some(value) = object.get(name) {
+                        check(root, property, value, &join(path, name))?;
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize, JsonSchema)]
+    struct TestArgs {
+        product_id: i32,
+        #[schemars(range(min = 1, max = 50))]
+        limit: Option<i64>,
+        price: Option<DecimalArg>,
+        #[serde(default)]
+        dry_run: bool,
+    }
+
+    /// The field error of a failed parse
+    fn field_error(args: Value) -> FieldError {
+        match parse::<TestArgs>(&args) {
+            Err(PluginError::InvalidField(err)) => err,
+            other => panic!("expected a field error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_valid_arguments() {
+        let args: TestArgs = parse(&json!({"product_id": 7, "limit": 50, "dry_run": true})).unwrap();
+        assert_eq!(args.product_id, 7);
+        assert_eq!(args.limit, Some(50));
+        assert!(args.dry_run);
+        assert!(args.price.is_none());
+    }
+
+    #[test]
+    fn rejects_missing_required_field() {
+        let err = field_error(json!({"limit": 5}));
+        assert_eq!(err.field, "product_id");
+        assert_eq!(err.received, Value::Null);
+
+        let err = field_error(json!({"product_id": null}));
+        assert_eq!(err.field, "product_id");
+        assert_eq!(field_error(Value::Null).field, "product_id");
+    }
+
+    #[test]
+    fn rejects_wrong_types() {
+        let err = field_error(json!({"product_id": "7"}));
+        assert_eq!(err.field, "product_id");
+        assert_eq!(err.expected, "integer");
+        assert_eq!(err.received, json!("7"));
+
+        assert_eq!(field_error(json!({"product_id": 7.5})).field, "product_id");
+        assert_eq!(field_error(json!({"product_id": 7, "dry_run": "yes"})).field, "dry_run");
+        assert_eq!(field_error(json!({"product_id": 7, "price": true})).field, "price");
+        assert_eq!(field_error(json!([7])).field, "arguments");
+    }
+
+    #[test]
+    fn ignores_unknown_fields() {
+        let args: TestArgs = parse(&json!({"product_id": 7, "tenant": "acme", "_meta": {"progressToken": 1}})).unwrap();
+        assert_eq!(args.product_id, 7);
+    }
+
+    #[test]
+    fn rejects_out_of_range_integers() {
+        let err = field_error(json!({"product_id": 2_147_483_648_i64}));
+        assert_eq!(err.field, "product_id");
+        assert_eq!(err.expected, "32-bit integer");
+        assert_eq!(field_error(json!({"product_id": -2_147_483_649_i64})).field, "product_id");
+        assert!(parse::<TestArgs>(&json!({"product_id": i32::MIN})).is_ok());
+
+        let err = field_error(json!({"product_id": 7, "limit": 51}));
+        assert_eq!(err.field, "limit");
+        assert_eq!(err.expected, "1..=50");
+        assert_eq!(field_error(json!({"product_id": 7, "limit": 0})).field, "limit");
+    }
+
+    #[test]
+    fn reads_decimals_from_strings_and_numbers() {
+        let decimal = |price: Value| {
+            let args: TestArgs = parse(&json!({"product_id": 7, "price": price})).unwrap();
+            args.price.unwrap().to_decimal("price")
+        };
+        assert_eq!(decimal(json!(49.99)).unwrap(), Decimal::new(4999, 2));
+        assert_eq!(decimal(json!("49.99")).unwrap(), Decimal::new(4999, 2));
+        assert_eq!(decimal(json!(10)).unwrap(), Decimal::from(10));
+        assert_eq!(decimal(json!("1e2")).unwrap(), Decimal::from(100));
+
+        match decimal(json!("cheap")) {
+            Err(PluginError::InvalidField(err)) => {
+                assert_eq!(err.field, "price");
+                assert_eq!(err.received, json!("cheap"));
+            }
+            other => panic!("expected a field error, got {other:?}"),
+        }
+    }
+}
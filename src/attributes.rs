@@ -0,0 +1,156 @@
+//! Product attributes
+//!
+//! Catalogues keep attributes such as colour or size either in a JSONB
+//! column of the product table or in an attribute table with one row per
+//! product and attribute (entity-attribute-value). `schema_mapping.
+//! attributes` names one of the two:
+//!
+//! ```json
+//! {"schema_mapping": {"attributes": {"column": "specs"}}}
+//! {"schema_mapping": {"attributes": {"table": "product_attributes", "product_id_column": "product_id",
+//!     "name_column": "name", "value_column": "value"}}}
+//! ```
+//!
+//! Either way every product carries its `attributes` as one JSON object,
+//! and `search_products` and `count_products` take an `attributes` object
+//! of required values, e.g. `{"color": "red", "size": "L"}`. Filters are
+//! bound as parameters, never spliced into the statement: on a column they
+//! become one JSONB containment `@>`, which a GIN index on the column
+//! answers; on a table an `EXISTS` per attribute comparing the value as
+//! text, best backed by an index on (name, value).
+
+use crate::error::PluginError;
+use crate::get_config;
+use crate::mapping::{quote_column, SchemaMapping};
+use crate::sql::quote_identifier;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sqlx::{Postgres, QueryBuilder};
+
+/// Where the product attributes are stored
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, JsonSchema)]
+pub(crate) struct AttributeMapping {
+    /// JSONB column of the product table holding the attributes as object
+    #[serde(default)]
+    column: Option<String>,
+
+    /// Attribute table with one row per product and attribute, optionally
+    /// schema qualified
+    #[serde(default)]
+    table: Option<String>,
+
+    /// Column of the attribute table referencing the product ID
+    #[serde(default = "default_product_id_column")]
+    product_id_column: String,
+
+    /// Column of the attribute table with the attribute name
+    #[serde(default = "default_name_column")]
+    name_column: String,
+
+    /// Column of the attribute table with the attribute value
+    #[serde(default = "default_value_column")]
+    value_column: String,
+}
+
+fn default_product_id_column() -> String {
+    "product_id".to_string()
+}
+
+fn default_name_column() -> String {
+    "name".to_string()
+}
+
+fn default_value_column() -> String {
+    "value".to_string()
+}
+
+impl AttributeMapping {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        match (&self.column, &self.table) {
+            (Some(column), None) => quote_column(column).map(|_| ()),
+            (None, Some(table)) => {
+                quote_identifier(table)?;
+                for column in [&self.product_id_column, &self.name_column, &self.value_column] {
+                    quote_column(column)?;
+                }
+                Ok(())
+            }
+            _ => Err("schema_mapping.attributes needs either a column or a table".to_string()),
+        }
+    }
+
+    /// Expression of the `attributes` object in the mapped relation
+    pub(crate) fn expression(&self, mapping: &SchemaMapping) -> String {
+        let quote = |column: &str| quote_column(column).expect("validated schema_mapping");
+        match (&self.column, &self.table) {
+            (Some(column), _) => format!("{}::jsonb", quote(column)),
+            (None, Some(table)) => format!(
+                "(SELECT jsonb_object_agg(attribute_rows.{name}, to_jsonb(attribute_rows.{value})) \
+                 FROM {} AS attribute_rows WHERE attribute_rows.{} = {}.{})",
+                quote_identifier(table).expect("validated schema_mapping"),
+                quote(&self.product_id_column),
+                mapping.table(),
+                mapping.column(&mapping.id_column),
+                name = quote(&self.name_column),
+                value = quote(&self.value_column),
+            ),
+            (None, None) => "NULL::jsonb".to_string(),
+        }
+    }
+}
+
+/// Check an `attributes` filter argument: the catalogue must map
+/// attributes, and every value must be a string, number or boolean
+pub(crate) fn validate_filter(filter: &Map<String, Value>) -> Result<(), PluginError> {
+    if get_config().schema_mapping.attributes.is_none() {
+        return Err(PluginError::Unsupported(
+            "Attribute filters require schema_mapping.attributes".to_string(),
+        ));
+    }
+    for (name, value) in filter {
+        if name.is_empty() {
+            return Err(PluginError::invalid_argument("Attribute names must not be empty"));
+        }
+        if !matches!(value, Value::String(_) | Value::Number(_) | Value::Bool(_)) {
+            return Err(PluginError::invalid_argument(format!(
+                "Invalid value for attribute '{name}': expected a string, number or boolean"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Append ` AND ...` conditions requiring the attribute values of `filter`
+/// of the products in the mapped relation
+pub(crate) fn push_filter(sql: &mut QueryBuilder<'_, Postgres>, filter: &Map<String, Value>) {
+    let config = get_config();
+    let Some(mapping) = &config.schema_mapping.attributes else {
+        return;
+    };
+    if filter.is_empty() {
+        return;
+    }
+    let Some(table) = &mapping.table else {
+        sql.push(" AND attributes @> ").push_bind(Value::Object(filter.clone())).push("::jsonb");
+        return;
+    };
+    let table = quote_identifier(table).expect("validated schema_mapping");
+    let quote = |column: &str| quote_column(column).expect("validated schema_mapping");
+    for (name, value) in filter {
+        let value = match value {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        sql.push(format!(
+            " AND EXISTS (SELECT 1 FROM {table} AS attribute_rows WHERE attribute_rows.{} = products.id \
+             AND attribute_rows.{} = ",
+            quote(&mapping.product_id_column),
+            quote(&mapping.name_column)
+        ))
+        .push_bind(name.clone())
+        .push(format!(" AND attribute_rows.{}::text = ", quote(&mapping.value_column)))
+        .push_bind(value)
+        .push(")");
+    }
+}
@@ -0,0 +1,209 @@
+//! Audit log of tool invocations
+//!
+//! Every tool call is recorded with its arguments, the user of its `_auth`
+//! context, latency and outcome,
+//! either in a Postgres table or in a JSONL file, as selected by
+//! `audit_log` (off, table or file). Request tasks only queue the entry;
+//! a background task does the writing, so auditing never delays a
+//! response, and a failing sink is reported on stderr instead of failing
+//! tool calls.
+//!
+//! The table (name configurable through `audit_log_table`):
+//!
+//! ```sql
+//! CREATE TABLE plugin_audit_log (
+//!     id         BIGSERIAL PRIMARY KEY,
+//!     called_at  TIMESTAMPTZ NOT NULL,
+//!     tool       TEXT NOT NULL,
+//!     arguments  JSONB NOT NULL,
+//!     latency_ms DOUBLE PRECISION NOT NULL,
+//!     status     TEXT NOT NULL,
+//!     error_code TEXT,
+//!     user_id    TEXT
+//! );
+//! ```
+//!
+//! Tables created before `user_id` was recorded need the column added:
+//! `ALTER TABLE plugin_audit_log ADD COLUMN user_id TEXT`.
+
+use crate::auth;
+use crate::backend::Database;
+use crate::error::PluginError;
+use crate::sql::quote_identifier;
+use crate::{get_config, PluginConfig};
+use arc_swap::ArcSwap;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Where audit entries go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AuditSink {
+    Off,
+    Table,
+    File,
+}
+
+impl AuditSink {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "off" => Ok(AuditSink::Off),
+            "table" => Ok(AuditSink::Table),
+            "file" => Ok(AuditSink::File),
+            other => Err(format!("Invalid audit_log '{other}', expected one of: off, table, file")),
+        }
+    }
+
+    /// Sink of the current configuration; validated before it was applied
+    fn current() -> Self {
+        AuditSink::parse(&get_config().audit_log).unwrap_or(AuditSink::Off)
+    }
+}
+
+#[derive(Serialize)]
+struct AuditEntry {
+    called_at: DateTime<Utc>,
+    tool: &'static str,
+    #[serde(serialize_with = "serialize_arguments")]
+    arguments: Arc<Value>,
+    latency_ms: f64,
+    status: &'static str,
+    error_code: Option<&'static str>,
+    user_id: Option<String>,
+}
+
+/// Handle used by request tasks to queue audit entries
+pub(crate) struct AuditLog {
+    tx: mpsc::UnboundedSender<AuditEntry>,
+}
+
+/// Validate the audit settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    match AuditSink::parse(&config.audit_log)? {
+        AuditSink::Off => Ok(()),
+        AuditSink::Table if config.read_only => Err(
+            "audit_log 'table' writes to the database and requires read_only to be false; use 'file' instead"
+                .to_string(),
+        ),
+        AuditSink::Table => quote_identifier(&config.audit_log_table).map(|_| ()),
+        AuditSink::File if config.audit_log_file.is_none() => {
+            Err("audit_log 'file' requires audit_log_file".to_string())
+        }
+        AuditSink::File => Ok(()),
+    }
+}
+
+impl AuditLog {
+    /// Start the background writer
+    ///
+    /// The writer exits once every `AuditLog` handle is dropped and the
+    /// queue is drained; await the returned handle to flush on shutdown.
+    pub(crate) fn start(db: Arc<ArcSwap<Database>>) -> (Self, JoinHandle<()>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let writer = tokio::spawn(write_entries(rx, db));
+        (AuditLog { tx }, writer)
+    }
+
+    /// Whether calls are currently audited, to skip copying arguments if not
+    pub(crate) fn enabled(&self) -> bool {
+        AuditSink::current() != AuditSink::Off
+    }
+
+    /// Queue the record of one tool call, with the `_auth` context moved
+    /// from the arguments to the user column
+    pub(crate) fn record(
+        &self,
+        tool: &'static str,
+        arguments: Arc<Value>,
+        elapsed: Duration,
+        result: &Result<Value, PluginError>,
+    ) {
+        let entry = AuditEntry {
+            called_at: Utc::now(),
+            tool,
+            user_id: auth::user_id(&arguments),
+            arguments,
+            latency_ms: elapsed.as_secs_f64() * 1000.0,
+            status: if result.is_ok() { "ok" } else { "error" },
+            error_code: result.as_ref().err().map(PluginError::code),
+        };
+        // Only fails once the writer is gone during shutdown
+        let _ = self.tx.send(entry);
+    }
+}
+
+/// Write the arguments without the `_auth` object, which is recorded as `user_id`
+fn serialize_arguments<S: Serializer>(arguments: &Arc<Value>, serializer: S) -> Result<S::Ok, S::Error> {
+    auth::WithoutContext(arguments).serialize(serializer)
+}
+
+async fn write_entries(mut rx: mpsc::UnboundedReceiver<AuditEntry>, db: Arc<ArcSwap<Database>>) {
+    // Kept open between entries, reopened when audit_log_file changes
+    let mut file: Option<(String, File)> = None;
+
+    while let Some(entry) = rx.recv().await {
+        let written = match AuditSink::current() {
+            AuditSink::Off => Ok(()),
+            AuditSink::Table => write_row(&db, &entry).await,
+            AuditSink::File => write_line(&mut file, &entry).await,
+        };
+        if let Err(err) = written {
+            tracing::error!("Failed to write audit entry for {}: {err}", entry.tool);
+        }
+    }
+
+    if let Some((_, mut file)) = file {
+        let _ = file.flush().await;
+    }
+}
+
+async fn write_row(db: &ArcSwap<Database>, entry: &AuditEntry) -> Result<(), String> {
+    let table = quote_identifier(&get_config().audit_log_table)?;
+    let db = db.load_full();
+    sqlx::query(&format!(
+        "INSERT INTO {table} (called_at, tool, arguments, latency_ms, status, error_code, user_id) \
+         VALUES ($1, $2, $3::jsonb, $4, $5, $6, $7)"
+    ))
+    .bind(entry.called_at)
+    .bind(entry.tool)
+    .bind(serde_json::to_string(&auth::WithoutContext(&entry.arguments)).map_err(|err| err.to_string())?)
+    .bind(entry.latency_ms)
+    .bind(entry.status)
+    .bind(entry.error_code)
+    .bind(&entry.user_id)
+    .execute(db.primary().map_err(|err| err.to_string())?)
+    .await
+    .map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+async fn write_line(file: &mut Option<(String, File)>, entry: &AuditEntry) -> Result<(), String> {
+    let path = get_config()
+        .audit_log_file
+        .clone()
+        .ok_or_else(|| "audit_log_file is not set".to_string())?;
+
+    if file.as_ref().is_none_or(|(open_path, _)| *open_path != path) {
+        if let Some((_, mut previous)) = file.take() {
+            let _ = previous.flush().await;
+        }
+        let opened = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(|err| format!("cannot open {path}: {err}"))?;
+        *file = Some((path, opened));
+    }
+
+    let mut line = serde_json::to_vec(entry).map_err(|err| err.to_string())?;
+    line.push(b'\n');
+    let (_, file) = file.as_mut().expect("opened above");
+    file.write_all(&line).await.map_err(|err| err.to_string())
+}
@@ -0,0 +1,186 @@
+//! Caller authentication context
+//!
+//! The host authenticates callers; the plugin learns who is calling from
+//! the `_auth` object the host adds to the tool arguments:
+//!
+//! ```json
+//! {"_auth": {"user_id": "u-1042", "roles": ["pricing"], "customer_id": "C-77"}}
+//! ```
+//!
+//! `user_id` is required, `roles` and `customer_id` are optional. The
+//! context is used by:
+//!
+//! - access control: callers with one of `access_policy.unredacted_roles`
+//!   see the fields named in `redacted_fields`
+//! - auditing: the user is recorded with every audit entry, and write tools
+//!   record it as `changed_by`, refusing a `changed_by` naming someone else;
+//!   only calls without the context record the `changed_by` they give
+//! - customer pricing: `get_product_price` prices for the context's
+//!   customer, and refuses a `customer_id` naming another one
+//!
+//! With `require_auth` set, calls without `_auth` are refused. The object
+//! is not checked against anything; hosts must strip any `_auth` a client
+//! sent before adding their own.
+//!
+//! `authorization` maps roles to the tools they may call. A tool listed for
+//! any role is only dispatched for callers with one of those roles, and
+//! refused with `permission_denied` before its handler runs; tools listed
+//! nowhere stay open to every caller. `"*"` grants a role every tool:
+//!
+//! ```json
+//! {"authorization": {"pricing_admin": ["update_product_price", "import_prices"], "support": ["*"]}}
+//! ```
+
+use crate::error::PluginError;
+use crate::{get_config, get_tools, PluginConfig};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Name of the argument carrying the context
+pub(crate) const AUTH_ARGUMENT: &str = "_auth";
+
+/// Entry of an `authorization` tool list granting every tool
+const ANY_TOOL: &str = "*";
+
+/// The authenticated caller of a tool call
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct AuthContext {
+    /// Identifier of the user, as known to the host
+    pub(crate) user_id: String,
+
+    /// Roles granted to the user
+    #[serde(default)]
+    pub(crate) roles: Vec<String>,
+
+    /// Customer the user buys for, if any
+    #[serde(default)]
+    pub(crate) customer_id: Option<String>,
+}
+
+impl AuthContext {
+    /// Whether the user has at least one of `roles`
+    pub(crate) fn has_any_role(&self, roles: &[String]) -> bool {
+        self.roles.iter().any(|role| roles.contains(role))
+    }
+}
+
+/// Validate the authorization policy, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    let tools = get_tools();
+    for (role, granted) in &config.authorization {
+        if role.is_empty() {
+            return Err("authorization roles must not be empty".to_string());
+        }
+        for tool in granted {
+            if tool != ANY_TOOL && !tools.contains_key(tool) {
+                return Err(format!("Unknown tool '{tool}' in authorization of role {role}"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Roles allowed to call `tool`, `None` if it is open to every caller
+fn allowed_roles(policy: &HashMap<String, Vec<String>>, tool: &str) -> Option<Vec<String>> {
+    if !policy.values().flatten().any(|granted| granted == tool) {
+        return None;
+    }
+    let mut roles: Vec<String> = policy
+        .iter()
+        .filter(|(_, granted)| granted.iter().any(|granted| granted == tool || granted == ANY_TOOL))
+        .map(|(role, _)| role.clone())
+        .collect();
+    roles.sort();
+    Some(roles)
+}
+
+/// The `_auth` argument of a call, `None` if absent
+pub(crate) fn parse(args: &Value) -> Result<Option<AuthContext>, PluginError> {
+    let auth = match args.get(AUTH_ARGUMENT) {
+        None | Some(Value::Null) => return Ok(None),
+        Some(auth) => AuthContext::deserialize(auth)
+            .map_err(|err| PluginError::invalid_argument(format!("Invalid {AUTH_ARGUMENT} parameter: {err}")))?,
+    };
+    if auth.user_id.is_empty() {
+        return Err(PluginError::invalid_argument(format!("{AUTH_ARGUMENT}.user_id must not be empty")));
+    }
+    Ok(Some(auth))
+}
+
+/// Check the `_auth` argument and the caller's authorization for `tool`
+/// before a call is dispatched
+pub(crate) fn check(tool: &str, args: &Value) -> Result<(), PluginError> {
+    let auth = parse(args)?;
+    let config = get_config();
+    if auth.is_none() && config.require_auth {
+        return Err(PluginError::PermissionDenied(format!(
+            "Calls must carry the {AUTH_ARGUMENT} context of an authenticated user"
+        )));
+    }
+    let Some(roles) = allowed_roles(&config.authorization, tool) else {
+        return Ok(());
+    };
+    match auth {
+        Some(auth) if auth.has_any_role(&roles) => Ok(()),
+        Some(auth) => Err(PluginError::PermissionDenied(format!(
+            "User {} may not call {tool}, it requires one of the roles: {}",
+            auth.user_id,
+            roles.join(", ")
+        ))),
+        None => Err(PluginError::PermissionDenied(format!(
+            "{tool} requires an {AUTH_ARGUMENT} context with one of the roles: {}",
+            roles.join(", ")
+        ))),
+    }
+}
+
+/// User of a call, `None` without a valid `_auth` argument
+pub(crate) fn user_id(args: &Value) -> Option<String> {
+    parse(args).ok().flatten().map(|auth| auth.user_id)
+}
+
+/// Who a price change is recorded as changed by: the user of the `_auth`
+/// context, else the `changed_by` argument
+pub(crate) fn changed_by(changed_by: Option<String>, args: &Value) -> Result<Option<String>, PluginError> {
+    let Some(auth) = parse(args)? else {
+        return Ok(changed_by);
+    };
+    match changed_by {
+        Some(changed_by) if changed_by != auth.user_id => Err(PluginError::PermissionDenied(format!(
+            "changed_by {changed_by} is not the user of the caller's {AUTH_ARGUMENT} context"
+        ))),
+        _ => Ok(Some(auth.user_id)),
+    }
+}
+
+/// Arguments serialized without the `_auth` object, which is recorded
+/// separately, and without copying them
+pub(crate) struct WithoutContext<'a>(pub(crate) &'a Value);
+
+impl Serialize for WithoutContext<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.as_object() {
+            Some(args) => serializer.collect_map(args.iter().filter(|(name, _)| *name != AUTH_ARGUMENT)),
+            None => self.0.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn changed_by_is_the_caller() {
+        let auth = json!({"_auth": {"user_id": "u-1042"}});
+        assert_eq!(changed_by(None, &auth).unwrap().as_deref(), Some("u-1042"));
+        assert_eq!(changed_by(Some("u-1042".to_string()), &auth).unwrap().as_deref(), Some("u-1042"));
+        let err = changed_by(Some("someone-else".to_string()), &auth).unwrap_err();
+        assert!(matches!(err, PluginError::PermissionDenied(_)), "{}", err.message());
+
+        assert_eq!(changed_by(Some("pricing-job".to_string()), &json!({})).unwrap().as_deref(), Some("pricing-job"));
+        assert_eq!(changed_by(None, &json!({})).unwrap(), None);
+    }
+}
@@ -0,0 +1,347 @@
+//! In-memory mock backend, enabled with the `mock` feature
+//!
+//! Serves the catalogue tools from products held in memory, so the plugin
+//! can be tried out and integration tested without a database. Products are
+//! loaded from `mock_fixture`, a JSON array of product objects or a CSV
+//! file with the header `id,name,price,description,category`, chosen by
+//! file extension. Without a fixture a small built-in demo catalogue is
+//! used. `database_url` is ignored.
+
+use super::{require_ilike, Database, DatabaseBackend, ListAfter, ProductList, ProductSearch};
+use crate::error::PluginError;
+use crate::search::{like_pattern, SearchHit, SearchSort};
+use crate::{get_config, CategoryCount, PluginConfig, Product, SortBy};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Demo catalogue used when `mock_fixture` is not set
+const DEMO_PRODUCTS: &str = include_str!("mock_products.json");
+
+/// Row of a CSV fixture; the price is parsed from text to stay exact
+#[derive(Deserialize)]
+struct CsvProduct {
+    id: i32,
+    name: String,
+    price: String,
+    description: Option<String>,
+    category: Option<String>,
+}
+
+fn parse_json(json: &str) -> Result<Vec<Product>, String> {
+    serde_json::from_str(json).map_err(|err| format!("Invalid mock fixture: {err}"))
+}
+
+fn parse_csv(path: &str) -> Result<Vec<Product>, String> {
+    let file = std::fs::File::open(path).map_err(|err| format!("Cannot read mock_fixture {path}: {err}"))?;
+    read_csv(file, path)
+}
+
+/// Products of CSV data read from `path`
+fn read_csv(data: impl std::io::Read, path: &str) -> Result<Vec<Product>, String> {
+    csv::Reader::from_reader(data)
+        .deserialize::<CsvProduct>()
+        .map(|row| {
+            let row = row.map_err(|err| format!("Invalid mock fixture {path}: {err}"))?;
+            let price = Decimal::from_str(row.price.trim())
+                .map_err(|_| format!("Invalid mock fixture {path}: product {} has price '{}'", row.id, row.price))?;
+            Ok(Product {
+                id: row.id,
+                name: row.name,
+                price,
+                description: row.description,
+                category: row.category,
+                extra: None,
+                unit_quantity: None,
+                unit_of_measure: None,
+                status: None,
+                brand: None,
+                image_url: None,
+                thumbnail_url: None,
+                attributes: None,
+            })
+        })
+        .collect()
+}
+
+/// Products from the configured fixture, or the demo catalogue
+pub(super) fn load_products(config: &PluginConfig) -> Result<Vec<Product>, String> {
+    let Some(path) = &config.mock_fixture else {
+        return parse_json(DEMO_PRODUCTS);
+    };
+    if path.ends_with(".csv") {
+        parse_csv(path)
+    } else if path.ends_with(".json") {
+        let json = std::fs::read_to_string(path).map_err(|err| format!("Cannot read mock_fixture {path}: {err}"))?;
+        parse_json(&json)
+    } else {
+        Err(format!("Invalid mock_fixture {path}: expected a .json or .csv file"))
+    }
+}
+
+pub(super) async fn connect(config: &PluginConfig) -> Result<Database, sqlx::Error> {
+    let mut products = load_products(config).map_err(|err| sqlx::Error::Configuration(err.into()))?;
+    products.sort_by_key(|product| product.id);
+    Ok(Box::new(MockBackend { products }))
+}
+
+/// Case-insensitive SQL LIKE: `%` matches any run of characters, `_` a
+/// single character and `\` escapes the next one
+fn like_matches(pattern: &str, text: &str) -> bool {
+    enum Token {
+        Any,
+        One,
+        Char(char),
+    }
+
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars().flat_map(char::to_lowercase);
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '%' => Token::Any,
+            '_' => Token::One,
+            '\\' => Token::Char(chars.next().unwrap_or('\\')),
+            c => Token::Char(c),
+        });
+    }
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+
+    // Greedy matching, backtracking to the last `%` on a mismatch
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        match tokens.get(p) {
+            Some(Token::One) => (p, t) = (p + 1, t + 1),
+            Some(Token::Char(c)) if *c == text[t] => (p, t) = (p + 1, t + 1),
+            Some(Token::Any) => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            _ => match backtrack {
+                Some((any, start)) => {
+                    backtrack = Some((any, start + 1));
+                    (p, t) = (any + 1, start + 1);
+                }
+                None => return false,
+            },
+        }
+    }
+    tokens[p..].iter().all(|token| matches!(token, Token::Any))
+}
+
+struct MockBackend {
+    /// Sorted by id
+    products: Vec<Product>,
+}
+
+impl DatabaseBackend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn fetch_product(&self, id: i32) -> BoxFuture<'_, Result<Option<Product>, PluginError>> {
+        let product = self.products.iter().find(|product| product.id == id).cloned();
+        async move { Ok(product) }.boxed()
+    }
+
+    fn fetch_products<'a>(&'a self, ids: &'a [i32]) -> BoxFuture<'a, Result<Vec<Product>, PluginError>> {
+        let products = self
+            .products
+            .iter()
+            .filter(|product| ids.contains(&product.id))
+            .cloned()
+            .collect();
+        async move { Ok(products) }.boxed()
+    }
+
+    fn search_products<'a>(
+        &'a self,
+        search: &'a ProductSearch<'a>,
+    ) -> BoxFuture<'a, Result<Vec<SearchHit>, PluginError>> {
+        async move {
+            require_ilike(search, self.name())?;
+
+            let pattern = like_pattern(search.query, search.raw_pattern);
+            let mapping = &get_config().schema_mapping;
+            let mut matches: Vec<&Product> = self
+                .products
+                .iter()
+                .filter(|product| like_matches(&pattern, &product.name))
+                .filter(|product| search.category.is_none_or(|category| product.category.as_deref() == Some(category)))
+                .filter(|product| search.min_price.is_none_or(|min_price| product.price >= min_price))
+                .filter(|product| search.max_price.is_none_or(|max_price| product.price <= max_price))
+                .filter(|product| search.include_inactive || mapping.is_active(product.status.as_deref()))
+                .collect();
+            // Already in id order, which also breaks ties (stable sort)
+            match search.sort {
+                SearchSort::Relevance => {}
+                SearchSort::PriceAsc => matches.sort_by_key(|product| product.price),
+                SearchSort::PriceDesc => matches.sort_by_key(|product| Reverse(product.price)),
+                SearchSort::Name => matches.sort_by(|a, b| a.name.cmp(&b.name)),
+            }
+
+            let limit = search.limit.map_or(usize::MAX, |limit| limit as usize);
+            Ok(matches
+                .into_iter()
+                .skip(search.offset as usize)
+                .take(limit)
+                .map(|product| SearchHit { product: product.clone(), rank: None })
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn list_products<'a>(&'a self, list: &'a ProductList) -> BoxFuture<'a, Result<Vec<Product>, PluginError>> {
+        let mapping = &get_config().schema_mapping;
+        let mut products: Vec<&Product> = self
+            .products
+            .iter()
+            .filter(|product| list.include_inactive || mapping.is_active(product.status.as_deref()))
+            .filter(|product| match &list.after {
+                None => true,
+                Some(ListAfter::Id(id)) => product.id > *id,
+                Some(ListAfter::Name(name, id)) => (&product.name, product.id) > (name, *id),
+                Some(ListAfter::Price(price, id)) => (product.price, product.id) > (*price, *id),
+            })
+            .collect();
+        // Already in id order, which also breaks ties (stable sort)
+        match list.sort_by {
+            SortBy::Id => {}
+            SortBy::Name => products.sort_by(|a, b| a.name.cmp(&b.name)),
+            SortBy::Price => products.sort_by_key(|product| product.price),
+        }
+        let products = products.into_iter().take(list.limit as usize).cloned().collect();
+        async move { Ok(products) }.boxed()
+    }
+
+    fn list_categories(&self) -> BoxFuture<'_, Result<Vec<CategoryCount>, PluginError>> {
+        let mut counts: BTreeMap<&str, i64> = BTreeMap::new();
+        for category in self.products.iter().filter_map(|product| product.category.as_deref()) {
+            *counts.entry(category).or_default() += 1;
+        }
+        let categories = counts
+            .into_iter()
+            .map(|(category, product_count)| CategoryCount {
+                category: category.to_string(),
+                product_count,
+            })
+            .collect();
+        async move { Ok(categories) }.boxed()
+    }
+
+    fn ping(&self) -> BoxFuture<'_, Result<(), PluginError>> {
+        async { Ok(()) }.boxed()
+    }
+
+    fn pool_stats(&self) -> Value {
+        json!({ "products": self.products.len() })
+    }
+
+    fn close(&self) -> BoxFuture<'_, ()> {
+        async {}.boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestConfig;
+    use futures::executor::block_on;
+
+    #[test]
+    fn like_wildcards() {
+        assert!(like_matches("%widget%", "Widget Pro"));
+        assert!(like_matches("widget", "WIDGET"));
+        assert!(!like_matches("widget", "Widget Pro"));
+        assert!(like_matches("w_dget%", "Wodget 2"));
+        assert!(!like_matches("w_dget", "Wdget"));
+        assert!(like_matches("%pro%max%", "Gadget Pro Ultra Max"));
+        assert!(!like_matches("%pro%max", "Gadget Pro Max Plus"));
+        assert!(like_matches("%", ""));
+        assert!(!like_matches("_", ""));
+    }
+
+    #[test]
+    fn like_escapes() {
+        assert!(like_matches("100\\%", "100%"));
+        assert!(!like_matches("100\\%", "1000"));
+        assert!(like_matches("a\\_b", "a_b"));
+        assert!(!like_matches("a\\_b", "axb"));
+        assert!(like_matches("c:\\\\temp", "C:\\Temp"));
+        assert!(like_matches(&like_pattern("50% off_", false), "Summer: 50% off_ today"));
+        assert!(!like_matches(&like_pattern("50% off", false), "Summer: 50 percent off"));
+    }
+
+    fn product(id: i32, name: &str, price: i64, status: Option<&str>) -> Product {
+        let csv = format!("id,name,price,description,category\n{id},{name},{price},,\n");
+        let mut product = read_csv(csv.as_bytes(), "products.csv").unwrap().remove(0);
+        product.status = status.map(str::to_string);
+        product
+    }
+
+    #[test]
+    fn list_products_pages_active_products() {
+        let _config = TestConfig::install(json!({"schema_mapping": {"active_statuses": ["active"]}}));
+        let db = MockBackend {
+            products: vec![
+                product(1, "Widget", 5, None),
+                product(2, "Gadget", 3, Some("active")),
+                product(3, "Bolt", 3, Some("discontinued")),
+                product(4, "Anvil", 9, None),
+            ],
+        };
+        let list = |sort_by, after, include_inactive| ProductList { sort_by, after, limit: 2, include_inactive };
+        let ids = |products: Vec<Product>| products.iter().map(|product| product.id).collect::<Vec<_>>();
+
+        assert_eq!(ids(block_on(db.list_products(&list(SortBy::Id, None, false))).unwrap()), [1, 2]);
+        let after = Some(ListAfter::Id(2));
+        assert_eq!(ids(block_on(db.list_products(&list(SortBy::Id, after, false))).unwrap()), [4]);
+        let after = Some(ListAfter::Id(2));
+        assert_eq!(ids(block_on(db.list_products(&list(SortBy::Id, after, true))).unwrap()), [3, 4]);
+
+        assert_eq!(ids(block_on(db.list_products(&list(SortBy::Name, None, true))).unwrap()), [4, 3]);
+        let after = Some(ListAfter::Name("Bolt".to_string(), 3));
+        assert_eq!(ids(block_on(db.list_products(&list(SortBy::Name, after, true))).unwrap()), [2, 1]);
+
+        // Equal prices are ordered by id
+        let after = Some(ListAfter::Price(Decimal::new(3, 0), 2));
+        assert_eq!(ids(block_on(db.list_products(&list(SortBy::Price, after, true))).unwrap()), [3, 1]);
+        let after = Some(ListAfter::Price(Decimal::new(3, 0), 2));
+        assert_eq!(ids(block_on(db.list_products(&list(SortBy::Price, after, false))).unwrap()), [1, 4]);
+    }
+
+    #[test]
+    fn csv_quoted_fields() {
+        let csv = "id,name,price,description,category\n\
+                   1,\"Cable, USB-C\",\" 9.99 \",\"Says \"\"fast\"\"\nand long\",Cables\n\
+                   2,Adapter,4.5,,\n";
+        let products = read_csv(csv.as_bytes(), "products.csv").unwrap();
+        assert_eq!(products.len(), 2);
+        assert_eq!(products[0].name, "Cable, USB-C");
+        assert_eq!(products[0].price, Decimal::new(999, 2));
+        assert_eq!(products[0].description.as_deref(), Some("Says \"fast\"\nand long"));
+        assert_eq!(products[0].category.as_deref(), Some("Cables"));
+        assert_eq!(products[1].price, Decimal::new(45, 1));
+        assert_eq!(products[1].description, None);
+        assert_eq!(products[1].category, None);
+    }
+
+    #[test]
+    fn csv_malformed_rows() {
+        let header = "id,name,price,description,category\n";
+        for (row, error) in [
+            ("x,Widget,1.00,,\n", "Invalid mock fixture products.csv"),
+            ("1,Widget,cheap,,\n", "product 1 has price 'cheap'"),
+            ("1,Widget\n", "Invalid mock fixture products.csv"),
+        ] {
+            let err = read_csv(format!("{header}{row}").as_bytes(), "products.csv").unwrap_err();
+            assert!(err.contains(error), "{row:?}: {err}");
+        }
+        assert!(parse_csv("/nonexistent/products.csv").unwrap_err().starts_with("Cannot read mock_fixture"));
+    }
+}
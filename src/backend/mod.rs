@@ -0,0 +1,293 @@
+//! Database backends
+//!
+//! The catalogue tools (`get_product_price`, `search_products` in `ilike`
+//! mode, `get_products_bulk`, `list_products`, `list_categories`, reading
+//! `product://` resources and `health_check`) run against any backend through the
+//! `DatabaseBackend` trait. Everything else relies on Postgres-only SQL
+//! and gets the pool through `DatabaseBackend::postgres`, which fails with
+//! an `unsupported` error on other backends.
+//!
+//! Postgres is always built in; MySQL, SQLite and the in-memory mock are
+//! enabled with the `mysql`, `sqlite` and `mock` cargo features and
+//! selected by the `backend` config field.
+
+#[cfg(feature = "mock")]
+mod mock;
+#[cfg(feature = "mysql")]
+mod mysql;
+pub(crate) mod postgres;
+mod replicas;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use crate::error::PluginError;
+use crate::search::{SearchHit, SearchMode, SearchSort};
+use crate::{CategoryCount, PluginConfig, Product, SortBy};
+use crate::progress::Progress;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use rust_decimal::Decimal;
+use serde_json::{json, Map, Value};
+use log::LevelFilter;
+use sqlx::pool::PoolOptions;
+use sqlx::{ConnectOptions, PgPool, Pool};
+use std::time::Duration;
+
+/// Connected database, swapped as a whole on reconfiguration
+pub(crate) type Database = Box<dyn DatabaseBackend>;
+
+/// Database engine selected by the `backend` config field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BackendKind {
+    Postgres,
+    Mysql,
+    Sqlite,
+    Mock,
+}
+
+impl BackendKind {
+    pub(crate) fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "postgres" | "postgresql" => Ok(BackendKind::Postgres),
+            "mysql" => Ok(BackendKind::Mysql),
+            "sqlite" => Ok(BackendKind::Sqlite),
+            "mock" => Ok(BackendKind::Mock),
+            other => Err(format!(
+                "Invalid backend '{other}', expected one of: postgres, mysql, sqlite, mock"
+            )),
+        }
+    }
+}
+
+/// Arguments of a product search, already validated
+pub(crate) struct ProductSearch<'a> {
+    pub(crate) mode: SearchMode,
+    pub(crate) query: &'a str,
+    pub(crate) raw_pattern: bool,
+    pub(crate) category: Option<&'a str>,
+    /// Needs `schema_mapping.brand_column`, so only Postgres searches see it
+    pub(crate) brand: Option<&'a str>,
+    /// Required attribute values, needs `schema_mapping.attributes` like
+    /// `brand` its column
+    pub(crate) attributes: Option<&'a Map<String, Value>>,
+    pub(crate) min_price: Option<Decimal>,
+    pub(crate) max_price: Option<Decimal>,
+    pub(crate) sort: SearchSort,
+    /// Maximum number of hits, all of them if `None`
+    pub(crate) limit: Option<i64>,
+    /// Number of hits to skip
+    pub(crate) offset: i64,
+    /// Also return products whose status is not active
+    pub(crate) include_inactive: bool,
+    /// Reports the number of rows read so far
+    pub(crate) progress: &'a Progress,
+}
+
+/// Last row of the previous `list_products` page: its sort value and id
+pub(crate) enum ListAfter {
+    Id(i32),
+    Name(String, i32),
+    Price(Decimal, i32),
+}
+
+/// Arguments of a `list_products` page, already validated
+pub(crate) struct ProductList {
+    /// Order of the products, ties broken by id
+    pub(crate) sort_by: SortBy,
+    /// Continue strictly after this row, matching `sort_by`
+    pub(crate) after: Option<ListAfter>,
+    /// Maximum number of products
+    pub(crate) limit: i64,
+    /// Also return products whose status is not active
+    pub(crate) include_inactive: bool,
+}
+
+/// Rows read between two progress reports of a streamed query
+const STREAM_BATCH_SIZE: usize = 100;
+
+/// Collect streamed rows, reporting progress every `STREAM_BATCH_SIZE` rows
+pub(crate) async fn collect_rows<T>(
+    mut rows: BoxStream<'_, Result<T, sqlx::Error>>,
+    progress: &Progress,
+) -> Result<Vec<T>, PluginError> {
+    let mut collected = Vec::new();
+    while let Some(row) = rows.try_next().await? {
+        collected.push(row);
+        if collected.len() % STREAM_BATCH_SIZE == 0 {
+            progress.report(collected.len() as u64, None, &format!("{} rows read", collected.len()));
+        }
+    }
+    Ok(collected)
+}
+
+/// Queries shared by all database engines
+pub(crate) trait DatabaseBackend: Send + Sync {
+    /// Backend name as used in the `backend` config field
+    fn name(&self) -> &'static str;
+
+    /// Pool for tools built on Postgres-only SQL
+    fn postgres(&self) -> Result<&PgPool, PluginError> {
+        Err(PluginError::Unsupported(format!(
+            "This tool requires the postgres backend, the plugin is configured for {}",
+            self.name()
+        )))
+    }
+
+    /// Pool for statements that write, never a read replica
+    fn primary(&self) -> Result<&PgPool, PluginError> {
+        self.postgres()
+    }
+
+    fn fetch_product(&self, id: i32) -> BoxFuture<'_, Result<Option<Product>, PluginError>>;
+
+    /// Products with the given ids, in no particular order
+    fn fetch_products<'a>(&'a self, ids: &'a [i32]) -> BoxFuture<'a, Result<Vec<Product>, PluginError>>;
+
+    fn search_products<'a>(
+        &'a self,
+        search: &'a ProductSearch<'a>,
+    ) -> BoxFuture<'a, Result<Vec<SearchHit>, PluginError>>;
+
+    /// One page of products in `list.sort_by` order
+    fn list_products<'a>(&'a self, list: &'a ProductList) -> BoxFuture<'a, Result<Vec<Product>, PluginError>>;
+
+    fn list_categories(&self) -> BoxFuture<'_, Result<Vec<CategoryCount>, PluginError>>;
+
+    /// Round trip to the database
+    fn ping(&self) -> BoxFuture<'_, Result<(), PluginError>>;
+
+    /// Connection pool utilization
+    fn pool_stats(&self) -> Value;
+
+    /// Close the pool once all connections are returned
+    fn close(&self) -> BoxFuture<'_, ()>;
+}
+
+/// Validate the backend settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if config.mock_fixture.is_some() && BackendKind::parse(&config.backend)? != BackendKind::Mock {
+        return Err("mock_fixture requires the mock backend".to_string());
+    }
+    for (index, url) in config.replica_database_urls.iter().enumerate() {
+        validate_endpoint(&replica_config(config, url))
+            .map_err(|err| format!("Replica {index}: {err}"))?;
+    }
+    validate_endpoint(config)
+}
+
+fn validate_endpoint(config: &PluginConfig) -> Result<(), String> {
+    match BackendKind::parse(&config.backend)? {
+        BackendKind::Postgres => postgres::connect_options(config).map(|_| ()),
+        #[cfg(feature = "mysql")]
+        BackendKind::Mysql => mysql::connect_options(config).map(|_| ()),
+        #[cfg(feature = "sqlite")]
+        BackendKind::Sqlite => sqlite::connect_options(config).map(|_| ()),
+        #[cfg(feature = "mock")]
+        BackendKind::Mock => mock::load_products(config).map(|_| ()),
+        #[allow(unreachable_patterns)]
+        _ => Err(format!(
+            "Backend {} is not available, rebuild the plugin with the '{}' feature",
+            config.backend, config.backend
+        )),
+    }
+}
+
+/// The configuration with a replica's URL in place of `database_url`
+fn replica_config(config: &PluginConfig, url: &str) -> PluginConfig {
+    PluginConfig {
+        database_url: url.to_string(),
+        replica_database_urls: Vec::new(),
+        ..config.clone()
+    }
+}
+
+/// Connect to the configured database and its read replicas
+///
+/// With `lazy` no connection is opened up front; queries connect on demand.
+/// A replica that cannot be reached does not fail the connection, it is
+/// connected lazily and left out until it recovers.
+pub(crate) async fn connect(config: &PluginConfig, lazy: bool) -> Result<Database, sqlx::Error> {
+    let primary = connect_endpoint(config, lazy).await?;
+    if config.replica_database_urls.is_empty() {
+        return Ok(primary);
+    }
+
+    let retry = Duration::from_secs(config.replica_retry_seconds);
+    let mut replicas = Vec::with_capacity(config.replica_database_urls.len());
+    for (index, url) in config.replica_database_urls.iter().enumerate() {
+        let replica_config = replica_config(config, url);
+        let replica = match connect_endpoint(&replica_config, lazy).await {
+            Ok(db) => replicas::Replica::new(index, db, true, retry),
+            Err(err @ sqlx::Error::Configuration(_)) => return Err(err),
+            Err(err) => {
+                tracing::warn!("Replica {index} unreachable, reading from the primary: {err}");
+                replicas::Replica::new(index, connect_endpoint(&replica_config, true).await?, false, retry)
+            }
+        };
+        replicas.push(replica);
+    }
+    Ok(Box::new(replicas::ReplicatedBackend::new(primary, replicas, retry)))
+}
+
+async fn connect_endpoint(config: &PluginConfig, lazy: bool) -> Result<Database, sqlx::Error> {
+    let kind = BackendKind::parse(&config.backend).map_err(|err| sqlx::Error::Configuration(err.into()))?;
+    match kind {
+        BackendKind::Postgres => postgres::connect(config, lazy).await,
+        #[cfg(feature = "mysql")]
+        BackendKind::Mysql => mysql::connect(config, lazy).await,
+        #[cfg(feature = "sqlite")]
+        BackendKind::Sqlite => sqlite::connect(config, lazy).await,
+        #[cfg(feature = "mock")]
+        BackendKind::Mock => mock::connect(config).await,
+        #[allow(unreachable_patterns)]
+        _ => Err(sqlx::Error::Configuration(
+            format!("Backend {} is not available in this build", config.backend).into(),
+        )),
+    }
+}
+
+/// Log statements slower than `slow_query_threshold_ms` as warnings
+fn log_slow_statements<O: ConnectOptions>(config: &PluginConfig, options: O) -> O {
+    match config.slow_query_threshold_ms {
+        0 => options.log_slow_statements(LevelFilter::Off, Duration::ZERO),
+        threshold_ms => options.log_slow_statements(LevelFilter::Warn, Duration::from_millis(threshold_ms)),
+    }
+}
+
+/// Pool settings shared by all backends
+///
+/// A zero idle timeout or lifetime turns the limit off.
+fn pool_options<DB: sqlx::Database>(config: &PluginConfig) -> PoolOptions<DB> {
+    let seconds = |seconds: u64| (seconds > 0).then(|| Duration::from_secs(seconds));
+    PoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(Duration::from_secs(config.timeout_seconds))
+        .idle_timeout(seconds(config.idle_timeout_seconds))
+        .max_lifetime(seconds(config.max_lifetime_seconds))
+        .test_before_acquire(config.test_before_acquire)
+}
+
+fn pool_stats<DB: sqlx::Database>(pool: &Pool<DB>) -> Value {
+    let size = pool.size();
+    let idle = pool.num_idle() as u32;
+    json!({
+        "size": size,
+        "idle": idle,
+        "acquired": size.saturating_sub(idle),
+        "max_connections": pool.options().get_max_connections()
+    })
+}
+
+/// Search modes other than `ilike` need Postgres
+#[cfg(any(feature = "mysql", feature = "sqlite", feature = "mock"))]
+fn require_ilike(search: &ProductSearch<'_>, backend: &str) -> Result<(), PluginError> {
+    match search.mode {
+        SearchMode::Ilike => Ok(()),
+        mode => Err(PluginError::Unsupported(format!(
+            "search_mode {} requires the postgres backend, the plugin is configured for {backend}",
+            mode.as_str()
+        ))),
+    }
+}
@@ -0,0 +1,200 @@
+//! MySQL backend, enabled with the `mysql` feature
+
+use super::{
+    collect_rows, log_slow_statements, pool_options, pool_stats, require_ilike, Database, DatabaseBackend, ListAfter,
+    ProductList, ProductSearch,
+};
+use crate::error::PluginError;
+use crate::search::{like_pattern, SearchHit};
+use crate::{secrets, CategoryCount, PluginConfig, Product, SortBy};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde_json::Value;
+use sqlx::mysql::{MySqlConnectOptions, MySqlPool, MySqlPoolOptions};
+use sqlx::{Executor, MySql, QueryBuilder};
+use std::str::FromStr;
+
+/// Connection options with environment variables and the password file applied
+pub(super) fn connect_options(config: &PluginConfig) -> Result<MySqlConnectOptions, String> {
+    let url = secrets::database_url(config)?;
+    // The sqlx error could echo the URL, and with it an interpolated password
+    let options = MySqlConnectOptions::from_str(&url)
+        .map_err(|_| "Invalid database_url: not a MySQL connection URL".to_string())?;
+
+    let options = match secrets::database_password(config)? {
+        Some(password) => options.password(&password),
+        None => options,
+    };
+    Ok(log_slow_statements(config, options))
+}
+
+pub(super) async fn connect(config: &PluginConfig, lazy: bool) -> Result<Database, sqlx::Error> {
+    let options = connect_options(config).map_err(|err| sqlx::Error::Configuration(err.into()))?;
+    let mut pool_options: MySqlPoolOptions = pool_options(config);
+
+    let read_only = config.read_only;
+    let statement_timeout_ms = config.statement_timeout_ms;
+    if read_only || statement_timeout_ms > 0 {
+        pool_options = pool_options.after_connect(move |conn, _meta| {
+            Box::pin(async move {
+                if read_only {
+                    conn.execute("SET SESSION TRANSACTION READ ONLY").await?;
+                }
+                if statement_timeout_ms > 0 {
+                    // Only SELECT statements honour it
+                    conn.execute(format!("SET SESSION max_execution_time = {statement_timeout_ms}").as_str())
+                        .await?;
+                }
+                Ok(())
+            })
+        });
+    }
+
+    let pool = if lazy {
+        pool_options.connect_lazy_with(options)
+    } else {
+        pool_options.connect_with(options).await?
+    };
+    Ok(Box::new(MySqlBackend { pool }))
+}
+
+struct MySqlBackend {
+    pool: MySqlPool,
+}
+
+impl DatabaseBackend for MySqlBackend {
+    fn name(&self) -> &'static str {
+        "mysql"
+    }
+
+    fn fetch_product(&self, id: i32) -> BoxFuture<'_, Result<Option<Product>, PluginError>> {
+        async move {
+            let product = sqlx::query_as::<_, Product>(
+                "SELECT id, name, price, description, category FROM products WHERE id = ?",
+            )
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+            Ok(product)
+        }
+        .boxed()
+    }
+
+    fn fetch_products<'a>(&'a self, ids: &'a [i32]) -> BoxFuture<'a, Result<Vec<Product>, PluginError>> {
+        async move {
+            if ids.is_empty() {
+                return Ok(Vec::new());
+            }
+            let mut sql = QueryBuilder::<MySql>::new(
+                "SELECT id, name, price, description, category FROM products WHERE id IN (",
+            );
+            let mut separated = sql.separated(", ");
+            for id in ids {
+                separated.push_bind(*id);
+            }
+            sql.push(")");
+
+            let products = sql.build_query_as::<Product>().fetch_all(&self.pool).await?;
+            Ok(products)
+        }
+        .boxed()
+    }
+
+    fn search_products<'a>(
+        &'a self,
+        search: &'a ProductSearch<'a>,
+    ) -> BoxFuture<'a, Result<Vec<SearchHit>, PluginError>> {
+        async move {
+            require_ilike(search, self.name())?;
+
+            // Backslash is MySQL's default LIKE escape character, and the
+            // default collations compare case-insensitively
+            let mut sql = QueryBuilder::<MySql>::new(
+                "SELECT id, name, price, description, category FROM products WHERE name LIKE ",
+            );
+            sql.push_bind(like_pattern(search.query, search.raw_pattern));
+            if let Some(category) = search.category {
+                sql.push(" AND category = ").push_bind(category);
+            }
+            if let Some(min_price) = search.min_price {
+                sql.push(" AND price >= ").push_bind(min_price);
+            }
+            if let Some(max_price) = search.max_price {
+                sql.push(" AND price <= ").push_bind(max_price);
+            }
+            sql.push(" ORDER BY ")
+                .push(search.sort.order_by(search.mode))
+                .push(" LIMIT ")
+                .push_bind(search.limit.unwrap_or(i64::MAX))
+                .push(" OFFSET ")
+                .push_bind(search.offset);
+
+            let rows = sql.build_query_as::<Product>().fetch(&self.pool);
+            let products = collect_rows(rows, search.progress).await?;
+            Ok(products
+                .into_iter()
+                .map(|product| SearchHit { product, rank: None })
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn list_products<'a>(&'a self, list: &'a ProductList) -> BoxFuture<'a, Result<Vec<Product>, PluginError>> {
+        async move {
+            // No status column without a schema mapping, so every product is active
+            let mut sql =
+                QueryBuilder::<MySql>::new("SELECT id, name, price, description, category FROM products WHERE true");
+            match &list.after {
+                None => {}
+                Some(ListAfter::Id(id)) => {
+                    sql.push(" AND id > ").push_bind(*id);
+                }
+                Some(ListAfter::Name(name, id)) => {
+                    sql.push(" AND (name, id) > (").push_bind(name.clone()).push(", ").push_bind(*id).push(")");
+                }
+                Some(ListAfter::Price(price, id)) => {
+                    sql.push(" AND (price, id) > (").push_bind(*price).push(", ").push_bind(*id).push(")");
+                }
+            }
+            sql.push(match list.sort_by {
+                SortBy::Id => " ORDER BY id",
+                SortBy::Name => " ORDER BY name, id",
+                SortBy::Price => " ORDER BY price, id",
+            });
+            sql.push(" LIMIT ").push_bind(list.limit);
+
+            let products = sql.build_query_as::<Product>().fetch_all(&self.pool).await?;
+            Ok(products)
+        }
+        .boxed()
+    }
+
+    fn list_categories(&self) -> BoxFuture<'_, Result<Vec<CategoryCount>, PluginError>> {
+        async move {
+            let categories = sqlx::query_as::<_, CategoryCount>(
+                "SELECT category, COUNT(*) AS product_count FROM products \
+                 WHERE category IS NOT NULL GROUP BY category ORDER BY category",
+            )
+            .fetch_all(&self.pool)
+            .await?;
+            Ok(categories)
+        }
+        .boxed()
+    }
+
+    fn ping(&self) -> BoxFuture<'_, Result<(), PluginError>> {
+        async move {
+            sqlx::query("SELECT 1").execute(&self.pool).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn pool_stats(&self) -> Value {
+        pool_stats(&self.pool)
+    }
+
+    fn close(&self) -> BoxFuture<'_, ()> {
+        self.pool.close().boxed()
+    }
+}
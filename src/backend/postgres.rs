@@ -0,0 +1,236 @@
+//! PostgreSQL backend
+
+use super::{
+    collect_rows, log_slow_statements, pool_options, pool_stats, Database, DatabaseBackend, ListAfter, ProductList,
+    ProductSearch,
+};
+use crate::error::PluginError;
+use crate::mapping::{self, PRODUCT_COLUMNS};
+use crate::search::{self, SearchHit, SearchMode};
+use crate::{secrets, CategoryCount, PluginConfig, Product, SortBy};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde_json::Value;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, PgSslMode};
+use sqlx::{Executor, PgPool, Postgres, QueryBuilder};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Path of a TLS file setting, which must name an existing file
+fn tls_file<'a>(name: &str, path: &'a Option<String>) -> Result<Option<&'a str>, String> {
+    match path.as_deref() {
+        Some(path) if !Path::new(path).is_file() => Err(format!("{name} {path} does not exist or is not a file")),
+        path => Ok(path),
+    }
+}
+
+/// Apply the `ssl_*` settings on top of the URL's parameters
+fn tls_options(config: &PluginConfig, mut options: PgConnectOptions) -> Result<PgConnectOptions, String> {
+    if let Some(mode) = &config.ssl_mode {
+        let mode = PgSslMode::from_str(mode).map_err(|_| {
+            format!("Invalid ssl_mode '{mode}', expected one of: disable, allow, prefer, require, verify-ca, verify-full")
+        })?;
+        options = options.ssl_mode(mode);
+    }
+    if let Some(path) = tls_file("ssl_root_cert", &config.ssl_root_cert)? {
+        options = options.ssl_root_cert(path);
+    }
+    match (
+        tls_file("ssl_client_cert", &config.ssl_client_cert)?,
+        tls_file("ssl_client_key", &config.ssl_client_key)?,
+    ) {
+        (Some(cert), Some(key)) => Ok(options.ssl_client_cert(cert).ssl_client_key(key)),
+        (None, None) => Ok(options),
+        _ => Err("ssl_client_cert and ssl_client_key must be set together".to_string()),
+    }
+}
+
+/// Connection options with environment variables, the password file and
+/// the TLS settings applied
+pub(crate) fn connect_options(config: &PluginConfig) -> Result<PgConnectOptions, String> {
+    let url = secrets::database_url(config)?;
+    // The sqlx error could echo the URL, and with it an interpolated password
+    let options = PgConnectOptions::from_str(&url)
+        .map_err(|_| "Invalid database_url: not a PostgreSQL connection URL".to_string())?;
+
+    let options = match secrets::database_password(config)? {
+        Some(password) => options.password(&password),
+        None => options,
+    };
+    tls_options(config, log_slow_statements(config, options))
+}
+
+/// Pool settings with the session setup every new connection runs
+///
+/// `search_path` must already be a quoted identifier.
+fn pg_pool_options(config: &PluginConfig, search_path: Option<String>) -> PgPoolOptions {
+    let read_only = config.read_only;
+    let statement_timeout_ms = config.statement_timeout_ms;
+    pool_options::<Postgres>(config).after_connect(move |conn, _meta| {
+        let search_path = search_path.clone();
+        Box::pin(async move {
+            if read_only {
+                // Applies to every transaction on the connection, including
+                // the implicit ones around single statements
+                conn.execute("SET default_transaction_read_only = on").await?;
+            }
+            if statement_timeout_ms > 0 {
+                conn.execute(format!("SET statement_timeout = {statement_timeout_ms}").as_str()).await?;
+            }
+            if let Some(search_path) = search_path {
+                conn.execute(format!("SET search_path TO {search_path}").as_str()).await?;
+            }
+            Ok(())
+        })
+    })
+}
+
+pub(super) async fn connect(config: &PluginConfig, lazy: bool) -> Result<Database, sqlx::Error> {
+    let options = connect_options(config).map_err(|err| sqlx::Error::Configuration(err.into()))?;
+    let pool_options = pg_pool_options(config, None);
+
+    let pool = if lazy {
+        pool_options.connect_lazy_with(options)
+    } else {
+        pool_options.connect_with(options).await?
+    };
+    Ok(Box::new(PgBackend { pool }))
+}
+
+/// Lazily connected pool whose connections use `search_path`
+///
+/// Used for tenant schemas; `search_path` must already be a quoted identifier.
+pub(crate) fn connect_with_search_path(config: &PluginConfig, search_path: String) -> Result<Database, PluginError> {
+    let options = connect_options(config).map_err(PluginError::internal)?;
+    let pool = pg_pool_options(config, Some(search_path)).connect_lazy_with(options);
+    Ok(Box::new(PgBackend { pool }))
+}
+
+struct PgBackend {
+    pool: PgPool,
+}
+
+impl DatabaseBackend for PgBackend {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn postgres(&self) -> Result<&PgPool, PluginError> {
+        Ok(&self.pool)
+    }
+
+    fn fetch_product(&self, id: i32) -> BoxFuture<'_, Result<Option<Product>, PluginError>> {
+        async move {
+            let product = sqlx::query_as::<_, Product>(&format!(
+                "SELECT {PRODUCT_COLUMNS} FROM {} WHERE id = $1",
+                mapping::products()
+            ))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+            Ok(product)
+        }
+        .boxed()
+    }
+
+    fn fetch_products<'a>(&'a self, ids: &'a [i32]) -> BoxFuture<'a, Result<Vec<Product>, PluginError>> {
+        async move {
+            // One round trip for the whole batch
+            let products = sqlx::query_as::<_, Product>(&format!(
+                "SELECT {PRODUCT_COLUMNS} FROM {} WHERE id = ANY($1)",
+                mapping::products()
+            ))
+            .bind(ids)
+            .fetch_all(&self.pool)
+            .await?;
+            Ok(products)
+        }
+        .boxed()
+    }
+
+    fn search_products<'a>(
+        &'a self,
+        search: &'a ProductSearch<'a>,
+    ) -> BoxFuture<'a, Result<Vec<SearchHit>, PluginError>> {
+        async move {
+            let mut sql = QueryBuilder::<Postgres>::new("");
+            search::push_search(&mut sql, search);
+
+            if search.mode != SearchMode::Trigram {
+                let rows = sql.build_query_as::<SearchHit>().fetch(&self.pool);
+                return collect_rows(rows, search.progress).await;
+            }
+
+            let mut tx = self.pool.begin().await?;
+            search::set_trigram_threshold(&mut tx).await?;
+            let hits = {
+                let rows = sql.build_query_as::<SearchHit>().fetch(&mut *tx);
+                collect_rows(rows, search.progress).await?
+            };
+            tx.commit().await?;
+            Ok(hits)
+        }
+        .boxed()
+    }
+
+    fn list_products<'a>(&'a self, list: &'a ProductList) -> BoxFuture<'a, Result<Vec<Product>, PluginError>> {
+        async move {
+            // Keyset pagination: continue strictly after the last row of the previous page
+            let mut sql =
+                QueryBuilder::<Postgres>::new(format!("SELECT {PRODUCT_COLUMNS} FROM {} WHERE true", mapping::products()));
+            match &list.after {
+                None => {}
+                Some(ListAfter::Id(id)) => {
+                    sql.push(" AND id > ").push_bind(*id);
+                }
+                Some(ListAfter::Name(name, id)) => {
+                    sql.push(" AND (name, id) > (").push_bind(name.clone()).push(", ").push_bind(*id).push(")");
+                }
+                Some(ListAfter::Price(price, id)) => {
+                    sql.push(" AND (price, id) > (").push_bind(*price).push(", ").push_bind(*id).push(")");
+                }
+            }
+            search::push_status_filter(&mut sql, list.include_inactive);
+            sql.push(match list.sort_by {
+                SortBy::Id => " ORDER BY id",
+                SortBy::Name => " ORDER BY name, id",
+                SortBy::Price => " ORDER BY price, id",
+            });
+            sql.push(" LIMIT ").push_bind(list.limit);
+
+            let products = sql.build_query_as::<Product>().fetch_all(&self.pool).await?;
+            Ok(products)
+        }
+        .boxed()
+    }
+
+    fn list_categories(&self) -> BoxFuture<'_, Result<Vec<CategoryCount>, PluginError>> {
+        async move {
+            let categories = sqlx::query_as::<_, CategoryCount>(&format!(
+                "SELECT category, count(*) AS product_count FROM {} \
+                 WHERE category IS NOT NULL GROUP BY category ORDER BY category",
+                mapping::products()
+            ))
+            .fetch_all(&self.pool)
+            .await?;
+            Ok(categories)
+        }
+        .boxed()
+    }
+
+    fn ping(&self) -> BoxFuture<'_, Result<(), PluginError>> {
+        async move {
+            sqlx::query_scalar::<_, i32>("SELECT 1").fetch_one(&self.pool).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn pool_stats(&self) -> Value {
+        pool_stats(&self.pool)
+    }
+
+    fn close(&self) -> BoxFuture<'_, ()> {
+        self.pool.close().boxed()
+    }
+}
@@ -0,0 +1,226 @@
+//! Read replica routing
+//!
+//! With `replica_database_urls` set, the primary is wrapped together with
+//! one pool per replica. Reads go to the replicas in turn; writes (through
+//! `DatabaseBackend::primary`) always go to the primary. A replica that
+//! fails with `db_unavailable` is left out for `replica_retry_seconds` and
+//! the read is repeated on the primary, so clients only see the failure if
+//! the primary is down as well. `ping`, and with it `health_check`, probes
+//! every replica and brings recovered ones back right away.
+//!
+//! Tools built on Postgres-only SQL get a replica's pool through
+//! `DatabaseBackend::postgres` and cannot repeat their statements on the
+//! primary. For them the replicas are probed every `PROBE_INTERVAL` in the
+//! background: a replica failing the probe is left out, and with every
+//! replica down they get the primary's pool.
+
+use super::{Database, DatabaseBackend, ProductList, ProductSearch};
+use crate::error::PluginError;
+use crate::search::SearchHit;
+use crate::{CategoryCount, Product};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+/// Interval between two background probes of the replicas
+const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// One replica and its health
+pub(super) struct Replica {
+    /// Position in `replica_database_urls`, used in logs instead of the URL
+    index: usize,
+    db: Database,
+    /// Set while the replica is left out after a failure
+    down_until: Mutex<Option<Instant>>,
+}
+
+impl Replica {
+    pub(super) fn new(index: usize, db: Database, healthy: bool, retry: Duration) -> Self {
+        Replica {
+            index,
+            db,
+            down_until: Mutex::new((!healthy).then(|| Instant::now() + retry)),
+        }
+    }
+
+    fn available(&self) -> bool {
+        self.down_until
+            .lock()
+            .unwrap()
+            .is_none_or(|until| Instant::now() >= until)
+    }
+
+    fn mark_down(&self, err: &PluginError, retry: Duration) {
+        let mut down_until = self.down_until.lock().unwrap();
+        if down_until.is_none() {
+            tracing::warn!("Replica {} unavailable, reading from the primary: {}", self.index, err.message());
+        }
+        *down_until = Some(Instant::now() + retry);
+    }
+
+    fn mark_up(&self) {
+        if self.down_until.lock().unwrap().take().is_some() {
+            tracing::info!("Replica {} is available again", self.index);
+        }
+    }
+}
+
+/// Primary plus read replicas
+pub(super) struct ReplicatedBackend {
+    primary: Database,
+    replicas: Arc<Vec<Replica>>,
+    /// Round-robin position
+    next: AtomicUsize,
+    /// Time a failed replica is left out
+    retry: Duration,
+    /// Background probe of the replicas, stopped with the backend
+    prober: JoinHandle<()>,
+}
+
+impl ReplicatedBackend {
+    pub(super) fn new(primary: Database, replicas: Vec<Replica>, retry: Duration) -> Self {
+        let replicas = Arc::new(replicas);
+        ReplicatedBackend {
+            primary,
+            prober: tokio::spawn(probe(replicas.clone(), retry)),
+            replicas,
+            next: AtomicUsize::new(0),
+            retry,
+        }
+    }
+
+    /// Next available replica in round-robin order
+    fn replica(&self) -> Option<&Replica> {
+        let count = self.replicas.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..count)
+            .map(|offset| &self.replicas[(start + offset) % count])
+            .find(|replica| replica.available())
+    }
+
+    /// Run a read on a replica, falling back to the primary if it is down
+    async fn read<'a, T>(
+        &'a self,
+        query: impl Fn(&'a dyn DatabaseBackend) -> BoxFuture<'a, Result<T, PluginError>>,
+    ) -> Result<T, PluginError> {
+        if let Some(replica) = self.replica() {
+            match query(&*replica.db).await {
+                Err(err @ (PluginError::DbUnavailable(_) | PluginError::PoolExhausted(..))) => {
+                    replica.mark_down(&err, self.retry)
+                }
+                result => {
+                    replica.mark_up();
+                    return result;
+                }
+            }
+        }
+        query(&*self.primary).await
+    }
+}
+
+impl Drop for ReplicatedBackend {
+    fn drop(&mut self) {
+        self.prober.abort();
+    }
+}
+
+/// Ping every replica every `PROBE_INTERVAL`, updating its health
+async fn probe(replicas: Arc<Vec<Replica>>, retry: Duration) {
+    loop {
+        tokio::time::sleep(PROBE_INTERVAL).await;
+        for replica in replicas.iter() {
+            match tokio::time::timeout(PROBE_INTERVAL, replica.db.ping()).await {
+                Ok(Ok(())) => replica.mark_up(),
+                Ok(Err(err)) => replica.mark_down(&err, retry),
+                Err(_) => replica.mark_down(&PluginError::DbUnavailable("Probe timed out".to_string()), retry),
+            }
+        }
+    }
+}
+
+impl DatabaseBackend for ReplicatedBackend {
+    fn name(&self) -> &'static str {
+        self.primary.name()
+    }
+
+    /// Pool of the next replica that passed its last probe, or the
+    /// primary's if none did
+    fn postgres(&self) -> Result<&PgPool, PluginError> {
+        match self.replica() {
+            Some(replica) => replica.db.postgres(),
+            None => self.primary.postgres(),
+        }
+    }
+
+    fn primary(&self) -> Result<&PgPool, PluginError> {
+        self.primary.primary()
+    }
+
+    fn fetch_product(&self, id: i32) -> BoxFuture<'_, Result<Option<Product>, PluginError>> {
+        self.read(move |db| db.fetch_product(id)).boxed()
+    }
+
+    fn fetch_products<'a>(&'a self, ids: &'a [i32]) -> BoxFuture<'a, Result<Vec<Product>, PluginError>> {
+        self.read(move |db| db.fetch_products(ids)).boxed()
+    }
+
+    fn search_products<'a>(
+        &'a self,
+        search: &'a ProductSearch<'a>,
+    ) -> BoxFuture<'a, Result<Vec<SearchHit>, PluginError>> {
+        self.read(move |db| db.search_products(search)).boxed()
+    }
+
+    fn list_products<'a>(&'a self, list: &'a ProductList) -> BoxFuture<'a, Result<Vec<Product>, PluginError>> {
+        self.read(move |db| db.list_products(list)).boxed()
+    }
+
+    fn list_categories(&self) -> BoxFuture<'_, Result<Vec<CategoryCount>, PluginError>> {
+        self.read(|db| db.list_categories()).boxed()
+    }
+
+    /// Ping the primary, and every replica to update its health
+    fn ping(&self) -> BoxFuture<'_, Result<(), PluginError>> {
+        async move {
+            for replica in self.replicas.iter() {
+                match replica.db.ping().await {
+                    Ok(()) => replica.mark_up(),
+                    Err(err) => replica.mark_down(&err, self.retry),
+                }
+            }
+            self.primary.ping().await
+        }
+        .boxed()
+    }
+
+    fn pool_stats(&self) -> Value {
+        let mut stats = self.primary.pool_stats();
+        stats["replicas"] = self
+            .replicas
+            .iter()
+            .map(|replica| {
+                json!({
+                    "replica": replica.index,
+                    "available": replica.available(),
+                    "pool": replica.db.pool_stats()
+                })
+            })
+            .collect();
+        stats
+    }
+
+    fn close(&self) -> BoxFuture<'_, ()> {
+        async move {
+            for replica in self.replicas.iter() {
+                replica.db.close().await;
+            }
+            self.primary.close().await;
+        }
+        .boxed()
+    }
+}
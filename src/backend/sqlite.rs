@@ -0,0 +1,230 @@
+//! SQLite backend, enabled with the `sqlite` feature
+//!
+//! Meant for demos and local development: `database_url` can point at a
+//! file (`sqlite://products.db`) or an in-memory database
+//! (`sqlite::memory:`). SQLite has no exact decimal type, so prices are
+//! read as text and parsed into `Decimal`.
+
+use super::{
+    collect_rows, log_slow_statements, pool_options, pool_stats, require_ilike, Database, DatabaseBackend, ListAfter,
+    ProductList, ProductSearch,
+};
+use crate::error::PluginError;
+use crate::search::{like_pattern, SearchHit};
+use crate::{secrets, CategoryCount, PluginConfig, Product, SortBy};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use rust_decimal::Decimal;
+use serde_json::Value;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::{QueryBuilder, Sqlite};
+use std::str::FromStr;
+
+/// Columns of `Product`, with the price cast to text
+const PRODUCT_COLUMNS: &str = "id, name, CAST(price AS TEXT) AS price, description, category";
+
+/// Connection options with environment variables applied
+pub(super) fn connect_options(config: &PluginConfig) -> Result<SqliteConnectOptions, String> {
+    let url = secrets::database_url(config)?;
+    let options = SqliteConnectOptions::from_str(&url)
+        .map_err(|err| format!("Invalid database_url: not a SQLite connection URL ({err})"))?;
+    Ok(log_slow_statements(config, options.read_only(config.read_only)))
+}
+
+pub(super) async fn connect(config: &PluginConfig, lazy: bool) -> Result<Database, sqlx::Error> {
+    let options = connect_options(config).map_err(|err| sqlx::Error::Configuration(err.into()))?;
+    let pool_options: SqlitePoolOptions = pool_options(config);
+
+    let pool = if lazy {
+        pool_options.connect_lazy_with(options)
+    } else {
+        pool_options.connect_with(options).await?
+    };
+    Ok(Box::new(SqliteBackend { pool }))
+}
+
+#[derive(sqlx::FromRow)]
+struct SqliteProduct {
+    id: i32,
+    name: String,
+    price: String,
+    description: Option<String>,
+    category: Option<String>,
+}
+
+impl TryFrom<SqliteProduct> for Product {
+    type Error = PluginError;
+
+    fn try_from(row: SqliteProduct) -> Result<Self, PluginError> {
+        let price = Decimal::from_str(&row.price)
+            .or_else(|_| Decimal::from_scientific(&row.price))
+            .map_err(|_| {
+                PluginError::Database(format!("Product {} has an invalid price '{}'", row.id, row.price))
+            })?;
+        Ok(Product {
+            id: row.id,
+            name: row.name,
+            price,
+            description: row.description,
+            category: row.category,
+            extra: None,
+            unit_quantity: None,
+            unit_of_measure: None,
+            status: None,
+            brand: None,
+            image_url: None,
+            thumbnail_url: None,
+            attributes: None,
+        })
+    }
+}
+
+struct SqliteBackend {
+    pool: SqlitePool,
+}
+
+impl DatabaseBackend for SqliteBackend {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn fetch_product(&self, id: i32) -> BoxFuture<'_, Result<Option<Product>, PluginError>> {
+        async move {
+            let row = sqlx::query_as::<_, SqliteProduct>(&format!(
+                "SELECT {PRODUCT_COLUMNS} FROM products WHERE id = ?"
+            ))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+            row.map(Product::try_from).transpose()
+        }
+        .boxed()
+    }
+
+    fn fetch_products<'a>(&'a self, ids: &'a [i32]) -> BoxFuture<'a, Result<Vec<Product>, PluginError>> {
+        async move {
+            if ids.is_empty() {
+                return Ok(Vec::new());
+            }
+            let mut sql = QueryBuilder::<Sqlite>::new(format!(
+                "SELECT {PRODUCT_COLUMNS} FROM products WHERE id IN ("
+            ));
+            let mut separated = sql.separated(", ");
+            for id in ids {
+                separated.push_bind(*id);
+            }
+            sql.push(")");
+
+            let rows = sql.build_query_as::<SqliteProduct>().fetch_all(&self.pool).await?;
+            rows.into_iter().map(Product::try_from).collect()
+        }
+        .boxed()
+    }
+
+    fn search_products<'a>(
+        &'a self,
+        search: &'a ProductSearch<'a>,
+    ) -> BoxFuture<'a, Result<Vec<SearchHit>, PluginError>> {
+        async move {
+            require_ilike(search, self.name())?;
+
+            // LIKE is case-insensitive for ASCII in SQLite
+            let mut sql = QueryBuilder::<Sqlite>::new(format!(
+                "SELECT {PRODUCT_COLUMNS} FROM products WHERE name LIKE "
+            ));
+            sql.push_bind(like_pattern(search.query, search.raw_pattern))
+                .push(" ESCAPE '\\'");
+            if let Some(category) = search.category {
+                sql.push(" AND category = ").push_bind(category);
+            }
+            // Compared as numbers; exact for the two decimal places prices have
+            if let Some(min_price) = search.min_price {
+                sql.push(" AND price >= CAST(").push_bind(min_price.to_string()).push(" AS REAL)");
+            }
+            if let Some(max_price) = search.max_price {
+                sql.push(" AND price <= CAST(").push_bind(max_price.to_string()).push(" AS REAL)");
+            }
+            sql.push(" ORDER BY ")
+                .push(search.sort.order_by(search.mode))
+                .push(" LIMIT ")
+                .push_bind(search.limit.unwrap_or(i64::MAX))
+                .push(" OFFSET ")
+                .push_bind(search.offset);
+
+            let rows = sql.build_query_as::<SqliteProduct>().fetch(&self.pool);
+            collect_rows(rows, search.progress)
+                .await?
+                .into_iter()
+                .map(|row| Ok(SearchHit { product: Product::try_from(row)?, rank: None }))
+                .collect()
+        }
+        .boxed()
+    }
+
+    fn list_products<'a>(&'a self, list: &'a ProductList) -> BoxFuture<'a, Result<Vec<Product>, PluginError>> {
+        async move {
+            // No status column without a schema mapping, so every product is
+            // active. Columns are qualified, as `price` alone is the text alias
+            let mut sql = QueryBuilder::<Sqlite>::new(format!("SELECT {PRODUCT_COLUMNS} FROM products WHERE true"));
+            match &list.after {
+                None => {}
+                Some(ListAfter::Id(id)) => {
+                    sql.push(" AND products.id > ").push_bind(*id);
+                }
+                Some(ListAfter::Name(name, id)) => {
+                    sql.push(" AND (products.name, products.id) > (")
+                        .push_bind(name.clone())
+                        .push(", ")
+                        .push_bind(*id)
+                        .push(")");
+                }
+                Some(ListAfter::Price(price, id)) => {
+                    sql.push(" AND (products.price, products.id) > (CAST(")
+                        .push_bind(price.to_string())
+                        .push(" AS REAL), ")
+                        .push_bind(*id)
+                        .push(")");
+                }
+            }
+            sql.push(match list.sort_by {
+                SortBy::Id => " ORDER BY products.id",
+                SortBy::Name => " ORDER BY products.name, products.id",
+                SortBy::Price => " ORDER BY products.price, products.id",
+            });
+            sql.push(" LIMIT ").push_bind(list.limit);
+
+            let rows = sql.build_query_as::<SqliteProduct>().fetch_all(&self.pool).await?;
+            rows.into_iter().map(Product::try_from).collect()
+        }
+        .boxed()
+    }
+
+    fn list_categories(&self) -> BoxFuture<'_, Result<Vec<CategoryCount>, PluginError>> {
+        async move {
+            let categories = sqlx::query_as::<_, CategoryCount>(
+                "SELECT category, COUNT(*) AS product_count FROM products \
+                 WHERE category IS NOT NULL GROUP BY category ORDER BY category",
+            )
+            .fetch_all(&self.pool)
+            .await?;
+            Ok(categories)
+        }
+        .boxed()
+    }
+
+    fn ping(&self) -> BoxFuture<'_, Result<(), PluginError>> {
+        async move {
+            sqlx::query("SELECT 1").execute(&self.pool).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn pool_stats(&self) -> Value {
+        pool_stats(&self.pool)
+    }
+
+    fn close(&self) -> BoxFuture<'_, ()> {
+        self.pool.close().boxed()
+    }
+}
@@ -0,0 +1,113 @@
+//! Batched tool calls
+//!
+//! `execute_batch` takes a list of `{"tool": ..., "arguments": {...}}`
+//! entries, at most `max_batch_size`, and runs them concurrently. Every
+//! entry is dispatched like a call from the host, so it passes the same
+//! authorization, argument limits, rate limits, circuit breaker and
+//! concurrency limits, and is redacted and audited on its own. The batch
+//! itself takes no concurrency permit, which its entries might otherwise
+//! wait behind. Entries wait for room in a full request queue, up to the
+//! request timeout, rather than failing with `server_busy` as a call from
+//! the host would.
+//!
+//! The result lists one outcome per entry, in the order given:
+//!
+//! ```json
+//! {"results": [
+//!     {"tool": "get_product_price", "ok": true, "result": {"id": 1, "price": "49.99"}},
+//!     {"tool": "get_product_price", "ok": false, "error": {"code": "not_found", "message": "Product 7 not found"}}
+//! ], "count": 2, "succeeded": 1, "failed": 1}
+//! ```
+//!
+//! A failing entry does not fail the others. Entries run with the batch's
+//! `_auth` context, replacing any of their own, and with its `tenant`
+//! unless they name one. Batches do not nest.
+
+use crate::args;
+use crate::auth::AUTH_ARGUMENT;
+use crate::dispatch_nested;
+use crate::error::PluginError;
+use mcp_plugin_api::utils;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+/// Name of the tool
+pub(crate) const TOOL: &str = "execute_batch";
+
+/// Arguments of execute_batch
+#[derive(Debug, Deserialize, JsonSchema)]
+struct BatchArgs {
+    #[schemars(length(min = 1))]
+    calls: Vec<BatchCall>,
+}
+
+/// One entry of a batch
+#[derive(Debug, Deserialize, JsonSchema)]
+struct BatchCall {
+    tool: String,
+    #[serde(default)]
+    arguments: Map<String, Value>,
+}
+
+/// Arguments of an entry, with the context of the batch call
+fn entry_arguments(mut arguments: Map<String, Value>, args: &Value) -> Value {
+    match args.get(AUTH_ARGUMENT) {
+        Some(auth) => arguments.insert(AUTH_ARGUMENT.to_string(), auth.clone()),
+        None => arguments.remove(AUTH_ARGUMENT),
+    };
+    if let Some(tenant) = args.get("tenant").filter(|tenant| !tenant.is_null()) {
+        arguments.entry("tenant").or_insert_with(|| tenant.clone());
+    }
+    Value::Object(arguments)
+}
+
+/// Outcome of an entry: the JSON content of its result, or its error
+fn outcome(tool: String, result: Result<Value, PluginError>) -> Value {
+    match result {
+        Ok(mut result) => {
+            let content = result["content"]
+                .as_array()
+                .and_then(|content| content.iter().find(|block| block["type"] == "json"))
+                .map(|block| block["json"].clone());
+            let mut outcome = json!({"tool": tool, "ok": true});
+            if let Some(meta) = result.as_object_mut().and_then(|result| result.remove("_meta")) {
+                outcome["_meta"] = meta;
+            }
+            // Results without JSON content, e.g. text, are passed on whole
+            outcome["result"] = content.unwrap_or(result);
+            outcome
+        }
+        Err(err) => json!({
+            "tool": tool,
+            "ok": false,
+            "error": {
+                "code": err.code(),
+                "message": err.message(),
+                "retryable": err.retryable()
+            }
+        }),
+    }
+}
+
+pub(crate) async fn handle_execute_batch(args: &Value) -> Result<Value, PluginError> {
+    let BatchArgs { calls } = args::parse(args)?;
+    if calls.iter().any(|call| call.tool == TOOL) {
+        return Err(PluginError::invalid_argument(format!("{TOOL} calls cannot be nested")));
+    }
+
+    let results = futures::future::join_all(calls.into_iter().map(|call| async move {
+        let arguments = entry_arguments(call.arguments, args);
+        let result = dispatch_nested(&call.tool, arguments).await;
+        outcome(call.tool, result)
+    }))
+    .await;
+
+    let succeeded = results.iter().filter(|result| result["ok"] == true).count();
+    Ok(utils::json_content(json!({
+        "count": results.len(),
+        "succeeded": succeeded,
+        "failed": results.len() - succeeded,
+        "results": results
+    })))
+}
@@ -0,0 +1,98 @@
+//! Brands
+//!
+//! With `schema_mapping.brand_column` every product carries its `brand`,
+//! `search_products` and `count_products` take a `brand` filter, and
+//! `list_brands` lists the brands with their number of products and price
+//! range, optionally within one category:
+//!
+//! ```json
+//! {"brands": [{"brand": "Acme", "product_count": 12, "min_price": "4.99",
+//!   "max_price": "129.00", "avg_price": "38.50"}], "count": 1, "base_currency": "USD"}
+//! ```
+//!
+//! Like searches, the list leaves inactive products out unless asked for.
+//! Products without a brand are not listed.
+
+use crate::args;
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::mapping;
+use crate::{format_price, get_config};
+use mcp_plugin_api::utils;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::{Postgres, QueryBuilder};
+
+/// Fail unless the product table has a mapped brand column
+pub(crate) fn require_brand_column() -> Result<(), PluginError> {
+    match get_config().schema_mapping.brand_column {
+        Some(_) => Ok(()),
+        None => Err(PluginError::Unsupported(
+            "Brands require schema_mapping.brand_column".to_string(),
+        )),
+    }
+}
+
+/// Arguments of list_brands
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ListBrandsArgs {
+    category: Option<String>,
+    #[serde(default)]
+    include_inactive: bool,
+}
+
+#[derive(sqlx::FromRow)]
+struct BrandSummary {
+    brand: String,
+    product_count: i64,
+    min_price: Decimal,
+    max_price: Decimal,
+    avg_price: Decimal,
+}
+
+pub(crate) async fn handle_list_brands(db: &dyn DatabaseBackend, args: &Value) -> Result<Value, PluginError> {
+    let pool = db.postgres()?;
+    require_brand_column()?;
+    let ListBrandsArgs { category, include_inactive } = args::parse(args)?;
+    let config = get_config();
+
+    let mut sql = QueryBuilder::<Postgres>::new(format!(
+        "SELECT brand, count(*) AS product_count, min(price) AS min_price, max(price) AS max_price, \
+         avg(price) AS avg_price FROM {} WHERE brand IS NOT NULL",
+        mapping::products()
+    ));
+    if let Some(category) = &category {
+        sql.push(" AND category = ").push_bind(category);
+    }
+    if !include_inactive {
+        sql.push(" AND (status IS NULL OR status = ANY(")
+            .push_bind(&config.schema_mapping.active_statuses)
+            .push("))");
+    }
+    sql.push(" GROUP BY brand ORDER BY brand");
+    let brands = sql.build_query_as::<BrandSummary>().fetch_all(pool).await?;
+
+    let brands: Vec<Value> = brands
+        .into_iter()
+        .map(|brand| {
+            json!({
+                "brand": brand.brand,
+                "product_count": brand.product_count,
+                "min_price": format_price(&brand.min_price),
+                "max_price": format_price(&brand.max_price),
+                "avg_price": format_price(&brand.avg_price)
+            })
+        })
+        .collect();
+    let mut result = json!({
+        "brands": brands,
+        "count": brands.len(),
+        "base_currency": config.base_currency
+    });
+    if let Some(category) = category {
+        result["category"] = category.into();
+    }
+    Ok(utils::json_content(result))
+}
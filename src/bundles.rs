@@ -0,0 +1,164 @@
+//! Bundle pricing
+//!
+//! Bundles are read from the `bundles` table and their contents from
+//! `bundle_components` (names configurable through `bundles_table` and
+//! `bundle_components_table`):
+//!
+//! ```sql
+//! CREATE TABLE bundles (
+//!     id    SERIAL PRIMARY KEY,
+//!     name  TEXT NOT NULL,
+//!     price NUMERIC(10,2)
+//! );
+//!
+//! CREATE TABLE bundle_components (
+//!     bundle_id  INTEGER NOT NULL REFERENCES bundles(id),
+//!     product_id INTEGER NOT NULL REFERENCES products(id),
+//!     quantity   INTEGER NOT NULL DEFAULT 1 CHECK (quantity > 0),
+//!     PRIMARY KEY (bundle_id, product_id)
+//! );
+//! ```
+//!
+//! A bundle with a `price` sells at that price. Without one it costs the
+//! sum of its components at their list prices, less
+//! `bundle_discount_percent`. Either way the components are listed, with
+//! the saving against buying them separately.
+
+use crate::args;
+use crate::backend::DatabaseBackend;
+use crate::currency;
+use crate::error::PluginError;
+use crate::locale;
+use crate::mapping;
+use crate::sql::quote_identifier;
+use crate::{format_price, get_config, round_price, PluginConfig};
+use mcp_plugin_api::utils;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Validate the bundle settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if config.bundle_discount_percent < Decimal::ZERO || config.bundle_discount_percent > Decimal::ONE_HUNDRED {
+        return Err("bundle_discount_percent must be between 0 and 100".to_string());
+    }
+    quote_identifier(&config.bundles_table)?;
+    quote_identifier(&config.bundle_components_table).map(|_| ())
+}
+
+/// Arguments of get_bundle_price
+#[derive(Debug, Deserialize, JsonSchema)]
+struct BundleArgs {
+    bundle_id: i32,
+    currency: Option<String>,
+    locale: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct Bundle {
+    id: i32,
+    name: String,
+    price: Option<Decimal>,
+}
+
+#[derive(sqlx::FromRow)]
+struct Component {
+    product_id: i32,
+    name: String,
+    quantity: i32,
+    price: Decimal,
+}
+
+impl Component {
+    fn total(&self) -> Decimal {
+        self.price * Decimal::from(self.quantity)
+    }
+}
+
+pub(crate) async fn handle_get_bundle_price(db: &dyn DatabaseBackend, args: &Value) -> Result<Value, PluginError> {
+    let pool = db.postgres()?;
+    let BundleArgs {
+        bundle_id,
+        currency,
+        locale,
+    } = args::parse(args)?;
+    let currency = currency::parse_currency(currency.as_deref())?;
+    let locale = locale::parse_locale(locale.as_deref())?;
+
+    let config = get_config();
+    let bundles = quote_identifier(&config.bundles_table).map_err(PluginError::internal)?;
+    let components_table = quote_identifier(&config.bundle_components_table).map_err(PluginError::internal)?;
+
+    let bundle = sqlx::query_as::<_, Bundle>(&format!("SELECT id, name, price FROM {bundles} WHERE id = $1"))
+        .bind(bundle_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| PluginError::not_found(format!("Bundle {bundle_id} not found")))?;
+
+    let components = sqlx::query_as::<_, Component>(&format!(
+        "SELECT components.product_id, products.name, components.quantity, products.price \
+         FROM {components_table} AS components JOIN {} ON products.id = components.product_id \
+         WHERE components.bundle_id = $1 ORDER BY components.product_id",
+        mapping::products()
+    ))
+    .bind(bundle_id)
+    .fetch_all(pool)
+    .await?;
+    if components.is_empty() {
+        return Err(PluginError::not_found(format!("Bundle {bundle_id} has no components")));
+    }
+
+    let components_total: Decimal = components.iter().map(Component::total).sum();
+    let (price, price_source, discount_percent) = match bundle.price {
+        Some(price) => (price, "bundle_price", None),
+        None => {
+            let discount = config.bundle_discount_percent;
+            let price = round_price(&(components_total * (Decimal::ONE - discount / Decimal::ONE_HUNDRED)));
+            (price, "components", Some(discount))
+        }
+    };
+    let savings = components_total - price;
+
+    let exchange_rate = match &currency {
+        Some(currency) => Some(currency::exchange_rate(db, currency).await?),
+        None => None,
+    };
+    let component_values: Vec<Value> = components
+        .iter()
+        .map(|component| {
+            json!({
+                "product_id": component.product_id,
+                "name": component.name,
+                "quantity": component.quantity,
+                "unit_price": format_price(&component.price),
+                "total_price": format_price(&component.total())
+            })
+        })
+        .collect();
+
+    let mut result = json!({
+        "bundle_id": bundle.id,
+        "name": bundle.name,
+        "price": format_price(&price),
+        "price_source": price_source,
+        "discount_percent": discount_percent.map(|percent| percent.to_string()),
+        "components_total": format_price(&components_total),
+        "savings": format_price(&savings),
+        "components": component_values,
+        "base_currency": config.base_currency
+    });
+    if let Some(locale) = &locale {
+        result["display_price"] = json!(locale.display_price(&price, &config.base_currency));
+    }
+    if let Some(exchange_rate) = &exchange_rate {
+        let mut converted = exchange_rate.converted(&price);
+        converted.display_price = locale
+            .as_ref()
+            .map(|locale| locale.display_price(&(price * exchange_rate.rate), &exchange_rate.currency));
+        result["converted_price"] = json!(converted);
+    }
+
+    // Return structured JSON data for programmatic clients
+    Ok(utils::json_content(result))
+}
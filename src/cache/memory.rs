@@ -0,0 +1,196 @@
+//! In-process query result cache
+//!
+//! The cache is bounded: once `max_entries` is reached the least recently
+//! used entry is evicted. It lives in the runtime thread and is shared by
+//! all request tasks, so every operation only holds the lock briefly and
+//! never across an `.await`.
+
+use super::{CacheBackend, CacheKey};
+use futures::future::{self, BoxFuture};
+use futures::FutureExt;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct Entry {
+    value: Value,
+    inserted_at: Instant,
+    /// Position in the recency order, see `CacheState::order`
+    tick: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, Entry>,
+    /// Recency order: lowest tick is the least recently used entry
+    order: BTreeMap<u64, CacheKey>,
+    next_tick: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    expirations: u64,
+}
+
+impl CacheState {
+    fn touch(&mut self, key: &CacheKey) {
+        let tick = self.next_tick;
+        self.next_tick += 1;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.order.remove(&entry.tick);
+            entry.tick = tick;
+            self.order.insert(tick, key.clone());
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) -> Option<Entry> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.tick);
+        Some(entry)
+    }
+}
+
+/// TTL cache with LRU eviction
+pub(crate) struct MemoryCache {
+    ttl: Duration,
+    max_entries: usize,
+    state: Mutex<CacheState>,
+}
+
+impl MemoryCache {
+    /// Create a cache; a zero `ttl` or `max_entries` disables caching
+    pub(crate) fn new(ttl: Duration, max_entries: usize) -> Self {
+        MemoryCache {
+            ttl,
+            max_entries,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        !self.ttl.is_zero() && self.max_entries > 0
+    }
+
+    fn lookup(&self, key: &CacheKey) -> Option<Value> {
+        if !self.enabled() {
+            return None;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let expired = match state.entries.get(key) {
+            Some(entry) => entry.inserted_at.elapsed() >= self.ttl,
+            None => {
+                state.misses += 1;
+                return None;
+            }
+        };
+
+        if expired {
+            state.remove(key);
+            state.expirations += 1;
+            state.misses += 1;
+            return None;
+        }
+
+        state.hits += 1;
+        state.touch(key);
+        state.entries.get(key).map(|entry| entry.value.clone())
+    }
+
+    /// Store a result, evicting the least recently used entry if full
+    fn store(&self, key: CacheKey, value: Value) {
+        if !self.enabled() {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.remove(&key);
+
+        while state.entries.len() >= self.max_entries {
+            let Some((_, oldest)) = state.order.pop_first() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+            state.evictions += 1;
+        }
+
+        let tick = state.next_tick;
+        state.next_tick += 1;
+        state.order.insert(tick, key.clone());
+        state.entries.insert(
+            key,
+            Entry {
+                value,
+                inserted_at: Instant::now(),
+                tick,
+            },
+        );
+    }
+
+    fn evict_product(&self, product_id: i32) {
+        let mut state = self.state.lock().unwrap();
+        let stale: Vec<CacheKey> = state
+            .entries
+            .keys()
+            .filter(|key| match key {
+                CacheKey::ProductPrice(id, _) => *id == product_id,
+                CacheKey::Search(_) => true,
+            })
+            .cloned()
+            .collect();
+        for key in &stale {
+            state.remove(key);
+        }
+    }
+
+    fn evict_all(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.order.clear();
+    }
+
+}
+
+impl CacheBackend for MemoryCache {
+    fn get<'a>(&'a self, key: &'a CacheKey) -> BoxFuture<'a, Option<Value>> {
+        future::ready(self.lookup(key)).boxed()
+    }
+
+    fn insert(&self, key: CacheKey, value: Value) -> BoxFuture<'_, ()> {
+        self.store(key, value);
+        future::ready(()).boxed()
+    }
+
+    fn invalidate_product(&self, product_id: i32) -> BoxFuture<'_, ()> {
+        self.evict_product(product_id);
+        future::ready(()).boxed()
+    }
+
+    fn clear(&self) -> BoxFuture<'_, ()> {
+        self.evict_all();
+        future::ready(()).boxed()
+    }
+
+    fn stats(&self) -> Value {
+        let state = self.state.lock().unwrap();
+        let lookups = state.hits + state.misses;
+        let hit_rate = if lookups == 0 {
+            0.0
+        } else {
+            state.hits as f64 / lookups as f64
+        };
+
+        json!({
+            "backend": "memory",
+            "enabled": self.enabled(),
+            "entries": state.entries.len(),
+            "max_entries": self.max_entries,
+            "ttl_seconds": self.ttl.as_secs(),
+            "hits": state.hits,
+            "misses": state.misses,
+            "hit_rate": hit_rate,
+            "evictions": state.evictions,
+            "expirations": state.expirations
+        })
+    }
+}
@@ -0,0 +1,100 @@
+//! Query result cache
+//!
+//! Tool results are cached per lookup key for `cache_ttl_seconds`. The
+//! `cache_backend` config field selects where: `memory` keeps them in the
+//! runtime thread of this process, `redis` in a Redis server shared by
+//! every plugin instance pointing at the same `redis_url` (enabled with the
+//! `redis` cargo feature). Both are used through the `CacheBackend` trait.
+//!
+//! The cache is an optimization only: a backend that cannot be reached
+//! reports misses and drops inserts instead of failing tool calls.
+
+mod memory;
+#[cfg(feature = "redis")]
+mod redis;
+
+use crate::PluginConfig;
+use futures::future::BoxFuture;
+use serde_json::Value;
+use std::time::Duration;
+
+pub(crate) use memory::MemoryCache;
+
+/// Connected cache, swapped as a whole on reconfiguration
+pub(crate) type Cache = Box<dyn CacheBackend>;
+
+/// Lookup key of a cached tool result
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum CacheKey {
+    /// get_product_price result for a product id and the key of the call's
+    /// arguments, see `coalesce::arguments_key`
+    ProductPrice(i32, String),
+    /// search_products result for the key of the call's arguments
+    Search(String),
+}
+
+/// Where cached results are kept, selected by the `cache_backend` config field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CacheKind {
+    Memory,
+    Redis,
+}
+
+impl CacheKind {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "memory" => Ok(CacheKind::Memory),
+            "redis" => Ok(CacheKind::Redis),
+            other => Err(format!("Invalid cache_backend '{other}', expected one of: memory, redis")),
+        }
+    }
+}
+
+/// Storage of cached tool results
+pub(crate) trait CacheBackend: Send + Sync {
+    /// Look up a fresh entry, counting the hit or miss
+    fn get<'a>(&'a self, key: &'a CacheKey) -> BoxFuture<'a, Option<Value>>;
+
+    /// Store a result for the configured time to live
+    fn insert(&self, key: CacheKey, value: Value) -> BoxFuture<'_, ()>;
+
+    /// Drop every entry that may contain the product's price
+    ///
+    /// Searches are not keyed by product, so all of them are dropped.
+    fn invalidate_product(&self, product_id: i32) -> BoxFuture<'_, ()>;
+
+    /// Drop every entry
+    fn clear(&self) -> BoxFuture<'_, ()>;
+
+    /// Snapshot of the cache counters as JSON
+    fn stats(&self) -> Value;
+}
+
+/// Validate the cache settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if config.redis_key_prefix.is_empty() {
+        return Err("redis_key_prefix must not be empty".to_string());
+    }
+    match CacheKind::parse(&config.cache_backend)? {
+        CacheKind::Memory if config.redis_url.is_some() => {
+            Err("redis_url requires cache_backend redis".to_string())
+        }
+        CacheKind::Memory => Ok(()),
+        #[cfg(feature = "redis")]
+        CacheKind::Redis => redis::validate_config(config),
+        #[allow(unreachable_patterns)]
+        _ => Err("cache_backend redis is not available, rebuild the plugin with the 'redis' feature".to_string()),
+    }
+}
+
+/// Create the configured cache; a zero `cache_ttl_seconds` disables caching
+pub(crate) async fn connect(config: &PluginConfig) -> Result<Cache, String> {
+    let ttl = Duration::from_secs(config.cache_ttl_seconds);
+    match CacheKind::parse(&config.cache_backend)? {
+        CacheKind::Memory => Ok(Box::new(MemoryCache::new(ttl, config.cache_max_entries))),
+        #[cfg(feature = "redis")]
+        CacheKind::Redis => Ok(Box::new(redis::RedisCache::connect(config, ttl).await?)),
+        #[allow(unreachable_patterns)]
+        _ => Err(format!("Cache backend {} is not available in this build", config.cache_backend)),
+    }
+}
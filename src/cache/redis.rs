@@ -0,0 +1,192 @@
+//! Redis cache, enabled with the `redis` feature
+//!
+//! Entries are JSON strings stored under `redis_key_prefix` with the
+//! configured time to live as Redis expiry. Tool arguments are hashed into
+//! the key, which keeps keys short and free of client input:
+//!
+//! ```text
+//! <prefix>product:<id>:<sha256 of the arguments>
+//! <prefix>search:<sha256 of the arguments>
+//! ```
+//!
+//! Invalidation deletes the matching keys for every instance sharing the
+//! server. `cache_max_entries` does not apply; bound the memory with the
+//! server's `maxmemory` and an eviction policy such as `volatile-lru`.
+//! Hit and miss counters are kept per instance.
+
+use super::{CacheBackend, CacheKey};
+use crate::PluginConfig;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, RedisResult};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Keys deleted per UNLINK command
+const UNLINK_BATCH: usize = 500;
+
+/// Validate the Redis settings, called before a configuration is applied
+pub(super) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    let url = config
+        .redis_url
+        .as_deref()
+        .ok_or("cache_backend redis requires redis_url")?;
+    // The redis error could echo the URL, and with it the password
+    redis::Client::open(url)
+        .map(|_| ())
+        .map_err(|_| "Invalid redis_url: not a Redis connection URL".to_string())
+}
+
+/// Escape the glob characters of a key prefix for SCAN MATCH
+fn escape_pattern(prefix: &str) -> String {
+    let mut escaped = String::with_capacity(prefix.len());
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+pub(super) struct RedisCache {
+    connection: ConnectionManager,
+    prefix: String,
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl RedisCache {
+    pub(super) async fn connect(config: &PluginConfig, ttl: Duration) -> Result<Self, String> {
+        let url = config.redis_url.as_deref().ok_or("cache_backend redis requires redis_url")?;
+        let client =
+            redis::Client::open(url).map_err(|_| "Invalid redis_url: not a Redis connection URL".to_string())?;
+        let connection = ConnectionManager::new(client)
+            .await
+            .map_err(|err| format!("Cannot connect to Redis: {err}"))?;
+        Ok(RedisCache {
+            connection,
+            prefix: config.redis_key_prefix.clone(),
+            ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        })
+    }
+
+    fn enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    fn key(&self, key: &CacheKey) -> String {
+        let digest = |args: &str| hex::encode(Sha256::digest(args.as_bytes()));
+        match key {
+            CacheKey::ProductPrice(id, args) => format!("{}product:{id}:{}", self.prefix, digest(args)),
+            CacheKey::Search(args) => format!("{}search:{}", self.prefix, digest(args)),
+        }
+    }
+
+    /// Count and log a failed command; the caller carries on as on a miss
+    fn failed(&self, operation: &str, err: redis::RedisError) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!("Redis cache {operation} failed: {err}");
+    }
+
+    /// Delete every key matching `pattern`, which is relative to the prefix
+    async fn unlink_matching(&self, pattern: &str) -> RedisResult<()> {
+        let mut connection = self.connection.clone();
+        let pattern = format!("{}{pattern}", escape_pattern(&self.prefix));
+        let mut keys: Vec<String> = Vec::new();
+        let mut iter = connection.scan_match::<_, String>(pattern).await?;
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+        drop(iter);
+
+        for batch in keys.chunks(UNLINK_BATCH) {
+            connection.unlink::<_, ()>(batch).await?;
+        }
+        Ok(())
+    }
+}
+
+impl CacheBackend for RedisCache {
+    fn get<'a>(&'a self, key: &'a CacheKey) -> BoxFuture<'a, Option<Value>> {
+        async move {
+            if !self.enabled() {
+                return None;
+            }
+            let mut connection = self.connection.clone();
+            let cached = match connection.get::<_, Option<String>>(self.key(key)).await {
+                Ok(cached) => cached.and_then(|text| serde_json::from_str(&text).ok()),
+                Err(err) => {
+                    self.failed("lookup", err);
+                    None
+                }
+            };
+            let counter = if cached.is_some() { &self.hits } else { &self.misses };
+            counter.fetch_add(1, Ordering::Relaxed);
+            cached
+        }
+        .boxed()
+    }
+
+    fn insert(&self, key: CacheKey, value: Value) -> BoxFuture<'_, ()> {
+        async move {
+            if !self.enabled() {
+                return;
+            }
+            let mut connection = self.connection.clone();
+            let stored = connection
+                .set_ex::<_, _, ()>(self.key(&key), value.to_string(), self.ttl.as_secs())
+                .await;
+            if let Err(err) = stored {
+                self.failed("insert", err);
+            }
+        }
+        .boxed()
+    }
+
+    fn invalidate_product(&self, product_id: i32) -> BoxFuture<'_, ()> {
+        async move {
+            for pattern in [format!("product:{product_id}:*"), "search:*".to_string()] {
+                if let Err(err) = self.unlink_matching(&pattern).await {
+                    self.failed("invalidation", err);
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn clear(&self) -> BoxFuture<'_, ()> {
+        async move {
+            if let Err(err) = self.unlink_matching("*").await {
+                self.failed("clear", err);
+            }
+        }
+        .boxed()
+    }
+
+    fn stats(&self) -> Value {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+        let hit_rate = if lookups == 0 { 0.0 } else { hits as f64 / lookups as f64 };
+
+        json!({
+            "backend": "redis",
+            "enabled": self.enabled(),
+            "ttl_seconds": self.ttl.as_secs(),
+            "key_prefix": self.prefix,
+            "hits": hits,
+            "misses": misses,
+            "hit_rate": hit_rate,
+            "errors": self.errors.load(Ordering::Relaxed)
+        })
+    }
+}
@@ -0,0 +1,180 @@
+//! Circuit breaker around database work
+//!
+//! After `circuit_breaker_failures` consecutive tool calls failed with
+//! `db_unavailable` or `timeout`, the circuit opens: calls fail right away
+//! with `db_unavailable` instead of each waiting for the pool's acquire
+//! timeout. After `circuit_breaker_open_seconds` one call is let through as
+//! a probe (half-open); its success closes the circuit, its failure opens
+//! it for another period. Other errors, such as invalid arguments, a
+//! missing product or a full queue, are mostly raised before any query ran
+//! and prove nothing either way: they neither count as failures nor reset
+//! the count, and a probe ending in one lets the next call probe instead.
+//! Tools that do not query the database,
+//! `health_check`, which must be able to observe the outage, and
+//! `execute_batch`, whose entries are admitted one by one, are exempt.
+//!
+//! Thresholds are read from the current configuration on every call, so a
+//! reconfiguration applies to the breaker's next decision.
+
+use crate::batch;
+use crate::error::PluginError;
+use crate::get_config;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Tools admitted regardless of the circuit state; the entries of a batch
+/// pass the breaker on their own
+const EXEMPT_TOOLS: [&str; 4] = ["health_check", "cache_stats", "get_plugin_metrics", batch::TOOL];
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    /// The probe call is running
+    HalfOpen,
+}
+
+/// Outcome of `CircuitBreaker::admit` to report back with `record`
+#[must_use]
+pub(crate) struct Admission {
+    exempt: bool,
+}
+
+pub(crate) struct CircuitBreaker {
+    state: Mutex<State>,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        CircuitBreaker {
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// Let a call of `tool` through, or fail fast while the circuit is open
+    pub(crate) fn admit(&self, tool: &str) -> Result<Admission, PluginError> {
+        if EXEMPT_TOOLS.contains(&tool) || get_config().circuit_breaker_failures == 0 {
+            return Ok(Admission { exempt: true });
+        }
+
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Closed { .. } => Ok(Admission { exempt: false }),
+            State::Open { until } if Instant::now() >= until => {
+                tracing::info!("Database circuit half-open, probing with a {tool} call");
+                *state = State::HalfOpen;
+                Ok(Admission { exempt: false })
+            }
+            State::Open { until } => Err(PluginError::DbUnavailable(format!(
+                "Database circuit open after repeated failures, next attempt in {}s",
+                until.saturating_duration_since(Instant::now()).as_secs().max(1)
+            ))),
+            State::HalfOpen => Err(PluginError::DbUnavailable(
+                "Database circuit open, a probe call is in progress".to_string(),
+            )),
+        }
+    }
+
+    /// Count the result of an admitted call
+    pub(crate) fn record<T>(&self, admission: Admission, result: &Result<T, PluginError>) {
+        if admission.exempt {
+            return;
+        }
+
+        let config = get_config();
+        let mut state = self.state.lock().unwrap();
+        let failed = match result {
+            Ok(_) => false,
+            Err(PluginError::DbUnavailable(_) | PluginError::PoolExhausted(..) | PluginError::Timeout(_)) => true,
+            Err(_) => {
+                // Not known to have reached the database; let the next call probe instead
+                if let State::HalfOpen = *state {
+                    *state = State::Open { until: Instant::now() };
+                }
+                return;
+            }
+        };
+        *state = match (*state, failed) {
+            (State::HalfOpen, false) => {
+                tracing::info!("Database circuit closed");
+                State::Closed { failures: 0 }
+            }
+            (_, false) => State::Closed { failures: 0 },
+            (State::Closed { failures }, true) if failures + 1 < config.circuit_breaker_failures => {
+                State::Closed { failures: failures + 1 }
+            }
+            (State::Open { until }, true) => State::Open { until },
+            (_, true) => {
+                let open_for = Duration::from_secs(config.circuit_breaker_open_seconds);
+                tracing::warn!("Database circuit open for {open_for:?} after repeated failures");
+                State::Open {
+                    until: Instant::now() + open_for,
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestConfig;
+    use serde_json::json;
+
+    fn unavailable() -> Result<(), PluginError> {
+        Err(PluginError::DbUnavailable("connection refused".to_string()))
+    }
+
+    #[test]
+    fn opens_probes_and_closes() {
+        let _config = TestConfig::install(json!({"circuit_breaker_failures": 2}));
+        let breaker = CircuitBreaker::default();
+
+        // Closed until the second consecutive failure
+        breaker.record(breaker.admit("search_products").unwrap(), &unavailable());
+        assert!(matches!(*breaker.state.lock().unwrap(), State::Closed { failures: 1 }));
+        breaker.record(breaker.admit("search_products").unwrap(), &unavailable());
+        assert!(matches!(*breaker.state.lock().unwrap(), State::Open { .. }));
+        assert!(breaker.admit("search_products").is_err());
+        assert!(breaker.admit("health_check").is_ok());
+
+        // Once the period is over one probe is let through, and only one
+        *breaker.state.lock().unwrap() = State::Open { until: Instant::now() };
+        let probe = breaker.admit("search_products").unwrap();
+        assert!(matches!(*breaker.state.lock().unwrap(), State::HalfOpen));
+        assert!(breaker.admit("search_products").is_err());
+
+        // A failed probe opens the circuit again, a successful one closes it
+        breaker.record(probe, &unavailable());
+        assert!(matches!(*breaker.state.lock().unwrap(), State::Open { .. }));
+        *breaker.state.lock().unwrap() = State::Open { until: Instant::now() };
+        breaker.record(breaker.admit("search_products").unwrap(), &Ok(()));
+        assert!(matches!(*breaker.state.lock().unwrap(), State::Closed { failures: 0 }));
+    }
+
+    #[test]
+    fn other_errors_prove_nothing() {
+        let _config = TestConfig::install(json!({"circuit_breaker_failures": 2}));
+        let breaker = CircuitBreaker::default();
+        let invalid = || Err::<(), _>(PluginError::invalid_argument("Invalid product_id"));
+        let not_found = || Err::<(), _>(PluginError::not_found("Product 42 not found"));
+
+        // They neither count as failures nor reset the count
+        breaker.record(breaker.admit("search_products").unwrap(), &unavailable());
+        breaker.record(breaker.admit("search_products").unwrap(), &invalid());
+        assert!(matches!(*breaker.state.lock().unwrap(), State::Closed { failures: 1 }));
+        breaker.record(breaker.admit("search_products").unwrap(), &unavailable());
+        assert!(matches!(*breaker.state.lock().unwrap(), State::Open { .. }));
+
+        // A probe ending in one leaves the circuit open for the next probe
+        *breaker.state.lock().unwrap() = State::Open { until: Instant::now() };
+        breaker.record(breaker.admit("get_product_price").unwrap(), &not_found());
+        assert!(matches!(*breaker.state.lock().unwrap(), State::Open { .. }));
+        breaker.record(breaker.admit("get_product_price").unwrap(), &invalid());
+        assert!(matches!(*breaker.state.lock().unwrap(), State::Open { .. }));
+        breaker.record(breaker.admit("get_product_price").unwrap(), &Ok(()));
+        assert!(matches!(*breaker.state.lock().unwrap(), State::Closed { failures: 0 }));
+    }
+}
@@ -0,0 +1,155 @@
+//! Coalescing of identical concurrent calls
+//!
+//! When several callers ask for the same thing at once, e.g. ten agents
+//! pricing product 42, only the first call of a tool in `coalesced_tools`
+//! runs; the others wait for it without taking a concurrency slot and share
+//! its result, so the database answers one query instead of ten. Calls are
+//! identical when they are for the same tool with the same arguments, the
+//! MCP `_meta` object and the caller's `_auth` context aside: callers are
+//! authorized before and results redacted and shaped after, for every call
+//! on its own. Only the customer of the context takes part, as prices
+//! default to it; `tenant` is an argument like any other.
+//!
+//! A call arriving after the first finished runs anew, keeping results for
+//! later calls is the cache's job. Should the first call be cancelled, the
+//! waiting ones run themselves. Only read tools belong in `coalesced_tools`.
+
+use crate::auth::{self, AUTH_ARGUMENT};
+use crate::error::PluginError;
+use crate::{get_config, get_tools, PluginConfig};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+/// Result of a call, published once it finished
+type Published<T> = watch::Receiver<Option<Result<T, PluginError>>>;
+
+/// Calls in flight by key, each with the channel its result is published on
+pub(crate) struct Coalescer<T> {
+    in_flight: Mutex<HashMap<String, Published<T>>>,
+}
+
+impl<T> Default for Coalescer<T> {
+    fn default() -> Self {
+        Coalescer {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// Removes the key of a call from the in-flight map once it finished or
+/// was cancelled
+struct InFlight<'a, T> {
+    coalescer: &'a Coalescer<T>,
+    key: String,
+}
+
+impl<T> Drop for InFlight<'_, T> {
+    fn drop(&mut self) {
+        self.coalescer.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+/// Validate the coalesced tools, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    let tools = get_tools();
+    match config.coalesced_tools.iter().find(|tool| !tools.contains_key(*tool)) {
+        Some(tool) => Err(format!("Unknown tool '{tool}' in coalesced_tools")),
+        None => Ok(()),
+    }
+}
+
+/// Arguments serialized without the `_auth` and `_meta` objects, and
+/// without copying them
+struct ToolArguments<'a>(&'a Value);
+
+impl Serialize for ToolArguments<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.as_object() {
+            Some(args) => serializer
+                .collect_map(args.iter().filter(|(name, _)| !matches!(name.as_str(), AUTH_ARGUMENT | "_meta"))),
+            None => self.0.serialize(serializer),
+        }
+    }
+}
+
+/// Key of the arguments of a call: the customer of its `_auth` context and
+/// its tool arguments, whose object keys serialize sorted
+///
+/// Calls with equal keys get the same result before redaction, so it also
+/// keys the result cache.
+pub(crate) fn arguments_key(args: &Value) -> String {
+    let customer = auth::parse(args).ok().flatten().and_then(|auth| auth.customer_id);
+    let args = serde_json::to_string(&ToolArguments(args)).expect("JSON values serialize");
+    format!("{customer:?}\n{args}")
+}
+
+/// Key of a call: the tool and the key of its arguments
+fn key(tool: &str, args: &Value) -> String {
+    format!("{tool}\n{}", arguments_key(args))
+}
+
+impl<T: Clone> Coalescer<T> {
+    /// Run `call`, or share the result of an identical call in flight
+    pub(crate) async fn run<F>(&self, tool: &str, args: &Value, call: F) -> Result<T, PluginError>
+    where
+        F: Future<Output = Result<T, PluginError>>,
+    {
+        if !get_config().coalesced_tools.iter().any(|coalesced| coalesced == tool) {
+            return call.await;
+        }
+
+        let key = key(tool, args);
+        let (tx, rx) = watch::channel(None);
+        let running = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(running) => Some(running.clone()),
+                None => {
+                    in_flight.insert(key.clone(), rx);
+                    None
+                }
+            }
+        };
+        let Some(mut running) = running else {
+            let _in_flight = InFlight { coalescer: self, key };
+            let result = call.await;
+            tx.send_replace(Some(result.clone()));
+            return result;
+        };
+
+        let shared = running.wait_for(Option::is_some).await.map(|result| result.clone());
+        match shared {
+            Ok(Some(result)) => {
+                tracing::debug!("Shared the result of an identical call");
+                result
+            }
+            // The first call was cancelled
+            _ => call.await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn key_of_tool_arguments() {
+        let args = json!({"product_id": 42, "currency": "EUR", "_meta": {"progressToken": 1},
+            "_auth": {"user_id": "u-1", "roles": ["pricing"]}});
+        let same = json!({"currency": "EUR", "product_id": 42, "_auth": {"user_id": "u-2"}});
+        assert_eq!(key("get_product_price", &args), key("get_product_price", &same));
+        let unauthenticated = json!({"product_id": 42, "currency": "EUR"});
+        assert_eq!(key("get_product_price", &args), key("get_product_price", &unauthenticated));
+
+        assert_ne!(key("get_product_price", &args), key("get_product_by_sku", &args));
+        assert_ne!(key("get_product_price", &args), key("get_product_price", &json!({"product_id": 42})));
+        let customer = json!({"product_id": 42, "currency": "EUR", "_auth": {"user_id": "u-1", "customer_id": "C-7"}});
+        assert_ne!(key("get_product_price", &args), key("get_product_price", &customer));
+    }
+}
@@ -0,0 +1,264 @@
+//! Competitor price comparison
+//!
+//! Reads observed competitor prices from the `competitor_prices` table
+//! (name configurable through `competitor_prices_table`), in the base
+//! currency:
+//!
+//! ```sql
+//! CREATE TABLE competitor_prices (
+//!     product_id  INTEGER NOT NULL REFERENCES products(id),
+//!     competitor  TEXT NOT NULL,
+//!     price       NUMERIC(10,2) NOT NULL,
+//!     observed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+//! );
+//! ```
+//!
+//! Only the latest observation per product and competitor counts.
+//! `compare_prices` with a `product_id` lists them next to our price and
+//! places our price among them: `cheapest` at or below every competitor,
+//! `most_expensive` at or above every competitor, `median` in between. A
+//! product is flagged when it costs more than `threshold_percent` (default
+//! `competitor_alert_percent`) above the cheapest competitor. Without a
+//! `product_id` the tool lists the flagged products instead, furthest above
+//! the cheapest competitor first.
+
+use crate::args::{self, DecimalArg};
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::mapping;
+use crate::sql::{is_undefined_table, quote_identifier};
+use crate::{format_price, get_config, PluginConfig};
+use chrono::{DateTime, Utc};
+use mcp_plugin_api::utils;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Page size of the flagged product list without `limit`
+const DEFAULT_FLAGGED_LIMIT: i64 = 50;
+
+/// Upper bound of the `limit` argument
+const MAX_FLAGGED_LIMIT: i64 = 500;
+
+/// Validate the competitor settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if config.competitor_alert_percent < Decimal::ZERO {
+        return Err("competitor_alert_percent must not be negative".to_string());
+    }
+    quote_identifier(&config.competitor_prices_table).map(|_| ())
+}
+
+/// Arguments of compare_prices
+#[derive(Debug, Deserialize, JsonSchema)]
+struct CompareArgs {
+    product_id: Option<i32>,
+    threshold_percent: Option<DecimalArg>,
+    category: Option<String>,
+    #[schemars(range(min = 1, max = "MAX_FLAGGED_LIMIT"))]
+    limit: Option<i64>,
+    #[serde(default)]
+    #[schemars(range(min = 0))]
+    offset: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct CompetitorPrice {
+    competitor: String,
+    price: Decimal,
+    observed_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow)]
+struct FlaggedProduct {
+    id: i32,
+    name: String,
+    category: Option<String>,
+    price: Decimal,
+    cheapest_price: Decimal,
+    cheapest_competitor: String,
+    competitors: i64,
+    total: i64,
+}
+
+/// Latest observation per product and competitor, as CTE `latest`
+fn latest_cte(table: &str, product_filter: &str) -> String {
+    format!(
+        "WITH latest AS ( \
+             SELECT DISTINCT ON (product_id, competitor) product_id, competitor, price, observed_at \
+             FROM {table} {product_filter} \
+             ORDER BY product_id, competitor, observed_at DESC \
+         )"
+    )
+}
+
+/// Percent by which `price` exceeds `reference`, negative when below
+fn percent_above(price: &Decimal, reference: &Decimal) -> Option<Decimal> {
+    (!reference.is_zero()).then(|| ((price - reference) / reference * Decimal::ONE_HUNDRED).round_dp(2))
+}
+
+/// Median of prices sorted in ascending order
+fn median(sorted: &[Decimal]) -> Option<Decimal> {
+    let middle = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        n if n % 2 == 1 => Some(sorted[middle]),
+        _ => Some((sorted[middle - 1] + sorted[middle]) / Decimal::TWO),
+    }
+}
+
+/// Cheapest competitor of a product and its latest price, `None` without
+/// observations or competitor table
+pub(crate) async fn cheapest_competitor(
+    pool: &sqlx::PgPool,
+    product_id: i32,
+) -> Result<Option<(String, Decimal)>, PluginError> {
+    let table = quote_identifier(&get_config().competitor_prices_table).map_err(PluginError::internal)?;
+    let result = sqlx::query_as::<_, (String, Decimal)>(&format!(
+        "{} SELECT competitor, price FROM latest ORDER BY price, competitor LIMIT 1",
+        latest_cte(&table, "WHERE product_id = $1")
+    ))
+    .bind(product_id)
+    .fetch_optional(pool)
+    .await;
+
+    match result {
+        Ok(cheapest) => Ok(cheapest),
+        Err(err) if is_undefined_table(&err) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub(crate) async fn handle_compare_prices(db: &dyn DatabaseBackend, args: &Value) -> Result<Value, PluginError> {
+    let pool = db.postgres()?;
+    let CompareArgs {
+        product_id,
+        threshold_percent,
+        category,
+        limit,
+        offset,
+    } = args::parse(args)?;
+
+    let config = get_config();
+    let threshold = match threshold_percent {
+        Some(threshold) => {
+            let threshold = threshold.to_decimal("threshold_percent")?;
+            if threshold < Decimal::ZERO {
+                return Err(PluginError::invalid_argument("threshold_percent must not be negative"));
+            }
+            threshold
+        }
+        None => config.competitor_alert_percent,
+    };
+    let table = quote_identifier(&config.competitor_prices_table).map_err(PluginError::internal)?;
+
+    let Some(product_id) = product_id else {
+        let limit = limit.unwrap_or(DEFAULT_FLAGGED_LIMIT);
+        let flagged = sqlx::query_as::<_, FlaggedProduct>(&format!(
+            "{} , cheapest AS ( \
+                 SELECT DISTINCT ON (product_id) product_id, competitor, price, \
+                        count(*) OVER (PARTITION BY product_id) AS competitors \
+                 FROM latest ORDER BY product_id, price, competitor \
+             ) \
+             SELECT products.id, products.name, products.category, products.price, \
+                    cheapest.price AS cheapest_price, cheapest.competitor AS cheapest_competitor, \
+                    cheapest.competitors, count(*) OVER () AS total \
+             FROM cheapest JOIN {} ON products.id = cheapest.product_id \
+             WHERE cheapest.price > 0 AND products.price > cheapest.price * (1 + $1 / 100) \
+               AND ($2::text IS NULL OR products.category = $2) \
+             ORDER BY products.price / cheapest.price DESC, products.id \
+             LIMIT $3 OFFSET $4",
+            latest_cte(&table, ""),
+            mapping::products()
+        ))
+        .bind(threshold)
+        .bind(&category)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+
+        let total = flagged.first().map_or(0, |product| product.total);
+        let products: Vec<Value> = flagged
+            .iter()
+            .map(|product| {
+                json!({
+                    "id": product.id,
+                    "name": product.name,
+                    "category": product.category,
+                    "price": format_price(&product.price),
+                    "cheapest_competitor": product.cheapest_competitor,
+                    "cheapest_competitor_price": format_price(&product.cheapest_price),
+                    "percent_above_cheapest": percent_above(&product.price, &product.cheapest_price)
+                        .map(|percent| percent.to_string()),
+                    "competitors": product.competitors
+                })
+            })
+            .collect();
+
+        // Return structured JSON data for programmatic clients
+        return Ok(utils::json_content(json!({
+            "threshold_percent": threshold.to_string(),
+            "products": products,
+            "count": products.len(),
+            "total": total,
+            "offset": offset,
+            "base_currency": config.base_currency
+        })));
+    };
+
+    let product = db
+        .fetch_product(product_id)
+        .await?
+        .ok_or_else(|| PluginError::not_found(format!("Product {product_id} not found")))?;
+
+    let competitors = sqlx::query_as::<_, CompetitorPrice>(&format!(
+        "{} SELECT competitor, price, observed_at FROM latest ORDER BY price, competitor",
+        latest_cte(&table, "WHERE product_id = $1")
+    ))
+    .bind(product_id)
+    .fetch_all(pool)
+    .await?;
+
+    let prices: Vec<Decimal> = competitors.iter().map(|competitor| competitor.price).collect();
+    let (cheapest, most_expensive) = (prices.first(), prices.last());
+    let position = match (cheapest, most_expensive) {
+        (Some(cheapest), _) if product.price <= *cheapest => Some("cheapest"),
+        (_, Some(most_expensive)) if product.price >= *most_expensive => Some("most_expensive"),
+        (Some(_), Some(_)) => Some("median"),
+        _ => None,
+    };
+    let percent_above_cheapest = cheapest.and_then(|cheapest| percent_above(&product.price, cheapest));
+    let flagged = percent_above_cheapest.is_some_and(|percent| percent > threshold);
+    let median_price = median(&prices);
+
+    let competitor_prices: Vec<Value> = competitors
+        .iter()
+        .map(|competitor| {
+            json!({
+                "competitor": competitor.competitor,
+                "price": format_price(&competitor.price),
+                "difference_percent": percent_above(&product.price, &competitor.price)
+                    .map(|percent| percent.to_string()),
+                "observed_at": competitor.observed_at.to_rfc3339()
+            })
+        })
+        .collect();
+
+    // Return structured JSON data for programmatic clients
+    Ok(utils::json_content(json!({
+        "product_id": product.id,
+        "name": product.name,
+        "price": format_price(&product.price),
+        "competitor_prices": competitor_prices,
+        "competitors": competitors.len(),
+        "cheapest_competitor_price": cheapest.map(format_price),
+        "median_competitor_price": median_price.as_ref().map(format_price),
+        "most_expensive_competitor_price": most_expensive.map(format_price),
+        "position": position,
+        "percent_above_cheapest": percent_above_cheapest.map(|percent| percent.to_string()),
+        "threshold_percent": threshold.to_string(),
+        "flagged": flagged,
+        "base_currency": config.base_currency
+    })))
+}
@@ -0,0 +1,121 @@
+//! Concurrency limits
+//!
+//! Every tool call runs in its own task, but only `max_concurrent_requests`
+//! of them may execute their handler at the same time, and at most
+//! `tool_concurrency_limits[tool]` for tools listed there. Excess calls wait
+//! for a permit, highest priority first (see the priority module), for up
+//! to `concurrency_wait_ms` and then fail with a
+//! retryable `server_busy` error, so a burst of searches queues in front of
+//! the pool instead of piling up on it.
+//!
+//! Tools listed in `batch_tools`, the heavy scans and bulk writes, share a
+//! class limit of `max_connections` less `reserved_interactive_connections`
+//! calls, at least one. A batch call holds one connection at a time, so the reserved
+//! connections stay free for interactive lookups like `get_product_price`
+//! however many exports are running.
+
+use crate::batch;
+use crate::error::PluginError;
+use crate::priority::{Priority, PriorityPermit, PrioritySemaphore};
+use crate::{get_tools, PluginConfig};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Permits held for the duration of one handler call
+pub(crate) struct Permits {
+    _global: Option<PriorityPermit>,
+    _batch: Option<PriorityPermit>,
+    _tool: Option<PriorityPermit>,
+}
+
+/// Semaphores built from one configuration
+///
+/// Replaced as a whole on reconfigure; running calls keep the permits of
+/// the limits they were admitted under.
+pub(crate) struct ConcurrencyLimits {
+    global: Option<PrioritySemaphore>,
+    /// Shared by the tools in `batch_tools`
+    batch: Option<PrioritySemaphore>,
+    batch_tools: HashSet<String>,
+    tools: HashMap<String, PrioritySemaphore>,
+    wait: Duration,
+}
+
+impl ConcurrencyLimits {
+    pub(crate) fn new(config: &PluginConfig) -> Self {
+        let semaphore = PrioritySemaphore::new;
+        ConcurrencyLimits {
+            global: (config.max_concurrent_requests > 0).then(|| semaphore(config.max_concurrent_requests)),
+            batch: (config.reserved_interactive_connections > 0).then(|| {
+                // A pool too small to reserve from still runs one batch call
+                let slots = config.max_connections.saturating_sub(config.reserved_interactive_connections);
+                semaphore(slots.max(1) as usize)
+            }),
+            batch_tools: config.batch_tools.iter().cloned().collect(),
+            tools: config
+                .tool_concurrency_limits
+                .iter()
+                .map(|(tool, limit)| (tool.clone(), semaphore(*limit)))
+                .collect(),
+            wait: Duration::from_millis(config.concurrency_wait_ms),
+        }
+    }
+
+    /// Wait for the permits `tool` needs to run, ahead of waiting calls of
+    /// lower priority
+    ///
+    /// The tool and class permits are taken first so calls to a throttled
+    /// tool do not hold global permits while they wait.
+    pub(crate) async fn acquire(&self, tool: &str, priority: Priority) -> Result<Permits, PluginError> {
+        // A batch only waits for its entries, which take their own permits
+        if tool == batch::TOOL {
+            return Ok(Permits {
+                _global: None,
+                _batch: None,
+                _tool: None,
+            });
+        }
+        let batch = self.batch.as_ref().filter(|_| self.batch_tools.contains(tool));
+        let acquire = async {
+            let tool_permit = match self.tools.get(tool) {
+                Some(semaphore) => Some(semaphore.acquire(priority).await),
+                None => None,
+            };
+            let batch_permit = match batch {
+                Some(semaphore) => Some(semaphore.acquire(priority).await),
+                None => None,
+            };
+            let global_permit = match &self.global {
+                Some(semaphore) => Some(semaphore.acquire(priority).await),
+                None => None,
+            };
+            (tool_permit, batch_permit, global_permit)
+        };
+
+        match tokio::time::timeout(self.wait, acquire).await {
+            Ok((tool_permit, batch_permit, global_permit)) => Ok(Permits {
+                _global: global_permit,
+                _batch: batch_permit,
+                _tool: tool_permit,
+            }),
+            Err(_) => Err(PluginError::ServerBusy(format!(
+                "Server busy: no capacity for {tool} within {} ms, retry later",
+                self.wait.as_millis()
+            ))),
+        }
+    }
+}
+
+/// Validate the concurrency settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    for (tool, limit) in &config.tool_concurrency_limits {
+        if *limit == 0 {
+            return Err(format!("Concurrency limit for {tool} must be at least 1"));
+        }
+    }
+    let tools = get_tools();
+    if let Some(tool) = config.batch_tools.iter().find(|tool| !tools.contains_key(*tool)) {
+        return Err(format!("Unknown tool '{tool}' in batch_tools"));
+    }
+    Ok(())
+}
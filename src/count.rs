@@ -0,0 +1,164 @@
+//! Product counts
+//!
+//! `count_products` answers "how many products under $10 in Toys?" without
+//! fetching a single row: it takes the filters of `search_products`, all of
+//! them optional including the query, and runs `SELECT count(*)` over the
+//! same conditions.
+//!
+//! Counting still visits every matching row. With `estimate: true` the
+//! count comes from the statistics instead, in constant time however large
+//! the table: the `reltuples` of the product table in `pg_class` when
+//! nothing is filtered, and the planner's row estimate for the conditions
+//! otherwise. Estimates are as fresh as the last `ANALYZE`; a table never
+//! analyzed is estimated by the planner from its size.
+
+use crate::args::{self, DecimalArg};
+use crate::attributes;
+use crate::backend::DatabaseBackend;
+use crate::brands;
+use crate::error::PluginError;
+use crate::get_config;
+use crate::mapping;
+use crate::search::{self, SearchMode};
+use mcp_plugin_api::utils;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+/// Arguments of count_products
+#[derive(Debug, Deserialize, JsonSchema)]
+struct CountArgs {
+    query: Option<String>,
+    #[serde(default)]
+    raw_pattern: bool,
+    search_mode: Option<String>,
+    category: Option<String>,
+    brand: Option<String>,
+    attributes: Option<Map<String, Value>>,
+    min_price: Option<DecimalArg>,
+    max_price: Option<DecimalArg>,
+    #[serde(default)]
+    include_inactive: bool,
+    #[serde(default)]
+    estimate: bool,
+}
+
+pub(crate) async fn handle_count_products(db: &dyn DatabaseBackend, args: &Value) -> Result<Value, PluginError> {
+    let pool = db.postgres()?;
+    let args: CountArgs = args::parse(args)?;
+    let config = get_config();
+    let mode = SearchMode::parse(args.search_mode.as_deref().unwrap_or(&config.search_mode))?;
+    if args.brand.is_some() {
+        brands::require_brand_column()?;
+    }
+    if let Some(attributes) = &args.attributes {
+        attributes::validate_filter(attributes)?;
+    }
+    let min_price = args.min_price.as_ref().map(|price| price.to_decimal("min_price")).transpose()?;
+    let max_price = args.max_price.as_ref().map(|price| price.to_decimal("max_price")).transpose()?;
+    if let (Some(min), Some(max)) = (min_price, max_price) {
+        if min > max {
+            return Err(PluginError::invalid_argument(format!(
+                "min_price ({min}) must not be greater than max_price ({max})"
+            )));
+        }
+    }
+
+    // `prefix FROM products WHERE <conditions>`
+    let statement = |prefix: &str| {
+        let mut sql = QueryBuilder::<Postgres>::new(format!("{prefix} FROM {} WHERE true", mapping::products()));
+        if let Some(query) = &args.query {
+            sql.push(" AND ");
+            search::push_match(&mut sql, mode, query, args.raw_pattern);
+        }
+        search::push_filters(
+            &mut sql,
+            args.category.as_deref(),
+            args.brand.as_deref(),
+            args.attributes.as_ref(),
+            min_price,
+            max_price,
+            args.include_inactive,
+        );
+        sql
+    };
+    let unfiltered = args.query.is_none()
+        && args.category.is_none()
+        && args.brand.is_none()
+        && args.attributes.as_ref().is_none_or(Map::is_empty)
+        && min_price.is_none()
+        && max_price.is_none()
+        && (args.include_inactive || config.schema_mapping.status_column.is_none())
+        && !config.access_policy.restricts_rows();
+
+    let table_rows = match args.estimate && unfiltered {
+        true => table_estimate(pool).await?,
+        false => None,
+    };
+    let (count, estimate_source) = match (args.estimate, table_rows) {
+        (_, Some(rows)) => (rows, Some("pg_class")),
+        (true, None) => {
+            let explain = statement("EXPLAIN (FORMAT JSON) SELECT 1");
+            (planner_estimate(pool, explain, mode).await?, Some("planner"))
+        }
+        (false, None) => (fetch_scalar(pool, statement("SELECT count(*)"), mode).await?, None),
+    };
+
+    let mut result = json!({
+        "count": count,
+        "estimated": estimate_source.is_some()
+    });
+    if let Some(source) = estimate_source {
+        result["estimate_source"] = source.into();
+    }
+    if args.query.is_some() {
+        result["search_mode"] = mode.as_str().into();
+    }
+    Ok(utils::json_content(result))
+}
+
+/// Run a statement returning one value, with the trigram threshold applied
+/// in trigram mode
+async fn fetch_scalar<T>(
+    pool: &PgPool,
+    mut sql: QueryBuilder<'_, Postgres>,
+    mode: SearchMode,
+) -> Result<T, PluginError>
+where
+    T: for<'r> sqlx::Decode<'r, Postgres> + sqlx::Type<Postgres> + Send + Unpin,
+{
+    if mode != SearchMode::Trigram {
+        return Ok(sql.build_query_scalar().fetch_one(pool).await?);
+    }
+    let mut tx = pool.begin().await?;
+    search::set_trigram_threshold(&mut tx).await?;
+    let value = sql.build_query_scalar().fetch_one(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(value)
+}
+
+/// Number of rows the planner expects `EXPLAIN (FORMAT JSON)` of a
+/// statement to return
+async fn planner_estimate(
+    pool: &PgPool,
+    explain: QueryBuilder<'_, Postgres>,
+    mode: SearchMode,
+) -> Result<i64, PluginError> {
+    let plan: Value = fetch_scalar(pool, explain, mode).await?;
+    plan[0]["Plan"]["Plan Rows"]
+        .as_f64()
+        .map(|rows| rows.round() as i64)
+        .ok_or_else(|| PluginError::internal("EXPLAIN returned no row estimate"))
+}
+
+/// Rows of the product table according to `pg_class`, `None` while the
+/// table was never analyzed
+async fn table_estimate(pool: &PgPool) -> Result<Option<i64>, PluginError> {
+    let table = get_config().schema_mapping.table();
+    let rows: f32 = sqlx::query_scalar("SELECT reltuples FROM pg_class WHERE oid = $1::regclass")
+        .bind(table)
+        .fetch_one(pool)
+        .await?;
+    Ok((rows >= 0.0).then(|| rows.round() as i64))
+}
@@ -0,0 +1,210 @@
+//! Currency conversion
+//!
+//! Prices are stored in the configured base currency. Exchange rates are
+//! taken from the static `currency_rates` map in the configuration or, for
+//! currencies not listed there, from the rates of the `fx_provider` or the
+//! optional `currency_rates_table` with the columns `(currency TEXT, rate
+//! NUMERIC)`, in that order. A rate is the amount of the target currency
+//! worth one unit of the base currency.
+//!
+//! With the `multi_currency` pricing model a catalogue may also store
+//! prices per currency in `product_prices_table`:
+//!
+//! ```sql
+//! CREATE TABLE product_prices (
+//!     product_id INTEGER NOT NULL REFERENCES products(id),
+//!     currency   CHAR(3) NOT NULL,
+//!     price      NUMERIC(10,2) NOT NULL,
+//!     PRIMARY KEY (product_id, currency)
+//! );
+//! ```
+//!
+//! A stored price is used as is; currencies without a row fall back to the
+//! converted base price.
+
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::fx;
+use crate::sql::{is_undefined_table, quote_identifier};
+use crate::{format_price, get_config, PluginConfig};
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Exchange rate from the base currency into `currency`
+#[derive(Debug, Clone)]
+pub(crate) struct ExchangeRate {
+    pub(crate) currency: String,
+    pub(crate) rate: Decimal,
+}
+
+/// A price converted from the base currency
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct ConvertedPrice {
+    currency: String,
+    /// Formatted like every other price
+    price: String,
+    /// Rate the base price was converted at, absent for stored prices
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exchange_rate: Option<String>,
+    /// The converted price formatted for the requested locale
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) display_price: Option<String>,
+}
+
+impl ExchangeRate {
+    /// Converted price, formatted like every other price
+    pub(crate) fn converted(&self, price: &Decimal) -> ConvertedPrice {
+        ConvertedPrice {
+            currency: self.currency.clone(),
+            price: format_price(&(price * self.rate)),
+            exchange_rate: Some(self.rate.to_string()),
+            display_price: None,
+        }
+    }
+
+    /// Converted price as JSON
+    pub(crate) fn convert(&self, price: &Decimal) -> Value {
+        json!(self.converted(price))
+    }
+}
+
+/// How prices are stored, see the `pricing_model` config field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PricingModel {
+    /// Base currency prices only, converted on request
+    SingleCurrency,
+    /// Base currency prices plus stored prices per currency
+    MultiCurrency,
+}
+
+impl PricingModel {
+    pub(crate) fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "single_currency" => Ok(PricingModel::SingleCurrency),
+            "multi_currency" => Ok(PricingModel::MultiCurrency),
+            other => Err(format!(
+                "Invalid pricing_model '{other}', expected single_currency or multi_currency"
+            )),
+        }
+    }
+
+    /// The configured pricing model
+    pub(crate) fn configured() -> Self {
+        PricingModel::parse(&get_config().pricing_model).unwrap_or(PricingModel::SingleCurrency)
+    }
+}
+
+/// A price stored in `currency`, as opposed to one converted into it
+pub(crate) fn stored_price(currency: &str, price: &Decimal) -> ConvertedPrice {
+    ConvertedPrice {
+        currency: currency.to_string(),
+        price: format_price(price),
+        exchange_rate: None,
+        display_price: None,
+    }
+}
+
+/// Price of a product stored in `currency`, `None` without a row or table
+///
+/// Needs the postgres backend.
+pub(crate) async fn product_price_in(
+    db: &dyn DatabaseBackend,
+    product_id: i32,
+    currency: &str,
+) -> Result<Option<Decimal>, PluginError> {
+    let table = quote_identifier(&get_config().product_prices_table).map_err(PluginError::internal)?;
+    let result = sqlx::query_scalar::<_, Decimal>(&format!(
+        "SELECT price FROM {table} WHERE product_id = $1 AND upper(currency) = $2"
+    ))
+    .bind(product_id)
+    .bind(currency)
+    .fetch_optional(db.postgres()?)
+    .await;
+
+    match result {
+        Ok(price) => Ok(price),
+        Err(err) if is_undefined_table(&err) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Normalize an ISO 4217 currency code to upper case
+pub(crate) fn normalize_currency(code: &str) -> Result<String, String> {
+    if code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(code.to_ascii_uppercase())
+    } else {
+        Err(format!("Invalid currency code '{code}', expected a 3 letter ISO code"))
+    }
+}
+
+/// Normalize an optional typed `currency` argument
+pub(crate) fn parse_currency(code: Option<&str>) -> Result<Option<String>, PluginError> {
+    code.map(normalize_currency)
+        .transpose()
+        .map_err(PluginError::invalid_argument)
+}
+
+/// Validate the currency settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    normalize_currency(&config.base_currency)?;
+    for (code, rate) in &config.currency_rates {
+        normalize_currency(code)?;
+        if *rate <= Decimal::ZERO {
+            return Err(format!("Exchange rate for {code} must be positive"));
+        }
+    }
+    if let Some(table) = &config.currency_rates_table {
+        quote_identifier(table)?;
+    }
+    PricingModel::parse(&config.pricing_model)?;
+    quote_identifier(&config.product_prices_table).map(|_| ())
+}
+
+/// Look up the exchange rate for `currency`
+///
+/// The `currency_rates_table` lookup needs the postgres backend.
+pub(crate) async fn exchange_rate(db: &dyn DatabaseBackend, currency: &str) -> Result<ExchangeRate, PluginError> {
+    let config = get_config();
+    let found = |rate| {
+        Ok(ExchangeRate {
+            currency: currency.to_string(),
+            rate,
+        })
+    };
+
+    if currency.eq_ignore_ascii_case(&config.base_currency) {
+        return found(Decimal::ONE);
+    }
+
+    if let Some(rate) = config
+        .currency_rates
+        .iter()
+        .find(|(code, _)| code.eq_ignore_ascii_case(currency))
+        .map(|(_, rate)| *rate)
+    {
+        return found(rate);
+    }
+
+    if let Some(rate) = fx::rate(&config.base_currency, currency) {
+        return found(rate);
+    }
+
+    if let Some(table) = &config.currency_rates_table {
+        let table = quote_identifier(table).map_err(PluginError::internal)?;
+        let rate = sqlx::query_scalar::<_, Decimal>(&format!(
+            "SELECT rate FROM {table} WHERE upper(currency) = $1"
+        ))
+        .bind(currency)
+        .fetch_optional(db.postgres()?)
+        .await?;
+        if let Some(rate) = rate {
+            return found(rate);
+        }
+    }
+
+    Err(PluginError::invalid_argument(format!(
+        "No exchange rate configured for currency {currency}"
+    )))
+}
@@ -0,0 +1,107 @@
+//! Customer-specific (contract) pricing
+//!
+//! Negotiated prices live in the `customer_prices` table (name configurable
+//! through `customer_prices_table`):
+//!
+//! ```sql
+//! CREATE TABLE customer_prices (
+//!     customer_id VARCHAR(100) NOT NULL,
+//!     product_id  INTEGER NOT NULL REFERENCES products(id),
+//!     price       NUMERIC(10,2) NOT NULL,
+//!     PRIMARY KEY (customer_id, product_id)
+//! );
+//! ```
+//!
+//! A contract price replaces the list price for that customer; products
+//! without one fall back to the list price.
+
+use crate::auth::AuthContext;
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::sql::{is_undefined_table, quote_identifier};
+use crate::{get_config, PluginConfig};
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// Where the price in a response came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PriceSource {
+    ListPrice,
+    CustomerContract,
+    Scheduled,
+}
+
+impl PriceSource {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            PriceSource::ListPrice => "list_price",
+            PriceSource::CustomerContract => "customer_contract",
+            PriceSource::Scheduled => "scheduled_price",
+        }
+    }
+}
+
+/// Validate the customer pricing settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    quote_identifier(&config.customer_prices_table).map(|_| ())
+}
+
+/// A typed `customer_id` argument, given as string or number
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub(crate) enum CustomerId {
+    Text(String),
+    Number(serde_json::Number),
+}
+
+/// Check an optional typed `customer_id` argument against the caller's
+/// context, whose customer is used if the argument is absent
+pub(crate) fn parse_customer(
+    customer_id: Option<CustomerId>,
+    auth: Option<&AuthContext>,
+) -> Result<Option<String>, PluginError> {
+    let context_customer = auth.and_then(|auth| auth.customer_id.as_deref());
+    let customer_id = match customer_id {
+        None => match context_customer {
+            Some(id) => id.to_string(),
+            None => return Ok(None),
+        },
+        Some(CustomerId::Text(id)) if !id.is_empty() => id,
+        Some(CustomerId::Number(id)) => id.to_string(),
+        Some(CustomerId::Text(_)) => return Err(PluginError::invalid_argument("Invalid customer_id parameter")),
+    };
+    if !get_config().customer_pricing_enabled {
+        return Err(PluginError::invalid_argument(
+            "customer_id is not supported: customer pricing is disabled",
+        ));
+    }
+    if context_customer.is_some_and(|context_customer| context_customer != customer_id) {
+        return Err(PluginError::PermissionDenied(format!(
+            "customer_id {customer_id} is not the customer of the caller's _auth context"
+        )));
+    }
+    Ok(Some(customer_id))
+}
+
+/// Negotiated price of a product for a customer, if there is one
+pub(crate) async fn contract_price(
+    db: &dyn DatabaseBackend,
+    customer_id: &str,
+    product_id: i32,
+) -> Result<Option<Decimal>, PluginError> {
+    let table = quote_identifier(&get_config().customer_prices_table).map_err(PluginError::internal)?;
+    let result = sqlx::query_scalar::<_, Decimal>(&format!(
+        "SELECT price FROM {table} WHERE customer_id = $1 AND product_id = $2"
+    ))
+    .bind(customer_id)
+    .bind(product_id)
+    .fetch_optional(db.postgres()?)
+    .await;
+
+    match result {
+        Ok(price) => Ok(price),
+        Err(err) if is_undefined_table(&err) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
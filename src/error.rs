@@ -0,0 +1,240 @@
+//! Structured plugin errors
+//!
+//! Tool handlers return `PluginError` so that failures carry a stable,
+//! machine readable code. The plugin ABI only transports an error string,
+//! so the error is serialized to a JSON object before it crosses the FFI
+//! boundary:
+//!
+//! ```json
+//! {"code": "not_found", "message": "Product 42 not found", "retryable": false}
+//! ```
+//!
+//! Arguments failing the tool's argument schema add the offending field:
+//!
+//! ```json
+//! {"code": "invalid_argument", "message": "Invalid product_id: expected integer, received \"abc\"",
+//!  "field": "product_id", "expected": "integer", "received": "abc", "retryable": false}
+//! ```
+
+use serde_json::{json, Value};
+use std::fmt;
+use std::time::Duration;
+
+/// Retry hint sent with `server_busy` errors
+const SERVER_BUSY_RETRY_AFTER: Duration = Duration::from_millis(100);
+
+/// Error returned by tool handlers
+#[derive(Debug, Clone)]
+pub(crate) enum PluginError {
+    /// The caller passed a missing or malformed argument
+    InvalidArgument(String),
+    /// An argument does not match the tool's argument schema
+    InvalidField(FieldError),
+    /// The requested entity does not exist
+    NotFound(String),
+    /// The database cannot be reached
+    DbUnavailable(String),
+    /// No pooled connection became free within the acquire timeout, which
+    /// also happens when none can be opened; carries the state of the pool,
+    /// null until the dispatcher adds it
+    PoolExhausted(String, Value),
+    /// The database rejected or failed the query
+    Database(String),
+    /// The request did not complete within the request timeout
+    Timeout(String),
+    /// The database cancelled a statement that ran past its statement timeout
+    QueryTimeout(String),
+    /// The plugin failed to start, e.g. invalid connection settings
+    InitFailed(String),
+    /// The configured database backend cannot serve the request
+    Unsupported(String),
+    /// The operation is not allowed by the plugin configuration
+    PermissionDenied(String),
+    /// The data changed since the caller last read it
+    Conflict(String),
+    /// The request queue is full; retry after `SERVER_BUSY_RETRY_AFTER`
+    ServerBusy(String),
+    /// The caller exceeded a rate limit; retry after the given delay
+    RateLimited(String, Duration),
+    /// An external service the request needs failed, e.g. the embedding
+    /// provider
+    Upstream(String),
+    /// Anything else, e.g. the runtime went away
+    Internal(String),
+}
+
+/// Field-level detail of an `InvalidField` error
+#[derive(Debug, Clone)]
+pub(crate) struct FieldError {
+    /// Path of the argument, e.g. `product_ids[2]`
+    pub(crate) field: String,
+    /// What the schema allows, e.g. `integer` or `1..=500`
+    pub(crate) expected: String,
+    /// The value that was passed, null if the field is missing
+    pub(crate) received: Value,
+    message: String,
+}
+
+impl FieldError {
+    pub(crate) fn new(field: impl Into<String>, expected: impl Into<String>, received: &Value) -> Self {
+        let field = field.into();
+        let expected = expected.into();
+        let message = match received {
+            Value::Null => format!("Invalid {field}: expected {expected}, received nothing"),
+            received => format!("Invalid {field}: expected {expected}, received {received}"),
+        };
+        FieldError {
+            field,
+            expected,
+            received: received.clone(),
+            message,
+        }
+    }
+}
+
+impl PluginError {
+    pub(crate) fn invalid_argument(message: impl Into<String>) -> Self {
+        PluginError::InvalidArgument(message.into())
+    }
+
+    pub(crate) fn not_found(message: impl Into<String>) -> Self {
+        PluginError::NotFound(message.into())
+    }
+
+    pub(crate) fn internal(message: impl Into<String>) -> Self {
+        PluginError::Internal(message.into())
+    }
+
+    /// Stable error code for clients
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            PluginError::InvalidArgument(_) | PluginError::InvalidField(_) => "invalid_argument",
+            PluginError::NotFound(_) => "not_found",
+            PluginError::DbUnavailable(_) | PluginError::PoolExhausted(..) => "db_unavailable",
+            PluginError::Database(_) => "database_error",
+            PluginError::Timeout(_) => "timeout",
+            PluginError::QueryTimeout(_) => "query_timeout",
+            PluginError::InitFailed(_) => "initialization_failed",
+            PluginError::Unsupported(_) => "unsupported",
+            PluginError::PermissionDenied(_) => "permission_denied",
+            PluginError::Conflict(_) => "conflict",
+            PluginError::ServerBusy(_) => "server_busy",
+            PluginError::RateLimited(..) => "rate_limited",
+            PluginError::Upstream(_) => "upstream_error",
+            PluginError::Internal(_) => "internal",
+        }
+    }
+
+    /// Whether repeating the same request later may succeed
+    pub(crate) fn retryable(&self) -> bool {
+        matches!(
+            self,
+            PluginError::DbUnavailable(_)
+                | PluginError::PoolExhausted(..)
+                | PluginError::Timeout(_)
+                | PluginError::ServerBusy(_)
+                | PluginError::RateLimited(..)
+                | PluginError::Upstream(_)
+        )
+    }
+
+    /// Suggested delay before retrying, for errors that carry one
+    pub(crate) fn retry_after(&self) -> Option<Duration> {
+        match self {
+            PluginError::ServerBusy(_) => Some(SERVER_BUSY_RETRY_AFTER),
+            PluginError::RateLimited(_, retry_after) => Some(*retry_after),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn message(&self) -> &str {
+        match self {
+            PluginError::InvalidArgument(message)
+            | PluginError::NotFound(message)
+            | PluginError::DbUnavailable(message)
+            | PluginError::Database(message)
+            | PluginError::Timeout(message)
+            | PluginError::QueryTimeout(message)
+            | PluginError::InitFailed(message)
+            | PluginError::Unsupported(message)
+            | PluginError::PermissionDenied(message)
+            | PluginError::Conflict(message)
+            | PluginError::ServerBusy(message)
+            | PluginError::RateLimited(message, _)
+            | PluginError::PoolExhausted(message, _)
+            | PluginError::Upstream(message)
+            | PluginError::Internal(message) => message,
+            PluginError::InvalidField(field) => &field.message,
+        }
+    }
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code(), self.message())
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+/// Whether the database cancelled a statement for running too long:
+/// Postgres `query_canceled` (SQLSTATE 57014) caused by `statement_timeout`
+/// rather than an operator, or MySQL `ER_QUERY_TIMEOUT` (3024)
+fn is_statement_timeout(err: &sqlx::Error) -> bool {
+    let sqlx::Error::Database(err) = err else {
+        return false;
+    };
+    if err.code().as_deref() == Some("57014") && err.message().contains("statement timeout") {
+        return true;
+    }
+    #[cfg(feature = "mysql")]
+    if err
+        .try_downcast_ref::<sqlx::mysql::MySqlDatabaseError>()
+        .is_some_and(|err| err.number() == 3024)
+    {
+        return true;
+    }
+    false
+}
+
+impl From<sqlx::Error> for PluginError {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::PoolTimedOut => {
+                PluginError::PoolExhausted(format!("Database unavailable: {err}"), Value::Null)
+            }
+            sqlx::Error::PoolClosed
+            | sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_) => PluginError::DbUnavailable(format!("Database unavailable: {err}")),
+            err if is_statement_timeout(&err) => PluginError::QueryTimeout(
+                "Query cancelled after running past the statement timeout, narrow it down".to_string(),
+            ),
+            err => PluginError::Database(format!("Database error: {err}")),
+        }
+    }
+}
+
+/// Serialize into the error payload handed to the host
+impl From<PluginError> for String {
+    fn from(err: PluginError) -> Self {
+        let mut payload = json!({
+            "code": err.code(),
+            "message": err.message(),
+            "retryable": err.retryable()
+        });
+        if let Some(retry_after) = err.retry_after() {
+            payload["retry_after_ms"] = json!(retry_after.as_millis() as u64);
+        }
+        if let PluginError::PoolExhausted(_, pool) = &err {
+            if !pool.is_null() {
+                payload["pool"] = pool.clone();
+            }
+        }
+        if let PluginError::InvalidField(field) = &err {
+            payload["field"] = json!(field.field);
+            payload["expected"] = json!(field.expected);
+            payload["received"] = field.received.clone();
+        }
+        payload.to_string()
+    }
+}
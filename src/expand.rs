@@ -0,0 +1,91 @@
+//! Nested resources of product responses
+//!
+//! `get_product_price`, the SKU and barcode lookups and `get_products_bulk`
+//! take an `expand` list naming related resources to return with each
+//! product, so one call gives the complete picture:
+//!
+//! - `price_history`: the last price changes, most recent first
+//! - `tiers`: the volume pricing tiers, as `get_price_tiers` lists them
+//! - `inventory`: the stock per warehouse, as `get_product_availability`
+//!   reports it
+//!
+//! Every resource is loaded with one query for all products of the call,
+//! and the queries of different resources run concurrently. Missing
+//! history or tier tables give empty lists, a missing inventory table an
+//! untracked availability.
+
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::{history, inventory, tiers};
+use futures::future::OptionFuture;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Price changes returned per product with `price_history`
+const HISTORY_LIMIT: i64 = 20;
+
+/// A resource the `expand` argument can name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Expansion {
+    PriceHistory,
+    Tiers,
+    Inventory,
+}
+
+/// Expanded resources of one product
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub(crate) struct Expanded {
+    /// Last price changes, most recent first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) price_history: Option<Vec<Value>>,
+
+    /// Volume pricing tiers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) tiers: Option<Vec<Value>>,
+
+    /// Stock on hand per warehouse
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) inventory: Option<Value>,
+}
+
+/// Load the resources named in `expand` for every product, by product ID
+pub(crate) async fn expand(
+    db: &dyn DatabaseBackend,
+    product_ids: &[i32],
+    expand: &[Expansion],
+) -> Result<HashMap<i32, Expanded>, PluginError> {
+    let mut expanded: HashMap<i32, Expanded> = product_ids.iter().map(|id| (*id, Expanded::default())).collect();
+    if expand.is_empty() || product_ids.is_empty() {
+        return Ok(expanded);
+    }
+
+    let wants = |expansion| expand.contains(&expansion);
+    let history: OptionFuture<_> = wants(Expansion::PriceHistory)
+        .then(|| history::recent_prices(db, product_ids, HISTORY_LIMIT))
+        .into();
+    let tiers: OptionFuture<_> = wants(Expansion::Tiers).then(|| tiers::price_tiers_of(db, product_ids)).into();
+    let inventory: OptionFuture<_> = wants(Expansion::Inventory)
+        .then(|| inventory::availability_of(db, product_ids))
+        .into();
+    let (history, tiers, inventory) = futures::join!(history, tiers, inventory);
+
+    if let Some(mut history) = history.transpose()? {
+        for (id, product) in &mut expanded {
+            product.price_history = Some(history.remove(id).unwrap_or_default());
+        }
+    }
+    if let Some(mut tiers) = tiers.transpose()? {
+        for (id, product) in &mut expanded {
+            product.tiers = Some(tiers::tiers_json(&tiers.remove(id).unwrap_or_default()));
+        }
+    }
+    if let Some(mut inventory) = inventory.transpose()? {
+        for (id, product) in &mut expanded {
+            product.inventory = inventory.remove(id);
+        }
+    }
+    Ok(expanded)
+}
@@ -0,0 +1,395 @@
+//! Catalogue export
+//!
+//! `export_products` writes a snapshot of the catalogue, optionally narrowed
+//! down by category and name, as CSV (with a header row) or JSONL (one JSON
+//! object per line) with the selected columns, ordered by id. Rows are
+//! streamed from the database and either
+//!
+//! - written to a file in `export_directory`, named by the caller, for
+//!   hosts sharing a file system with the analyst, or
+//! - returned inline as text content blocks of `CHUNK_ROWS` rows each.
+//!
+//! Files are written under a temporary name and renamed once complete, and
+//! an existing file is never overwritten. The caller only names the file;
+//! the directory is taken from the configuration. Both ways stop after
+//! `max_rows` rows, capped by `export_max_rows`, and report whether the
+//! catalogue had more.
+
+use crate::access;
+use crate::args;
+use crate::auth;
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::mapping::{self, PRODUCT_COLUMNS};
+use crate::progress::Progress;
+use crate::search::like_pattern;
+use crate::{format_price, get_config, PluginConfig, Product};
+use futures::TryStreamExt;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sqlx::{Postgres, QueryBuilder};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+/// Rows returned inline when the caller does not pass `max_rows`
+const DEFAULT_INLINE_ROWS: i64 = 1000;
+
+/// Rows per text content block of an inline export
+const CHUNK_ROWS: usize = 500;
+
+/// Rows between two progress notifications
+const PROGRESS_INTERVAL: u64 = 1000;
+
+/// Validate the export settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if config.export_max_rows < 1 {
+        return Err("export_max_rows must be at least 1".to_string());
+    }
+    match &config.export_directory {
+        Some(directory) if !Path::new(directory).is_absolute() => {
+            Err("export_directory must be an absolute path".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+impl ExportFormat {
+    fn as_str(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum ExportColumn {
+    Id,
+    Name,
+    Price,
+    Description,
+    Category,
+}
+
+const ALL_COLUMNS: [ExportColumn; 5] = [
+    ExportColumn::Id,
+    ExportColumn::Name,
+    ExportColumn::Price,
+    ExportColumn::Description,
+    ExportColumn::Category,
+];
+
+impl ExportColumn {
+    fn as_str(self) -> &'static str {
+        match self {
+            ExportColumn::Id => "id",
+            ExportColumn::Name => "name",
+            ExportColumn::Price => "price",
+            ExportColumn::Description => "description",
+            ExportColumn::Category => "category",
+        }
+    }
+
+    fn value(self, product: &Product) -> Value {
+        match self {
+            ExportColumn::Id => json!(product.id),
+            ExportColumn::Name => json!(product.name),
+            ExportColumn::Price => json!(format_price(&product.price)),
+            ExportColumn::Description => json!(product.description),
+            ExportColumn::Category => json!(product.category),
+        }
+    }
+}
+
+/// Arguments of export_products
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ExportArgs {
+    #[serde(default)]
+    format: ExportFormat,
+    columns: Option<Vec<ExportColumn>>,
+    category: Option<String>,
+    query: Option<String>,
+    #[schemars(range(min = 1))]
+    max_rows: Option<i64>,
+    #[schemars(length(min = 1, max = 255))]
+    file: Option<String>,
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break
+fn csv_field(value: &Value) -> String {
+    let text = match value {
+        Value::Null => return String::new(),
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    };
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+/// One exported row including its line break
+fn format_row(format: ExportFormat, columns: &[ExportColumn], product: &Product) -> String {
+    match format {
+        ExportFormat::Csv => {
+            let fields: Vec<String> = columns.iter().map(|column| csv_field(&column.value(product))).collect();
+            format!("{}\n", fields.join(","))
+        }
+        ExportFormat::Jsonl => {
+            let row: Map<String, Value> = columns
+                .iter()
+                .map(|column| (column.as_str().to_string(), column.value(product)))
+                .collect();
+            format!("{}\n", Value::Object(row))
+        }
+    }
+}
+
+/// Path of a caller-named file inside a configured directory
+///
+/// Only a plain file name is accepted, so the path cannot leave the directory.
+pub(crate) fn file_in(directory: &str, file: &str) -> Result<PathBuf, PluginError> {
+    let plain = !file.starts_with('.')
+        && !file.contains(['/', '\\', '\0'])
+        && Path::new(file).file_name().is_some_and(|name| name == file);
+    if !plain {
+        return Err(PluginError::invalid_argument(format!(
+            "Invalid file '{file}': expected a plain file name"
+        )));
+    }
+    Ok(Path::new(directory).join(file))
+}
+
+/// Path of the export file inside `export_directory`
+fn export_path(file: &str) -> Result<PathBuf, PluginError> {
+    let directory = get_config().export_directory.clone().ok_or_else(|| {
+        PluginError::PermissionDenied("Exporting to a file requires export_directory".to_string())
+    })?;
+    file_in(&directory, file)
+}
+
+/// Destination of the exported rows
+enum Sink {
+    File {
+        path: PathBuf,
+        partial: PathBuf,
+        writer: tokio::io::BufWriter<tokio::fs::File>,
+        bytes: u64,
+    },
+    Inline {
+        chunks: Vec<String>,
+        rows_in_chunk: usize,
+    },
+}
+
+fn io_error(err: std::io::Error) -> PluginError {
+    PluginError::internal(format!("Cannot write the export file: {err}"))
+}
+
+impl Sink {
+    async fn file(path: PathBuf) -> Result<Self, PluginError> {
+        if tokio::fs::try_exists(&path).await.map_err(io_error)? {
+            return Err(PluginError::Conflict(format!(
+                "Export file {} already exists",
+                path.display()
+            )));
+        }
+        let mut partial = path.clone().into_os_string();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&partial)
+            .await
+            .map_err(|err| match err.kind() {
+                std::io::ErrorKind::AlreadyExists => {
+                    PluginError::Conflict(format!("An export to {} is in progress", path.display()))
+                }
+                _ => io_error(err),
+            })?;
+        Ok(Sink::File {
+            path,
+            partial,
+            writer: tokio::io::BufWriter::new(file),
+            bytes: 0,
+        })
+    }
+
+    async fn write(&mut self, line: String) -> Result<(), PluginError> {
+        match self {
+            Sink::File { writer, bytes, .. } => {
+                *bytes += line.len() as u64;
+                writer.write_all(line.as_bytes()).await.map_err(io_error)
+            }
+            Sink::Inline { chunks, rows_in_chunk } => {
+                match chunks.last_mut() {
+                    Some(chunk) if *rows_in_chunk < CHUNK_ROWS => chunk.push_str(&line),
+                    _ => {
+                        chunks.push(line);
+                        *rows_in_chunk = 0;
+                    }
+                }
+                *rows_in_chunk += 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Remove the partial file of a failed export
+    async fn discard(self) {
+        if let Sink::File { partial, .. } = self {
+            let _ = tokio::fs::remove_file(partial).await;
+        }
+    }
+}
+
+pub(crate) async fn handle_export_products(db: &dyn DatabaseBackend, args: &Value) -> Result<Value, PluginError> {
+    let pool = db.postgres()?;
+    let ExportArgs {
+        format,
+        columns,
+        category,
+        query,
+        max_rows,
+        file,
+    } = args::parse(args)?;
+
+    let columns = match columns {
+        Some(columns) if columns.is_empty() => {
+            return Err(PluginError::invalid_argument("columns must name at least one column"))
+        }
+        Some(columns) => {
+            let mut unique = Vec::with_capacity(columns.len());
+            for column in columns {
+                if !unique.contains(&column) {
+                    unique.push(column);
+                }
+            }
+            unique
+        }
+        None => ALL_COLUMNS.to_vec(),
+    };
+    // Redacted fields are left out of exports as of every other result
+    let auth = auth::parse(args)?;
+    let columns: Vec<ExportColumn> = columns
+        .into_iter()
+        .filter(|column| access::is_visible(column.as_str(), auth.as_ref()))
+        .collect();
+    if columns.is_empty() {
+        return Err(PluginError::PermissionDenied(
+            "Every requested column is redacted by access_policy".to_string(),
+        ));
+    }
+    let max_rows_cap = get_config().export_max_rows;
+    let default_rows = if file.is_some() { max_rows_cap } else { DEFAULT_INLINE_ROWS };
+    let max_rows = max_rows.unwrap_or(default_rows).min(max_rows_cap);
+
+    let mut sink = match &file {
+        Some(file) => Sink::file(export_path(file)?).await?,
+        None => Sink::Inline {
+            chunks: Vec::new(),
+            rows_in_chunk: 0,
+        },
+    };
+
+    let mut sql = QueryBuilder::<Postgres>::new(format!(
+        "SELECT {PRODUCT_COLUMNS} FROM {} WHERE TRUE",
+        mapping::products()
+    ));
+    if let Some(category) = category {
+        sql.push(" AND category = ").push_bind(category);
+    }
+    if let Some(query) = &query {
+        sql.push(" AND name ILIKE ")
+            .push_bind(like_pattern(query, false))
+            .push(" ESCAPE '\\'");
+    }
+    // One row beyond max_rows tells whether the export was cut off
+    sql.push(" ORDER BY id LIMIT ").push_bind(max_rows + 1);
+
+    let progress = Progress::from_args(args);
+    let exported = async {
+        if let ExportFormat::Csv = format {
+            let header: Vec<&str> = columns.iter().map(|column| column.as_str()).collect();
+            sink.write(format!("{}\n", header.join(","))).await?;
+        }
+        let mut rows = sql.build_query_as::<Product>().fetch(pool);
+        let mut count: i64 = 0;
+        let mut truncated = false;
+        while let Some(product) = rows.try_next().await? {
+            if count == max_rows {
+                truncated = true;
+                break;
+            }
+            sink.write(format_row(format, &columns, &product)).await?;
+            count += 1;
+            if (count as u64).is_multiple_of(PROGRESS_INTERVAL) {
+                progress.report(count as u64, Some(max_rows as u64), &format!("{count} rows exported"));
+            }
+        }
+        Ok::<_, PluginError>((count, truncated))
+    }
+    .await;
+    let (count, truncated) = match exported {
+        Ok(exported) => exported,
+        Err(err) => {
+            sink.discard().await;
+            return Err(err);
+        }
+    };
+
+    let column_names: Vec<&str> = columns.iter().map(|column| column.as_str()).collect();
+    let summary = json!({
+        "format": format.as_str(),
+        "columns": column_names,
+        "rows": count,
+        "truncated": truncated
+    });
+    match sink {
+        Sink::File {
+            path,
+            partial,
+            mut writer,
+            bytes,
+        } => {
+            let finished = async {
+                writer.flush().await?;
+                writer.into_inner().sync_all().await?;
+                // Unlike rename, linking fails if the file appeared meanwhile
+                tokio::fs::hard_link(&partial, &path).await
+            }
+            .await;
+            let _ = tokio::fs::remove_file(&partial).await;
+            if let Err(err) = finished {
+                return Err(match err.kind() {
+                    std::io::ErrorKind::AlreadyExists => {
+                        PluginError::Conflict(format!("Export file {} already exists", path.display()))
+                    }
+                    _ => io_error(err),
+                });
+            }
+            tracing::info!(file = %path.display(), rows = count, "Catalogue exported");
+            let mut summary = summary;
+            summary["file"] = json!(path.display().to_string());
+            summary["bytes"] = json!(bytes);
+            Ok(mcp_plugin_api::utils::json_content(summary))
+        }
+        Sink::Inline { chunks, .. } => {
+            let mut content = vec![json!({"type": "json", "json": summary})];
+            content.extend(chunks.into_iter().map(|chunk| json!({"type": "text", "text": chunk})));
+            Ok(json!({ "content": content }))
+        }
+    }
+}
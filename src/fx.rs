@@ -0,0 +1,287 @@
+//! Exchange rates from an external provider
+//!
+//! With `fx_provider` set, a background task in the runtime fetches the
+//! rates from the base currency every `fx_refresh_seconds` and keeps them in
+//! memory for `currency::exchange_rate`, which prefers them to the
+//! `currency_rates_table` and lets the static `currency_rates` override
+//! them. Providers:
+//!
+//! - `ecb`: the daily reference rates of the European Central Bank
+//!   (`fx_provider_url` defaults to the public eurofxref XML feed), cross
+//!   rates computed for bases other than EUR
+//! - `json`: an exchangerate.host style API answering `{"rates": {"USD":
+//!   1.08, ...}}`; `{base}` in `fx_provider_url` is replaced by the base
+//!   currency and `${NAME}` by environment variables, e.g. for an API key
+//!
+//! A failed refresh keeps the last rates that were fetched and is retried
+//! with exponential backoff, bounded by the refresh interval. Rates are
+//! dropped only when a reconfiguration changes the provider or the base
+//! currency.
+
+use crate::{get_config, secrets, PluginConfig};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use once_cell::sync::Lazy;
+use reqwest::Url;
+use rust_decimal::Decimal;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Daily reference rates of the European Central Bank
+const ECB_DAILY_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-daily.xml";
+
+/// Maximum time a provider request may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Delay before the first retry of a failed refresh, doubled after every failure
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Decimal places kept of computed cross rates
+const CROSS_RATE_DECIMAL_PLACES: u32 = 10;
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+/// Rates of the last successful refresh
+struct Rates {
+    /// Provider and base currency the rates were fetched for
+    source: (String, String),
+    rates: HashMap<String, Decimal>,
+    fetched_at: Instant,
+}
+
+static RATES: Lazy<RwLock<Option<Rates>>> = Lazy::new(|| RwLock::new(None));
+
+static RECONFIGURED: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Source of exchange rates
+trait RateProvider: Send + Sync {
+    /// Amounts of every known currency worth one unit of `base`
+    fn fetch<'a>(&'a self, base: &'a str) -> BoxFuture<'a, Result<HashMap<String, Decimal>, String>>;
+}
+
+/// Provider selected by the `fx_provider` config field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProviderKind {
+    None,
+    Ecb,
+    Json,
+}
+
+impl ProviderKind {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "none" => Ok(ProviderKind::None),
+            "ecb" => Ok(ProviderKind::Ecb),
+            "json" => Ok(ProviderKind::Json),
+            other => Err(format!("Invalid fx_provider '{other}', expected one of: none, ecb, json")),
+        }
+    }
+}
+
+/// Validate the provider settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    let kind = ProviderKind::parse(&config.fx_provider)?;
+    match &config.fx_provider_url {
+        None if kind == ProviderKind::Json => Err("fx_provider json requires fx_provider_url".to_string()),
+        Some(_) if kind == ProviderKind::None => Err("fx_provider_url requires an fx_provider".to_string()),
+        Some(url) => {
+            // Leave out the URL, which may embed an API key
+            let url = secrets::interpolate_env(url, "fx_provider_url")?;
+            let parsed = Url::parse(&url).map_err(|err| format!("Invalid fx_provider_url: {err}"))?;
+            if !matches!(parsed.scheme(), "http" | "https") {
+                return Err("Invalid fx_provider_url: expected an http or https URL".to_string());
+            }
+            Ok(())
+        }
+        None => Ok(()),
+    }?;
+    if config.fx_refresh_seconds < 60 {
+        return Err("fx_refresh_seconds must be at least 60".to_string());
+    }
+    Ok(())
+}
+
+/// Rate from `base` into `currency` from the last refresh
+pub(crate) fn rate(base: &str, currency: &str) -> Option<Decimal> {
+    RATES
+        .read()
+        .unwrap()
+        .as_ref()
+        .filter(|rates| rates.source.1.eq_ignore_ascii_case(base))
+        .and_then(|rates| rates.rates.get(&currency.to_ascii_uppercase()).copied())
+}
+
+/// Parse a rate given as JSON number or decimal string
+fn parse_rate(value: &Value) -> Option<Decimal> {
+    let text = match value {
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => text.clone(),
+        _ => return None,
+    };
+    Decimal::from_str(&text)
+        .or_else(|_| Decimal::from_scientific(&text))
+        .ok()
+        .filter(|rate| *rate > Decimal::ZERO)
+}
+
+async fn get_text(url: &str) -> Result<String, String> {
+    let response = CLIENT
+        .get(url)
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| err.without_url().to_string())?;
+    response.text().await.map_err(|err| err.without_url().to_string())
+}
+
+struct EcbProvider {
+    url: String,
+}
+
+impl EcbProvider {
+    /// Rates from EUR in the eurofxref XML: `<Cube currency='USD' rate='1.0876'/>`
+    fn parse(xml: &str) -> Result<HashMap<String, Decimal>, String> {
+        let mut rates = HashMap::from([("EUR".to_string(), Decimal::ONE)]);
+        for entry in xml.split("currency='").skip(1) {
+            let (currency, rest) = entry.split_once('\'').ok_or("Malformed ECB rate entry")?;
+            let rate = rest
+                .split_once("rate='")
+                .and_then(|(_, rest)| rest.split_once('\''))
+                .and_then(|(rate, _)| parse_rate(&Value::from(rate)))
+                .ok_or_else(|| format!("Malformed ECB rate for {currency}"))?;
+            rates.insert(currency.to_string(), rate);
+        }
+        if rates.len() == 1 {
+            return Err("No rates in the ECB response".to_string());
+        }
+        Ok(rates)
+    }
+}
+
+impl RateProvider for EcbProvider {
+    fn fetch<'a>(&'a self, base: &'a str) -> BoxFuture<'a, Result<HashMap<String, Decimal>, String>> {
+        async move {
+            let from_eur = Self::parse(&get_text(&self.url).await?)?;
+            let base_rate = *from_eur
+                .get(base)
+                .ok_or_else(|| format!("The ECB publishes no rate for the base currency {base}"))?;
+            Ok(from_eur
+                .into_iter()
+                .map(|(currency, rate)| (currency, (rate / base_rate).round_dp(CROSS_RATE_DECIMAL_PLACES)))
+                .collect())
+        }
+        .boxed()
+    }
+}
+
+struct JsonProvider {
+    url: String,
+}
+
+impl RateProvider for JsonProvider {
+    fn fetch<'a>(&'a self, base: &'a str) -> BoxFuture<'a, Result<HashMap<String, Decimal>, String>> {
+        async move {
+            let body = get_text(&self.url.replace("{base}", base)).await?;
+            let response: Value = serde_json::from_str(&body).map_err(|err| format!("Invalid JSON: {err}"))?;
+            // Some APIs ignore the requested base on free plans
+            if let Some(answered) = response["base"].as_str().filter(|answered| !answered.eq_ignore_ascii_case(base)) {
+                return Err(format!("Provider answered with rates from {answered}, not {base}"));
+            }
+            let rates = response["rates"].as_object().ok_or("Response has no rates object")?;
+            rates
+                .iter()
+                .map(|(currency, rate)| {
+                    parse_rate(rate)
+                        .map(|rate| (currency.to_ascii_uppercase(), rate))
+                        .ok_or_else(|| format!("Invalid rate for {currency}"))
+                })
+                .collect()
+        }
+        .boxed()
+    }
+}
+
+fn provider(config: &PluginConfig) -> Option<Box<dyn RateProvider>> {
+    // Interpolation succeeded when the configuration was validated
+    let url = config
+        .fx_provider_url
+        .as_deref()
+        .and_then(|url| secrets::interpolate_env(url, "fx_provider_url").ok());
+    match ProviderKind::parse(&config.fx_provider).ok()? {
+        ProviderKind::None => None,
+        ProviderKind::Ecb => Some(Box::new(EcbProvider {
+            url: url.unwrap_or_else(|| ECB_DAILY_URL.to_string()),
+        })),
+        ProviderKind::Json => Some(Box::new(JsonProvider { url: url? })),
+    }
+}
+
+/// Spawn the refresh task on the current runtime
+pub(crate) fn start() -> JoinHandle<()> {
+    tokio::spawn(run())
+}
+
+/// Refresh with the current configuration, called once it is applied
+pub(crate) fn reconfigured() {
+    RECONFIGURED.notify_one();
+}
+
+async fn run() {
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        let config = get_config();
+        let Some(provider) = provider(&config) else {
+            *RATES.write().unwrap() = None;
+            RECONFIGURED.notified().await;
+            continue;
+        };
+        let source = (config.fx_provider.clone(), config.base_currency.to_ascii_uppercase());
+        let refresh = Duration::from_secs(config.fx_refresh_seconds);
+
+        // Rates for another provider or base currency are of no use any more
+        {
+            let mut rates = RATES.write().unwrap();
+            if rates.as_ref().is_some_and(|rates| rates.source != source) {
+                *rates = None;
+            }
+        }
+
+        let wait = match provider.fetch(&source.1).await {
+            Ok(fetched) => {
+                tracing::info!(provider = source.0, currencies = fetched.len(), "Exchange rates refreshed");
+                *RATES.write().unwrap() = Some(Rates {
+                    source,
+                    rates: fetched,
+                    fetched_at: Instant::now(),
+                });
+                backoff = INITIAL_BACKOFF;
+                refresh
+            }
+            Err(err) => {
+                let age = RATES.read().unwrap().as_ref().map(|rates| rates.fetched_at.elapsed());
+                match age {
+                    Some(age) => tracing::warn!(
+                        "Exchange rate refresh from {} failed: {err}, keeping rates from {}s ago",
+                        source.0,
+                        age.as_secs()
+                    ),
+                    None => tracing::warn!("Exchange rate refresh from {} failed: {err}", source.0),
+                }
+                let wait = backoff.min(refresh);
+                backoff = (backoff * 2).min(refresh);
+                wait
+            }
+        };
+
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = RECONFIGURED.notified() => backoff = INITIAL_BACKOFF,
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Health check
+//!
+//! Probes the database with a trivial query and combines the result with pool
+//! utilization into an overall status:
+//!
+//! - `healthy`: the probe succeeded quickly and the pool has capacity left
+//! - `degraded`: the probe succeeded, but slowly or with the pool exhausted
+//! - `unhealthy`: the probe failed or timed out
+
+use crate::backend::{postgres, DatabaseBackend};
+use crate::error::PluginError;
+use crate::get_config;
+use mcp_plugin_api::utils;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+/// Maximum time the connectivity probe may take
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Probe latency above which the database is reported as degraded
+const DEGRADED_LATENCY: Duration = Duration::from_millis(500);
+
+/// Connection target from the configured URL, without the password
+fn database_target(db: &dyn DatabaseBackend) -> Value {
+    if db.postgres().is_err() {
+        return json!({ "backend": db.name() });
+    }
+    match postgres::connect_options(&get_config()) {
+        Ok(options) => {
+            let database = options.get_database().unwrap_or_default();
+            json!({
+                "backend": db.name(),
+                "host": options.get_host(),
+                "port": options.get_port(),
+                "database": database,
+                "url": format!(
+                    "postgresql://{}:***@{}:{}/{database}",
+                    options.get_username(),
+                    options.get_host(),
+                    options.get_port()
+                )
+            })
+        }
+        Err(err) => json!({ "error": err }),
+    }
+}
+
+pub(crate) async fn handle_health_check(db: &dyn DatabaseBackend, _args: &Value) -> Result<Value, PluginError> {
+    let started = Instant::now();
+    let probe = tokio::time::timeout(PROBE_TIMEOUT, db.ping()).await;
+    let latency = started.elapsed();
+
+    let pool = db.pool_stats();
+    let pool_exhausted = pool["idle"] == 0 && pool["size"] == pool["max_connections"];
+
+    let (status, probe) = match probe {
+        Ok(Ok(_)) => {
+            let status = if latency > DEGRADED_LATENCY || pool_exhausted {
+                "degraded"
+            } else {
+                "healthy"
+            };
+            (status, json!({ "ok": true, "latency_ms": latency.as_millis() as u64 }))
+        }
+        Ok(Err(err)) => (
+            "unhealthy",
+            json!({ "ok": false, "error": err.message() }),
+        ),
+        Err(_) => (
+            "unhealthy",
+            json!({ "ok": false, "error": format!("Probe timed out after {PROBE_TIMEOUT:?}") }),
+        ),
+    };
+
+    // Return structured JSON data for programmatic clients
+    Ok(utils::json_content(json!({
+        "status": status,
+        "database": database_target(db),
+        "probe": probe,
+        "pool": pool
+    })))
+}
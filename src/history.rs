@@ -0,0 +1,364 @@
+//! Price history
+//!
+//! Reads the `price_history` table (name configurable through
+//! `price_history_table`):
+//!
+//! ```sql
+//! CREATE TABLE price_history (
+//!     product_id   INTEGER NOT NULL REFERENCES products(id),
+//!     price        NUMERIC NOT NULL,
+//!     effective_at TIMESTAMPTZ NOT NULL
+//! );
+//! ```
+//!
+//! Every row records the price a product had from `effective_at` on. The
+//! series can be returned raw or downsampled per day or week.
+//!
+//! `get_recent_price_changes` reads the same table the other way round:
+//! every product with a row in the last hours or days, with the price it had
+//! before the period, its current catalogue price and the number of changes.
+
+use crate::args;
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::mapping;
+use crate::sql::{is_undefined_table, quote_identifier};
+use crate::{format_price, get_config, parse_timestamp, PluginConfig};
+use chrono::{DateTime, Duration, Utc};
+use mcp_plugin_api::utils;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Period covered when the caller does not pass `from`
+const DEFAULT_HISTORY_DAYS: i64 = 90;
+
+/// Period of get_recent_price_changes without `hours` or `days`
+const DEFAULT_RECENT_HOURS: i64 = 24;
+
+/// Longest period get_recent_price_changes looks back, in hours
+const MAX_RECENT_HOURS: i64 = 366 * 24;
+
+/// Page size of get_recent_price_changes without `limit`
+const DEFAULT_RECENT_LIMIT: i64 = 100;
+
+/// Upper bound of the `limit` argument of get_recent_price_changes
+const MAX_RECENT_LIMIT: i64 = 500;
+
+/// Aggregation applied to the raw price changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interval {
+    Raw,
+    Day,
+    Week,
+}
+
+impl Interval {
+    fn parse(value: &str) -> Result<Self, PluginError> {
+        match value {
+            "raw" => Ok(Interval::Raw),
+            "day" | "daily" => Ok(Interval::Day),
+            "week" | "weekly" => Ok(Interval::Week),
+            other => Err(PluginError::invalid_argument(format!(
+                "Invalid interval '{other}', expected one of: raw, day, week"
+            ))),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Interval::Raw => "raw",
+            Interval::Day => "day",
+            Interval::Week => "week",
+        }
+    }
+}
+
+/// Arguments of get_price_history
+#[derive(Debug, Deserialize, JsonSchema)]
+struct HistoryArgs {
+    product_id: i32,
+    from: Option<String>,
+    to: Option<String>,
+    interval: Option<String>,
+}
+
+/// Arguments of get_recent_price_changes
+#[derive(Debug, Deserialize, JsonSchema)]
+struct RecentChangesArgs {
+    hours: Option<i64>,
+    days: Option<i64>,
+    category: Option<String>,
+    #[schemars(range(min = 1, max = "MAX_RECENT_LIMIT"))]
+    limit: Option<i64>,
+    #[serde(default)]
+    #[schemars(range(min = 0))]
+    offset: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct PricePoint {
+    price: Decimal,
+    effective_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ProductPricePoint {
+    product_id: i32,
+    #[sqlx(flatten)]
+    point: PricePoint,
+}
+
+#[derive(sqlx::FromRow)]
+struct PriceBucket {
+    period_start: DateTime<Utc>,
+    min_price: Decimal,
+    max_price: Decimal,
+    avg_price: Decimal,
+    last_price: Decimal,
+    changes: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct PriceChange {
+    id: i32,
+    name: String,
+    category: Option<String>,
+    previous_price: Option<Decimal>,
+    current_price: Decimal,
+    changes: i64,
+    last_changed_at: DateTime<Utc>,
+    total: i64,
+}
+
+/// Validate the price history settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    quote_identifier(&config.price_history_table).map(|_| ())
+}
+
+pub(crate) async fn handle_get_price_history(db: &dyn DatabaseBackend, args: &Value) -> Result<Value, PluginError> {
+    let pool = db.postgres()?;
+
+    let HistoryArgs {
+        product_id,
+        from,
+        to,
+        interval,
+    } = args::parse(args)?;
+
+    let to = to.map(|to| parse_timestamp(&to, "to")).transpose()?.unwrap_or_else(Utc::now);
+    let from = from
+        .map(|from| parse_timestamp(&from, "from"))
+        .transpose()?
+        .unwrap_or_else(|| to - Duration::days(DEFAULT_HISTORY_DAYS));
+    if from > to {
+        return Err(PluginError::invalid_argument(format!(
+            "from ({from}) must not be after to ({to})"
+        )));
+    }
+
+    let interval = interval.as_deref().map_or(Ok(Interval::Raw), Interval::parse)?;
+
+    let table = quote_identifier(&get_config().price_history_table).map_err(PluginError::internal)?;
+
+    let points: Vec<Value> = match interval {
+        Interval::Raw => sqlx::query_as::<_, PricePoint>(&format!(
+            "SELECT price, effective_at FROM {table} \
+             WHERE product_id = $1 AND effective_at >= $2 AND effective_at <= $3 \
+             ORDER BY effective_at"
+        ))
+        .bind(product_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(|point| {
+            json!({
+                "effective_at": point.effective_at.to_rfc3339(),
+                "price": format_price(&point.price)
+            })
+        })
+        .collect(),
+        Interval::Day | Interval::Week => sqlx::query_as::<_, PriceBucket>(&format!(
+            "SELECT date_trunc($4, effective_at) AS period_start, \
+                    min(price) AS min_price, max(price) AS max_price, avg(price) AS avg_price, \
+                    (array_agg(price ORDER BY effective_at DESC))[1] AS last_price, \
+                    count(*) AS changes \
+             FROM {table} \
+             WHERE product_id = $1 AND effective_at >= $2 AND effective_at <= $3 \
+             GROUP BY period_start ORDER BY period_start"
+        ))
+        .bind(product_id)
+        .bind(from)
+        .bind(to)
+        .bind(interval.as_str())
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(|bucket| {
+            json!({
+                "period_start": bucket.period_start.to_rfc3339(),
+                "min_price": format_price(&bucket.min_price),
+                "max_price": format_price(&bucket.max_price),
+                "avg_price": format_price(&bucket.avg_price),
+                "last_price": format_price(&bucket.last_price),
+                "changes": bucket.changes
+            })
+        })
+        .collect(),
+    };
+
+    // Return structured JSON data for programmatic clients
+    Ok(utils::json_content(json!({
+        "product_id": product_id,
+        "from": from.to_rfc3339(),
+        "to": to.to_rfc3339(),
+        "interval": interval.as_str(),
+        "points": points,
+        "count": points.len()
+    })))
+}
+
+/// The last `limit` price changes of several products in one query, most
+/// recent first; products without history are left out, as are all without
+/// a history table
+pub(crate) async fn recent_prices(
+    db: &dyn DatabaseBackend,
+    product_ids: &[i32],
+    limit: i64,
+) -> Result<HashMap<i32, Vec<Value>>, PluginError> {
+    let table = quote_identifier(&get_config().price_history_table).map_err(PluginError::internal)?;
+    let result = sqlx::query_as::<_, ProductPricePoint>(&format!(
+        "SELECT product_id, price, effective_at FROM ( \
+             SELECT product_id, price, effective_at, \
+                    row_number() OVER (PARTITION BY product_id ORDER BY effective_at DESC) AS n \
+             FROM {table} WHERE product_id = ANY($1) AND effective_at <= now() \
+         ) AS history \
+         WHERE n <= $2 ORDER BY product_id, effective_at DESC"
+    ))
+    .bind(product_ids)
+    .bind(limit)
+    .fetch_all(db.postgres()?)
+    .await;
+
+    let rows = match result {
+        Ok(rows) => rows,
+        Err(err) if is_undefined_table(&err) => Vec::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let mut history: HashMap<i32, Vec<Value>> = HashMap::new();
+    for row in rows {
+        history.entry(row.product_id).or_default().push(json!({
+            "effective_at": row.point.effective_at.to_rfc3339(),
+            "price": format_price(&row.point.price)
+        }));
+    }
+    Ok(history)
+}
+
+/// Look-back period in hours from the `hours` or `days` argument
+fn recent_hours(hours: Option<i64>, days: Option<i64>) -> Result<i64, PluginError> {
+    let invalid = |name: &str| {
+        PluginError::invalid_argument(format!(
+            "Invalid {name} parameter, expected a period of at most {} days",
+            MAX_RECENT_HOURS / 24
+        ))
+    };
+    let hours = match (hours, days) {
+        (None, None) => DEFAULT_RECENT_HOURS,
+        (Some(hours), None) => hours,
+        (None, Some(days)) => days.checked_mul(24).ok_or_else(|| invalid("days"))?,
+        (Some(_), Some(_)) => return Err(PluginError::invalid_argument("Pass either hours or days, not both")),
+    };
+    if !(1..=MAX_RECENT_HOURS).contains(&hours) {
+        return Err(invalid(if days.is_none() { "hours" } else { "days" }));
+    }
+    Ok(hours)
+}
+
+pub(crate) async fn handle_get_recent_price_changes(
+    db: &dyn DatabaseBackend,
+    args: &Value,
+) -> Result<Value, PluginError> {
+    let pool = db.postgres()?;
+
+    let RecentChangesArgs {
+        hours,
+        days,
+        category,
+        limit,
+        offset,
+    } = args::parse(args)?;
+    let hours = recent_hours(hours, days)?;
+    let limit = limit.unwrap_or(DEFAULT_RECENT_LIMIT);
+
+    let table = quote_identifier(&get_config().price_history_table).map_err(PluginError::internal)?;
+    let to = Utc::now();
+    let since = to - Duration::hours(hours);
+
+    // Most recently changed first; the window count gives the total for paging
+    let changes = sqlx::query_as::<_, PriceChange>(&format!(
+        "WITH changed AS ( \
+             SELECT product_id, count(*) AS changes, max(effective_at) AS last_changed_at \
+             FROM {table} WHERE effective_at >= $1 AND effective_at <= $2 \
+             GROUP BY product_id \
+         ) \
+         SELECT products.id, products.name, products.category, \
+                (SELECT before.price FROM {table} AS before \
+                 WHERE before.product_id = changed.product_id AND before.effective_at < $1 \
+                 ORDER BY before.effective_at DESC LIMIT 1) AS previous_price, \
+                products.price AS current_price, changed.changes, changed.last_changed_at, \
+                count(*) OVER () AS total \
+         FROM changed JOIN {} ON products.id = changed.product_id \
+         WHERE $3::text IS NULL OR products.category = $3 \
+         ORDER BY changed.last_changed_at DESC, products.id \
+         LIMIT $4 OFFSET $5",
+        mapping::products()
+    ))
+    .bind(since)
+    .bind(to)
+    .bind(category)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    let total = changes.first().map_or(0, |change| change.total);
+    let products: Vec<Value> = changes
+        .iter()
+        .map(|change| {
+            let change_percent = change
+                .previous_price
+                .filter(|previous| !previous.is_zero())
+                .map(|previous| {
+                    ((change.current_price - previous) / previous * Decimal::ONE_HUNDRED)
+                        .round_dp(2)
+                        .to_string()
+                });
+            json!({
+                "id": change.id,
+                "name": change.name,
+                "category": change.category,
+                "previous_price": change.previous_price.as_ref().map(format_price),
+                "current_price": format_price(&change.current_price),
+                "change_percent": change_percent,
+                "changes": change.changes,
+                "last_changed_at": change.last_changed_at.to_rfc3339()
+            })
+        })
+        .collect();
+
+    // Return structured JSON data for programmatic clients
+    Ok(utils::json_content(json!({
+        "from": since.to_rfc3339(),
+        "to": to.to_rfc3339(),
+        "products": products,
+        "count": products.len(),
+        "total": total,
+        "offset": offset,
+        "base_currency": get_config().base_currency
+    })))
+}
@@ -0,0 +1,147 @@
+//! Idempotent writes
+//!
+//! The write tools accept an `idempotency_key`. The first call with a key
+//! stores its result in the idempotency table (name configurable through
+//! `idempotency_table`) within the transaction of the write itself:
+//!
+//! ```sql
+//! CREATE TABLE idempotency_keys (
+//!     tool       TEXT NOT NULL,
+//!     key        TEXT NOT NULL,
+//!     arguments  JSONB NOT NULL,
+//!     response   JSONB,
+//!     created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+//!     PRIMARY KEY (tool, key)
+//! );
+//! ```
+//!
+//! A retry with the same key and arguments returns that result, marked
+//! `"replayed": true`, instead of applying the change again; a retry
+//! arriving while the first call still runs waits for it. Reusing a key
+//! with other arguments fails with `conflict`. Keys expire after
+//! `idempotency_ttl_hours`, after which their rows may also be deleted.
+
+use crate::error::PluginError;
+use crate::sql::{is_undefined_table, quote_identifier};
+use crate::{get_config, PluginConfig};
+use serde_json::{json, Value};
+use sqlx::types::Json;
+use sqlx::PgConnection;
+use std::collections::BTreeMap;
+
+/// Upper bound of the key length
+pub(crate) const MAX_KEY_LENGTH: u32 = 255;
+
+/// Validate the idempotency settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if config.idempotency_ttl_hours < 1 {
+        return Err("idempotency_ttl_hours must be at least 1".to_string());
+    }
+    quote_identifier(&config.idempotency_table).map(|_| ())
+}
+
+/// The arguments a key is bound to, without the key and host metadata,
+/// borrowed as they can be large
+pub(crate) fn bound_arguments(args: &Value) -> BTreeMap<&str, &Value> {
+    args.as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(name, _)| !matches!(name.as_str(), "idempotency_key" | "_meta"))
+        .map(|(name, value)| (name.as_str(), value))
+        .collect()
+}
+
+/// Whether stored arguments are the ones a key is bound to
+fn same_arguments(stored: &Value, arguments: &BTreeMap<&str, &Value>) -> bool {
+    stored.as_object().is_some_and(|stored| {
+        stored.len() == arguments.len()
+            && arguments.iter().all(|(name, value)| stored.get(*name) == Some(*value))
+    })
+}
+
+/// Claim `key` for a call to `tool` within the write transaction
+///
+/// Returns the stored result if the key was used before, marked as
+/// replayed; the caller then rolls back. A concurrent call with the same
+/// key blocks here until the first one commits or rolls back.
+pub(crate) async fn claim(
+    conn: &mut PgConnection,
+    tool: &str,
+    key: &str,
+    args: &Value,
+) -> Result<Option<Value>, PluginError> {
+    let config = get_config();
+    let table = quote_identifier(&config.idempotency_table).map_err(PluginError::internal)?;
+    let arguments = bound_arguments(args);
+    let missing_table = |err: sqlx::Error| {
+        if is_undefined_table(&err) {
+            PluginError::Unsupported(format!(
+                "idempotency_key needs the {} table, see the idempotency module",
+                config.idempotency_table
+            ))
+        } else {
+            err.into()
+        }
+    };
+
+    // An expired key is free again
+    sqlx::query(&format!(
+        "DELETE FROM {table} WHERE tool = $1 AND key = $2 AND created_at < now() - make_interval(hours => $3)"
+    ))
+    .bind(tool)
+    .bind(key)
+    .bind(config.idempotency_ttl_hours as i32)
+    .execute(&mut *conn)
+    .await
+    .map_err(missing_table)?;
+
+    let claimed = sqlx::query(&format!(
+        "INSERT INTO {table} (tool, key, arguments) VALUES ($1, $2, $3) ON CONFLICT (tool, key) DO NOTHING"
+    ))
+    .bind(tool)
+    .bind(key)
+    .bind(Json(&arguments))
+    .execute(&mut *conn)
+    .await
+    .map_err(missing_table)?
+    .rows_affected()
+        == 1;
+    if claimed {
+        return Ok(None);
+    }
+
+    let (stored_arguments, response) = sqlx::query_as::<_, (Value, Option<Value>)>(&format!(
+        "SELECT arguments, response FROM {table} WHERE tool = $1 AND key = $2"
+    ))
+    .bind(tool)
+    .bind(key)
+    .fetch_one(&mut *conn)
+    .await?;
+    if !same_arguments(&stored_arguments, &arguments) {
+        return Err(PluginError::Conflict(format!(
+            "idempotency_key {key} was already used for {tool} with different arguments"
+        )));
+    }
+    let mut response = response.ok_or_else(|| {
+        PluginError::internal(format!("No result was stored for idempotency_key {key}"))
+    })?;
+    response["replayed"] = json!(true);
+    Ok(Some(response))
+}
+
+/// Store the result of the call that claimed `key`, before its commit
+pub(crate) async fn complete(
+    conn: &mut PgConnection,
+    tool: &str,
+    key: &str,
+    response: &Value,
+) -> Result<(), PluginError> {
+    let table = quote_identifier(&get_config().idempotency_table).map_err(PluginError::internal)?;
+    sqlx::query(&format!("UPDATE {table} SET response = $3 WHERE tool = $1 AND key = $2"))
+        .bind(tool)
+        .bind(key)
+        .bind(response)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,179 @@
+//! Product images
+//!
+//! `schema_mapping.image_url_column` and `thumbnail_url_column` map the
+//! product images, returned as `image_url` and `thumbnail_url` with every
+//! product. Clients rendering product cards can fetch the image itself as
+//! the binary resource `product-image://{id}` (or
+//! `product-image://{id}/thumbnail`), whose contents carry the bytes in
+//! `blob`, base64 encoded, with the image's MIME type.
+//!
+//! An image given as `http://` or `https://` URL is downloaded from there.
+//! Any other value is an object key under `image_store_path`, the directory
+//! where the object store holding the images is mounted, e.g.
+//! `products/42/front.jpg`; keys cannot leave the directory. Images larger
+//! than `image_max_bytes` are refused. A product without a thumbnail
+//! serves its image instead.
+
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::{get_config, PluginConfig};
+use base64::Engine;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+/// URI scheme of image resources
+pub(crate) const IMAGE_SCHEME: &str = "product-image://";
+
+/// Path suffix of thumbnail resources
+const THUMBNAIL_SUFFIX: &str = "/thumbnail";
+
+/// Maximum time an image download may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// MIME type when neither the server nor the file extension tells
+const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+/// Validate the image settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    match &config.image_store_path {
+        Some(path) if !Path::new(path).is_absolute() => Err("image_store_path must be an absolute path".to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// Resource template of product images
+pub(crate) fn resource_template() -> Value {
+    json!({
+        "uriTemplate": format!("{IMAGE_SCHEME}{{id}}"),
+        "name": "Product image",
+        "description": "Image of a product by product ID, append /thumbnail for its thumbnail"
+    })
+}
+
+/// Product ID and whether the thumbnail is asked for
+fn parse_image_uri(uri: &str) -> Result<(i32, bool), PluginError> {
+    let path = uri.strip_prefix(IMAGE_SCHEME);
+    let (id, thumbnail) = match path.and_then(|path| path.strip_suffix(THUMBNAIL_SUFFIX)) {
+        Some(id) => (Some(id), true),
+        None => (path, false),
+    };
+    id.and_then(|id| id.parse::<i32>().ok())
+        .map(|id| (id, thumbnail))
+        .ok_or_else(|| {
+            PluginError::invalid_argument(format!(
+                "Invalid resource URI '{uri}', expected {IMAGE_SCHEME}{{id}} or {IMAGE_SCHEME}{{id}}{THUMBNAIL_SUFFIX}"
+            ))
+        })
+}
+
+pub(crate) async fn handle_read_image(db: &dyn DatabaseBackend, uri: &str) -> Result<Value, PluginError> {
+    let (product_id, thumbnail) = parse_image_uri(uri)?;
+    let product = db
+        .fetch_product(product_id)
+        .await?
+        .ok_or_else(|| PluginError::not_found(format!("Product {product_id} not found")))?;
+    let location = match thumbnail {
+        true => product.thumbnail_url.or(product.image_url),
+        false => product.image_url,
+    }
+    .ok_or_else(|| PluginError::not_found(format!("Product {product_id} has no image")))?;
+
+    let (bytes, mime_type) = match location.starts_with("http://") || location.starts_with("https://") {
+        true => download(&location).await?,
+        false => read_from_store(&location).await?,
+    };
+    Ok(json!({
+        "contents": [{
+            "uri": uri,
+            "mimeType": mime_type,
+            "blob": base64::engine::general_purpose::STANDARD.encode(bytes)
+        }]
+    }))
+}
+
+/// Fail once an image grew past `image_max_bytes`
+fn check_size(size: u64) -> Result<(), PluginError> {
+    let max_bytes = get_config().image_max_bytes;
+    if size > max_bytes {
+        return Err(PluginError::invalid_argument(format!(
+            "Image is larger than image_max_bytes ({max_bytes} bytes)"
+        )));
+    }
+    Ok(())
+}
+
+async fn download(url: &str) -> Result<(Vec<u8>, String), PluginError> {
+    let upstream = |err: reqwest::Error| PluginError::Upstream(format!("Image download failed: {}", err.without_url()));
+    let mut response = CLIENT
+        .get(url)
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(upstream)?;
+    if let Some(length) = response.content_length() {
+        check_size(length)?;
+    }
+    let mime_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| mime_type_of(url).to_string());
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(upstream)? {
+        bytes.extend_from_slice(&chunk);
+        check_size(bytes.len() as u64)?;
+    }
+    Ok((bytes, mime_type))
+}
+
+/// Path of an object key under `image_store_path`
+fn store_path(key: &str) -> Result<PathBuf, PluginError> {
+    let directory = get_config().image_store_path.clone().ok_or_else(|| {
+        PluginError::Unsupported(format!("Image '{key}' is not a URL and image_store_path is not set"))
+    })?;
+    let key = Path::new(key.trim_start_matches('/'));
+    if !key.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(PluginError::invalid_argument(format!(
+            "Invalid image key '{}': must stay within image_store_path",
+            key.display()
+        )));
+    }
+    Ok(Path::new(&directory).join(key))
+}
+
+async fn read_from_store(key: &str) -> Result<(Vec<u8>, String), PluginError> {
+    let path = store_path(key)?;
+    let not_found = |err: std::io::Error| match err.kind() {
+        std::io::ErrorKind::NotFound => PluginError::not_found(format!("Image '{key}' not found in image_store_path")),
+        _ => PluginError::internal(format!("Cannot read image '{key}': {err}")),
+    };
+    check_size(tokio::fs::metadata(&path).await.map_err(not_found)?.len())?;
+    let bytes = tokio::fs::read(&path).await.map_err(not_found)?;
+    Ok((bytes, mime_type_of(key).to_string()))
+}
+
+/// MIME type by the file extension of a path or URL
+fn mime_type_of(location: &str) -> &'static str {
+    let path = location.split(['?', '#']).next().unwrap_or_default();
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("svg") => "image/svg+xml",
+        Some("pdf") => "application/pdf",
+        _ => DEFAULT_MIME_TYPE,
+    }
+}
@@ -0,0 +1,384 @@
+//! Bulk price import
+//!
+//! `import_prices` takes CSV rows of `sku,price`, given inline as `csv` or
+//! as the name of a file in `import_directory`. A header row starting with
+//! `sku` is skipped; fields may be quoted, but not span lines. Every row is
+//! validated first: an unknown or duplicate SKU, a malformed or negative
+//! price fails that row. All remaining rows are then applied in one
+//! transaction, recorded in the price audit table like single updates, and
+//! announced to webhooks after the commit. With `strict` any failed row
+//! leaves every price unchanged. A dry run, requested with `dry_run` or
+//! forced by the config flag of that name, runs the same statements and
+//! rolls them back, so the result shows what would change and how many
+//! rows the update affected.
+//!
+//! Products are found through `sku_column`. Like the SKU lookup, the lowest
+//! product ID wins should several products share a SKU.
+
+use crate::alerts;
+use crate::args::{self, DecimalArg};
+use crate::auth;
+use crate::backend::DatabaseBackend;
+use crate::cache::CacheBackend;
+use crate::error::PluginError;
+use crate::export::file_in;
+use crate::idempotency::{self, MAX_KEY_LENGTH};
+use crate::mapping;
+use crate::sql::quote_identifier;
+use crate::writes::{self, ensure_writes_enabled};
+use crate::{format_price, get_config, webhooks, PluginConfig};
+use chrono::Utc;
+use mcp_plugin_api::utils;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Validate the import settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if config.import_max_rows < 1 {
+        return Err("import_max_rows must be at least 1".to_string());
+    }
+    match &config.import_directory {
+        Some(directory) if !Path::new(directory).is_absolute() => {
+            Err("import_directory must be an absolute path".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Arguments of import_prices
+#[derive(Debug, Deserialize, JsonSchema)]
+pub(crate) struct ImportArgs {
+    csv: Option<String>,
+    #[schemars(length(min = 1, max = 255))]
+    file: Option<String>,
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    strict: bool,
+    changed_by: Option<String>,
+    reason: Option<String>,
+    #[schemars(length(min = 1, max = "MAX_KEY_LENGTH"))]
+    idempotency_key: Option<String>,
+}
+
+/// Outcome of one CSV row
+struct Row {
+    line: usize,
+    sku: String,
+    new_price: Option<Decimal>,
+    product_id: Option<i32>,
+    old_price: Option<Decimal>,
+    audit_id: Option<i64>,
+    error: Option<String>,
+}
+
+impl Row {
+    fn new(line: usize, sku: String, new_price: Option<Decimal>, error: Option<String>) -> Self {
+        Row {
+            line,
+            sku,
+            new_price,
+            product_id: None,
+            old_price: None,
+            audit_id: None,
+            error,
+        }
+    }
+
+    fn failed(line: usize, sku: String, error: impl Into<String>) -> Self {
+        Row::new(line, sku, None, Some(error.into()))
+    }
+
+    fn changes(&self) -> bool {
+        self.error.is_none() && self.old_price != self.new_price
+    }
+
+    fn to_json(&self, dry_run: bool, applied: bool) -> Value {
+        let status = match (&self.error, self.changes(), dry_run, applied) {
+            (Some(_), ..) => "failed",
+            (None, false, ..) => "unchanged",
+            (None, true, true, _) => "would_update",
+            (None, true, false, true) => "updated",
+            (None, true, false, false) => "skipped",
+        };
+        let mut row = json!({"line": self.line, "sku": self.sku, "status": status});
+        if let Some(product_id) = self.product_id {
+            row["product_id"] = json!(product_id);
+        }
+        if let Some(old_price) = &self.old_price {
+            row["old_price"] = json!(format_price(old_price));
+        }
+        if let Some(new_price) = &self.new_price {
+            row["new_price"] = json!(format_price(new_price));
+        }
+        if let Some(audit_id) = self.audit_id {
+            row["audit_id"] = json!(audit_id);
+        }
+        if let Some(error) = &self.error {
+            row["error"] = json!(error);
+        }
+        row
+    }
+}
+
+/// Split one CSV line into fields, honouring double quotes
+fn split_csv_line(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            (c, _) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted field".to_string());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// Parse and check the CSV rows, without looking at the database
+fn parse_rows(content: &str, max_rows: usize) -> Result<Vec<Row>, PluginError> {
+    let mut rows: Vec<Row> = Vec::new();
+    let mut first_line: HashMap<String, usize> = HashMap::new();
+    let mut header_allowed = true;
+    for (index, line) in content.lines().enumerate() {
+        let line_number = index + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let is_first = std::mem::replace(&mut header_allowed, false);
+        if rows.len() == max_rows {
+            return Err(PluginError::invalid_argument(format!(
+                "Too many rows: at most {max_rows} can be imported at once"
+            )));
+        }
+        let fields = match split_csv_line(line) {
+            Ok(fields) => fields,
+            Err(err) => {
+                rows.push(Row::failed(line_number, String::new(), err));
+                continue;
+            }
+        };
+        let sku = fields[0].trim().to_string();
+        if is_first && sku.eq_ignore_ascii_case("sku") {
+            continue;
+        }
+        if fields.len() != 2 {
+            rows.push(Row::failed(line_number, sku, format!("Expected 2 fields (sku,price), found {}", fields.len())));
+            continue;
+        }
+        if sku.is_empty() {
+            rows.push(Row::failed(line_number, sku, "Empty sku"));
+            continue;
+        }
+        if let Some(first) = first_line.get(&sku) {
+            rows.push(Row::failed(line_number, sku, format!("Duplicate sku, first given on line {first}")));
+            continue;
+        }
+        first_line.insert(sku.clone(), line_number);
+        let price = match DecimalArg::Text(fields[1].trim().to_string()).to_decimal("price") {
+            Ok(price) if price < Decimal::ZERO => {
+                rows.push(Row::failed(line_number, sku, "Price must not be negative"));
+                continue;
+            }
+            Ok(price) => price,
+            Err(err) => {
+                rows.push(Row::failed(line_number, sku, err.message()));
+                continue;
+            }
+        };
+        rows.push(Row::new(line_number, sku, Some(price), None));
+    }
+    Ok(rows)
+}
+
+/// CSV content from the `csv` argument or a file in `import_directory`
+async fn read_content(csv: Option<String>, file: Option<String>) -> Result<String, PluginError> {
+    match (csv, file) {
+        (Some(csv), None) => Ok(csv),
+        (None, Some(file)) => {
+            let directory = get_config().import_directory.clone().ok_or_else(|| {
+                PluginError::PermissionDenied("Importing from a file requires import_directory".to_string())
+            })?;
+            tokio::fs::read_to_string(file_in(&directory, &file)?)
+                .await
+                .map_err(|err| match err.kind() {
+                    std::io::ErrorKind::NotFound => PluginError::not_found(format!("Import file {file} not found")),
+                    _ => PluginError::internal(format!("Cannot read import file {file}: {err}")),
+                })
+        }
+        _ => Err(PluginError::invalid_argument("Pass exactly one of csv and file")),
+    }
+}
+
+pub(crate) async fn handle_import_prices(
+    db: &dyn DatabaseBackend,
+    cache: &dyn CacheBackend,
+    args: &Value,
+) -> Result<Value, PluginError> {
+    ensure_writes_enabled()?;
+    let ImportArgs {
+        csv,
+        file,
+        dry_run,
+        strict,
+        changed_by,
+        reason,
+        idempotency_key,
+    } = args::parse(args)?;
+    let changed_by = auth::changed_by(changed_by, args)?;
+    let dry_run = writes::is_dry_run(dry_run);
+    let content = read_content(csv, file).await?;
+
+    let config = get_config();
+    let mut rows = parse_rows(&content, config.import_max_rows)?;
+
+    let audit_table = quote_identifier(&config.price_audit_table).map_err(PluginError::internal)?;
+    let sku_column = quote_identifier(&config.sku_column).map_err(PluginError::internal)?;
+    let mapping = &config.schema_mapping;
+    let table = mapping.table();
+    let (id, price) = (mapping.column(&mapping.id_column), mapping.column(&mapping.price_column));
+    let mut tx = db.primary()?.begin().await?;
+
+    // A retry of an import that was applied gets the original result; dry
+    // runs apply nothing, so their key is not claimed
+    let idempotency_key = idempotency_key.filter(|_| !dry_run);
+    if let Some(key) = &idempotency_key {
+        if let Some(response) = idempotency::claim(&mut tx, "import_prices", key, args).await? {
+            tx.rollback().await?;
+            return Ok(utils::json_content(response));
+        }
+    }
+
+    // Lock the products so the reported old prices are the ones replaced
+    let skus: Vec<String> = rows.iter().filter(|row| row.error.is_none()).map(|row| row.sku.clone()).collect();
+    let found = sqlx::query_as::<_, (String, i32, Decimal)>(&format!(
+        "SELECT {sku_column}::text, {id}, {price} FROM {table} \
+         WHERE {sku_column}::text = ANY($1) AND {} ORDER BY {id} FOR UPDATE",
+        mapping::visible_rows()
+    ))
+    .bind(&skus)
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut products: HashMap<String, (i32, Decimal)> = HashMap::new();
+    for (sku, product_id, current_price) in found {
+        products.entry(sku).or_insert((product_id, current_price));
+    }
+    for row in rows.iter_mut().filter(|row| row.error.is_none()) {
+        match products.get(&row.sku) {
+            Some((product_id, current_price)) => {
+                row.product_id = Some(*product_id);
+                row.old_price = Some(*current_price);
+            }
+            None => row.error = Some("Unknown sku".to_string()),
+        }
+    }
+
+    let failed = rows.iter().filter(|row| row.error.is_some()).count();
+    let changes: Vec<usize> = (0..rows.len()).filter(|index| rows[*index].changes()).collect();
+    let execute = !(changes.is_empty() || (strict && failed > 0));
+    let apply = execute && !dry_run;
+
+    let mut affected_rows = 0;
+    if execute {
+        let product_ids: Vec<i32> = changes.iter().map(|index| rows[*index].product_id.unwrap()).collect();
+        let old_prices: Vec<Decimal> = changes.iter().map(|index| rows[*index].old_price.unwrap()).collect();
+        let new_prices: Vec<Decimal> = changes.iter().map(|index| rows[*index].new_price.unwrap()).collect();
+
+        affected_rows = sqlx::query(&format!(
+            "UPDATE {table} AS target SET {price} = changes.new_price \
+             FROM unnest($1::int[], $2::numeric[]) AS changes(product_id, new_price) \
+             WHERE target.{id} = changes.product_id"
+        ))
+        .bind(&product_ids)
+        .bind(&new_prices)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        let audit_ids = sqlx::query_as::<_, (i64, i32)>(&format!(
+            "INSERT INTO {audit_table} (product_id, old_price, new_price, changed_by, reason) \
+             SELECT product_id, old_price, new_price, $4, $5 \
+             FROM unnest($1::int[], $2::numeric[], $3::numeric[]) AS changes(product_id, old_price, new_price) \
+             RETURNING id, product_id"
+        ))
+        .bind(&product_ids)
+        .bind(&old_prices)
+        .bind(&new_prices)
+        .bind(&changed_by)
+        .bind(&reason)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        // Audit entries of a dry run are rolled back, so they get no IDs
+        let audit_ids: HashMap<i32, i64> = audit_ids.into_iter().map(|(audit_id, product_id)| (product_id, audit_id)).collect();
+        for index in changes.iter().filter(|_| apply) {
+            let row = &mut rows[*index];
+            row.audit_id = row.product_id.and_then(|product_id| audit_ids.get(&product_id).copied());
+        }
+    }
+
+    let results: Vec<Value> = rows.iter().map(|row| row.to_json(dry_run, apply)).collect();
+    let response = json!({
+        "dry_run": dry_run,
+        "applied": apply,
+        "rows": rows.len(),
+        "updated": if apply { changes.len() } else { 0 },
+        "would_update": if dry_run { changes.len() } else { 0 },
+        "affected_rows": affected_rows,
+        "unchanged": rows.len() - failed - changes.len(),
+        "failed": failed,
+        "results": results
+    });
+
+    if apply {
+        if let Some(key) = &idempotency_key {
+            idempotency::complete(&mut tx, "import_prices", key, &response).await?;
+        }
+        tx.commit().await?;
+
+        let changed_at = Utc::now().to_rfc3339();
+        for index in &changes {
+            let row = &rows[*index];
+            let product_id = row.product_id.unwrap();
+            cache.invalidate_product(product_id).await;
+            webhooks::notify(
+                "price.updated",
+                json!({
+                    "event": "price.updated",
+                    "product_id": product_id,
+                    "old_price": row.old_price.as_ref().map(format_price),
+                    "new_price": row.new_price.as_ref().map(format_price),
+                    "changed_by": changed_by,
+                    "reason": reason,
+                    "audit_id": row.audit_id,
+                    "changed_at": changed_at
+                }),
+            );
+        }
+        alerts::wake();
+        tracing::info!(updated = changes.len(), failed, "Prices imported");
+    } else {
+        tx.rollback().await?;
+    }
+
+    // Return structured JSON data for programmatic clients
+    Ok(utils::json_content(response))
+}
@@ -0,0 +1,246 @@
+//! Schema introspection
+//!
+//! Before a Postgres pool is put to use, the product table of
+//! `schema_mapping` is looked up in the system catalogs, and every mapped
+//! column must exist with a type the plugin can read:
+//!
+//! - `id_column`: smallint or integer
+//! - `price_column` and `cost_column`: numeric
+//! - `name_column`, `description_column` and `category_column`: a string
+//!   type such as text or varchar
+//! - `unit_quantity_column`: a numeric type
+//! - the other columns are read as text or JSON and may have any type
+//!
+//! A mismatch fails init or the reconfiguration with a message naming the
+//! column, e.g. "column price has type text, expected numeric", instead of
+//! a decode error on the first request. A missing SKU or barcode column is
+//! only logged, as the lookups using them are optional, and so is a
+//! missing table while `enable_dev_tools` lets `seed_demo_data` create it.
+//! The check can be turned off with `validate_schema`; if the catalogs
+//! cannot be read, it is skipped with a warning.
+//!
+//! `describe_schema` reports the detected columns, the mapping with the
+//! result of the checks and the table's indexes.
+
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::mapping::SchemaMapping;
+use crate::{get_config, PluginConfig};
+use mcp_plugin_api::utils;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+
+/// A column of the product table as found in the catalogs
+#[derive(Debug, sqlx::FromRow)]
+struct Column {
+    name: String,
+    /// Full type, e.g. `character varying(200)`
+    data_type: String,
+    /// Name of the base type, e.g. `varchar`
+    type_name: String,
+    /// Postgres type category, e.g. `N` for numeric and `S` for string types
+    category: String,
+    nullable: bool,
+}
+
+/// Types a mapped column may have
+#[derive(Debug, Clone, Copy)]
+enum Expected {
+    Integer,
+    Numeric,
+    AnyNumber,
+    String,
+    Any,
+}
+
+impl Expected {
+    fn accepts(&self, column: &Column) -> bool {
+        match self {
+            Expected::Integer => matches!(column.type_name.as_str(), "int2" | "int4"),
+            Expected::Numeric => column.type_name == "numeric",
+            Expected::AnyNumber => column.category == "N",
+            Expected::String => column.category == "S",
+            Expected::Any => true,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Expected::Integer => "smallint or integer",
+            Expected::Numeric => "numeric",
+            Expected::AnyNumber => "a numeric type",
+            Expected::String => "a string type such as text",
+            Expected::Any => "any type",
+        }
+    }
+}
+
+/// The mapped columns with their role and the types they may have
+fn mapped_columns(mapping: &SchemaMapping) -> Vec<(&'static str, &str, Expected)> {
+    let mut columns = vec![
+        ("id", mapping.id_column.as_str(), Expected::Integer),
+        ("name", mapping.name_column.as_str(), Expected::String),
+        ("price", mapping.price_column.as_str(), Expected::Numeric),
+    ];
+    let optional = [
+        ("description", &mapping.description_column, Expected::String),
+        ("category", &mapping.category_column, Expected::String),
+        ("cost", &mapping.cost_column, Expected::Numeric),
+        ("unit_quantity", &mapping.unit_quantity_column, Expected::AnyNumber),
+        ("unit_of_measure", &mapping.unit_of_measure_column, Expected::Any),
+        ("status", &mapping.status_column, Expected::Any),
+        ("brand", &mapping.brand_column, Expected::Any),
+        ("image_url", &mapping.image_url_column, Expected::String),
+        ("thumbnail_url", &mapping.thumbnail_url_column, Expected::String),
+    ];
+    for (role, column, expected) in optional {
+        if let Some(column) = column {
+            columns.push((role, column.as_str(), expected));
+        }
+    }
+    for column in &mapping.extra_columns {
+        columns.push(("extra", column.as_str(), Expected::Any));
+    }
+    columns
+}
+
+/// Columns of `table`, `None` if it does not exist
+async fn table_columns(pool: &PgPool, table: &str) -> Result<Option<Vec<Column>>, sqlx::Error> {
+    let exists: Option<String> = sqlx::query_scalar("SELECT to_regclass($1)::text")
+        .bind(table)
+        .fetch_one(pool)
+        .await?;
+    if exists.is_none() {
+        return Ok(None);
+    }
+    let columns = sqlx::query_as::<_, Column>(
+        "SELECT a.attname::text AS name, format_type(a.atttypid, a.atttypmod) AS data_type, \
+         t.typname::text AS type_name, t.typcategory::text AS category, NOT a.attnotnull AS nullable \
+         FROM pg_attribute a JOIN pg_type t ON t.oid = a.atttypid \
+         WHERE a.attrelid = to_regclass($1) AND a.attnum > 0 AND NOT a.attisdropped \
+         ORDER BY a.attnum",
+    )
+    .bind(table)
+    .fetch_all(pool)
+    .await?;
+    Ok(Some(columns))
+}
+
+/// Problem with a mapped column, `None` if it can be read
+fn column_problem(columns: &[Column], name: &str, expected: Expected) -> Option<String> {
+    match columns.iter().find(|column| column.name == name) {
+        None => Some(format!("column {name} does not exist")),
+        Some(column) if !expected.accepts(column) => Some(format!(
+            "column {name} has type {}, expected {}",
+            column.data_type,
+            expected.describe()
+        )),
+        Some(_) => None,
+    }
+}
+
+/// Check the product table against the mapping before a pool is used
+///
+/// Fails with every problem found; a backend other than Postgres, or
+/// catalogs that cannot be read, pass.
+pub(crate) async fn validate_schema(db: &dyn DatabaseBackend, config: &PluginConfig) -> Result<(), String> {
+    let Ok(pool) = db.postgres() else {
+        return Ok(());
+    };
+    if !config.validate_schema {
+        return Ok(());
+    }
+    let mapping = &config.schema_mapping;
+    let columns = match table_columns(pool, &mapping.table()).await {
+        Ok(Some(columns)) => columns,
+        // seed_demo_data can still create it
+        Ok(None) if config.enable_dev_tools => {
+            tracing::warn!("Product table {} does not exist yet", mapping.table);
+            return Ok(());
+        }
+        Ok(None) => return Err(format!("Product table {} does not exist", mapping.table)),
+        Err(err) => {
+            tracing::warn!("Schema validation skipped, the catalogs could not be read: {err}");
+            return Ok(());
+        }
+    };
+
+    let problems: Vec<String> = mapped_columns(mapping)
+        .into_iter()
+        .filter_map(|(_, name, expected)| column_problem(&columns, name, expected))
+        .collect();
+    if !problems.is_empty() {
+        return Err(format!("Product table {} does not match schema_mapping: {}", mapping.table, problems.join("; ")));
+    }
+
+    for (setting, column) in [("sku_column", &config.sku_column), ("barcode_column", &config.barcode_column)] {
+        if !columns.iter().any(|found| &found.name == column) {
+            tracing::warn!("Product table {} has no {setting} {column}, its lookups will fail", mapping.table);
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn handle_describe_schema(db: &dyn DatabaseBackend, _args: &Value) -> Result<Value, PluginError> {
+    let pool = db.postgres()?;
+    let config = get_config();
+    let mapping = &config.schema_mapping;
+    let columns = table_columns(pool, &mapping.table())
+        .await?
+        .ok_or_else(|| PluginError::not_found(format!("Product table {} does not exist", mapping.table)))?;
+
+    let mapped: Vec<Value> = mapped_columns(mapping)
+        .into_iter()
+        .map(|(role, name, expected)| {
+            let column = columns.iter().find(|column| column.name == name);
+            json!({
+                "role": role,
+                "column": name,
+                "type": column.map(|column| &column.data_type),
+                "expected": expected.describe(),
+                "problem": column_problem(&columns, name, expected)
+            })
+        })
+        .collect();
+    let lookups: Vec<Value> = [("sku", &config.sku_column), ("barcode", &config.barcode_column)]
+        .into_iter()
+        .map(|(role, name)| {
+            let column = columns.iter().find(|column| &column.name == name);
+            json!({
+                "role": role,
+                "column": name,
+                "type": column.map(|column| &column.data_type),
+                "problem": column.is_none().then(|| format!("column {name} does not exist"))
+            })
+        })
+        .collect();
+
+    let indexes = sqlx::query_as::<_, (String, String)>(
+        "SELECT indexrelid::regclass::text, pg_get_indexdef(indexrelid) FROM pg_index \
+         WHERE indrelid = to_regclass($1) ORDER BY 1",
+    )
+    .bind(mapping.table())
+    .fetch_all(pool)
+    .await?;
+    let estimated_rows: Option<f32> = sqlx::query_scalar("SELECT reltuples FROM pg_class WHERE oid = to_regclass($1)")
+        .bind(mapping.table())
+        .fetch_optional(pool)
+        .await?;
+
+    let valid = mapped.iter().all(|column| column["problem"].is_null());
+    Ok(utils::json_content(json!({
+        "table": mapping.table,
+        "valid": valid,
+        "estimated_rows": estimated_rows.filter(|rows| *rows >= 0.0).map(|rows| rows as i64),
+        "columns": columns
+            .iter()
+            .map(|column| json!({"name": column.name, "type": column.data_type, "nullable": column.nullable}))
+            .collect::<Vec<Value>>(),
+        "mapping": mapped,
+        "lookups": lookups,
+        "indexes": indexes
+            .into_iter()
+            .map(|(name, definition)| json!({"name": name, "definition": definition}))
+            .collect::<Vec<Value>>()
+    })))
+}
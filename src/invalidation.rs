@@ -0,0 +1,153 @@
+//! Cache invalidation through Postgres LISTEN/NOTIFY
+//!
+//! With `cache_invalidation_channel` set, a background task in the runtime
+//! LISTENs on that channel and evicts cached results when a notification
+//! arrives. The payload names the changed products as a comma-separated
+//! list of IDs, e.g. from a trigger:
+//!
+//! ```sql
+//! CREATE FUNCTION notify_product_change() RETURNS trigger AS $$
+//! BEGIN
+//!     PERFORM pg_notify('product_changes', COALESCE(NEW.id, OLD.id)::text);
+//!     RETURN NULL;
+//! END $$ LANGUAGE plpgsql;
+//!
+//! CREATE TRIGGER products_notify AFTER INSERT OR UPDATE OR DELETE ON products
+//!     FOR EACH ROW EXECUTE FUNCTION notify_product_change();
+//! ```
+//!
+//! Any other payload, an empty one included, clears the whole cache. So does
+//! (re)connecting the listener, since changes made while it was not
+//! listening are unknown. Every notification also has the price alert
+//! rules evaluated. The listener holds one connection of the primary
+//! pool; after a connection error it reconnects with exponential backoff,
+//! and after a reconfiguration it moves to the new pool and channel.
+
+use crate::alerts;
+use crate::backend::{BackendKind, Database};
+use crate::cache::{Cache, CacheBackend};
+use crate::error::PluginError;
+use crate::{get_config, PluginConfig};
+use arc_swap::ArcSwap;
+use sqlx::postgres::PgListener;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Delay before the first reconnection attempt, doubled after every failure
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Upper bound for the delay between two reconnection attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Validate the invalidation settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    match &config.cache_invalidation_channel {
+        Some(channel) if channel.is_empty() => Err("cache_invalidation_channel must not be empty".to_string()),
+        Some(_) if BackendKind::parse(&config.backend)? != BackendKind::Postgres => {
+            Err("cache_invalidation_channel requires the postgres backend".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Handle of the listener task
+pub(crate) struct CacheInvalidation {
+    reconfigured: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+impl CacheInvalidation {
+    /// Spawn the listener task on the current runtime
+    pub(crate) fn start(db: Arc<ArcSwap<Database>>, cache: Arc<ArcSwap<Cache>>) -> Self {
+        let reconfigured = Arc::new(Notify::new());
+        let task = tokio::spawn(run(db, cache, reconfigured.clone()));
+        CacheInvalidation { reconfigured, task }
+    }
+
+    /// Reconnect with the current configuration, called once it is applied
+    pub(crate) fn reconfigured(&self) {
+        self.reconfigured.notify_one();
+    }
+
+    /// Stop listening and return the connection to the pool
+    pub(crate) fn stop(&self) {
+        self.task.abort();
+    }
+}
+
+/// Evict the products named in a notification payload
+async fn invalidate(cache: &dyn CacheBackend, payload: &str) {
+    let ids: Result<Vec<i32>, _> = payload.split(',').map(|id| id.trim().parse::<i32>()).collect();
+    match ids {
+        Ok(ids) => {
+            tracing::debug!(?ids, "Products changed, evicting cached results");
+            for id in ids {
+                cache.invalidate_product(id).await;
+            }
+        }
+        Err(_) => {
+            tracing::debug!(payload, "Catalogue changed, clearing the cache");
+            cache.clear().await;
+        }
+    }
+}
+
+async fn listen(db: &Database, channel: &str) -> Result<PgListener, PluginError> {
+    let mut listener = PgListener::connect_with(db.primary()?).await?;
+    listener.listen(channel).await?;
+    Ok(listener)
+}
+
+async fn run(db: Arc<ArcSwap<Database>>, cache: Arc<ArcSwap<Cache>>, reconfigured: Arc<Notify>) {
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        let Some(channel) = get_config().cache_invalidation_channel.clone() else {
+            reconfigured.notified().await;
+            continue;
+        };
+
+        let current = db.load_full();
+        let connected = tokio::select! {
+            connected = listen(&current, &channel) => connected,
+            _ = reconfigured.notified() => continue,
+        };
+        let mut listener = match connected {
+            Ok(listener) => listener,
+            Err(err) => {
+                tracing::warn!("Cannot listen on {channel}: {}, retrying in {backoff:?}", err.message());
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {}
+                    _ = reconfigured.notified() => {}
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                continue;
+            }
+        };
+
+        tracing::info!("Listening on {channel} for cache invalidation");
+        backoff = INITIAL_BACKOFF;
+        cache.load_full().clear().await;
+        loop {
+            tokio::select! {
+                notification = listener.try_recv() => match notification {
+                    Ok(Some(notification)) => {
+                        invalidate(&**cache.load_full(), notification.payload()).await;
+                        alerts::wake();
+                    }
+                    // try_recv() reconnects on the next call
+                    Ok(None) => {
+                        tracing::warn!("Cache invalidation listener lost its connection, reconnecting");
+                        cache.load_full().clear().await;
+                    }
+                    Err(err) => {
+                        tracing::warn!("Cache invalidation listener failed: {err}");
+                        break;
+                    }
+                },
+                _ = reconfigured.notified() => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Stock availability
+//!
+//! Reads quantities on hand from an inventory table with one row per
+//! product and warehouse. Table and column names are configurable:
+//!
+//! ```sql
+//! CREATE TABLE inventory (
+//!     product_id INTEGER NOT NULL REFERENCES products(id),
+//!     warehouse  VARCHAR(100) NOT NULL,
+//!     quantity   INTEGER NOT NULL
+//! );
+//! ```
+//!
+//! Deployments without inventory tracking still get prices: if the table
+//! does not exist the availability is reported as untracked instead of
+//! failing the call.
+
+use crate::args;
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::sql::{is_undefined_table, quote_identifier};
+use crate::{currency, get_config, locale, product_json, PluginConfig};
+use mcp_plugin_api::utils;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Arguments of get_product_availability
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ProductArgs {
+    product_id: i32,
+    currency: Option<String>,
+    locale: Option<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct WarehouseStock {
+    warehouse: String,
+    quantity: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct ProductStock {
+    product_id: i32,
+    #[sqlx(flatten)]
+    stock: WarehouseStock,
+}
+
+/// Validate the inventory settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    quote_identifier(&config.inventory_table)?;
+    for column in [
+        &config.inventory_product_column,
+        &config.inventory_warehouse_column,
+        &config.inventory_quantity_column,
+    ] {
+        if column.contains('.') {
+            return Err(format!("Invalid inventory column '{column}': must not be qualified"));
+        }
+        quote_identifier(column)?;
+    }
+    Ok(())
+}
+
+/// Stock per warehouse of several products in one query, or `None` if the
+/// inventory table does not exist; products without stock rows are left out
+async fn warehouse_stock(
+    db: &dyn DatabaseBackend,
+    product_ids: &[i32],
+) -> Result<Option<HashMap<i32, Vec<WarehouseStock>>>, PluginError> {
+    let config = get_config();
+    let quote = |name: &str| quote_identifier(name).map_err(PluginError::internal);
+    let table = quote(&config.inventory_table)?;
+    let product = quote(&config.inventory_product_column)?;
+    let warehouse = quote(&config.inventory_warehouse_column)?;
+    let quantity = quote(&config.inventory_quantity_column)?;
+
+    let result = sqlx::query_as::<_, ProductStock>(&format!(
+        "SELECT {product}::integer AS product_id, {warehouse}::text AS warehouse, \
+                sum({quantity})::bigint AS quantity \
+         FROM {table} WHERE {product} = ANY($1) \
+         GROUP BY 1, 2 ORDER BY 1, 2"
+    ))
+    .bind(product_ids)
+    .fetch_all(db.postgres()?)
+    .await;
+
+    let rows = match result {
+        Ok(rows) => rows,
+        Err(err) if is_undefined_table(&err) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let mut stock: HashMap<i32, Vec<WarehouseStock>> = HashMap::new();
+    for row in rows {
+        stock.entry(row.product_id).or_default().push(row.stock);
+    }
+    Ok(Some(stock))
+}
+
+/// Availability of a product from its stock, untracked without an inventory table
+fn availability_json(warehouses: Option<Vec<WarehouseStock>>) -> Value {
+    match warehouses {
+        Some(warehouses) => {
+            let quantity_on_hand: i64 = warehouses.iter().map(|stock| stock.quantity).sum();
+            json!({
+                "tracked": true,
+                "quantity_on_hand": quantity_on_hand,
+                "in_stock": quantity_on_hand > 0,
+                "warehouses": warehouses
+            })
+        }
+        None => json!({
+            "tracked": false,
+            "message": format!("Inventory table {} does not exist", get_config().inventory_table)
+        }),
+    }
+}
+
+/// Availability of several products in one query
+pub(crate) async fn availability_of(
+    db: &dyn DatabaseBackend,
+    product_ids: &[i32],
+) -> Result<HashMap<i32, Value>, PluginError> {
+    let mut stock = warehouse_stock(db, product_ids).await?;
+    Ok(product_ids
+        .iter()
+        .map(|id| {
+            let warehouses = stock.as_mut().map(|stock| stock.remove(id).unwrap_or_default());
+            (*id, availability_json(warehouses))
+        })
+        .collect())
+}
+
+pub(crate) async fn handle_get_product_availability(
+    db: &dyn DatabaseBackend,
+    args: &Value,
+) -> Result<Value, PluginError> {
+    let ProductArgs {
+        product_id,
+        currency,
+        locale,
+    } = args::parse(args)?;
+    let currency = currency::parse_currency(currency.as_deref())?;
+    let locale = locale::parse_locale(locale.as_deref())?;
+
+    let product = db
+        .fetch_product(product_id)
+        .await?
+        .ok_or_else(|| PluginError::not_found(format!("Product {product_id} not found")))?;
+
+    let exchange_rate = match &currency {
+        Some(currency) => Some(currency::exchange_rate(db, currency).await?),
+        None => None,
+    };
+
+    let mut availability = availability_of(db, &[product_id]).await?;
+    let availability = availability.remove(&product_id).unwrap_or(Value::Null);
+
+    // Return structured JSON data for programmatic clients
+    Ok(utils::json_content(json!({
+        "product": product_json(&product, exchange_rate.as_ref(), locale.as_ref()),
+        "availability": availability,
+        "base_currency": get_config().base_currency
+    })))
+}
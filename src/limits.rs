@@ -0,0 +1,65 @@
+//! Argument limits
+//!
+//! Before a tool runs, its arguments are held to the deployment's limits,
+//! whatever the tool itself accepts:
+//!
+//! - `query`: at most `max_query_length` characters
+//! - `limit`: at most `max_limit`
+//! - list arguments, e.g. `product_ids`: at most `max_batch_size` items
+//!
+//! A call over a limit fails with an `invalid_argument` error naming the
+//! argument, the allowed range and the value received, so that a runaway
+//! caller cannot make the database scan or return more than intended.
+
+use crate::error::{FieldError, PluginError};
+use crate::{get_config, PluginConfig};
+use serde_json::{json, Value};
+
+/// Validate the argument limits, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if config.max_query_length == 0 {
+        return Err("max_query_length must be at least 1".to_string());
+    }
+    if config.max_limit < 1 {
+        return Err("max_limit must be at least 1".to_string());
+    }
+    if config.max_batch_size == 0 {
+        return Err("max_batch_size must be at least 1".to_string());
+    }
+    Ok(())
+}
+
+/// Check the arguments of a tool call against the limits
+pub(crate) fn check(args: &Value) -> Result<(), PluginError> {
+    let Some(args) = args.as_object() else {
+        return Ok(());
+    };
+    let config = get_config();
+
+    if let Some(Value::String(query)) = args.get("query") {
+        if query.chars().count() > config.max_query_length {
+            let expected = format!("at most {} characters", config.max_query_length);
+            // The query itself may be huge, so only its length is echoed
+            let received = json!(format!("{} characters", query.chars().count()));
+            return Err(PluginError::InvalidField(FieldError::new("query", expected, &received)));
+        }
+    }
+
+    if let Some(limit) = args.get("limit").filter(|limit| limit.is_number()) {
+        if limit.as_f64().is_some_and(|limit| limit > config.max_limit as f64) {
+            let expected = format!("at most {}", config.max_limit);
+            return Err(PluginError::InvalidField(FieldError::new("limit", expected, limit)));
+        }
+    }
+
+    for (name, value) in args {
+        if let Value::Array(items) = value {
+            if items.len() > config.max_batch_size {
+                let expected = format!("at most {} items", config.max_batch_size);
+                let received = json!(format!("{} items", items.len()));
+                return Err(PluginError::InvalidField(FieldError::new(name.as_str(), expected, &received)));
+            }
+        }
+    }
+    Ok(())
+}
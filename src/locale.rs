@@ -0,0 +1,157 @@
+//! Localized price display
+//!
+//! With a `locale` argument, or `default_locale` in the configuration,
+//! product prices come with a `display_price` string formatted the way a
+//! reader in that locale expects, e.g. `1.299,00 €` for `de-DE` and
+//! `€1,299.00` for `en-IE`. The numeric `price` stays as it is; the display
+//! string is meant to be quoted verbatim instead of reformatted by the
+//! model. Prices are rounded to `price_decimal_places` like everywhere else.
+//!
+//! Locales are BCP 47 tags from a built-in table; a bare language such as
+//! `de` picks the first locale of that language.
+
+use crate::error::PluginError;
+use crate::{format_price, get_config, PluginConfig};
+use rust_decimal::Decimal;
+
+/// Separators and currency placement of one locale
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Locale {
+    tag: &'static str,
+    decimal_separator: char,
+    group_separator: &'static str,
+    symbol_first: bool,
+    /// Space between symbol and amount
+    symbol_spaced: bool,
+}
+
+const fn locale(
+    tag: &'static str,
+    decimal_separator: char,
+    group_separator: &'static str,
+    symbol_first: bool,
+    symbol_spaced: bool,
+) -> Locale {
+    Locale {
+        tag,
+        decimal_separator,
+        group_separator,
+        symbol_first,
+        symbol_spaced,
+    }
+}
+
+/// Supported locales, the first of each language is its default
+const LOCALES: [Locale; 20] = [
+    locale("en-US", '.', ",", true, false),
+    locale("en-GB", '.', ",", true, false),
+    locale("en-IE", '.', ",", true, false),
+    locale("en-CA", '.', ",", true, false),
+    locale("en-AU", '.', ",", true, false),
+    locale("de-DE", ',', ".", false, true),
+    locale("de-AT", ',', "\u{a0}", true, true),
+    locale("de-CH", '.', "’", true, true),
+    locale("fr-FR", ',', "\u{202f}", false, true),
+    locale("fr-CA", ',', "\u{a0}", false, true),
+    locale("fr-CH", ',', "\u{202f}", false, true),
+    locale("es-ES", ',', ".", false, true),
+    locale("es-MX", '.', ",", true, false),
+    locale("it-IT", ',', ".", false, true),
+    locale("nl-NL", ',', ".", true, true),
+    locale("pt-BR", ',', ".", true, true),
+    locale("pt-PT", ',', "\u{a0}", false, true),
+    locale("sv-SE", ',', "\u{a0}", false, true),
+    locale("pl-PL", ',', "\u{a0}", false, true),
+    locale("ja-JP", '.', ",", true, false),
+];
+
+/// Symbol of a currency, or its ISO code if it has no common one
+fn currency_symbol(currency: &str) -> &str {
+    match currency {
+        "USD" => "$",
+        "EUR" => "€",
+        "GBP" => "£",
+        "JPY" => "¥",
+        "INR" => "₹",
+        "KRW" => "₩",
+        "BRL" => "R$",
+        "CAD" => "CA$",
+        "AUD" => "A$",
+        "MXN" => "MX$",
+        "PLN" => "zł",
+        "SEK" => "kr",
+        other => other,
+    }
+}
+
+impl Locale {
+    /// Look up a locale tag such as `de-DE`, `de_de` or `de`
+    pub(crate) fn parse(tag: &str) -> Result<Locale, String> {
+        let tag = tag.replace('_', "-");
+        LOCALES
+            .iter()
+            .find(|locale| locale.tag.eq_ignore_ascii_case(&tag))
+            .or_else(|| {
+                LOCALES.iter().find(|locale| {
+                    locale
+                        .tag
+                        .split_once('-')
+                        .is_some_and(|(language, _)| language.eq_ignore_ascii_case(&tag))
+                })
+            })
+            .copied()
+            .ok_or_else(|| {
+                let supported: Vec<&str> = LOCALES.iter().map(|locale| locale.tag).collect();
+                format!("Unsupported locale '{tag}', expected one of: {}", supported.join(", "))
+            })
+    }
+
+    /// `price` in `currency` as a reader of this locale writes it
+    pub(crate) fn display_price(&self, price: &Decimal, currency: &str) -> String {
+        let formatted = format_price(price);
+        let (sign, unsigned) = match formatted.strip_prefix('-') {
+            Some(unsigned) => ("-", unsigned),
+            None => ("", formatted.as_str()),
+        };
+        let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+
+        let mut amount = String::new();
+        for (index, digit) in integer.chars().enumerate() {
+            if index > 0 && (integer.len() - index) % 3 == 0 {
+                amount.push_str(self.group_separator);
+            }
+            amount.push(digit);
+        }
+        if !fraction.is_empty() {
+            amount.push(self.decimal_separator);
+            amount.push_str(fraction);
+        }
+
+        let symbol = currency_symbol(currency);
+        let space = if self.symbol_spaced || symbol == currency { "\u{a0}" } else { "" };
+        if self.symbol_first {
+            format!("{sign}{symbol}{space}{amount}")
+        } else {
+            format!("{sign}{amount}{space}{symbol}")
+        }
+    }
+}
+
+/// Validate the locale settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if let Some(tag) = &config.default_locale {
+        Locale::parse(tag)?;
+    }
+    Ok(())
+}
+
+/// Resolve an optional typed `locale` argument, falling back to `default_locale`
+pub(crate) fn parse_locale(tag: Option<&str>) -> Result<Option<Locale>, PluginError> {
+    match tag {
+        None => Ok(get_config()
+            .default_locale
+            .as_deref()
+            .map(|tag| Locale::parse(tag).expect("validated default_locale"))),
+        Some(tag) => Locale::parse(tag).map(Some).map_err(PluginError::invalid_argument),
+    }
+}
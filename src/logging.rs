@@ -0,0 +1,185 @@
+//! Logging
+//!
+//! Diagnostics go through `tracing`. Stdout may carry the MCP protocol, so
+//! log output is written to stderr or to `log_file`, as plain text or one
+//! JSON object per line. The subscriber is installed once at init and
+//! reloaded with the level, format and file of every new configuration.
+//!
+//! Tool calls and single statements slower than `slow_query_threshold_ms`
+//! are logged as warnings: statements by sqlx under the target
+//! `sqlx::query`, tool calls with their sanitized arguments under
+//! `plug_pricing::slow_query`.
+
+use crate::{get_config, PluginConfig};
+use serde_json::Value;
+use std::fs::OpenOptions;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::{Layer, Layered, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Subscriber below the output layer
+type Filtered = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+
+/// Layer writing the events in the configured format
+type Output = Box<dyn Layer<Filtered> + Send + Sync>;
+
+/// Handles replacing the filter and output of the installed subscriber
+struct Handles {
+    filter: reload::Handle<EnvFilter, Registry>,
+    output: reload::Handle<Output, Filtered>,
+}
+
+/// Reload handles, set once a subscriber was installed (or the host
+/// already had one)
+static INSTALLED: OnceLock<Handles> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("Invalid log_format '{other}', expected text or json")),
+        }
+    }
+}
+
+fn env_filter(config: &PluginConfig) -> Result<EnvFilter, String> {
+    EnvFilter::try_new(&config.log_level)
+        .map_err(|err| format!("Invalid log_level '{}': {err}", config.log_level))
+}
+
+/// Validate the logging settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    LogFormat::parse(&config.log_format)?;
+    env_filter(config)?;
+    Ok(())
+}
+
+/// Argument keys whose values identify people and are left out of logs
+const REDACTED_ARGUMENTS: [&str; 4] = ["customer_id", "client_id", "changed_by", "_auth"];
+
+/// Longest string argument written to the log in full
+const MAX_LOGGED_STRING: usize = 200;
+
+/// Tool arguments as written to the log: identifying values are redacted,
+/// long strings shortened and the MCP `_meta` object dropped
+fn sanitize_arguments(args: &Value) -> Value {
+    match args {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(key, _)| key.as_str() != "_meta")
+                .map(|(key, value)| {
+                    let value = if REDACTED_ARGUMENTS.contains(&key.as_str()) {
+                        Value::from("***")
+                    } else {
+                        sanitize_arguments(value)
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(sanitize_arguments).collect()),
+        Value::String(text) if text.chars().count() > MAX_LOGGED_STRING => {
+            Value::from(format!("{}...", text.chars().take(MAX_LOGGED_STRING).collect::<String>()))
+        }
+        value => value.clone(),
+    }
+}
+
+/// Warn about a tool call that took longer than `slow_query_threshold_ms`
+pub(crate) fn log_slow_call(tool: &str, elapsed: Duration, args: &Value) {
+    let threshold_ms = get_config().slow_query_threshold_ms;
+    if threshold_ms == 0 || elapsed < Duration::from_millis(threshold_ms) {
+        return;
+    }
+    tracing::warn!(
+        target: "plug_pricing::slow_query",
+        tool,
+        elapsed_ms = elapsed.as_millis() as u64,
+        threshold_ms,
+        arguments = %sanitize_arguments(args),
+        "Slow tool call"
+    );
+}
+
+/// Output layer for the format and file of `config`
+fn output(config: &PluginConfig) -> Result<Output, String> {
+    let writer = match &config.log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|err| format!("Cannot open log_file {path}: {err}"))?;
+            BoxMakeWriter::new(Mutex::new(file))
+        }
+        None => BoxMakeWriter::new(std::io::stderr),
+    };
+
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(false);
+    Ok(match LogFormat::parse(&config.log_format)? {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    })
+}
+
+/// Install the global subscriber, once
+pub(crate) fn init(config: &PluginConfig) -> Result<(), String> {
+    if INSTALLED.get().is_some() {
+        return Ok(());
+    }
+
+    let (filter, filter_handle) = reload::Layer::new(env_filter(config)?);
+    let (output, output_handle) = reload::Layer::new(output(config)?);
+    let installed = tracing_subscriber::registry().with(filter).with(output).try_init();
+
+    // The host process may have installed its own subscriber; plugin events
+    // then go wherever the host sends them
+    if let Err(err) = installed {
+        eprintln!("Pricing plugin: using the host's logging, cannot install subscriber: {err}");
+    }
+    let _ = INSTALLED.set(Handles {
+        filter: filter_handle,
+        output: output_handle,
+    });
+    Ok(())
+}
+
+/// Apply the logging settings of a new configuration to the installed
+/// subscriber; on error the previous settings stay in effect
+pub(crate) fn reconfigure(config: &PluginConfig) -> Result<(), String> {
+    let Some(handles) = INSTALLED.get() else {
+        return init(config);
+    };
+    let (filter, output) = (env_filter(config)?, output(config)?);
+    // Only fails once the subscriber is gone, e.g. the host's was installed
+    let _ = handles.filter.reload(filter);
+    let _ = handles.output.reload(output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reconfigure_reloads_the_level() {
+        let config = |level: &str| -> PluginConfig { serde_json::from_value(json!({ "log_level": level })).unwrap() };
+        init(&config("warn")).unwrap();
+        assert!(!tracing::enabled!(tracing::Level::DEBUG));
+        reconfigure(&config("debug")).unwrap();
+        assert!(tracing::enabled!(tracing::Level::DEBUG));
+        assert!(reconfigure(&config("warn,sqlx=nonsense")).is_err());
+        assert!(tracing::enabled!(tracing::Level::DEBUG));
+    }
+}
@@ -0,0 +1,147 @@
+//! Product lookup by SKU or barcode
+//!
+//! Clients often know a product by its SKU or its EAN/UPC barcode rather
+//! than the internal ID. Both live in columns of the products table, named
+//! by `sku_column` and `barcode_column`. The product is resolved to its ID
+//! and then priced exactly like `get_product_price`, so the same optional
+//! arguments apply and the payload is identical.
+
+use crate::args;
+use crate::backend::DatabaseBackend;
+use crate::cache::CacheBackend;
+use crate::error::PluginError;
+use crate::sql::quote_identifier;
+use crate::{get_config, handle_get_product_price, PluginConfig};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Arguments of get_product_by_sku, besides those of get_product_price
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SkuArgs {
+    sku: String,
+}
+
+/// Arguments of get_product_by_barcode, besides those of get_product_price
+#[derive(Debug, Deserialize, JsonSchema)]
+struct BarcodeArgs {
+    barcode: String,
+}
+
+/// Validate the lookup settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    for column in [&config.sku_column, &config.barcode_column] {
+        if column.contains('.') {
+            return Err(format!("Invalid lookup column '{column}': must not be qualified"));
+        }
+        quote_identifier(column)?;
+    }
+    Ok(())
+}
+
+/// Normalize a GTIN barcode (EAN-8, UPC-A, EAN-13 or GTIN-14)
+///
+/// Spaces and hyphens are dropped and the check digit is verified.
+fn normalize_barcode(barcode: &str) -> Result<String, PluginError> {
+    let digits: String = barcode.chars().filter(|c| !matches!(c, ' ' | '-')).collect();
+    if !matches!(digits.len(), 8 | 12 | 13 | 14) || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(PluginError::invalid_argument(format!(
+            "Invalid barcode '{barcode}', expected 8, 12, 13 or 14 digits"
+        )));
+    }
+
+    // GS1 check digit: weights 3 and 1 alternate from the right, excluding the check digit
+    let values: Vec<u32> = digits.bytes().map(|b| u32::from(b - b'0')).collect();
+    let (check, payload) = values.split_last().expect("barcode has at least 8 digits");
+    let sum: u32 = payload
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, digit)| if i % 2 == 0 { digit * 3 } else { *digit })
+        .sum();
+    if (10 - sum % 10) % 10 != *check {
+        return Err(PluginError::invalid_argument(format!(
+            "Invalid barcode '{barcode}': check digit does not match"
+        )));
+    }
+    Ok(digits)
+}
+
+/// Spellings a barcode may be stored under
+///
+/// A UPC-A code is the EAN-13 code with a leading zero dropped, so either
+/// form finds the product.
+fn barcode_variants(digits: &str) -> Vec<String> {
+    let mut variants = vec![digits.to_string()];
+    match digits.len() {
+        12 => variants.push(format!("0{digits}")),
+        13 if digits.starts_with('0') => variants.push(digits[1..].to_string()),
+        _ => {}
+    }
+    variants
+}
+
+/// ID of the product whose `column` holds one of `values`
+///
+/// Should several products match, the lowest ID wins.
+async fn resolve_product_id(
+    db: &dyn DatabaseBackend,
+    column: &str,
+    values: &[String],
+) -> Result<Option<i32>, PluginError> {
+    let column = quote_identifier(column).map_err(PluginError::internal)?;
+    let mapping = &get_config().schema_mapping;
+    let (table, id) = (mapping.table(), mapping.column(&mapping.id_column));
+    let id = sqlx::query_scalar::<_, i32>(&format!(
+        "SELECT {id} FROM {table} WHERE {column}::text = ANY($1) ORDER BY {id} LIMIT 1"
+    ))
+    .bind(values)
+    .fetch_optional(db.postgres()?)
+    .await?;
+    Ok(id)
+}
+
+/// Price the product as `get_product_price` would, with `product_id` set
+async fn product_price(
+    db: &dyn DatabaseBackend,
+    cache: &dyn CacheBackend,
+    args: &Value,
+    product_id: i32,
+) -> Result<Value, PluginError> {
+    let mut args = args.clone();
+    args["product_id"] = product_id.into();
+    handle_get_product_price(db, cache, &args).await
+}
+
+pub(crate) async fn handle_get_product_by_sku(
+    db: &dyn DatabaseBackend,
+    cache: &dyn CacheBackend,
+    args: &Value,
+) -> Result<Value, PluginError> {
+    let SkuArgs { sku } = args::parse(args)?;
+    let sku = sku.trim();
+    if sku.is_empty() {
+        return Err(PluginError::invalid_argument("Missing or invalid sku parameter"));
+    }
+
+    let config = get_config();
+    let product_id = resolve_product_id(db, &config.sku_column, &[sku.to_string()])
+        .await?
+        .ok_or_else(|| PluginError::not_found(format!("No product with SKU {sku}")))?;
+    product_price(db, cache, args, product_id).await
+}
+
+pub(crate) async fn handle_get_product_by_barcode(
+    db: &dyn DatabaseBackend,
+    cache: &dyn CacheBackend,
+    args: &Value,
+) -> Result<Value, PluginError> {
+    let BarcodeArgs { barcode } = args::parse(args)?;
+    let digits = normalize_barcode(&barcode)?;
+
+    let config = get_config();
+    let product_id = resolve_product_id(db, &config.barcode_column, &barcode_variants(&digits))
+        .await?
+        .ok_or_else(|| PluginError::not_found(format!("No product with barcode {digits}")))?;
+    product_price(db, cache, args, product_id).await
+}
@@ -0,0 +1,395 @@
+//! Plugin ABI extensions not (yet) covered by `mcp-plugin-api`
+
+/// Declare a plugin shutdown function with automatic wrapper generation
+///
+/// Mirrors `declare_plugin_init!`: the native function has the signature
+///
+/// ```ignore
+/// fn my_shutdown() -> Result<(), String>
+/// ```
+///
+/// and the macro generates an exported `plugin_shutdown` C ABI function. The
+/// `PluginDeclaration` has no slot for it, so hosts that support teardown
+/// look the symbol up by name before unloading the library.
+macro_rules! declare_plugin_shutdown {
+    ($native_fn:ident) => {
+        /// Auto-generated shutdown function for plugin ABI
+        ///
+        /// Called by the host before the plugin library is unloaded.
+        /// On failure the error message is returned like in `plugin_init`
+        /// and must be released with `free_string`.
+        ///
+        /// # Safety
+        ///
+        /// `error_msg_ptr` and `error_msg_len` must be valid for writes.
+        #[no_mangle]
+        pub unsafe extern "C" fn plugin_shutdown(
+            error_msg_ptr: *mut *mut ::std::primitive::u8,
+            error_msg_len: *mut ::std::primitive::usize,
+        ) -> ::std::primitive::i32 {
+            match $native_fn() {
+                ::std::result::Result::Ok(_) => 0, // Success
+                ::std::result::Result::Err(e) => {
+                    ::mcp_plugin_api::utils::return_error(&e, error_msg_ptr, error_msg_len)
+                }
+            }
+        }
+    };
+}
+
+/// Declare MCP resource support with automatic wrapper generation
+///
+/// Takes three native functions:
+///
+/// ```ignore
+/// fn list() -> Result<Value, String>            // {"resources": [...]}
+/// fn templates() -> Result<Value, String>       // {"resourceTemplates": [...]}
+/// fn read(uri: &str) -> Result<Value, String>   // {"contents": [...]}
+/// ```
+///
+/// and generates the exported `plugin_list_resources`,
+/// `plugin_list_resource_templates` and `plugin_read_resource` C ABI
+/// functions. Results and errors are returned like those of
+/// `generated_list_tools` and `generated_execute_tool`.
+macro_rules! declare_resources {
+    (list: $list_fn:ident, templates: $templates_fn:ident, read: $read_fn:ident $(,)?) => {
+        /// Auto-generated function listing the plugin's resources
+        ///
+        /// # Safety
+        ///
+        /// `result_buf` and `result_len` must be valid for writes.
+        #[no_mangle]
+        pub unsafe extern "C" fn plugin_list_resources(
+            result_buf: *mut *mut ::std::primitive::u8,
+            result_len: *mut ::std::primitive::usize,
+        ) -> ::std::primitive::i32 {
+            match $list_fn() {
+                ::std::result::Result::Ok(result) => {
+                    ::mcp_plugin_api::utils::return_success(result, result_buf, result_len)
+                }
+                ::std::result::Result::Err(e) => {
+                    ::mcp_plugin_api::utils::return_error(&e, result_buf, result_len)
+                }
+            }
+        }
+
+        /// Auto-generated function listing the plugin's resource templates
+        ///
+        /// # Safety
+        ///
+        /// `result_buf` and `result_len` must be valid for writes.
+        #[no_mangle]
+        pub unsafe extern "C" fn plugin_list_resource_templates(
+            result_buf: *mut *mut ::std::primitive::u8,
+            result_len: *mut ::std::primitive::usize,
+        ) -> ::std::primitive::i32 {
+            match $templates_fn() {
+                ::std::result::Result::Ok(result) => {
+                    ::mcp_plugin_api::utils::return_success(result, result_buf, result_len)
+                }
+                ::std::result::Result::Err(e) => {
+                    ::mcp_plugin_api::utils::return_error(&e, result_buf, result_len)
+                }
+            }
+        }
+
+        /// Auto-generated function reading a resource by URI
+        ///
+        /// # Safety
+        ///
+        /// `uri` must be a valid null-terminated C string, `result_buf` and
+        /// `result_len` must be valid for writes.
+        #[no_mangle]
+        pub unsafe extern "C" fn plugin_read_resource(
+            uri: *const ::std::os::raw::c_char,
+            result_buf: *mut *mut ::std::primitive::u8,
+            result_len: *mut ::std::primitive::usize,
+        ) -> ::std::primitive::i32 {
+            let uri = match ::std::ffi::CStr::from_ptr(uri).to_str() {
+                ::std::result::Result::Ok(uri) => uri,
+                ::std::result::Result::Err(_) => {
+                    return ::mcp_plugin_api::utils::return_error(
+                        "Invalid resource URI encoding",
+                        result_buf,
+                        result_len,
+                    )
+                }
+            };
+
+            match $read_fn(uri) {
+                ::std::result::Result::Ok(result) => {
+                    ::mcp_plugin_api::utils::return_success(result, result_buf, result_len)
+                }
+                ::std::result::Result::Err(e) => {
+                    ::mcp_plugin_api::utils::return_error(&e, result_buf, result_len)
+                }
+            }
+        }
+    };
+}
+
+/// Declare progress notification support
+///
+/// Takes the native function storing the host callback
+///
+/// ```ignore
+/// fn set_callback(callback: Option<ProgressCallback>)
+/// ```
+///
+/// and generates the exported `plugin_set_progress_callback` C ABI
+/// function. Hosts that forward MCP progress notifications look it up by
+/// name and register their callback after init; passing a null pointer
+/// removes it again.
+macro_rules! declare_progress_callback {
+    ($set_fn:path) => {
+        /// Auto-generated function registering the host's progress callback
+        ///
+        /// # Safety
+        ///
+        /// `callback` must be null or a function that stays valid until it
+        /// is replaced or the plugin is unloaded.
+        #[no_mangle]
+        pub unsafe extern "C" fn plugin_set_progress_callback(
+            callback: ::std::option::Option<$crate::progress::ProgressCallback>,
+        ) {
+            $set_fn(callback)
+        }
+    };
+}
+
+/// Declare a configuration that can be replaced after init
+///
+/// Replaces `declare_plugin_config!`, whose generated
+/// `plugin_configure` rejects every call after the first one. Here the
+/// first call stores the configuration and later calls hand the parsed
+/// configuration to the native function
+///
+/// ```ignore
+/// fn reconfigure(config: ConfigType) -> Result<(), String>
+/// ```
+///
+/// which is expected to apply it and publish it through the generated
+/// `store_config`. `get_config` returns a snapshot, so a handler sees one
+/// consistent configuration even if it is replaced concurrently.
+macro_rules! declare_reloadable_config {
+    ($config_type:ty, $reconfigure_fn:ident) => {
+        static __PLUGIN_CONFIG: ::std::sync::OnceLock<::arc_swap::ArcSwap<$config_type>> =
+            ::std::sync::OnceLock::new();
+
+        /// Get the current plugin configuration
+        ///
+        /// # Panics
+        ///
+        /// Panics if the plugin has not been configured yet.
+        pub(crate) fn get_config() -> ::std::sync::Arc<$config_type> {
+            __PLUGIN_CONFIG
+                .get()
+                .expect("Plugin not configured - configure() must be called first")
+                .load_full()
+        }
+
+        /// Get the current plugin configuration, `None` before the first
+        /// `plugin_configure`
+        pub(crate) fn try_get_config() -> ::std::option::Option<::std::sync::Arc<$config_type>> {
+            __PLUGIN_CONFIG.get().map(|config| config.load_full())
+        }
+
+        /// Publish a new configuration to subsequent `get_config` calls
+        fn store_config(config: ::std::sync::Arc<$config_type>) {
+            match __PLUGIN_CONFIG.get() {
+                ::std::option::Option::Some(current) => current.store(config),
+                ::std::option::Option::None => {
+                    let _ = __PLUGIN_CONFIG.set(::arc_swap::ArcSwap::new(config));
+                }
+            }
+        }
+
+        /// Auto-generated configuration function
+        ///
+        /// Called by the framework during plugin loading and again whenever
+        /// the configuration changes.
+        ///
+        /// # Returns
+        /// - 0 on success
+        /// - 1 on JSON parsing error
+        /// - 3 if the new configuration could not be applied
+        ///
+        /// # Safety
+        ///
+        /// `config_json` must point to `config_len` readable bytes.
+        #[no_mangle]
+        pub unsafe extern "C" fn plugin_configure(
+            config_json: *const ::std::primitive::u8,
+            config_len: ::std::primitive::usize,
+        ) -> ::std::primitive::i32 {
+            let config_slice = ::std::slice::from_raw_parts(config_json, config_len);
+            let config: $config_type = match ::serde_json::from_slice(config_slice) {
+                ::std::result::Result::Ok(c) => c,
+                ::std::result::Result::Err(e) => {
+                    ::std::eprintln!("Failed to parse plugin config: {}", e);
+                    return 1; // Error code
+                }
+            };
+
+            if __PLUGIN_CONFIG.get().is_none() {
+                store_config(::std::sync::Arc::new(config));
+                return 0;
+            }
+
+            match $reconfigure_fn(config) {
+                ::std::result::Result::Ok(_) => 0, // Success
+                ::std::result::Result::Err(e) => {
+                    ::std::eprintln!("Failed to apply plugin config: {}", e);
+                    3
+                }
+            }
+        }
+    };
+}
+
+/// Declare the output schemas of the tools
+///
+/// Takes the native function mapping a tool name to its output schema, one
+/// extending the input schema of every tool with arguments handled outside
+/// the tools themselves and one telling whether a tool is offered
+///
+/// ```ignore
+/// fn output_schema(tool: &str) -> Option<Value>
+/// fn extend_input_schema(input_schema: &mut Value)
+/// fn is_enabled(tool: &str) -> bool
+/// ```
+///
+/// and generates two C ABI functions next to the ones of `declare_tools!`:
+/// `plugin_list_tools`, which lists the offered tools like
+/// `generated_list_tools` with the `outputSchema` added where one is
+/// declared, and is passed to `declare_plugin!` in its place; and
+/// `plugin_get_tool_output_schemas`, which returns the schemas of the
+/// offered tools as `{"<tool>": <schema>}` for hosts that validate results
+/// themselves.
+macro_rules! declare_tool_output_schemas {
+    ($schema_fn:path, $input_fn:path, $enabled_fn:path) => {
+        /// Auto-generated function listing the tools with their output schemas
+        ///
+        /// # Safety
+        ///
+        /// `result_buf` and `result_len` must be valid for writes.
+        #[no_mangle]
+        pub unsafe extern "C" fn plugin_list_tools(
+            result_buf: *mut *mut ::std::primitive::u8,
+            result_len: *mut ::std::primitive::usize,
+        ) -> ::std::primitive::i32 {
+            let tools: ::std::vec::Vec<::serde_json::Value> = get_tools()
+                .values()
+                .filter(|tool| $enabled_fn(&tool.name))
+                .map(|tool| {
+                    let mut schema = tool.to_json_schema();
+                    $input_fn(&mut schema["inputSchema"]);
+                    if let ::std::option::Option::Some(output) = $schema_fn(&tool.name) {
+                        schema["outputSchema"] = output;
+                    }
+                    schema
+                })
+                .collect();
+            ::mcp_plugin_api::utils::return_success(::serde_json::Value::Array(tools), result_buf, result_len)
+        }
+
+        /// Auto-generated function returning the output schemas by tool name
+        ///
+        /// # Safety
+        ///
+        /// `schema_ptr` and `schema_len` must be valid for writes.
+        #[no_mangle]
+        pub unsafe extern "C" fn plugin_get_tool_output_schemas(
+            schema_ptr: *mut *mut ::std::primitive::u8,
+            schema_len: *mut ::std::primitive::usize,
+        ) -> ::std::primitive::i32 {
+            let schemas: ::serde_json::Map<::std::string::String, ::serde_json::Value> = get_tools()
+                .keys()
+                .filter(|name| $enabled_fn(name))
+                .filter_map(|name| $schema_fn(name).map(|schema| (name.clone(), schema)))
+                .collect();
+            ::mcp_plugin_api::utils::return_success(::serde_json::Value::Object(schemas), schema_ptr, schema_len)
+        }
+    };
+}
+
+/// Declare the tools together with their async handlers
+///
+/// Takes the `declare_tools!` entries without their `.handler(...)`, each
+/// followed by `=>` and its async handler as a closure over the
+/// `ToolContext` and the call arguments:
+///
+/// ```ignore
+/// declare_async_tools! {
+///     tools: [
+///         Tool::builder("list_categories", "List product categories")
+///             .param_string("tenant", "Tenant whose schema to use", false)
+///             => |ctx, args| handle_list_categories(&**ctx.db, args),
+///     ]
+/// }
+/// ```
+///
+/// and expands to the `declare_tools!` invocation, with `dispatch_async`
+/// of the tool's name as every handler, and to
+/// `register_declared_tools(registry: &mut Registry)`, which registers the
+/// async handlers with the runtime's registry.
+///
+/// It also generates `plugin_execute_tool`, passed to `declare_plugin!` in
+/// place of `generated_execute_tool`. That one lends the handler arguments
+/// it parsed, which `dispatch_async` would have to copy to move them to
+/// the runtime; `plugin_execute_tool` hands them over.
+macro_rules! declare_async_tools {
+    (tools: [ $(
+        Tool::builder($name:literal, $description:expr) $( .$method:ident( $($arg:expr),* $(,)? ) )*
+            => |$ctx:ident, $args:ident| $handler:expr
+    ),* $(,)? ]) => {
+        declare_tools! {
+            tools: [ $(
+                Tool::builder($name, $description) $( .$method( $($arg),* ) )*
+                    .handler(|args| dispatch_async($name, args.clone()))
+            ),* ]
+        }
+
+        /// Auto-generated function executing a tool with the arguments it
+        /// parsed
+        ///
+        /// # Safety
+        ///
+        /// `tool_name` must be a valid C string and `args_json` valid for
+        /// reads of `args_len` bytes. `result_buf` and `result_len` must be
+        /// valid for writes.
+        #[no_mangle]
+        pub unsafe extern "C" fn plugin_execute_tool(
+            tool_name: *const ::std::os::raw::c_char,
+            args_json: *const ::std::primitive::u8,
+            args_len: ::std::primitive::usize,
+            result_buf: *mut *mut ::std::primitive::u8,
+            result_len: *mut ::std::primitive::usize,
+        ) -> ::std::primitive::i32 {
+            use ::mcp_plugin_api::utils::{return_error, return_success};
+
+            let ::std::result::Result::Ok(name) = ::std::ffi::CStr::from_ptr(tool_name).to_str() else {
+                return return_error("Invalid tool name encoding", result_buf, result_len);
+            };
+            let args_slice = ::std::slice::from_raw_parts(args_json, args_len);
+            let args: ::serde_json::Value = match ::serde_json::from_slice(args_slice) {
+                ::std::result::Result::Ok(args) => args,
+                ::std::result::Result::Err(e) => {
+                    return return_error(&format!("Invalid JSON arguments: {e}"), result_buf, result_len)
+                }
+            };
+            if !get_tools().contains_key(name) {
+                return return_error(&format!("Unknown tool: {name}"), result_buf, result_len);
+            }
+            match dispatch_async(name, args) {
+                ::std::result::Result::Ok(result) => return_success(result, result_buf, result_len),
+                ::std::result::Result::Err(e) => return_error(&e, result_buf, result_len),
+            }
+        }
+
+        /// Register the async handlers of the tools in `declare_async_tools!`
+        fn register_declared_tools(registry: &mut Registry) {
+            $( registry.register($name, |$ctx, $args| ::std::boxed::Box::pin($handler)); )*
+        }
+    };
+}
@@ -0,0 +1,310 @@
+//! Product table mapping
+//!
+//! The plugin's queries are written against a `products` relation with the
+//! columns `id, name, price, description, category, extra, unit_quantity,
+//! unit_of_measure, status, brand, image_url, thumbnail_url, attributes`.
+//! The
+//! `schema_mapping` config points that relation at an existing catalogue
+//! table instead: every query reads from a sub-select renaming the mapped
+//! columns to the canonical names, which Postgres flattens into the outer
+//! query, so filters and indexes on the underlying table still apply.
+//!
+//! `extra_columns` are passed through as the JSON object `extra` of every
+//! product. Writes and SKU/barcode lookups address the mapped table and
+//! columns directly. The optional `cost_column` is never part of the
+//! `products` relation; only the margin tools read it, through their own
+//! relation.
+//!
+//! With a `status_column`, products whose status is not one of
+//! `active_statuses` are left out of searches unless asked for. The
+//! `access_policy` restricts the relation to the products a deployment may
+//! see, see the access module.
+
+use crate::attributes::AttributeMapping;
+use crate::backend::BackendKind;
+use crate::sql::quote_identifier;
+use crate::{get_config, PluginConfig};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// Columns selected by every product query, in the mapped relation
+pub(crate) const PRODUCT_COLUMNS: &str =
+    "id, name, price, description, category, extra, unit_quantity, unit_of_measure, status, brand, image_url, \
+     thumbnail_url, attributes";
+
+/// Where the product catalogue lives
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, JsonSchema)]
+pub(crate) struct SchemaMapping {
+    /// Product table, optionally schema qualified
+    #[serde(default = "default_table")]
+    pub(crate) table: String,
+
+    /// Integer primary key column
+    #[serde(default = "default_id_column")]
+    pub(crate) id_column: String,
+
+    /// Product name column
+    #[serde(default = "default_name_column")]
+    pub(crate) name_column: String,
+
+    /// Price column (NUMERIC, in base currency)
+    #[serde(default = "default_price_column")]
+    pub(crate) price_column: String,
+
+    /// Description column, null if the table has none
+    #[serde(default = "default_description_column")]
+    pub(crate) description_column: Option<String>,
+
+    /// Category column, null if the table has none
+    #[serde(default = "default_category_column")]
+    pub(crate) category_column: Option<String>,
+
+    /// Further columns returned under `extra` with every product
+    #[serde(default)]
+    pub(crate) extra_columns: Vec<String>,
+
+    /// Unit cost column (NUMERIC, in base currency), read by the margin
+    /// tools only; null if the table has none
+    #[serde(default)]
+    pub(crate) cost_column: Option<String>,
+
+    /// Package size column (NUMERIC), e.g. 500 for 500 g; null if the
+    /// table has none
+    #[serde(default)]
+    pub(crate) unit_quantity_column: Option<String>,
+
+    /// Unit of the package size column, e.g. g, kg, ml, l or pc; null if
+    /// the table has none
+    #[serde(default)]
+    pub(crate) unit_of_measure_column: Option<String>,
+
+    /// Product status column, e.g. with active, discontinued or draft;
+    /// null if the table has none
+    #[serde(default)]
+    pub(crate) status_column: Option<String>,
+
+    /// Statuses of products that are for sale
+    #[serde(default = "default_active_statuses")]
+    pub(crate) active_statuses: Vec<String>,
+
+    /// Brand column, returned as `brand` and filtered on by searches, the
+    /// brand tools and the access policy; null if the table has none
+    #[serde(default)]
+    pub(crate) brand_column: Option<String>,
+
+    /// Product image column, a URL or a key under image_store_path; null
+    /// if the table has none
+    #[serde(default)]
+    pub(crate) image_url_column: Option<String>,
+
+    /// Thumbnail image column, like image_url_column; null if the table
+    /// has none
+    #[serde(default)]
+    pub(crate) thumbnail_url_column: Option<String>,
+
+    /// JSONB column or attribute table holding further product
+    /// attributes, see the attributes module; null if there are none
+    #[serde(default)]
+    pub(crate) attributes: Option<AttributeMapping>,
+}
+
+impl Default for SchemaMapping {
+    fn default() -> Self {
+        SchemaMapping {
+            table: default_table(),
+            id_column: default_id_column(),
+            name_column: default_name_column(),
+            price_column: default_price_column(),
+            description_column: default_description_column(),
+            category_column: default_category_column(),
+            extra_columns: Vec::new(),
+            cost_column: None,
+            unit_quantity_column: None,
+            unit_of_measure_column: None,
+            status_column: None,
+            active_statuses: default_active_statuses(),
+            brand_column: None,
+            image_url_column: None,
+            thumbnail_url_column: None,
+            attributes: None,
+        }
+    }
+}
+
+fn default_table() -> String {
+    "products".to_string()
+}
+
+fn default_id_column() -> String {
+    "id".to_string()
+}
+
+fn default_name_column() -> String {
+    "name".to_string()
+}
+
+fn default_price_column() -> String {
+    "price".to_string()
+}
+
+fn default_description_column() -> Option<String> {
+    Some("description".to_string())
+}
+
+fn default_category_column() -> Option<String> {
+    Some("category".to_string())
+}
+
+fn default_active_statuses() -> Vec<String> {
+    vec!["active".to_string()]
+}
+
+/// Quote a column name, which must not be qualified
+pub(crate) fn quote_column(column: &str) -> Result<String, String> {
+    if column.contains('.') {
+        return Err(format!("Invalid column '{column}': must not be qualified"));
+    }
+    quote_identifier(column)
+}
+
+impl SchemaMapping {
+    fn validate(&self) -> Result<(), String> {
+        quote_identifier(&self.table)?;
+        let columns = [&self.id_column, &self.name_column, &self.price_column]
+            .into_iter()
+            .chain(&self.description_column)
+            .chain(&self.category_column)
+            .chain(&self.extra_columns)
+            .chain(&self.cost_column)
+            .chain(&self.unit_quantity_column)
+            .chain(&self.unit_of_measure_column)
+            .chain(&self.status_column)
+            .chain(&self.brand_column)
+            .chain(&self.image_url_column)
+            .chain(&self.thumbnail_url_column);
+        for column in columns {
+            quote_column(column)?;
+        }
+        if let Some(attributes) = &self.attributes {
+            attributes.validate()?;
+        }
+        if self.active_statuses.is_empty() {
+            return Err("schema_mapping.active_statuses must not be empty".to_string());
+        }
+        Ok(())
+    }
+
+    /// The mapped table, quoted
+    pub(crate) fn table(&self) -> String {
+        quote_identifier(&self.table).expect("validated schema_mapping")
+    }
+
+    /// A mapped column, quoted
+    pub(crate) fn column(&self, column: &str) -> String {
+        quote_column(column).expect("validated schema_mapping")
+    }
+
+    /// A mapped optional text column, or NULL if the table has none
+    fn optional_column(&self, column: &Option<String>) -> String {
+        match column {
+            Some(column) => self.column(column),
+            None => "NULL::text".to_string(),
+        }
+    }
+
+    /// Sub-select with the product's price and cost as `margins`, if a
+    /// cost column is mapped, restricted to the rows matching `filter`
+    pub(crate) fn margin_relation(&self, filter: Option<&str>) -> Option<String> {
+        let cost = self.cost_column.as_ref()?;
+        Some(format!(
+            "(SELECT {} AS id, {} AS name, {} AS category, {} AS price, {} AS cost FROM {}{}) AS margins",
+            self.column(&self.id_column),
+            self.column(&self.name_column),
+            self.optional_column(&self.category_column),
+            self.column(&self.price_column),
+            self.column(cost),
+            self.table(),
+            where_clause(filter)
+        ))
+    }
+
+    /// Sub-select exposing the table under the canonical column names,
+    /// restricted to the rows matching `filter`
+    fn relation(&self, filter: Option<&str>) -> String {
+        let optional = |column: &Option<String>| self.optional_column(column);
+        let extra = if self.extra_columns.is_empty() {
+            "NULL::jsonb".to_string()
+        } else {
+            let fields: Vec<String> = self
+                .extra_columns
+                .iter()
+                .map(|column| format!("'{column}', {}", self.column(column)))
+                .collect();
+            format!("jsonb_build_object({})", fields.join(", "))
+        };
+        let attributes = match &self.attributes {
+            Some(attributes) => attributes.expression(self),
+            None => "NULL::jsonb".to_string(),
+        };
+        let unit_quantity = match &self.unit_quantity_column {
+            Some(column) => format!("{}::numeric", self.column(column)),
+            None => "NULL::numeric".to_string(),
+        };
+        format!(
+            "(SELECT {} AS id, {} AS name, {} AS price, {} AS description, {} AS category, {extra} AS extra, \
+             {unit_quantity} AS unit_quantity, {}::text AS unit_of_measure, {}::text AS status, {}::text AS brand, \
+             {}::text AS image_url, {}::text AS thumbnail_url, {attributes} AS attributes FROM {}{}) AS products",
+            self.column(&self.id_column),
+            self.column(&self.name_column),
+            self.column(&self.price_column),
+            optional(&self.description_column),
+            optional(&self.category_column),
+            optional(&self.unit_of_measure_column),
+            optional(&self.status_column),
+            optional(&self.brand_column),
+            optional(&self.image_url_column),
+            optional(&self.thumbnail_url_column),
+            self.table(),
+            where_clause(filter)
+        )
+    }
+
+    /// Whether a product with `status` is for sale; products without a
+    /// status always are
+    pub(crate) fn is_active(&self, status: Option<&str>) -> bool {
+        status.is_none_or(|status| self.active_statuses.iter().any(|active| active == status))
+    }
+}
+
+fn where_clause(filter: Option<&str>) -> String {
+    filter.map(|filter| format!(" WHERE {filter}")).unwrap_or_default()
+}
+
+/// Validate the mapping, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    config.schema_mapping.validate()?;
+    if config.schema_mapping != SchemaMapping::default()
+        && BackendKind::parse(&config.backend)? != BackendKind::Postgres
+    {
+        return Err("schema_mapping requires the postgres backend".to_string());
+    }
+    Ok(())
+}
+
+/// The `products` relation to use in FROM clauses of Postgres queries,
+/// holding the products the access policy lets this deployment see
+pub(crate) fn products() -> String {
+    let config = get_config();
+    let filter = config.access_policy.row_filter(&config.schema_mapping);
+    config.schema_mapping.relation(filter.as_deref())
+}
+
+/// Condition on the mapped table selecting the products this deployment
+/// may see, for statements addressing the table directly
+pub(crate) fn visible_rows() -> String {
+    let config = get_config();
+    config
+        .access_policy
+        .row_filter(&config.schema_mapping)
+        .unwrap_or_else(|| "true".to_string())
+}
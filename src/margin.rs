@@ -0,0 +1,183 @@
+//! Cost and margin
+//!
+//! Unit costs are sensitive, so the margin tools refuse to run unless
+//! `expose_costs` is set, which in turn requires `schema_mapping.cost_column`.
+//! The cost column is read only here and never returned by the other tools.
+//!
+//! The margin is the gross margin on the list price: `price - cost`, and in
+//! percent of the price. Products without a cost have no margin and are
+//! left out of `find_low_margin_products`.
+
+use crate::args::{self, DecimalArg};
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::{format_price, get_config, PluginConfig};
+use mcp_plugin_api::utils;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Page size of find_low_margin_products without `limit`
+const DEFAULT_LOW_MARGIN_LIMIT: i64 = 50;
+
+/// Upper bound of the `limit` argument
+const MAX_LOW_MARGIN_LIMIT: i64 = 500;
+
+/// Validate the margin settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if config.expose_costs && config.schema_mapping.cost_column.is_none() {
+        return Err("expose_costs requires schema_mapping.cost_column".to_string());
+    }
+    Ok(())
+}
+
+/// The relation with price and cost, if costs may be exposed
+fn margin_relation() -> Result<String, PluginError> {
+    let config = get_config();
+    if !config.expose_costs {
+        return Err(PluginError::PermissionDenied(
+            "Margin tools are disabled, set expose_costs to allow them".to_string(),
+        ));
+    }
+    let filter = config.access_policy.row_filter(&config.schema_mapping);
+    config
+        .schema_mapping
+        .margin_relation(filter.as_deref())
+        .ok_or_else(|| PluginError::internal("expose_costs is set without a cost column"))
+}
+
+/// Unit cost of a product, `None` unless expose_costs is set and a cost is recorded
+pub(crate) async fn product_cost(pool: &sqlx::PgPool, product_id: i32) -> Result<Option<Decimal>, PluginError> {
+    let Ok(relation) = margin_relation() else {
+        return Ok(None);
+    };
+    let cost = sqlx::query_scalar::<_, Option<Decimal>>(&format!("SELECT cost FROM {relation} WHERE id = $1"))
+        .bind(product_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(cost.flatten())
+}
+
+#[derive(sqlx::FromRow)]
+struct ProductMargin {
+    id: i32,
+    name: String,
+    category: Option<String>,
+    price: Decimal,
+    cost: Option<Decimal>,
+}
+
+impl ProductMargin {
+    fn margin(&self) -> Option<Decimal> {
+        self.cost.map(|cost| self.price - cost)
+    }
+
+    fn margin_percent(&self) -> Option<Decimal> {
+        let margin = self.margin()?;
+        (!self.price.is_zero()).then(|| (margin / self.price * Decimal::ONE_HUNDRED).round_dp(2))
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "category": self.category,
+            "price": format_price(&self.price),
+            "cost": self.cost.as_ref().map(format_price),
+            "margin": self.margin().as_ref().map(format_price),
+            "margin_percent": self.margin_percent().map(|percent| percent.to_string())
+        })
+    }
+}
+
+/// Arguments of get_product_margin
+#[derive(Debug, Deserialize, JsonSchema)]
+struct MarginArgs {
+    product_id: i32,
+}
+
+pub(crate) async fn handle_get_product_margin(db: &dyn DatabaseBackend, args: &Value) -> Result<Value, PluginError> {
+    let relation = margin_relation()?;
+    let pool = db.postgres()?;
+    let MarginArgs { product_id } = args::parse(args)?;
+
+    let product = sqlx::query_as::<_, ProductMargin>(&format!(
+        "SELECT id, name, category, price, cost FROM {relation} WHERE id = $1"
+    ))
+    .bind(product_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| PluginError::not_found(format!("Product {product_id} not found")))?;
+
+    let mut result = product.to_json();
+    result["base_currency"] = json!(get_config().base_currency);
+
+    // Return structured JSON data for programmatic clients
+    Ok(utils::json_content(result))
+}
+
+/// Arguments of find_low_margin_products
+#[derive(Debug, Deserialize, JsonSchema)]
+struct LowMarginArgs {
+    threshold_percent: DecimalArg,
+    category: Option<String>,
+    #[schemars(range(min = 1, max = "MAX_LOW_MARGIN_LIMIT"))]
+    limit: Option<i64>,
+    #[serde(default)]
+    #[schemars(range(min = 0))]
+    offset: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct LowMarginRow {
+    #[sqlx(flatten)]
+    product: ProductMargin,
+    total: i64,
+}
+
+pub(crate) async fn handle_find_low_margin_products(
+    db: &dyn DatabaseBackend,
+    args: &Value,
+) -> Result<Value, PluginError> {
+    let relation = margin_relation()?;
+    let pool = db.postgres()?;
+    let LowMarginArgs {
+        threshold_percent,
+        category,
+        limit,
+        offset,
+    } = args::parse(args)?;
+    let threshold = threshold_percent.to_decimal("threshold_percent")?;
+    let limit = limit.unwrap_or(DEFAULT_LOW_MARGIN_LIMIT);
+
+    // Lowest margin first; a zero price counts as the lowest possible margin
+    let rows = sqlx::query_as::<_, LowMarginRow>(&format!(
+        "SELECT id, name, category, price, cost, count(*) OVER () AS total \
+         FROM {relation} \
+         WHERE cost IS NOT NULL \
+           AND (price = 0 OR (price - cost) / price * 100 < $1) \
+           AND ($2::text IS NULL OR category = $2) \
+         ORDER BY CASE WHEN price = 0 THEN NULL ELSE (price - cost) / price END ASC NULLS FIRST, id \
+         LIMIT $3 OFFSET $4"
+    ))
+    .bind(threshold)
+    .bind(&category)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    let total = rows.first().map_or(0, |row| row.total);
+    let products: Vec<Value> = rows.iter().map(|row| row.product.to_json()).collect();
+
+    // Return structured JSON data for programmatic clients
+    Ok(utils::json_content(json!({
+        "threshold_percent": threshold.to_string(),
+        "products": products,
+        "count": products.len(),
+        "total": total,
+        "offset": offset,
+        "base_currency": get_config().base_currency
+    })))
+}
@@ -0,0 +1,252 @@
+//! Per-call timing and size report
+//!
+//! With `include_meta` set, globally or as a call argument (which takes
+//! precedence), the result of a tool call gets a `_meta` object telling
+//! where the time went:
+//!
+//! - `duration_ms`: from dispatch to the response, including `queue_ms`
+//!   spent waiting for a concurrency slot
+//! - `pool_wait_ms`: time to get a connection from the Postgres pool when
+//!   the call started, null on other backends
+//! - `query_ms`, `queries` and `rows_scanned`: time spent in, number of and
+//!   rows read by the catalogue queries of the `DatabaseBackend` trait
+//!   (lookups, searches and categories); statements tools run on the pool
+//!   directly are not counted
+//! - `rows_returned`: rows in the result, 1 for a single object
+//! - `cache_hit`: whether the result cache answered, null if the tool did
+//!   not consult it
+//! - `request_bytes` and `response_bytes`: size of the arguments and of the
+//!   result without `_meta`
+//!
+//! The database and cache of such calls are wrapped to take the
+//! measurements; other calls run unwrapped.
+
+use crate::backend::{Database, DatabaseBackend, ProductList, ProductSearch};
+use crate::cache::{Cache, CacheBackend, CacheKey};
+use crate::error::PluginError;
+use crate::registry::ToolContext;
+use crate::search::SearchHit;
+use crate::truncation::rows_field;
+use crate::{get_config, CategoryCount, Product};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// `cache_hit` states, besides 0 while the cache was not consulted
+const CACHE_MISS: u8 = 1;
+const CACHE_HIT: u8 = 2;
+
+/// Measurements of one tool call
+#[derive(Default)]
+pub(crate) struct CallMeta {
+    query_micros: AtomicU64,
+    queries: AtomicU64,
+    rows_scanned: AtomicU64,
+    /// Last cache lookup, one of the `CACHE_*` states
+    cache: AtomicU8,
+}
+
+impl CallMeta {
+    /// Time a catalogue query and count the rows it read
+    async fn query<T>(
+        &self,
+        query: impl Future<Output = Result<T, PluginError>>,
+        rows: impl Fn(&T) -> usize,
+    ) -> Result<T, PluginError> {
+        let started = Instant::now();
+        let result = query.await;
+        self.query_micros.fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        if let Ok(value) = &result {
+            self.rows_scanned.fetch_add(rows(value) as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Add the `_meta` object to a successful tool result
+    pub(crate) fn attach(
+        &self,
+        mut result: Value,
+        args: &Value,
+        elapsed: Duration,
+        queued: Duration,
+        pool_wait: Option<Duration>,
+    ) -> Value {
+        let cache_hit = match self.cache.load(Ordering::Relaxed) {
+            CACHE_HIT => Some(true),
+            CACHE_MISS => Some(false),
+            _ => None,
+        };
+        let meta = json!({
+            "duration_ms": millis(elapsed),
+            "queue_ms": millis(queued),
+            "pool_wait_ms": pool_wait.map(millis),
+            "query_ms": self.query_micros.load(Ordering::Relaxed) as f64 / 1000.0,
+            "queries": self.queries.load(Ordering::Relaxed),
+            "rows_scanned": self.rows_scanned.load(Ordering::Relaxed),
+            "rows_returned": rows_returned(&result),
+            "cache_hit": cache_hit,
+            "request_bytes": args.to_string().len(),
+            "response_bytes": result.to_string().len()
+        });
+        if let Some(result) = result.as_object_mut() {
+            result.insert("_meta".to_string(), meta);
+        }
+        result
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1000.0
+}
+
+/// Rows of the JSON content of a result
+fn rows_returned(result: &Value) -> usize {
+    let content = result["content"].as_array().into_iter().flatten();
+    content
+        .filter(|block| block["type"] == "json")
+        .map(|block| match block["json"].as_object() {
+            Some(object) => rows_field(object).map_or(1, |field| object[&field].as_array().map_or(0, Vec::len)),
+            None => block["json"].as_array().map_or(0, Vec::len),
+        })
+        .sum()
+}
+
+/// Add the `include_meta` argument to a tool's input schema
+pub(crate) fn extend_input_schema(input_schema: &mut Value) {
+    input_schema["properties"]["include_meta"] = json!({
+        "type": "boolean",
+        "description": "Add a _meta object with the call's duration, query time, rows, cache hit and pool wait to the result"
+    });
+}
+
+/// Whether the call reports its measurements
+pub(crate) fn requested(args: &Value) -> Result<bool, PluginError> {
+    match &args["include_meta"] {
+        Value::Null => Ok(get_config().include_meta),
+        Value::Bool(include_meta) => Ok(*include_meta),
+        _ => Err(PluginError::invalid_argument("Invalid include_meta parameter, expected a boolean")),
+    }
+}
+
+/// Time to get a Postgres connection, `None` on other backends or if none
+/// can be had
+pub(crate) async fn pool_wait(db: &dyn DatabaseBackend) -> Option<Duration> {
+    let pool = db.postgres().ok()?;
+    let started = Instant::now();
+    let connection = pool.acquire().await.ok()?;
+    let waited = started.elapsed();
+    drop(connection);
+    Some(waited)
+}
+
+/// Context of a call whose database and cache take the measurements
+pub(crate) fn metered(ctx: ToolContext, meta: &Arc<CallMeta>) -> ToolContext {
+    let db: Database = Box::new(MeteredBackend {
+        inner: ctx.db,
+        meta: meta.clone(),
+    });
+    let cache: Cache = Box::new(MeteredCache {
+        inner: ctx.cache,
+        meta: meta.clone(),
+    });
+    ToolContext {
+        db: Arc::new(db),
+        cache: Arc::new(cache),
+        metrics: ctx.metrics,
+    }
+}
+
+/// Database of a metered call
+struct MeteredBackend {
+    inner: Arc<Database>,
+    meta: Arc<CallMeta>,
+}
+
+impl DatabaseBackend for MeteredBackend {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn postgres(&self) -> Result<&PgPool, PluginError> {
+        self.inner.postgres()
+    }
+
+    fn primary(&self) -> Result<&PgPool, PluginError> {
+        self.inner.primary()
+    }
+
+    fn fetch_product(&self, id: i32) -> BoxFuture<'_, Result<Option<Product>, PluginError>> {
+        self.meta.query(self.inner.fetch_product(id), |product| usize::from(product.is_some())).boxed()
+    }
+
+    fn fetch_products<'a>(&'a self, ids: &'a [i32]) -> BoxFuture<'a, Result<Vec<Product>, PluginError>> {
+        self.meta.query(self.inner.fetch_products(ids), Vec::len).boxed()
+    }
+
+    fn search_products<'a>(
+        &'a self,
+        search: &'a ProductSearch<'a>,
+    ) -> BoxFuture<'a, Result<Vec<SearchHit>, PluginError>> {
+        self.meta.query(self.inner.search_products(search), Vec::len).boxed()
+    }
+
+    fn list_products<'a>(&'a self, list: &'a ProductList) -> BoxFuture<'a, Result<Vec<Product>, PluginError>> {
+        self.meta.query(self.inner.list_products(list), Vec::len).boxed()
+    }
+
+    fn list_categories(&self) -> BoxFuture<'_, Result<Vec<CategoryCount>, PluginError>> {
+        self.meta.query(self.inner.list_categories(), Vec::len).boxed()
+    }
+
+    fn ping(&self) -> BoxFuture<'_, Result<(), PluginError>> {
+        self.inner.ping()
+    }
+
+    fn pool_stats(&self) -> Value {
+        self.inner.pool_stats()
+    }
+
+    fn close(&self) -> BoxFuture<'_, ()> {
+        self.inner.close()
+    }
+}
+
+/// Cache of a metered call
+struct MeteredCache {
+    inner: Arc<Cache>,
+    meta: Arc<CallMeta>,
+}
+
+impl CacheBackend for MeteredCache {
+    fn get<'a>(&'a self, key: &'a CacheKey) -> BoxFuture<'a, Option<Value>> {
+        async move {
+            let cached = self.inner.get(key).await;
+            let state = if cached.is_some() { CACHE_HIT } else { CACHE_MISS };
+            self.meta.cache.store(state, Ordering::Relaxed);
+            cached
+        }
+        .boxed()
+    }
+
+    fn insert(&self, key: CacheKey, value: Value) -> BoxFuture<'_, ()> {
+        self.inner.insert(key, value)
+    }
+
+    fn invalidate_product(&self, product_id: i32) -> BoxFuture<'_, ()> {
+        self.inner.invalidate_product(product_id)
+    }
+
+    fn clear(&self) -> BoxFuture<'_, ()> {
+        self.inner.clear()
+    }
+
+    fn stats(&self) -> Value {
+        self.inner.stats()
+    }
+}
@@ -0,0 +1,199 @@
+//! Plugin metrics
+//!
+//! Request counters, error counters by code and latency histograms per tool
+//! are recorded by the runtime thread after every request. Together with
+//! cache and pool statistics they are served by the `get_plugin_metrics`
+//! tool, as JSON or in the Prometheus text exposition format.
+
+use crate::args;
+use crate::backend::DatabaseBackend;
+use crate::cache::CacheBackend;
+use crate::error::PluginError;
+use mcp_plugin_api::utils;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds of the latency histogram buckets in seconds
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket (not cumulative), last slot is +Inf
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    /// Cumulative counts per bucket bound, as Prometheus expects them
+    fn cumulative(&self) -> impl Iterator<Item = (String, u64)> + '_ {
+        let bounds = LATENCY_BUCKETS
+            .iter()
+            .map(|bound| bound.to_string())
+            .chain(std::iter::once("+Inf".to_string()));
+        bounds.zip(self.buckets.iter().scan(0, |total, count| {
+            *total += count;
+            Some(*total)
+        }))
+    }
+}
+
+#[derive(Default)]
+struct ToolMetrics {
+    requests: u64,
+    errors: BTreeMap<&'static str, u64>,
+    latency: Histogram,
+}
+
+/// Metrics registry shared by all request tasks
+#[derive(Default)]
+pub(crate) struct Metrics {
+    tools: Mutex<HashMap<&'static str, ToolMetrics>>,
+}
+
+impl Metrics {
+    /// Record the outcome of one request
+    pub(crate) fn record(&self, tool: &'static str, elapsed: Duration, result: &Result<Value, PluginError>) {
+        let mut tools = self.tools.lock().unwrap();
+        let metrics = tools.entry(tool).or_default();
+        metrics.requests += 1;
+        metrics.latency.observe(elapsed.as_secs_f64());
+        if let Err(err) = result {
+            *metrics.errors.entry(err.code()).or_default() += 1;
+        }
+    }
+
+    fn tools_json(&self) -> Value {
+        let tools = self.tools.lock().unwrap();
+        let sorted: BTreeMap<_, _> = tools.iter().collect();
+        let tools: serde_json::Map<String, Value> = sorted
+            .into_iter()
+            .map(|(tool, metrics)| {
+                let latency = &metrics.latency;
+                let buckets: serde_json::Map<String, Value> = latency
+                    .cumulative()
+                    .map(|(bound, count)| (bound, json!(count)))
+                    .collect();
+                let value = json!({
+                    "requests": metrics.requests,
+                    "errors": metrics.errors,
+                    "latency_seconds": {
+                        "count": latency.count,
+                        "sum": latency.sum,
+                        "avg": if latency.count == 0 { 0.0 } else { latency.sum / latency.count as f64 },
+                        "buckets": buckets
+                    }
+                });
+                (tool.to_string(), value)
+            })
+            .collect();
+        Value::Object(tools)
+    }
+
+    fn render_prometheus(&self, cache: &Value, pool: &Value) -> String {
+        let tools = self.tools.lock().unwrap();
+        let sorted: BTreeMap<_, _> = tools.iter().collect();
+        let mut out = String::new();
+
+        // Writing into a String cannot fail
+        let _ = writeln!(out, "# HELP plugin_requests_total Tool calls handled by the plugin");
+        let _ = writeln!(out, "# TYPE plugin_requests_total counter");
+        for (tool, metrics) in &sorted {
+            let _ = writeln!(out, "plugin_requests_total{{tool=\"{tool}\"}} {}", metrics.requests);
+        }
+
+        let _ = writeln!(out, "# HELP plugin_request_errors_total Failed tool calls by error code");
+        let _ = writeln!(out, "# TYPE plugin_request_errors_total counter");
+        for (tool, metrics) in &sorted {
+            for (code, count) in &metrics.errors {
+                let _ = writeln!(
+                    out,
+                    "plugin_request_errors_total{{tool=\"{tool}\",code=\"{code}\"}} {count}"
+                );
+            }
+        }
+
+        let _ = writeln!(out, "# HELP plugin_request_duration_seconds Tool call latency");
+        let _ = writeln!(out, "# TYPE plugin_request_duration_seconds histogram");
+        for (tool, metrics) in &sorted {
+            for (bound, count) in metrics.latency.cumulative() {
+                let _ = writeln!(
+                    out,
+                    "plugin_request_duration_seconds_bucket{{tool=\"{tool}\",le=\"{bound}\"}} {count}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "plugin_request_duration_seconds_sum{{tool=\"{tool}\"}} {}",
+                metrics.latency.sum
+            );
+            let _ = writeln!(
+                out,
+                "plugin_request_duration_seconds_count{{tool=\"{tool}\"}} {}",
+                metrics.latency.count
+            );
+        }
+
+        let gauges = [
+            ("plugin_cache_hits_total", "counter", "Query cache hits", &cache["hits"]),
+            ("plugin_cache_misses_total", "counter", "Query cache misses", &cache["misses"]),
+            ("plugin_cache_evictions_total", "counter", "Query cache LRU evictions", &cache["evictions"]),
+            ("plugin_cache_entries", "gauge", "Entries in the query cache", &cache["entries"]),
+            ("plugin_pool_connections", "gauge", "Open database connections", &pool["size"]),
+            ("plugin_pool_idle_connections", "gauge", "Idle database connections", &pool["idle"]),
+            ("plugin_pool_max_connections", "gauge", "Configured maximum pool size", &pool["max_connections"]),
+        ];
+        // Counters a cache backend does not keep are null, leave them out
+        for (name, kind, help, value) in gauges.into_iter().filter(|(.., value)| !value.is_null()) {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            let _ = writeln!(out, "{name} {value}");
+        }
+
+        out
+    }
+}
+
+/// Arguments of get_plugin_metrics
+#[derive(Debug, Deserialize, JsonSchema)]
+struct MetricsArgs {
+    format: Option<String>,
+}
+
+pub(crate) async fn handle_get_plugin_metrics(
+    db: &dyn DatabaseBackend,
+    cache: &dyn CacheBackend,
+    metrics: &Metrics,
+    args: &Value,
+) -> Result<Value, PluginError> {
+    let MetricsArgs { format } = args::parse(args)?;
+    let cache = cache.stats();
+    let pool = db.pool_stats();
+
+    match format.as_deref().unwrap_or("json") {
+        "json" => Ok(utils::json_content(json!({
+            "tools": metrics.tools_json(),
+            "cache": cache,
+            "pool": pool
+        }))),
+        "prometheus" => Ok(utils::text_content(metrics.render_prometheus(&cache, &pool))),
+        other => Err(PluginError::invalid_argument(format!(
+            "Invalid format '{other}', expected json or prometheus"
+        ))),
+    }
+}
@@ -0,0 +1,69 @@
+//! Migrations for plugin-owned tables
+//!
+//! The tables the plugin writes or keeps for its own features, the price
+//! audit, price history, tool call audit log, idempotency keys and price
+//! alerts, are
+//! defined by the migrations in `migrations/`, embedded at build time.
+//! With `manage_schema` set they are applied at init and on every
+//! reconfiguration, before the pool is used, and recorded in sqlx's
+//! `_sqlx_migrations` table. sqlx holds a Postgres advisory lock on the
+//! database while it applies them, so when several instances start at the
+//! same time one migrates and the others wait and find nothing left to do.
+//!
+//! The migrations create the tables under their default names, so the
+//! table name settings must keep those. Migrations recorded by other
+//! applications sharing the database are ignored. Without `manage_schema`
+//! the tables are created by hand, see the README.
+
+use crate::backend::{BackendKind, DatabaseBackend};
+use crate::PluginConfig;
+
+/// Table name settings of the managed tables with the names the
+/// migrations create
+fn managed_tables(config: &PluginConfig) -> [(&'static str, &str, &'static str); 6] {
+    [
+        ("price_audit_table", &config.price_audit_table, "price_audit"),
+        ("price_history_table", &config.price_history_table, "price_history"),
+        ("audit_log_table", &config.audit_log_table, "plugin_audit_log"),
+        ("idempotency_table", &config.idempotency_table, "idempotency_keys"),
+        ("price_alerts_table", &config.price_alerts_table, "price_alerts"),
+        ("triggered_alerts_table", &config.triggered_alerts_table, "triggered_price_alerts"),
+    ]
+}
+
+/// Validate the migration settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if !config.manage_schema {
+        return Ok(());
+    }
+    if BackendKind::parse(&config.backend)? != BackendKind::Postgres {
+        return Err("manage_schema requires the postgres backend".to_string());
+    }
+    if config.read_only {
+        return Err("manage_schema requires read_only to be set to false".to_string());
+    }
+    for (setting, table, managed) in managed_tables(config) {
+        if table != managed {
+            return Err(format!(
+                "manage_schema creates the table {managed}, {setting} must not name another table"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Apply the pending migrations if `manage_schema` is set
+pub(crate) async fn run(db: &dyn DatabaseBackend, config: &PluginConfig) -> Result<(), String> {
+    if !config.manage_schema {
+        return Ok(());
+    }
+    let pool = db.primary().map_err(|err| err.message().to_string())?;
+    let mut migrator = sqlx::migrate!("./migrations");
+    migrator.set_ignore_missing(true);
+    migrator
+        .run(pool)
+        .await
+        .map_err(|err| format!("Migrating the plugin tables failed: {err}"))?;
+    tracing::info!(migrations = migrator.iter().count(), "Plugin tables are up to date");
+    Ok(())
+}
@@ -0,0 +1,128 @@
+//! Connection pool pressure
+//!
+//! A call that finds every connection busy for `timeout_seconds` fails with
+//! `db_unavailable`, and the error carries a `pool` object describing the
+//! pressure at that moment, so a "pool timed out" can be told apart from a
+//! database outage:
+//!
+//! ```json
+//! {"code": "db_unavailable",
+//!  "message": "Connection pool exhausted: 10 of 10 connections busy for 5 s, 37 calls queued",
+//!  "pool": {"size": 10, "idle": 0, "acquired": 10, "max_connections": 10, "queued_calls": 37},
+//!  "retryable": true}
+//! ```
+//!
+//! With `max_connections_burst` above `max_connections` the pool adapts to
+//! sustained pressure: once every connection has been busy for
+//! `PRESSURE_SAMPLES` consecutive seconds it is replaced by a pool of up to
+//! `max_connections_burst` connections, and once a minute has passed
+//! without needing more than `max_connections`, by one of the configured
+//! size again. Calls running on a replaced pool finish on it; its
+//! connections are closed as they are returned.
+
+use crate::backend::{self, Database, DatabaseBackend};
+use crate::error::PluginError;
+use crate::{get_config, queue_depth, PluginConfig};
+use arc_swap::ArcSwap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Interval between two looks at the pool
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Consecutive saturated samples after which the pool grows
+const PRESSURE_SAMPLES: u32 = 5;
+
+/// Consecutive samples within `max_connections` after which a grown pool
+/// shrinks back
+const RELAXED_SAMPLES: u32 = 60;
+
+/// Validate the burst size, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    match config.max_connections_burst {
+        Some(burst) if burst <= config.max_connections => Err(format!(
+            "max_connections_burst ({burst}) must be greater than max_connections ({})",
+            config.max_connections
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Add the pool state to a pool exhaustion error of a call on `db`
+pub(crate) fn diagnose(err: PluginError, db: &dyn DatabaseBackend) -> PluginError {
+    let PluginError::PoolExhausted(..) = err else {
+        return err;
+    };
+    let mut pool = db.pool_stats();
+    let queued_calls = queue_depth();
+    pool["queued_calls"] = queued_calls.into();
+    let count = |field: &str| pool[field].as_u64().unwrap_or_default();
+    let message = format!(
+        "Connection pool exhausted: {} of {} connections busy for {} s, {queued_calls} calls queued",
+        count("acquired"),
+        count("max_connections"),
+        get_config().timeout_seconds
+    );
+    PluginError::PoolExhausted(message, pool)
+}
+
+/// Spawn the pool sizing task on the current runtime
+pub(crate) fn start(db: Arc<ArcSwap<Database>>) -> JoinHandle<()> {
+    tokio::spawn(run(db))
+}
+
+async fn run(db: Arc<ArcSwap<Database>>) {
+    let mut saturated = 0;
+    let mut relaxed = 0;
+    loop {
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+        let config = get_config();
+        let Some(burst) = config.max_connections_burst else {
+            continue;
+        };
+
+        let current = db.load_full();
+        let stats = current.pool_stats();
+        let (Some(acquired), Some(size)) = (stats["acquired"].as_u64(), stats["max_connections"].as_u64()) else {
+            // A backend without a pool
+            continue;
+        };
+        saturated = if acquired >= size { saturated + 1 } else { 0 };
+        relaxed = if acquired < u64::from(config.max_connections) { relaxed + 1 } else { 0 };
+
+        let target = if size < u64::from(burst) && saturated >= PRESSURE_SAMPLES {
+            burst
+        } else if size > u64::from(config.max_connections) && relaxed >= RELAXED_SAMPLES {
+            config.max_connections
+        } else {
+            continue;
+        };
+        tracing::info!(from = size, to = target, "Resizing the connection pool");
+        if let Err(err) = resize(&db, &current, &config, target).await {
+            tracing::warn!("Resizing the connection pool failed: {}", err.message());
+        }
+        saturated = 0;
+        relaxed = 0;
+    }
+}
+
+/// Replace the pool `current` with one of `size` connections, unless it
+/// was replaced by a reconfiguration meanwhile
+async fn resize(
+    db: &ArcSwap<Database>,
+    current: &Arc<Database>,
+    config: &PluginConfig,
+    size: u32,
+) -> Result<(), PluginError> {
+    let config = PluginConfig {
+        max_connections: size,
+        ..config.clone()
+    };
+    let resized: Arc<Database> = Arc::new(backend::connect(&config, true).await?);
+    let previous = db.compare_and_swap(current, resized);
+    if !Arc::ptr_eq(&previous, current) {
+        tracing::debug!("Pool replaced by a reconfiguration, not resized");
+    }
+    Ok(())
+}
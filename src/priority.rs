@@ -0,0 +1,254 @@
+//! Call priorities
+//!
+//! Every tool call gets a priority: the `_meta.priority` hint of the host
+//! if it carries one (`high`, `normal` or `low`), else the tool's entry in
+//! `tool_priorities`, else `normal`. The runtime takes all calls waiting in
+//! the command channel at once and dispatches them highest priority first,
+//! in arrival order within a priority. Reconfigure and shutdown commands
+//! still wait for the calls received before them.
+//!
+//! Dispatching is quick, so under congestion calls mostly wait for their
+//! concurrency permits. These are handed out by `PrioritySemaphore` in the
+//! same order, so health checks and single-product lookups are not stuck
+//! behind a burst of searches.
+
+use crate::get_tools;
+use crate::PluginConfig;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+/// Priority of a tool call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl Priority {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "low" => Some(Priority::Low),
+            "normal" => Some(Priority::Normal),
+            "high" => Some(Priority::High),
+            _ => None,
+        }
+    }
+
+    /// Priority of a call to `tool` with `args`
+    ///
+    /// An unknown hint is ignored rather than failing the call, hosts may
+    /// use values of their own.
+    pub(crate) fn of(config: &PluginConfig, tool: &str, args: &Value) -> Self {
+        args["_meta"]["priority"]
+            .as_str()
+            .and_then(Priority::parse)
+            .or_else(|| config.tool_priorities.get(tool).copied())
+            .unwrap_or(Priority::Normal)
+    }
+}
+
+/// Validate the tool priorities, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    let tools = get_tools();
+    match config.tool_priorities.keys().find(|tool| !tools.contains_key(*tool)) {
+        Some(tool) => Err(format!("Unknown tool '{tool}' in tool_priorities")),
+        None => Ok(()),
+    }
+}
+
+/// A queued item with its priority and arrival number
+struct Entry<T> {
+    priority: Priority,
+    sequence: Reverse<u64>,
+    item: T,
+}
+
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Entry<T> {}
+
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Entry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.priority, self.sequence).cmp(&(other.priority, other.sequence))
+    }
+}
+
+/// Items waiting for dispatch, highest priority and then earliest first
+pub(crate) struct PriorityQueue<T> {
+    entries: BinaryHeap<Entry<T>>,
+    sequence: u64,
+}
+
+impl<T> Default for PriorityQueue<T> {
+    fn default() -> Self {
+        PriorityQueue {
+            entries: BinaryHeap::new(),
+            sequence: 0,
+        }
+    }
+}
+
+impl<T> PriorityQueue<T> {
+    pub(crate) fn push(&mut self, priority: Priority, item: T) {
+        self.entries.push(Entry {
+            priority,
+            sequence: Reverse(self.sequence),
+            item,
+        });
+        self.sequence += 1;
+    }
+
+    /// The next item with the priority it was queued with
+    pub(crate) fn pop(&mut self) -> Option<(Priority, T)> {
+        self.entries.pop().map(|entry| (entry.priority, entry.item))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Counting semaphore handing freed permits to the highest priority waiter,
+/// in arrival order within a priority
+#[derive(Clone)]
+pub(crate) struct PrioritySemaphore {
+    inner: Arc<Mutex<SemaphoreState>>,
+}
+
+struct SemaphoreState {
+    available: usize,
+    waiters: PriorityQueue<oneshot::Sender<PriorityPermit>>,
+}
+
+/// A permit of a `PrioritySemaphore`, returned when dropped
+pub(crate) struct PriorityPermit {
+    /// `None` once the permit was handed on
+    semaphore: Option<PrioritySemaphore>,
+}
+
+impl PrioritySemaphore {
+    pub(crate) fn new(permits: usize) -> Self {
+        PrioritySemaphore {
+            inner: Arc::new(Mutex::new(SemaphoreState {
+                available: permits,
+                waiters: PriorityQueue::default(),
+            })),
+        }
+    }
+
+    /// Wait for a permit
+    ///
+    /// Dropping the future gives up the place in the queue; a permit
+    /// granted meanwhile goes to the next waiter.
+    pub(crate) async fn acquire(&self, priority: Priority) -> PriorityPermit {
+        let granted = {
+            let mut state = self.inner.lock().unwrap();
+            if state.available > 0 {
+                state.available -= 1;
+                None
+            } else {
+                let (tx, rx) = oneshot::channel();
+                state.waiters.push(priority, tx);
+                Some(rx)
+            }
+        };
+        match granted {
+            None => PriorityPermit {
+                semaphore: Some(self.clone()),
+            },
+            // The sender is only dropped after sending
+            Some(rx) => rx.await.expect("permit sent"),
+        }
+    }
+
+    /// Hand a returned permit to the next waiter still waiting, or keep it
+    fn release(&self) {
+        loop {
+            let waiter = {
+                let mut state = self.inner.lock().unwrap();
+                match state.waiters.pop() {
+                    Some((_, waiter)) => waiter,
+                    None => {
+                        state.available += 1;
+                        return;
+                    }
+                }
+            };
+            let permit = PriorityPermit {
+                semaphore: Some(self.clone()),
+            };
+            match waiter.send(permit) {
+                Ok(()) => return,
+                // The waiter gave up, try the next one
+                Err(mut permit) => permit.semaphore = None,
+            }
+        }
+    }
+}
+
+impl Drop for PriorityPermit {
+    fn drop(&mut self) {
+        if let Some(semaphore) = self.semaphore.take() {
+            semaphore.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pops_by_priority_then_arrival() {
+        let mut queue = PriorityQueue::default();
+        queue.push(Priority::Normal, "normal 1");
+        queue.push(Priority::Low, "low");
+        queue.push(Priority::High, "high 1");
+        queue.push(Priority::Normal, "normal 2");
+        queue.push(Priority::High, "high 2");
+
+        let order: Vec<&str> = std::iter::from_fn(|| queue.pop().map(|(_, item)| item)).collect();
+        assert_eq!(order, ["high 1", "high 2", "normal 1", "normal 2", "low"]);
+        assert!(queue.is_empty());
+    }
+
+    #[tokio::test]
+    async fn hands_permits_to_the_highest_priority_waiter() {
+        let semaphore = PrioritySemaphore::new(1);
+        let held = semaphore.acquire(Priority::Normal).await;
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let mut waiters = Vec::new();
+        for (name, priority) in [("low", Priority::Low), ("high", Priority::High), ("normal", Priority::Normal)] {
+            let (semaphore, order) = (semaphore.clone(), order.clone());
+            waiters.push(tokio::spawn(async move {
+                let _permit = semaphore.acquire(priority).await;
+                order.lock().unwrap().push(name);
+            }));
+            // Queue the waiters in this order
+            tokio::task::yield_now().await;
+        }
+        drop(held);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), ["high", "normal", "low"]);
+    }
+}
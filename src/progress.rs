@@ -0,0 +1,66 @@
+//! Progress notifications
+//!
+//! Long running tools report how far they got through a callback the host
+//! registers with `plugin_set_progress_callback`. A call only reports
+//! progress if its arguments carry an MCP progress token in
+//! `_meta.progressToken`, which hosts copy from the request. Each report is
+//! the JSON params of an MCP `notifications/progress` message:
+//!
+//! ```json
+//! {"progressToken": "abc", "progress": 200, "message": "200 rows read"}
+//! ```
+//!
+//! The callback is invoked from the plugin's runtime threads, not from the
+//! thread that called `execute_tool`, and must be thread safe. The JSON is
+//! only valid for the duration of the call.
+
+use serde_json::{json, Value};
+use std::sync::RwLock;
+
+/// Host callback receiving progress notification params as UTF-8 JSON
+pub(crate) type ProgressCallback = unsafe extern "C" fn(params: *const u8, params_len: usize);
+
+static CALLBACK: RwLock<Option<ProgressCallback>> = RwLock::new(None);
+
+/// Register or, with `None`, remove the host callback
+pub(crate) fn set_callback(callback: Option<ProgressCallback>) {
+    *CALLBACK.write().unwrap() = callback;
+}
+
+/// Progress reporter of one tool call
+pub(crate) struct Progress {
+    token: Option<Value>,
+}
+
+impl Progress {
+    /// Reporter for the progress token in the call arguments, if any
+    pub(crate) fn from_args(args: &Value) -> Self {
+        let token = match &args["_meta"]["progressToken"] {
+            token @ (Value::String(_) | Value::Number(_)) => Some(token.clone()),
+            _ => None,
+        };
+        Progress { token }
+    }
+
+    /// Send one progress notification, if the caller asked for them
+    pub(crate) fn report(&self, progress: u64, total: Option<u64>, message: &str) {
+        let Some(token) = &self.token else {
+            return;
+        };
+        let Some(callback) = *CALLBACK.read().unwrap() else {
+            return;
+        };
+
+        let mut params = json!({
+            "progressToken": token,
+            "progress": progress,
+            "message": message
+        });
+        if let Some(total) = total {
+            params["total"] = json!(total);
+        }
+        let params = params.to_string();
+        // SAFETY: the host registered the callback for exactly this signature
+        unsafe { callback(params.as_ptr(), params.len()) };
+    }
+}
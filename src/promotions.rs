@@ -0,0 +1,229 @@
+//! Promotions
+//!
+//! Evaluates discounts from the `promotions` table (name configurable
+//! through `promotions_table`):
+//!
+//! ```sql
+//! CREATE TABLE promotions (
+//!     id            SERIAL PRIMARY KEY,
+//!     name          TEXT NOT NULL,
+//!     kind          TEXT NOT NULL,      -- percentage, fixed_amount, buy_x_get_y
+//!     value         NUMERIC(10,2),      -- percent off or amount off per unit
+//!     buy_quantity  INTEGER,            -- buy_x_get_y: units to pay for ...
+//!     free_quantity INTEGER,            -- ... and units added for free
+//!     product_id    INTEGER REFERENCES products(id),
+//!     category      VARCHAR(100),
+//!     starts_at     TIMESTAMPTZ,
+//!     ends_at       TIMESTAMPTZ,
+//!     stackable     BOOLEAN NOT NULL DEFAULT false,
+//!     priority      INTEGER NOT NULL DEFAULT 0
+//! );
+//! ```
+//!
+//! A promotion targets one product, a category, or every product if both
+//! are NULL, and is active within `[starts_at, ends_at)` (open ended if
+//! NULL). Stackable promotions are applied one after another by descending
+//! priority; a non-stackable promotion is applied alone. Whichever of the
+//! two gives the lower price wins.
+
+use crate::args;
+use crate::backend::DatabaseBackend;
+use crate::currency;
+use crate::error::PluginError;
+use crate::sql::{is_undefined_table, quote_identifier};
+use crate::{format_price, get_config, parse_timestamp, PluginConfig};
+use chrono::{DateTime, Utc};
+use mcp_plugin_api::utils;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(sqlx::FromRow)]
+struct PromotionRow {
+    id: i32,
+    name: String,
+    kind: String,
+    value: Option<Decimal>,
+    buy_quantity: Option<i32>,
+    free_quantity: Option<i32>,
+    stackable: bool,
+}
+
+/// Discount rule of a promotion
+#[derive(Debug, Clone, Copy)]
+enum Rule {
+    /// Percent off the unit price
+    Percentage(Decimal),
+    /// Amount off the unit price
+    FixedAmount(Decimal),
+    /// For every `buy` units paid, `free` more are free
+    BuyXGetY { buy: i32, free: i32 },
+}
+
+impl Rule {
+    fn parse(row: &PromotionRow) -> Result<Self, PluginError> {
+        let invalid = |reason: &str| {
+            PluginError::Database(format!("Promotion {} is invalid: {reason}", row.id))
+        };
+        match row.kind.as_str() {
+            "percentage" => match row.value {
+                Some(percent) if percent > Decimal::ZERO && percent <= Decimal::ONE_HUNDRED => {
+                    Ok(Rule::Percentage(percent))
+                }
+                _ => Err(invalid("percentage needs a value between 0 and 100")),
+            },
+            "fixed_amount" => match row.value {
+                Some(amount) if amount > Decimal::ZERO => Ok(Rule::FixedAmount(amount)),
+                _ => Err(invalid("fixed_amount needs a positive value")),
+            },
+            "buy_x_get_y" => match (row.buy_quantity, row.free_quantity) {
+                (Some(buy), Some(free)) if buy > 0 && free > 0 => Ok(Rule::BuyXGetY { buy, free }),
+                _ => Err(invalid("buy_x_get_y needs positive buy_quantity and free_quantity")),
+            },
+            other => Err(invalid(&format!("unknown kind '{other}'"))),
+        }
+    }
+
+    /// Total after applying the rule to `quantity` units costing `total`
+    fn apply(&self, total: Decimal, quantity: i32) -> Decimal {
+        let discounted = match *self {
+            Rule::Percentage(percent) => total * (Decimal::ONE_HUNDRED - percent) / Decimal::ONE_HUNDRED,
+            Rule::FixedAmount(amount) => total - amount * Decimal::from(quantity),
+            Rule::BuyXGetY { buy, free } => {
+                let free_units = quantity / (buy + free) * free;
+                total * Decimal::from(quantity - free_units) / Decimal::from(quantity)
+            }
+        };
+        discounted.max(Decimal::ZERO)
+    }
+}
+
+struct Promotion {
+    id: i32,
+    name: String,
+    kind: String,
+    rule: Rule,
+    stackable: bool,
+}
+
+/// Validate the promotion settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    quote_identifier(&config.promotions_table).map(|_| ())
+}
+
+/// Promotions targeting the product that are active at `at`, by priority
+async fn active_promotions(
+    db: &dyn DatabaseBackend,
+    product_id: i32,
+    category: Option<&str>,
+    at: DateTime<Utc>,
+) -> Result<Vec<Promotion>, PluginError> {
+    let table = quote_identifier(&get_config().promotions_table).map_err(PluginError::internal)?;
+    let result = sqlx::query_as::<_, PromotionRow>(&format!(
+        "SELECT id, name, kind, value, buy_quantity, free_quantity, stackable FROM {table} \
+         WHERE (starts_at IS NULL OR starts_at <= $3) AND (ends_at IS NULL OR ends_at > $3) \
+           AND (product_id = $1 OR category = $2 OR (product_id IS NULL AND category IS NULL)) \
+         ORDER BY priority DESC, id"
+    ))
+    .bind(product_id)
+    .bind(category)
+    .bind(at)
+    .fetch_all(db.postgres()?)
+    .await;
+
+    let rows = match result {
+        Ok(rows) => rows,
+        Err(err) if is_undefined_table(&err) => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    rows.into_iter()
+        .map(|row| {
+            Ok(Promotion {
+                rule: Rule::parse(&row)?,
+                id: row.id,
+                name: row.name,
+                kind: row.kind,
+                stackable: row.stackable,
+            })
+        })
+        .collect()
+}
+
+/// Pick the cheapest combination: all stackable promotions chained, or the
+/// best single non-stackable one
+fn best_combination(promotions: &[Promotion], total: Decimal, quantity: i32) -> (Decimal, Vec<&Promotion>) {
+    let stacked: Vec<&Promotion> = promotions.iter().filter(|p| p.stackable).collect();
+    let mut best = (
+        stacked.iter().fold(total, |total, p| p.rule.apply(total, quantity)),
+        stacked,
+    );
+
+    for promotion in promotions.iter().filter(|p| !p.stackable) {
+        let discounted = promotion.rule.apply(total, quantity);
+        if discounted < best.0 {
+            best = (discounted, vec![promotion]);
+        }
+    }
+    best
+}
+
+/// Arguments of get_effective_price
+#[derive(Debug, Deserialize, JsonSchema)]
+struct EffectivePriceArgs {
+    product_id: i32,
+    #[schemars(range(min = 1))]
+    quantity: Option<i32>,
+    at: Option<String>,
+    currency: Option<String>,
+}
+
+pub(crate) async fn handle_get_effective_price(
+    db: &dyn DatabaseBackend,
+    args: &Value,
+) -> Result<Value, PluginError> {
+    let EffectivePriceArgs {
+        product_id,
+        quantity,
+        at,
+        currency,
+    } = args::parse(args)?;
+    let quantity = quantity.unwrap_or(1);
+    let at = at.map(|at| parse_timestamp(&at, "at")).transpose()?.unwrap_or_else(Utc::now);
+    let currency = currency::parse_currency(currency.as_deref())?;
+
+    let product = db
+        .fetch_product(product_id)
+        .await?
+        .ok_or_else(|| PluginError::not_found(format!("Product {product_id} not found")))?;
+
+    let promotions = active_promotions(db, product_id, product.category.as_deref(), at).await?;
+    let original_total = product.price * Decimal::from(quantity);
+    let (final_total, applied) = best_combination(&promotions, original_total, quantity);
+    let final_price = final_total / Decimal::from(quantity);
+
+    let mut response = json!({
+        "product_id": product.id,
+        "quantity": quantity,
+        "evaluated_at": at.to_rfc3339(),
+        "original_price": format_price(&product.price),
+        "final_price": format_price(&final_price),
+        "original_total": format_price(&original_total),
+        "final_total": format_price(&final_total),
+        "discount": format_price(&(original_total - final_total)),
+        "applied_promotion_ids": applied.iter().map(|p| p.id).collect::<Vec<_>>(),
+        "applied_promotions": applied
+            .iter()
+            .map(|p| json!({ "id": p.id, "name": p.name, "kind": p.kind }))
+            .collect::<Vec<_>>(),
+        "base_currency": get_config().base_currency
+    });
+    if let Some(currency) = &currency {
+        let exchange_rate = currency::exchange_rate(db, currency).await?;
+        response["converted_final_price"] = exchange_rate.convert(&final_price);
+        response["converted_final_total"] = exchange_rate.convert(&final_total);
+    }
+
+    // Return structured JSON data for programmatic clients
+    Ok(utils::json_content(response))
+}
@@ -0,0 +1,172 @@
+//! Rate limiting
+//!
+//! `rate_limits` maps tool names to token buckets: a bucket holds up to
+//! `burst` tokens, refills at `per_second` tokens per second and every call
+//! takes one. A call finding the bucket empty fails right away with a
+//! retryable `rate_limited` error telling the client when the next token is
+//! due. The entry `"*"` applies to each tool without an entry of its own.
+//!
+//! With `per_client`, calls are counted separately per `client_id` argument
+//! as passed by the host; calls without one share a bucket.
+
+use crate::error::PluginError;
+use crate::PluginConfig;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Key of `rate_limits` applying to every tool without its own entry
+const ANY_TOOL: &str = "*";
+
+/// Number of buckets above which idle ones are dropped
+const MAX_BUCKETS: usize = 10_000;
+
+/// Token bucket settings of one tool
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub(crate) struct RateLimit {
+    /// Sustained calls per second
+    per_second: f64,
+
+    /// Calls allowed at once on top of the sustained rate (default: the
+    /// per-second rate, rounded up)
+    #[serde(default)]
+    burst: Option<u32>,
+
+    /// Keep a separate bucket per `client_id` argument
+    #[serde(default)]
+    per_client: bool,
+}
+
+impl RateLimit {
+    fn capacity(&self) -> f64 {
+        match self.burst {
+            Some(burst) => f64::from(burst),
+            None => self.per_second.ceil().max(1.0),
+        }
+    }
+}
+
+/// Validate the rate limits, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    for (tool, limit) in &config.rate_limits {
+        if !(limit.per_second.is_finite() && limit.per_second > 0.0) {
+            return Err(format!("rate_limits.{tool}.per_second must be greater than 0"));
+        }
+        if limit.burst == Some(0) {
+            return Err(format!("rate_limits.{tool}.burst must be at least 1"));
+        }
+    }
+    Ok(())
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Buckets built from one configuration
+///
+/// Replaced as a whole on reconfigure, which refills every bucket.
+pub(crate) struct RateLimiter {
+    limits: HashMap<String, RateLimit>,
+    buckets: Mutex<HashMap<(String, Option<String>), Bucket>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(config: &PluginConfig) -> Self {
+        RateLimiter {
+            limits: config.rate_limits.clone(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn limit(&self, tool: &str) -> Option<&RateLimit> {
+        self.limits.get(tool).or_else(|| self.limits.get(ANY_TOOL))
+    }
+
+    /// Take a token for a call of `tool`, or fail with `rate_limited`
+    pub(crate) fn check(&self, tool: &str, args: &Value) -> Result<(), PluginError> {
+        let Some(limit) = self.limit(tool) else {
+            return Ok(());
+        };
+        let client = if limit.per_client {
+            args["client_id"].as_str().map(str::to_string)
+        } else {
+            None
+        };
+
+        let now = Instant::now();
+        let capacity = limit.capacity();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS {
+            // A bucket refilled to capacity is the same as a new one
+            buckets.retain(|(tool, _), bucket| {
+                self.limit(tool).is_some_and(|limit| {
+                    bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * limit.per_second
+                        < limit.capacity()
+                })
+            });
+        }
+
+        let bucket = buckets.entry((tool.to_string(), client)).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.per_second).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        let retry_after = Duration::from_secs_f64((1.0 - bucket.tokens) / limit.per_second);
+        Err(PluginError::RateLimited(
+            format!("Rate limit of {} calls per second exceeded for {tool}", limit.per_second),
+            retry_after,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn limiter(limits: Value) -> RateLimiter {
+        RateLimiter::new(&serde_json::from_value(json!({ "rate_limits": limits })).unwrap())
+    }
+
+    #[test]
+    fn exhausts_the_burst() {
+        let limiter = limiter(json!({"search_products": {"per_second": 1.0, "burst": 2}}));
+        assert!(limiter.check("search_products", &json!({})).is_ok());
+        assert!(limiter.check("search_products", &json!({})).is_ok());
+        match limiter.check("search_products", &json!({})) {
+            Err(PluginError::RateLimited(_, retry_after)) => assert!(retry_after <= Duration::from_secs(1)),
+            other => panic!("expected rate_limited, got {other:?}"),
+        }
+        assert!(limiter.check("get_product_price", &json!({})).is_ok());
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let limiter = limiter(json!({"*": {"per_second": 10.0, "burst": 1}}));
+        assert!(limiter.check("search_products", &json!({})).is_ok());
+        assert!(limiter.check("search_products", &json!({})).is_err());
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(limiter.check("search_products", &json!({})).is_ok());
+    }
+
+    #[test]
+    fn counts_clients_separately() {
+        let limiter = limiter(json!({"*": {"per_second": 1.0, "burst": 1, "per_client": true}}));
+        assert!(limiter.check("search_products", &json!({"client_id": "a"})).is_ok());
+        assert!(limiter.check("search_products", &json!({"client_id": "a"})).is_err());
+        assert!(limiter.check("search_products", &json!({"client_id": "b"})).is_ok());
+        assert!(limiter.check("search_products", &json!({})).is_ok());
+    }
+}
@@ -0,0 +1,52 @@
+//! Tool registry
+//!
+//! Every tool call reaches the runtime as one generic `Command::Execute`
+//! carrying the tool name. The runtime looks the name up here and runs the
+//! registered async handler with the database, cache and metrics current
+//! at the time the call was received. Tools are registered from their
+//! `declare_async_tools!` entries, so adding one takes that entry only.
+
+use crate::backend::Database;
+use crate::cache::Cache;
+use crate::error::PluginError;
+use crate::metrics::Metrics;
+use futures::future::BoxFuture;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Shared state a handler may use
+pub(crate) struct ToolContext {
+    pub(crate) db: Arc<Database>,
+    pub(crate) cache: Arc<Cache>,
+    pub(crate) metrics: Arc<Metrics>,
+}
+
+/// Async tool handler
+///
+/// A plain function pointer, so registrations are non-capturing closures
+/// adapting a handler to the context, e.g.
+/// `|ctx, args| Box::pin(handle_x(&**ctx.db, args))`.
+pub(crate) type Handler =
+    for<'a> fn(&'a ToolContext, &'a Value) -> BoxFuture<'a, Result<Value, PluginError>>;
+
+/// Tool names mapped to their handlers
+#[derive(Default)]
+pub(crate) struct Registry {
+    handlers: HashMap<&'static str, Handler>,
+}
+
+impl Registry {
+    /// Register `handler` under `tool`, replacing an earlier registration
+    pub(crate) fn register(&mut self, tool: &'static str, handler: Handler) -> &mut Self {
+        self.handlers.insert(tool, handler);
+        self
+    }
+
+    /// Handler of `tool` together with its static name
+    pub(crate) fn get(&self, tool: &str) -> Option<(&'static str, Handler)> {
+        self.handlers
+            .get_key_value(tool)
+            .map(|(name, handler)| (*name, *handler))
+    }
+}
@@ -0,0 +1,90 @@
+//! MCP resources
+//!
+//! Every product is exposed as a `product://{id}` resource with the same
+//! JSON payload `get_product_price` returns. Listing is capped at
+//! `resource_list_limit` products; the `product://{id}` template lets
+//! clients address any other product directly. Product images are served
+//! as `product-image://{id}` resources, see the images module.
+
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::images::{self, IMAGE_SCHEME};
+use crate::mapping::{self, PRODUCT_COLUMNS};
+use crate::{get_config, product_json, Product};
+use serde_json::{json, Value};
+
+/// URI scheme of product resources
+const PRODUCT_SCHEME: &str = "product://";
+
+/// MIME type of product resource contents
+const PRODUCT_MIME_TYPE: &str = "application/json";
+
+/// Resource templates offered to clients
+pub(crate) fn resource_templates() -> Value {
+    json!({
+        "resourceTemplates": [{
+            "uriTemplate": format!("{PRODUCT_SCHEME}{{id}}"),
+            "name": "Product",
+            "description": "Product with price, description and category by product ID",
+            "mimeType": PRODUCT_MIME_TYPE
+        }, images::resource_template()]
+    })
+}
+
+fn parse_product_uri(uri: &str) -> Result<i32, PluginError> {
+    uri.strip_prefix(PRODUCT_SCHEME)
+        .and_then(|id| id.parse::<i32>().ok())
+        .ok_or_else(|| {
+            PluginError::invalid_argument(format!(
+                "Invalid resource URI '{uri}', expected {PRODUCT_SCHEME}{{id}}"
+            ))
+        })
+}
+
+pub(crate) async fn handle_list_resources(db: &dyn DatabaseBackend, _args: &Value) -> Result<Value, PluginError> {
+    let pool = db.postgres()?;
+    let products = sqlx::query_as::<_, Product>(&format!(
+        "SELECT {PRODUCT_COLUMNS} FROM {} ORDER BY id LIMIT $1",
+        mapping::products()
+    ))
+    .bind(get_config().resource_list_limit)
+    .fetch_all(pool)
+    .await?;
+
+    let resources: Vec<Value> = products
+        .iter()
+        .map(|product| {
+            json!({
+                "uri": format!("{PRODUCT_SCHEME}{}", product.id),
+                "name": product.name,
+                "description": product.description,
+                "mimeType": PRODUCT_MIME_TYPE
+            })
+        })
+        .collect();
+
+    Ok(json!({ "resources": resources }))
+}
+
+pub(crate) async fn handle_read_resource(db: &dyn DatabaseBackend, args: &Value) -> Result<Value, PluginError> {
+    let uri = args["uri"]
+        .as_str()
+        .ok_or_else(|| PluginError::invalid_argument("Missing or invalid uri parameter"))?;
+    if uri.starts_with(IMAGE_SCHEME) {
+        return images::handle_read_image(db, uri).await;
+    }
+    let product_id = parse_product_uri(uri)?;
+
+    let product = db
+        .fetch_product(product_id)
+        .await?
+        .ok_or_else(|| PluginError::not_found(format!("Product {product_id} not found")))?;
+
+    Ok(json!({
+        "contents": [{
+            "uri": uri,
+            "mimeType": PRODUCT_MIME_TYPE,
+            "text": product_json(&product, None, None).to_string()
+        }]
+    }))
+}
@@ -0,0 +1,145 @@
+//! Typed tool responses and their output schemas
+//!
+//! The core pricing tools build their results from the types below instead
+//! of ad-hoc JSON. Their JSON Schema, derived with `schemars` like the
+//! config schema, is declared as the MCP `outputSchema` of the tools in
+//! `tools/list`, and the results carry the same object as
+//! `structuredContent` next to the JSON content block, so clients can
+//! validate and use them without parsing free-form JSON. All schemas are
+//! also exported by `plugin_get_tool_output_schemas`.
+
+use crate::currency::ConvertedPrice;
+use crate::expand::Expanded;
+use crate::shaping;
+use crate::units::UnitPrice;
+use crate::Product;
+use mcp_plugin_api::utils;
+use schemars::{schema_for, JsonSchema};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// A product with its price as returned by the pricing tools
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct PricedProduct {
+    #[serde(flatten)]
+    pub(crate) product: Product,
+
+    /// The price formatted for the requested locale, e.g. "1.299,00 €"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) display_price: Option<String>,
+
+    /// The price in the requested currency
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) converted_price: Option<ConvertedPrice>,
+
+    /// Price per kg, l, m or unit, in the base currency
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) unit_price: Option<UnitPrice>,
+
+    /// Search relevance, higher is better (ranked search modes only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) rank: Option<f32>,
+}
+
+/// Result of get_product_price and the SKU and barcode lookups
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct PriceResponse {
+    pub(crate) product: PricedProduct,
+
+    /// Where the price comes from: list_price, customer_contract or
+    /// scheduled_price
+    pub(crate) price_source: &'static str,
+
+    /// Currency of `product.price`
+    pub(crate) base_currency: String,
+
+    /// Customer whose contract price applies
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) customer_id: Option<String>,
+
+    /// Catalogue price replaced by the contract or scheduled price
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) list_price: Option<String>,
+
+    /// Time the price was looked up for, if not now
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) as_of: Option<String>,
+
+    /// Start of the scheduled price
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) effective_from: Option<String>,
+
+    /// End of the scheduled price, absent if open-ended
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) effective_to: Option<String>,
+
+    /// Where `product.converted_price` comes from: product_prices (a
+    /// stored price) or exchange_rate (the converted base price)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) converted_price_source: Option<&'static str>,
+
+    /// Set when the product is discontinued or otherwise not active
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) warning: Option<String>,
+
+    /// Net price, tax amount and gross price for the requested region
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) tax: Option<Value>,
+
+    /// Unit and total price for the requested quantity
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) quantity_pricing: Option<Value>,
+
+    /// Related resources named in `expand`
+    #[serde(flatten)]
+    pub(crate) expanded: Expanded,
+}
+
+/// Result of search_products
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct SearchResponse {
+    pub(crate) products: Vec<PricedProduct>,
+
+    /// Number of products returned
+    pub(crate) count: usize,
+
+    /// Matching strategy used: ilike, fulltext or trigram
+    pub(crate) search_mode: &'static str,
+
+    /// Result order
+    pub(crate) sort: &'static str,
+
+    /// Number of matching products skipped
+    pub(crate) offset: i64,
+
+    /// More products matched than `max_results`
+    pub(crate) truncated: bool,
+
+    /// Names of the products closest to the query, if it matched nothing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) did_you_mean: Option<Vec<String>>,
+
+    /// Currency of the product prices
+    pub(crate) base_currency: String,
+}
+
+/// Tool result with the response as JSON content and as structured content
+pub(crate) fn structured_content<T: Serialize>(response: &T) -> Value {
+    let value = json!(response);
+    let mut result = utils::json_content(value.clone());
+    result["structuredContent"] = value;
+    result
+}
+
+/// Output schema of a tool, if it declares one
+pub(crate) fn output_schema(tool: &str) -> Option<Value> {
+    let schema = match tool {
+        "get_product_price" | "get_product_by_sku" | "get_product_by_barcode" => schema_for!(PriceResponse),
+        "search_products" => schema_for!(SearchResponse),
+        _ => return None,
+    };
+    let mut schema = json!(schema);
+    // Redacted fields and those left out through `fields` may be missing
+    shaping::relax_schema(&mut schema);
+    Some(schema)
+}
@@ -0,0 +1,259 @@
+//! Price recommendation rules
+//!
+//! `pricing_rules` is an ordered list of rules, each taking the price the
+//! previous rules arrived at (the current list price at first) and possibly
+//! replacing it:
+//!
+//! ```json
+//! "pricing_rules": [
+//!     {"rule": "target_margin", "percent": "35"},
+//!     {"rule": "competitor_undercut", "percent": "2"},
+//!     {"rule": "min_margin", "percent": "15"},
+//!     {"rule": "psychological_ending", "ending": "0.99"}
+//! ]
+//! ```
+//!
+//! - `target_margin`: the price earning `percent` margin on the cost
+//! - `competitor_undercut`: `percent` below the cheapest competitor, if
+//!   the price is not already lower
+//! - `min_margin`: raise the price to at least `percent` margin on the cost
+//! - `max_change`: keep the price within `percent` of the list price
+//! - `psychological_ending`: move to the nearest price ending in `ending`,
+//!   e.g. 19.99 for 20.40
+//!
+//! A rule whose input is missing (no cost, no competitor prices) does not
+//! fire. Every rule reports whether it fired and why, so the recommendation
+//! can be explained. Margins are in percent of the price, like those of
+//! the margin tools; costs are only used with `expose_costs`.
+//! `suggest_price` only recommends, it never changes the price.
+
+use crate::args;
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::{competitors, margin};
+use crate::{format_price, get_config, PluginConfig};
+use mcp_plugin_api::utils;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// A rule of the `pricing_rules` config field
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub(crate) enum PricingRule {
+    /// Price earning `percent` margin on the cost
+    TargetMargin { percent: Decimal },
+    /// `percent` below the cheapest competitor
+    CompetitorUndercut { percent: Decimal },
+    /// At least `percent` margin on the cost
+    MinMargin { percent: Decimal },
+    /// At most `percent` away from the current list price
+    MaxChange { percent: Decimal },
+    /// Nearest price with the fractional part `ending`, e.g. 0.99
+    PsychologicalEnding { ending: Decimal },
+}
+
+impl PricingRule {
+    fn name(&self) -> &'static str {
+        match self {
+            PricingRule::TargetMargin { .. } => "target_margin",
+            PricingRule::CompetitorUndercut { .. } => "competitor_undercut",
+            PricingRule::MinMargin { .. } => "min_margin",
+            PricingRule::MaxChange { .. } => "max_change",
+            PricingRule::PsychologicalEnding { .. } => "psychological_ending",
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            PricingRule::TargetMargin { percent } | PricingRule::MinMargin { percent }
+                if *percent < Decimal::ZERO || *percent >= Decimal::ONE_HUNDRED =>
+            {
+                Err(format!("{}: percent must be at least 0 and below 100", self.name()))
+            }
+            PricingRule::CompetitorUndercut { percent } | PricingRule::MaxChange { percent }
+                if *percent < Decimal::ZERO || *percent > Decimal::ONE_HUNDRED =>
+            {
+                Err(format!("{}: percent must be between 0 and 100", self.name()))
+            }
+            PricingRule::PsychologicalEnding { ending } if *ending < Decimal::ZERO || *ending >= Decimal::ONE => {
+                Err(format!("{}: ending must be at least 0 and below 1", self.name()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Validate the rules, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    for (index, rule) in config.pricing_rules.iter().enumerate() {
+        rule.validate().map_err(|err| format!("Invalid pricing_rules[{index}]: {err}"))?;
+    }
+    Ok(())
+}
+
+/// What the rules know about a product
+struct RuleInputs {
+    list_price: Decimal,
+    /// Unit cost, `None` if unknown or not to be used
+    cost: Option<Decimal>,
+    /// Cheapest competitor and its latest price
+    cheapest_competitor: Option<(String, Decimal)>,
+    decimal_places: u32,
+}
+
+/// Outcome of one rule
+struct RuleStep {
+    rule: &'static str,
+    fired: bool,
+    /// Price after the rule
+    price: Decimal,
+    explanation: String,
+}
+
+impl RuleStep {
+    fn to_json(&self) -> Value {
+        json!({
+            "rule": self.rule,
+            "fired": self.fired,
+            "price": format_price(&self.price),
+            "explanation": self.explanation
+        })
+    }
+}
+
+/// Price earning `percent` margin on `cost`
+fn price_for_margin(cost: Decimal, percent: Decimal) -> Decimal {
+    cost / (Decimal::ONE - percent / Decimal::ONE_HUNDRED)
+}
+
+/// Nearest price with the fractional part `ending`, never below `ending`
+fn with_ending(price: Decimal, ending: Decimal) -> Decimal {
+    let below = price.floor() + ending;
+    let candidates = [below - Decimal::ONE, below, below + Decimal::ONE];
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate >= ending)
+        .min_by_key(|candidate| (*candidate - price).abs())
+        .unwrap_or(ending)
+}
+
+/// Apply the rules in order and return the recommended price with every step
+fn apply(rules: &[PricingRule], inputs: &RuleInputs) -> (Decimal, Vec<RuleStep>) {
+    let round = |price: Decimal| price.round_dp(inputs.decimal_places);
+    let mut price = inputs.list_price;
+    let mut steps = Vec::with_capacity(rules.len());
+    for rule in rules {
+        let (next, explanation) = match rule {
+            PricingRule::TargetMargin { percent } => match inputs.cost {
+                Some(cost) => (
+                    Some(round(price_for_margin(cost, *percent))),
+                    format!("Priced for a {percent}% margin on the cost"),
+                ),
+                None => (None, "No cost known for the product".to_string()),
+            },
+            PricingRule::CompetitorUndercut { percent } => match &inputs.cheapest_competitor {
+                Some((competitor, competitor_price)) => {
+                    let undercut = round(competitor_price * (Decimal::ONE - percent / Decimal::ONE_HUNDRED));
+                    if price > undercut {
+                        (Some(undercut), format!("{percent}% below the cheapest competitor {competitor}"))
+                    } else {
+                        (None, format!("Already at least {percent}% below the cheapest competitor {competitor}"))
+                    }
+                }
+                None => (None, "No competitor prices known for the product".to_string()),
+            },
+            PricingRule::MinMargin { percent } => match inputs.cost {
+                Some(cost) => {
+                    let floor = round(price_for_margin(cost, *percent));
+                    if price < floor {
+                        (Some(floor), format!("Raised to keep a {percent}% margin on the cost"))
+                    } else {
+                        (None, format!("Margin is at least {percent}%"))
+                    }
+                }
+                None => (None, "No cost known for the product".to_string()),
+            },
+            PricingRule::MaxChange { percent } => {
+                let delta = inputs.list_price * percent / Decimal::ONE_HUNDRED;
+                let (low, high) = (round(inputs.list_price - delta), round(inputs.list_price + delta));
+                if price < low {
+                    (Some(low), format!("Limited to {percent}% below the list price"))
+                } else if price > high {
+                    (Some(high), format!("Limited to {percent}% above the list price"))
+                } else {
+                    (None, format!("Within {percent}% of the list price"))
+                }
+            }
+            PricingRule::PsychologicalEnding { ending } => {
+                let ended = with_ending(price, *ending);
+                if ended != price {
+                    (Some(ended), format!("Moved to the nearest price ending in {ending}"))
+                } else {
+                    (None, format!("Already ends in {ending}"))
+                }
+            }
+        };
+        let fired = next.is_some_and(|next| next != price);
+        if let Some(next) = next {
+            price = next;
+        }
+        steps.push(RuleStep {
+            rule: rule.name(),
+            fired,
+            price,
+            explanation,
+        });
+    }
+    (price, steps)
+}
+
+/// Arguments of suggest_price
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SuggestArgs {
+    product_id: i32,
+}
+
+pub(crate) async fn handle_suggest_price(db: &dyn DatabaseBackend, args: &Value) -> Result<Value, PluginError> {
+    let pool = db.postgres()?;
+    let SuggestArgs { product_id } = args::parse(args)?;
+
+    let config = get_config();
+    if config.pricing_rules.is_empty() {
+        return Err(PluginError::PermissionDenied(
+            "No pricing rules configured, set pricing_rules to get suggestions".to_string(),
+        ));
+    }
+
+    let product = db
+        .fetch_product(product_id)
+        .await?
+        .ok_or_else(|| PluginError::not_found(format!("Product {product_id} not found")))?;
+    let inputs = RuleInputs {
+        list_price: product.price,
+        cost: margin::product_cost(pool, product_id).await?,
+        cheapest_competitor: competitors::cheapest_competitor(pool, product_id).await?,
+        decimal_places: config.price_decimal_places,
+    };
+
+    let (suggested, steps) = apply(&config.pricing_rules, &inputs);
+    let change = suggested - product.price;
+    let change_percent =
+        (!product.price.is_zero()).then(|| (change / product.price * Decimal::ONE_HUNDRED).round_dp(2));
+    let rules_fired: Vec<&str> = steps.iter().filter(|step| step.fired).map(|step| step.rule).collect();
+    let steps: Vec<Value> = steps.iter().map(RuleStep::to_json).collect();
+
+    // Return structured JSON data for programmatic clients
+    Ok(utils::json_content(json!({
+        "product_id": product.id,
+        "name": product.name,
+        "current_price": format_price(&product.price),
+        "suggested_price": format_price(&suggested),
+        "change": format_price(&change),
+        "change_percent": change_percent.map(|percent| percent.to_string()),
+        "rules_fired": rules_fired,
+        "steps": steps,
+        "base_currency": config.base_currency
+    })))
+}
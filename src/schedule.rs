@@ -0,0 +1,199 @@
+//! Scheduled prices
+//!
+//! With `scheduled_prices_enabled` set, prices can be planned ahead in the
+//! `scheduled_prices` table (name configurable through
+//! `scheduled_prices_table`):
+//!
+//! ```sql
+//! CREATE TABLE scheduled_prices (
+//!     product_id     INTEGER NOT NULL REFERENCES products(id),
+//!     price          NUMERIC(10,2) NOT NULL,
+//!     effective_from TIMESTAMPTZ NOT NULL,
+//!     effective_to   TIMESTAMPTZ
+//! );
+//! ```
+//!
+//! A row replaces the list price from `effective_from` up to, but not
+//! including, `effective_to`, or for good if that is null. Of overlapping
+//! rows the one starting last wins, so a short sale can sit on top of a
+//! long-running price. Outside every row the list price applies.
+//! `get_product_price` answers for the current time or its `as_of`
+//! argument; `get_upcoming_price_changes` lists the rows starting in the
+//! next days.
+
+use crate::args;
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::mapping;
+use crate::sql::{is_undefined_table, quote_identifier};
+use crate::{format_price, get_config, PluginConfig};
+use chrono::{DateTime, Duration, Utc};
+use mcp_plugin_api::utils;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Period of get_upcoming_price_changes without `days`
+const DEFAULT_UPCOMING_DAYS: i64 = 7;
+
+/// Longest period get_upcoming_price_changes looks ahead, in days
+const MAX_UPCOMING_DAYS: i64 = 366;
+
+/// Page size of get_upcoming_price_changes without `limit`
+const DEFAULT_UPCOMING_LIMIT: i64 = 100;
+
+/// Upper bound of the `limit` argument of get_upcoming_price_changes
+const MAX_UPCOMING_LIMIT: i64 = 500;
+
+/// Validate the schedule settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    quote_identifier(&config.scheduled_prices_table).map(|_| ())
+}
+
+/// Fail unless scheduled prices are enabled
+pub(crate) fn ensure_enabled() -> Result<(), PluginError> {
+    if get_config().scheduled_prices_enabled {
+        Ok(())
+    } else {
+        Err(PluginError::PermissionDenied(
+            "Scheduled prices are disabled, set scheduled_prices_enabled to use them".to_string(),
+        ))
+    }
+}
+
+/// A scheduled price in effect
+#[derive(Debug, sqlx::FromRow)]
+pub(crate) struct ScheduledPrice {
+    pub(crate) price: Decimal,
+    pub(crate) effective_from: DateTime<Utc>,
+    pub(crate) effective_to: Option<DateTime<Utc>>,
+}
+
+/// Scheduled price of a product at `at`, `None` outside every row or
+/// without the table
+pub(crate) async fn scheduled_price(
+    db: &dyn DatabaseBackend,
+    product_id: i32,
+    at: DateTime<Utc>,
+) -> Result<Option<ScheduledPrice>, PluginError> {
+    let table = quote_identifier(&get_config().scheduled_prices_table).map_err(PluginError::internal)?;
+    let result = sqlx::query_as::<_, ScheduledPrice>(&format!(
+        "SELECT price, effective_from, effective_to FROM {table} \
+         WHERE product_id = $1 AND effective_from <= $2 AND (effective_to IS NULL OR effective_to > $2) \
+         ORDER BY effective_from DESC LIMIT 1"
+    ))
+    .bind(product_id)
+    .bind(at)
+    .fetch_optional(db.postgres()?)
+    .await;
+
+    match result {
+        Ok(scheduled) => Ok(scheduled),
+        Err(err) if is_undefined_table(&err) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Arguments of get_upcoming_price_changes
+#[derive(Debug, Deserialize, JsonSchema)]
+struct UpcomingArgs {
+    #[schemars(range(min = 1, max = "MAX_UPCOMING_DAYS"))]
+    days: Option<i64>,
+    category: Option<String>,
+    #[schemars(range(min = 1, max = "MAX_UPCOMING_LIMIT"))]
+    limit: Option<i64>,
+    #[serde(default)]
+    #[schemars(range(min = 0))]
+    offset: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct UpcomingChange {
+    id: i32,
+    name: String,
+    category: Option<String>,
+    list_price: Decimal,
+    price: Decimal,
+    effective_from: DateTime<Utc>,
+    effective_to: Option<DateTime<Utc>>,
+    total: i64,
+}
+
+pub(crate) async fn handle_get_upcoming_price_changes(
+    db: &dyn DatabaseBackend,
+    args: &Value,
+) -> Result<Value, PluginError> {
+    ensure_enabled()?;
+    let pool = db.postgres()?;
+    let UpcomingArgs {
+        days,
+        category,
+        limit,
+        offset,
+    } = args::parse(args)?;
+    let days = days.unwrap_or(DEFAULT_UPCOMING_DAYS);
+    let limit = limit.unwrap_or(DEFAULT_UPCOMING_LIMIT);
+
+    let table = quote_identifier(&get_config().scheduled_prices_table).map_err(PluginError::internal)?;
+    let from = Utc::now();
+    let to = from + Duration::days(days);
+
+    // Soonest first; the window count gives the total for paging
+    let result = sqlx::query_as::<_, UpcomingChange>(&format!(
+        "SELECT products.id, products.name, products.category, products.price AS list_price, \
+                scheduled.price, scheduled.effective_from, scheduled.effective_to, \
+                count(*) OVER () AS total \
+         FROM {table} AS scheduled JOIN {} ON products.id = scheduled.product_id \
+         WHERE scheduled.effective_from > $1 AND scheduled.effective_from <= $2 \
+           AND ($3::text IS NULL OR products.category = $3) \
+         ORDER BY scheduled.effective_from, products.id \
+         LIMIT $4 OFFSET $5",
+        mapping::products()
+    ))
+    .bind(from)
+    .bind(to)
+    .bind(&category)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await;
+    let changes = match result {
+        Ok(changes) => changes,
+        Err(err) if is_undefined_table(&err) => Vec::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    let total = changes.first().map_or(0, |change| change.total);
+    let upcoming: Vec<Value> = changes
+        .iter()
+        .map(|change| {
+            let change_percent = (!change.list_price.is_zero()).then(|| {
+                ((change.price - change.list_price) / change.list_price * Decimal::ONE_HUNDRED)
+                    .round_dp(2)
+                    .to_string()
+            });
+            json!({
+                "id": change.id,
+                "name": change.name,
+                "category": change.category,
+                "list_price": format_price(&change.list_price),
+                "scheduled_price": format_price(&change.price),
+                "change_percent": change_percent,
+                "effective_from": change.effective_from.to_rfc3339(),
+                "effective_to": change.effective_to.map(|to| to.to_rfc3339())
+            })
+        })
+        .collect();
+
+    // Return structured JSON data for programmatic clients
+    Ok(utils::json_content(json!({
+        "from": from.to_rfc3339(),
+        "to": to.to_rfc3339(),
+        "changes": upcoming,
+        "count": upcoming.len(),
+        "total": total,
+        "offset": offset,
+        "base_currency": get_config().base_currency
+    })))
+}
@@ -0,0 +1,307 @@
+//! Product search modes
+//!
+//! `search_products` matches the query against products in one of three
+//! ways, chosen by the `search_mode` config or per call:
+//!
+//! - `ilike`: case-insensitive substring match on the name
+//! - `fulltext`: Postgres full-text search over name and description,
+//!   ranked with `ts_rank`
+//! - `trigram`: fuzzy name match through the `pg_trgm` extension, ranked by
+//!   `similarity`, tolerating typos like "blutooth speker"; names match
+//!   from a similarity of `trigram_threshold`
+//!
+//! For large tables the full-text and trigram modes should be backed by
+//! matching indexes, see the README.
+//!
+//! Results are ordered by one of a fixed set of `sort` options, each mapped
+//! to a constant ORDER BY clause, and paged with `limit` and `offset`.
+//!
+//! A search without any match suggests the product names closest to the
+//! query as `did_you_mean`, by `pg_trgm` word similarity, in every mode.
+
+use crate::attributes;
+use crate::backend::{DatabaseBackend, ProductSearch};
+use crate::error::PluginError;
+use crate::mapping::{self, PRODUCT_COLUMNS};
+use crate::sql::escape_like;
+use crate::{get_config, PluginConfig, Product};
+use rust_decimal::Decimal;
+use serde_json::{Map, Value};
+use sqlx::{PgConnection, Postgres, QueryBuilder};
+
+/// How the search query is matched against products
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SearchMode {
+    Ilike,
+    Fulltext,
+    Trigram,
+}
+
+impl SearchMode {
+    pub(crate) fn parse(value: &str) -> Result<Self, PluginError> {
+        match value {
+            "ilike" => Ok(SearchMode::Ilike),
+            "fulltext" => Ok(SearchMode::Fulltext),
+            "trigram" => Ok(SearchMode::Trigram),
+            other => Err(PluginError::invalid_argument(format!(
+                "Invalid search_mode '{other}', expected one of: ilike, fulltext, trigram"
+            ))),
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            SearchMode::Ilike => "ilike",
+            SearchMode::Fulltext => "fulltext",
+            SearchMode::Trigram => "trigram",
+        }
+    }
+}
+
+/// Upper bound of the `limit` argument
+pub(crate) const MAX_SEARCH_LIMIT: i64 = 500;
+
+/// Order of search results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SearchSort {
+    /// Best match first in ranked modes, by ID otherwise
+    Relevance,
+    PriceAsc,
+    PriceDesc,
+    Name,
+}
+
+impl SearchSort {
+    pub(crate) fn parse(value: &str) -> Result<Self, PluginError> {
+        match value {
+            "relevance" => Ok(SearchSort::Relevance),
+            "price_asc" => Ok(SearchSort::PriceAsc),
+            "price_desc" => Ok(SearchSort::PriceDesc),
+            "name" => Ok(SearchSort::Name),
+            other => Err(PluginError::invalid_argument(format!(
+                "Invalid sort '{other}', expected one of: relevance, price_asc, price_desc, name"
+            ))),
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            SearchSort::Relevance => "relevance",
+            SearchSort::PriceAsc => "price_asc",
+            SearchSort::PriceDesc => "price_desc",
+            SearchSort::Name => "name",
+        }
+    }
+
+    /// ORDER BY expression, never built from client input
+    ///
+    /// Ties are broken by ID so that paging with `offset` is stable.
+    pub(crate) fn order_by(&self, mode: SearchMode) -> &'static str {
+        match (self, mode) {
+            (SearchSort::Relevance, SearchMode::Ilike) => "id",
+            (SearchSort::Relevance, SearchMode::Fulltext | SearchMode::Trigram) => "rank DESC, id",
+            (SearchSort::PriceAsc, _) => "price ASC, id",
+            (SearchSort::PriceDesc, _) => "price DESC, id",
+            (SearchSort::Name, _) => "name, id",
+        }
+    }
+}
+
+/// Product row with its relevance, NULL for unranked modes
+#[derive(sqlx::FromRow)]
+pub(crate) struct SearchHit {
+    #[sqlx(flatten)]
+    pub(crate) product: Product,
+    pub(crate) rank: Option<f32>,
+}
+
+/// Validate the search settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    SearchMode::parse(&config.search_mode).map_err(|err| err.message().to_string())?;
+
+    // Inlined into the query text so expression indexes can be used
+    let language = &config.search_language;
+    let valid = !language.is_empty()
+        && language.len() <= 63
+        && language.chars().all(|c| c.is_ascii_lowercase() || c == '_');
+    if !valid {
+        return Err(format!(
+            "Invalid search_language '{language}': expected a text search configuration name like 'english'"
+        ));
+    }
+    if !(0.0..=1.0).contains(&config.trigram_threshold) {
+        return Err("trigram_threshold must be between 0 and 1".to_string());
+    }
+    if config.did_you_mean_limit < 0 {
+        return Err("did_you_mean_limit must not be negative".to_string());
+    }
+    Ok(())
+}
+
+/// LIKE pattern for a name search
+///
+/// The query matches as a literal substring unless `raw` is set, in which
+/// case it is used as the pattern itself.
+pub(crate) fn like_pattern(query: &str, raw: bool) -> String {
+    if raw {
+        query.to_string()
+    } else {
+        format!("%{}%", escape_like(query))
+    }
+}
+
+/// Append `SELECT ... FROM <products> WHERE <match>` for the given mode
+///
+/// With `raw` the query is passed through unchanged: as LIKE pattern in
+/// `ilike` mode and in `to_tsquery` syntax in `fulltext` mode.
+pub(crate) fn push_select(sql: &mut QueryBuilder<'_, Postgres>, mode: SearchMode, query: &str, raw: bool) {
+    let products = mapping::products();
+    sql.push(format!("SELECT {PRODUCT_COLUMNS}, "));
+
+    match mode {
+        SearchMode::Ilike => {
+            sql.push("NULL::real");
+        }
+        SearchMode::Fulltext => {
+            let ts_query = if raw { "to_tsquery" } else { "plainto_tsquery" };
+            sql.push(format!("ts_rank({}, {ts_query}('{}', ", document(), get_config().search_language))
+                .push_bind(query.to_string())
+                .push("))");
+        }
+        SearchMode::Trigram => {
+            sql.push("similarity(name, ").push_bind(query.to_string()).push(")");
+        }
+    }
+    sql.push(format!(" AS rank FROM {products} WHERE "));
+    push_match(sql, mode, query, raw);
+}
+
+/// Text the fulltext mode searches, inlined so expression indexes apply
+fn document() -> String {
+    format!(
+        "to_tsvector('{}', name || ' ' || coalesce(description, ''))",
+        get_config().search_language
+    )
+}
+
+/// Append the condition selecting the products matching `query`
+pub(crate) fn push_match(sql: &mut QueryBuilder<'_, Postgres>, mode: SearchMode, query: &str, raw: bool) {
+    match mode {
+        SearchMode::Ilike => {
+            sql.push("name ILIKE ")
+                .push_bind(like_pattern(query, raw))
+                .push(" ESCAPE '\\'");
+        }
+        SearchMode::Fulltext => {
+            let ts_query = if raw { "to_tsquery" } else { "plainto_tsquery" };
+            sql.push(format!("{} @@ {ts_query}('{}', ", document(), get_config().search_language))
+                .push_bind(query.to_string())
+                .push(")");
+        }
+        SearchMode::Trigram => {
+            sql.push("name % ").push_bind(query.to_string());
+        }
+    }
+}
+
+/// Append ` AND ...` conditions for the category, brand, attribute, price
+/// range and status filters of a search
+pub(crate) fn push_filters(
+    sql: &mut QueryBuilder<'_, Postgres>,
+    category: Option<&str>,
+    brand: Option<&str>,
+    attributes: Option<&Map<String, Value>>,
+    min_price: Option<Decimal>,
+    max_price: Option<Decimal>,
+    include_inactive: bool,
+) {
+    if let Some(category) = category {
+        sql.push(" AND category = ").push_bind(category.to_string());
+    }
+    if let Some(brand) = brand {
+        sql.push(" AND brand = ").push_bind(brand.to_string());
+    }
+    if let Some(attributes) = attributes {
+        attributes::push_filter(sql, attributes);
+    }
+    if let Some(min_price) = min_price {
+        sql.push(" AND price >= ").push_bind(min_price);
+    }
+    if let Some(max_price) = max_price {
+        sql.push(" AND price <= ").push_bind(max_price);
+    }
+    push_status_filter(sql, include_inactive);
+}
+
+/// Append ` AND ...` leaving out inactive products unless asked for
+pub(crate) fn push_status_filter(sql: &mut QueryBuilder<'_, Postgres>, include_inactive: bool) {
+    if !include_inactive {
+        sql.push(" AND (status IS NULL OR status = ANY(")
+            .push_bind(get_config().schema_mapping.active_statuses.clone())
+            .push("))");
+    }
+}
+
+/// Append the complete statement of a product search
+pub(crate) fn push_search(sql: &mut QueryBuilder<'_, Postgres>, search: &ProductSearch<'_>) {
+    push_select(sql, search.mode, search.query, search.raw_pattern);
+    push_filters(
+        sql,
+        search.category,
+        search.brand,
+        search.attributes,
+        search.min_price,
+        search.max_price,
+        search.include_inactive,
+    );
+    sql.push(" ORDER BY ")
+        .push(search.sort.order_by(search.mode))
+        .push(" LIMIT ")
+        .push_bind(search.limit.unwrap_or(i64::MAX))
+        .push(" OFFSET ")
+        .push_bind(search.offset);
+}
+
+/// Apply `trigram_threshold` to the trigram searches of the transaction
+///
+/// `%` matches against this setting; unlike a similarity() condition it
+/// can use the trigram index.
+pub(crate) async fn set_trigram_threshold(conn: &mut PgConnection) -> Result<(), PluginError> {
+    sqlx::query("SELECT set_config('pg_trgm.similarity_threshold', $1, true)")
+        .bind(get_config().trigram_threshold.to_string())
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Names of the products closest to `query`, best first, for a search that
+/// matched nothing
+///
+/// Suggestions are a courtesy: without Postgres or `pg_trgm` there are
+/// none, and a failing query only logs a warning.
+pub(crate) async fn did_you_mean(db: &dyn DatabaseBackend, query: &str, include_inactive: bool) -> Vec<String> {
+    let config = get_config();
+    let Ok(pool) = db.postgres() else {
+        return Vec::new();
+    };
+    if config.did_you_mean_limit == 0 {
+        return Vec::new();
+    }
+    let suggestions = sqlx::query_scalar::<_, String>(&format!(
+        "SELECT name FROM {} WHERE word_similarity($1, name) >= $2 \
+         AND ($3 OR status IS NULL OR status = ANY($4)) \
+         GROUP BY name ORDER BY max(word_similarity($1, name)) DESC, name LIMIT $5",
+        mapping::products()
+    ))
+    .bind(query)
+    .bind(config.trigram_threshold)
+    .bind(include_inactive)
+    .bind(&config.schema_mapping.active_statuses)
+    .bind(config.did_you_mean_limit)
+    .fetch_all(pool)
+    .await;
+    suggestions.unwrap_or_else(|err| {
+        tracing::warn!("did_you_mean suggestions failed: {err}");
+        Vec::new()
+    })
+}
@@ -0,0 +1,70 @@
+//! Connection secrets
+//!
+//! Keeps passwords out of the plugin configuration. `database_url` may
+//! reference environment variables as `${NAME}`, and the password can be
+//! read from a file such as a mounted Kubernetes or Docker secret:
+//!
+//! ```json
+//! {
+//!     "database_url": "postgresql://${PGUSER}@${PGHOST}:5432/products",
+//!     "database_password_file": "/run/secrets/db_password"
+//! }
+//! ```
+//!
+//! Both are resolved whenever a configuration is validated or a pool is
+//! connected, so a missing variable or unreadable file fails init with an
+//! error naming it.
+
+use crate::PluginConfig;
+
+/// Replace every `${NAME}` in `text` with the value of the environment variable
+///
+/// `$$` stands for a literal `$`.
+pub(crate) fn interpolate_env(text: &str, field: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(pos) = rest.find('$') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            result.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let end = after
+                .find('}')
+                .ok_or_else(|| format!("Unterminated '${{' in {field}"))?;
+            let name = &after[..end];
+            if name.is_empty() {
+                return Err(format!("Empty variable reference '${{}}' in {field}"));
+            }
+            let value = std::env::var(name).map_err(|_| {
+                format!("Environment variable {name} referenced in {field} is not set")
+            })?;
+            result.push_str(&value);
+            rest = &after[end + 1..];
+        } else {
+            result.push('$');
+        }
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// `database_url` with environment variables substituted
+pub(crate) fn database_url(config: &PluginConfig) -> Result<String, String> {
+    interpolate_env(&config.database_url, "database_url")
+}
+
+/// Password read from `database_password_file`, if one is configured
+pub(crate) fn database_password(config: &PluginConfig) -> Result<Option<String>, String> {
+    let Some(path) = &config.database_password_file else {
+        return Ok(None);
+    };
+    let password = std::fs::read_to_string(path)
+        .map_err(|err| format!("Cannot read database_password_file {path}: {err}"))?;
+    // Secret files usually end with a newline that is not part of the password
+    Ok(Some(password.trim_end_matches(['\r', '\n']).to_string()))
+}
@@ -0,0 +1,111 @@
+//! Demo data for development databases
+//!
+//! `seed_demo_data` creates the products table of the README quickstart if
+//! it does not exist and appends generated products to it, so the plugin
+//! can be tried against an empty database. Like the write tools it is
+//! opt-in: it refuses to run unless `enable_dev_tools` is set, which needs
+//! `read_only` off. Products are generated in SQL from a fixed set of names
+//! and categories with deterministic prices, numbered after the products
+//! already present.
+
+use crate::args;
+use crate::backend::DatabaseBackend;
+use crate::cache::CacheBackend;
+use crate::error::PluginError;
+use crate::mapping::SchemaMapping;
+use crate::{get_config, PluginConfig};
+use mcp_plugin_api::utils;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Products inserted when the caller does not pass `count`
+const DEFAULT_SEED_COUNT: i64 = 100;
+
+/// Upper bound of the `count` argument
+const MAX_SEED_COUNT: i64 = 10_000;
+
+/// Arguments of seed_demo_data
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SeedArgs {
+    #[schemars(range(min = 1, max = "MAX_SEED_COUNT"))]
+    count: Option<i64>,
+}
+
+/// Validate the dev tool settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if config.enable_dev_tools && config.read_only {
+        return Err("enable_dev_tools requires read_only to be set to false".to_string());
+    }
+    Ok(())
+}
+
+pub(crate) async fn handle_seed_demo_data(
+    db: &dyn DatabaseBackend,
+    cache: &dyn CacheBackend,
+    args: &Value,
+) -> Result<Value, PluginError> {
+    let config = get_config();
+    if !config.enable_dev_tools {
+        return Err(PluginError::PermissionDenied(
+            "Dev tools are disabled, set enable_dev_tools to allow them".to_string(),
+        ));
+    }
+    if config.schema_mapping != SchemaMapping::default() {
+        return Err(PluginError::Unsupported(
+            "seed_demo_data only fills the default products table, not a schema_mapping table".to_string(),
+        ));
+    }
+
+    let SeedArgs { count } = args::parse(args)?;
+    let count = count.unwrap_or(DEFAULT_SEED_COUNT);
+
+    let mut tx = db.primary()?.begin().await?;
+    let created = sqlx::query_scalar::<_, bool>("SELECT to_regclass('products') IS NULL")
+        .fetch_one(&mut *tx)
+        .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS products (
+            id SERIAL PRIMARY KEY,
+            name VARCHAR(255) NOT NULL,
+            description TEXT,
+            price DECIMAL(10,2) NOT NULL,
+            category VARCHAR(100),
+            stock INTEGER NOT NULL DEFAULT 0
+        )",
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let existing = sqlx::query_scalar::<_, i64>("SELECT count(*) FROM products")
+        .fetch_one(&mut *tx)
+        .await?;
+    let inserted = sqlx::query(
+        "INSERT INTO products (name, description, price, category, stock)
+         SELECT (ARRAY['Widget', 'Gadget', 'Gizmo', 'Sprocket', 'Doohickey'])[1 + n % 5]
+                    || ' ' || (ARRAY['Pro', 'Lite', 'Max', 'Mini', 'Plus', 'Classic'])[1 + n / 5 % 6]
+                    || ' ' || n,
+                'Demo product number ' || n,
+                round(4.99 + (n::bigint * 7919 % 20000) / 100.0, 2),
+                (ARRAY['Widgets', 'Gadgets', 'Gizmos', 'Sprockets', 'Doohickeys'])[1 + n % 5],
+                (n::bigint * 37 % 250)::int
+         FROM generate_series($1 + 1, $1 + $2) AS n",
+    )
+    // int4, so n can subscript the arrays
+    .bind(existing as i32)
+    .bind(count as i32)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    tx.commit().await?;
+
+    // Cached searches and category counts no longer match the table
+    cache.clear().await;
+
+    Ok(utils::json_content(json!({
+        "table": "products",
+        "table_created": created,
+        "inserted": inserted,
+        "product_count": existing + inserted as i64
+    })))
+}
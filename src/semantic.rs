@@ -0,0 +1,606 @@
+//! Semantic product search
+//!
+//! `semantic_search_products` finds products by meaning rather than by
+//! words, e.g. "hiking boots" for "waterproof trail shoes". Product
+//! embeddings are kept in a pgvector table (names configurable through
+//! `semantic_search`), filled by whatever pipeline embeds the catalogue:
+//!
+//! ```sql
+//! CREATE EXTENSION vector;
+//! CREATE TABLE product_embeddings (
+//!     product_id INTEGER PRIMARY KEY REFERENCES products (id),
+//!     embedding  vector(1536) NOT NULL
+//! );
+//! CREATE INDEX ON product_embeddings USING hnsw (embedding vector_cosine_ops);
+//! ```
+//!
+//! The embeddings can also be a column of the product table itself, with
+//! `table` set to it and `product_id_column` to its ID column.
+//!
+//! The query is embedded by the configured provider, which must use the
+//! model the catalogue was embedded with:
+//!
+//! - `openai`: an OpenAI compatible `/v1/embeddings` endpoint, answering
+//!   `{"data": [{"embedding": [...]}]}`, as served by OpenAI, Azure,
+//!   Ollama or vLLM
+//! - `json`: an endpoint taking `{"text": "...", "model": "..."}` and
+//!   answering `{"embedding": [...]}`
+//!
+//! `${NAME}` in `url` and `api_key` is replaced by environment variables.
+//! Products are ranked by cosine similarity to the query, and those below
+//! `min_score` are left out.
+//!
+//! In `hybrid` mode the query also runs as keyword search in one of the
+//! `search_products` modes, and both rankings are merged by reciprocal
+//! rank fusion: a product scores `keyword_weight / (rrf_k + rank)` for its
+//! keyword rank plus `semantic_weight / (rrf_k + rank)` for its semantic
+//! rank, ranks starting at 1, and nothing for a ranking it is missing
+//! from. Every result carries the breakdown, so the weights can be tuned.
+
+use crate::args;
+use crate::backend::{BackendKind, DatabaseBackend, ProductSearch};
+use crate::error::PluginError;
+use crate::mapping::{self, quote_column, PRODUCT_COLUMNS};
+use crate::progress::Progress;
+use crate::search::{SearchHit, SearchMode, SearchSort};
+use crate::sql::{is_undefined_table, quote_identifier};
+use crate::{currency, get_config, locale, product_json, secrets, PluginConfig};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use mcp_plugin_api::utils;
+use once_cell::sync::Lazy;
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Number of products when the caller does not pass `limit`
+const DEFAULT_SEMANTIC_LIMIT: i64 = 10;
+
+/// Upper bound of the `limit` argument
+const MAX_SEMANTIC_LIMIT: i64 = 100;
+
+/// Candidates read from each ranking in hybrid mode, per returned product
+const HYBRID_CANDIDATE_FACTOR: i64 = 3;
+
+/// Maximum time an embedding request may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+/// The `semantic_search` config field
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub(crate) struct SemanticSearch {
+    /// Embedding provider: none (semantic search off), openai or json
+    #[serde(default = "default_provider")]
+    provider: String,
+
+    /// Endpoint the query is embedded with, e.g.
+    /// https://api.openai.com/v1/embeddings
+    #[serde(default)]
+    url: Option<String>,
+
+    /// Bearer token sent to the endpoint, e.g. "${OPENAI_API_KEY}"
+    #[serde(default)]
+    api_key: Option<String>,
+
+    /// Embedding model passed to the endpoint
+    #[serde(default)]
+    model: Option<String>,
+
+    /// Dimensions of the stored embeddings; answers of another size fail
+    #[serde(default)]
+    dimensions: Option<usize>,
+
+    /// Table with the product embeddings, optionally schema qualified
+    #[serde(default = "default_table")]
+    table: String,
+
+    /// Column of the embeddings table referencing the product id
+    #[serde(default = "default_product_id_column")]
+    product_id_column: String,
+
+    /// pgvector column holding the embeddings
+    #[serde(default = "default_embedding_column")]
+    embedding_column: String,
+
+    /// Lowest cosine similarity (0-1) of a returned product
+    #[serde(default = "default_min_score")]
+    min_score: f64,
+
+    /// Weight of the keyword ranking in hybrid mode
+    #[serde(default = "default_weight")]
+    keyword_weight: f64,
+
+    /// Weight of the semantic ranking in hybrid mode
+    #[serde(default = "default_weight")]
+    semantic_weight: f64,
+
+    /// Rank offset of reciprocal rank fusion; larger values flatten the
+    /// advantage of the top ranks
+    #[serde(default = "default_rrf_k")]
+    rrf_k: u32,
+}
+
+impl Default for SemanticSearch {
+    fn default() -> Self {
+        SemanticSearch {
+            provider: default_provider(),
+            url: None,
+            api_key: None,
+            model: None,
+            dimensions: None,
+            table: default_table(),
+            product_id_column: default_product_id_column(),
+            embedding_column: default_embedding_column(),
+            min_score: default_min_score(),
+            keyword_weight: default_weight(),
+            semantic_weight: default_weight(),
+            rrf_k: default_rrf_k(),
+        }
+    }
+}
+
+fn default_provider() -> String {
+    "none".to_string()
+}
+
+fn default_table() -> String {
+    "product_embeddings".to_string()
+}
+
+fn default_product_id_column() -> String {
+    "product_id".to_string()
+}
+
+fn default_embedding_column() -> String {
+    "embedding".to_string()
+}
+
+fn default_min_score() -> f64 {
+    0.5
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_rrf_k() -> u32 {
+    60
+}
+
+/// Provider selected by `semantic_search.provider`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProviderKind {
+    None,
+    OpenAi,
+    Json,
+}
+
+impl ProviderKind {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "none" => Ok(ProviderKind::None),
+            "openai" => Ok(ProviderKind::OpenAi),
+            "json" => Ok(ProviderKind::Json),
+            other => Err(format!(
+                "Invalid semantic_search.provider '{other}', expected one of: none, openai, json"
+            )),
+        }
+    }
+}
+
+/// Validate the semantic search settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    let semantic = &config.semantic_search;
+    if ProviderKind::parse(&semantic.provider)? == ProviderKind::None {
+        return Ok(());
+    }
+    if BackendKind::parse(&config.backend)? != BackendKind::Postgres {
+        return Err("semantic_search requires the postgres backend".to_string());
+    }
+    let url = semantic
+        .url
+        .as_deref()
+        .ok_or("semantic_search.provider requires semantic_search.url")?;
+    // Leave out the URL, which may embed an API key
+    let url = secrets::interpolate_env(url, "semantic_search.url")?;
+    let parsed = Url::parse(&url).map_err(|err| format!("Invalid semantic_search.url: {err}"))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err("Invalid semantic_search.url: expected an http or https URL".to_string());
+    }
+    if let Some(api_key) = &semantic.api_key {
+        secrets::interpolate_env(api_key, "semantic_search.api_key")?;
+    }
+    if semantic.dimensions == Some(0) {
+        return Err("semantic_search.dimensions must be at least 1".to_string());
+    }
+    if !(0.0..=1.0).contains(&semantic.min_score) {
+        return Err("semantic_search.min_score must be between 0 and 1".to_string());
+    }
+    if semantic.keyword_weight < 0.0 || semantic.semantic_weight < 0.0 {
+        return Err("semantic_search weights must not be negative".to_string());
+    }
+    if semantic.keyword_weight + semantic.semantic_weight <= 0.0 {
+        return Err("semantic_search.keyword_weight or semantic_weight must be positive".to_string());
+    }
+    if semantic.rrf_k < 1 {
+        return Err("semantic_search.rrf_k must be at least 1".to_string());
+    }
+    quote_identifier(&semantic.table)?;
+    quote_column(&semantic.product_id_column)?;
+    quote_column(&semantic.embedding_column)?;
+    Ok(())
+}
+
+/// Source of query embeddings
+trait EmbeddingProvider: Send + Sync {
+    /// Embedding of `text`
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>, String>>;
+}
+
+/// Endpoint settings shared by the providers
+struct Endpoint {
+    url: String,
+    api_key: Option<String>,
+    model: Option<String>,
+}
+
+impl Endpoint {
+    async fn post(&self, body: Value) -> Result<Value, String> {
+        let mut request = CLIENT
+            .post(&self.url)
+            .timeout(REQUEST_TIMEOUT)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string());
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| err.without_url().to_string())?;
+        let body = response.text().await.map_err(|err| err.without_url().to_string())?;
+        serde_json::from_str(&body).map_err(|err| format!("Invalid JSON: {err}"))
+    }
+}
+
+/// Parse an embedding given as array of numbers
+fn parse_embedding(value: &Value) -> Result<Vec<f32>, String> {
+    value
+        .as_array()
+        .ok_or("Response has no embedding")?
+        .iter()
+        .map(|component| component.as_f64().map(|component| component as f32))
+        .collect::<Option<Vec<f32>>>()
+        .ok_or_else(|| "Embedding contains a non-numeric component".to_string())
+}
+
+struct OpenAiProvider(Endpoint);
+
+impl EmbeddingProvider for OpenAiProvider {
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>, String>> {
+        async move {
+            let mut body = json!({"input": text});
+            if let Some(model) = &self.0.model {
+                body["model"] = json!(model);
+            }
+            let response = self.0.post(body).await?;
+            parse_embedding(&response["data"][0]["embedding"])
+        }
+        .boxed()
+    }
+}
+
+struct JsonProvider(Endpoint);
+
+impl EmbeddingProvider for JsonProvider {
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>, String>> {
+        async move {
+            let mut body = json!({"text": text});
+            if let Some(model) = &self.0.model {
+                body["model"] = json!(model);
+            }
+            let response = self.0.post(body).await?;
+            parse_embedding(&response["embedding"])
+        }
+        .boxed()
+    }
+}
+
+fn provider(semantic: &SemanticSearch) -> Option<Box<dyn EmbeddingProvider>> {
+    // Interpolation succeeded when the configuration was validated
+    let endpoint = Endpoint {
+        url: secrets::interpolate_env(semantic.url.as_deref()?, "semantic_search.url").ok()?,
+        api_key: semantic
+            .api_key
+            .as_deref()
+            .and_then(|api_key| secrets::interpolate_env(api_key, "semantic_search.api_key").ok()),
+        model: semantic.model.clone(),
+    };
+    match ProviderKind::parse(&semantic.provider).ok()? {
+        ProviderKind::None => None,
+        ProviderKind::OpenAi => Some(Box::new(OpenAiProvider(endpoint))),
+        ProviderKind::Json => Some(Box::new(JsonProvider(endpoint))),
+    }
+}
+
+/// pgvector text representation of an embedding, e.g. `[0.1,-0.2]`
+fn vector_literal(embedding: &[f32]) -> String {
+    let components: Vec<String> = embedding.iter().map(f32::to_string).collect();
+    format!("[{}]", components.join(","))
+}
+
+/// Arguments of semantic_search_products
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SemanticSearchArgs {
+    #[schemars(length(min = 1))]
+    query: String,
+    category: Option<String>,
+    #[schemars(range(min = 0, max = 1))]
+    min_score: Option<f64>,
+    #[schemars(range(min = 1, max = "MAX_SEMANTIC_LIMIT"))]
+    limit: Option<i64>,
+    currency: Option<String>,
+    locale: Option<String>,
+    #[serde(default)]
+    include_inactive: bool,
+    mode: Option<String>,
+    search_mode: Option<String>,
+}
+
+/// How semantic_search_products ranks products
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Semantic,
+    Hybrid,
+}
+
+impl Mode {
+    fn parse(value: &str) -> Result<Self, PluginError> {
+        match value {
+            "semantic" => Ok(Mode::Semantic),
+            "hybrid" => Ok(Mode::Hybrid),
+            other => Err(PluginError::invalid_argument(format!(
+                "Invalid mode '{other}', expected one of: semantic, hybrid"
+            ))),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Semantic => "semantic",
+            Mode::Hybrid => "hybrid",
+        }
+    }
+}
+
+/// Filters shared by the semantic and keyword queries
+struct Filters<'a> {
+    category: Option<&'a str>,
+    include_inactive: bool,
+    limit: i64,
+}
+
+/// Products closest to `embedding`, most similar first, with the cosine
+/// similarity as rank
+async fn vector_hits(
+    pool: &PgPool,
+    semantic: &SemanticSearch,
+    active_statuses: &[String],
+    embedding: &[f32],
+    min_score: f64,
+    filters: &Filters<'_>,
+) -> Result<Vec<SearchHit>, PluginError> {
+    let vector = vector_literal(embedding);
+    let table = quote_identifier(&semantic.table).map_err(PluginError::internal)?;
+    let product_id = quote_column(&semantic.product_id_column).map_err(PluginError::internal)?;
+    let column = quote_column(&semantic.embedding_column).map_err(PluginError::internal)?;
+    let mut sql = QueryBuilder::<Postgres>::new(format!("SELECT {PRODUCT_COLUMNS}, (1 - (embedding <=> "));
+    sql.push_bind(vector.clone())
+        .push(format!(
+            "::vector))::real AS rank FROM {} \
+             JOIN (SELECT {product_id} AS embedding_product_id, {column} AS embedding FROM {table}) AS embeddings \
+             ON embedding_product_id = id WHERE 1 - (embedding <=> ",
+            mapping::products()
+        ))
+        .push_bind(vector.clone())
+        .push("::vector) >= ")
+        .push_bind(min_score);
+    if let Some(category) = filters.category {
+        sql.push(" AND category = ").push_bind(category.to_string());
+    }
+    if !filters.include_inactive {
+        sql.push(" AND (status IS NULL OR status = ANY(")
+            .push_bind(active_statuses.to_vec())
+            .push("))");
+    }
+    // Ordering by the distance itself lets an index on it be used
+    sql.push(" ORDER BY embedding <=> ")
+        .push_bind(vector)
+        .push("::vector, id LIMIT ")
+        .push_bind(filters.limit);
+
+    sql.build_query_as::<SearchHit>().fetch_all(pool).await.map_err(|err| {
+        if is_undefined_table(&err) {
+            PluginError::Unsupported(format!(
+                "Semantic search needs the {} table, see the semantic module",
+                semantic.table
+            ))
+        } else {
+            err.into()
+        }
+    })
+}
+
+/// A product of the fused ranking with its place in either ranking
+struct Fused {
+    hit: SearchHit,
+    keyword: Option<(usize, Option<f32>)>,
+    semantic: Option<(usize, Option<f32>)>,
+    score: f64,
+}
+
+/// Merge the keyword and semantic rankings by weighted reciprocal rank fusion
+fn fuse(
+    semantic: &SemanticSearch,
+    keyword_hits: Vec<SearchHit>,
+    vector_hits: Vec<SearchHit>,
+    limit: i64,
+) -> Vec<Fused> {
+    let k = f64::from(semantic.rrf_k);
+    let mut fused: Vec<Fused> = Vec::new();
+    let mut positions: HashMap<i32, usize> = HashMap::new();
+    for (keyword, hits) in [(true, keyword_hits), (false, vector_hits)] {
+        let weight = if keyword { semantic.keyword_weight } else { semantic.semantic_weight };
+        for (index, hit) in hits.into_iter().enumerate() {
+            let rank = index + 1;
+            let score = weight / (k + rank as f64);
+            let place = Some((rank, hit.rank));
+            let position = *positions.entry(hit.product.id).or_insert_with(|| {
+                fused.push(Fused {
+                    hit,
+                    keyword: None,
+                    semantic: None,
+                    score: 0.0,
+                });
+                fused.len() - 1
+            });
+            let entry = &mut fused[position];
+            entry.score += score;
+            if keyword {
+                entry.keyword = place;
+            } else {
+                entry.semantic = place;
+            }
+        }
+    }
+    fused.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.hit.product.id.cmp(&b.hit.product.id)));
+    fused.truncate(limit as usize);
+    fused
+}
+
+pub(crate) async fn handle_semantic_search_products(
+    db: &dyn DatabaseBackend,
+    args: &Value,
+) -> Result<Value, PluginError> {
+    let pool = db.postgres()?;
+    let SemanticSearchArgs {
+        query,
+        category,
+        min_score,
+        limit,
+        currency,
+        locale,
+        include_inactive,
+        mode,
+        search_mode,
+    } = args::parse(args)?;
+    let config = get_config();
+    let semantic = &config.semantic_search;
+    let provider = provider(semantic).ok_or_else(|| {
+        PluginError::Unsupported("Semantic search needs a semantic_search.provider".to_string())
+    })?;
+    let mode = mode.as_deref().map_or(Ok(Mode::Semantic), Mode::parse)?;
+    let search_mode = SearchMode::parse(search_mode.as_deref().unwrap_or(&config.search_mode))?;
+    let min_score = min_score.unwrap_or(semantic.min_score);
+    let limit = limit.unwrap_or(DEFAULT_SEMANTIC_LIMIT);
+    let currency = currency::parse_currency(currency.as_deref())?;
+    let locale = locale::parse_locale(locale.as_deref())?;
+
+    let embedding = provider
+        .embed(&query)
+        .await
+        .map_err(|err| PluginError::Upstream(format!("Embedding the query failed: {err}")))?;
+    if let Some(dimensions) = semantic.dimensions.filter(|dimensions| *dimensions != embedding.len()) {
+        return Err(PluginError::Upstream(format!(
+            "The embedding provider answered with {} dimensions, expected {dimensions}",
+            embedding.len()
+        )));
+    }
+
+    let filters = Filters {
+        category: category.as_deref(),
+        include_inactive,
+        limit: match mode {
+            Mode::Semantic => limit,
+            Mode::Hybrid => limit * HYBRID_CANDIDATE_FACTOR,
+        },
+    };
+    let active_statuses = &config.schema_mapping.active_statuses;
+    let mut vector_hits = vector_hits(pool, semantic, active_statuses, &embedding, min_score, &filters).await?;
+    let fused = match mode {
+        Mode::Semantic => None,
+        Mode::Hybrid => {
+            let progress = Progress::from_args(args);
+            let search = ProductSearch {
+                mode: search_mode,
+                query: &query,
+                raw_pattern: false,
+                category: filters.category,
+                brand: None,
+                attributes: None,
+                min_price: None,
+                max_price: None,
+                sort: SearchSort::Relevance,
+                limit: Some(filters.limit),
+                offset: 0,
+                include_inactive,
+                progress: &progress,
+            };
+            let keyword_hits = db.search_products(&search).await?;
+            Some(fuse(semantic, keyword_hits, std::mem::take(&mut vector_hits), limit))
+        }
+    };
+
+    let exchange_rate = match &currency {
+        Some(currency) => Some(currency::exchange_rate(db, currency).await?),
+        None => None,
+    };
+    let priced = |hit: &SearchHit| product_json(&hit.product, exchange_rate.as_ref(), locale.as_ref());
+
+    let products: Vec<Value> = match &fused {
+        None => vector_hits
+            .iter()
+            .map(|hit| {
+                let mut value = priced(hit);
+                value["score"] = json!(hit.rank);
+                value
+            })
+            .collect(),
+        Some(fused) => fused
+            .iter()
+            .map(|fused| {
+                let mut value = priced(&fused.hit);
+                value["score"] = json!(fused.score);
+                value["scores"] = json!({
+                    "keyword": fused.keyword.map(|(rank, score)| json!({"rank": rank, "score": score})),
+                    "semantic": fused.semantic.map(|(rank, similarity)| json!({"rank": rank, "similarity": similarity}))
+                });
+                value
+            })
+            .collect(),
+    };
+
+    let mut result = json!({
+        "query": query,
+        "mode": mode.as_str(),
+        "products": products,
+        "count": products.len(),
+        "min_score": min_score,
+        "base_currency": config.base_currency
+    });
+    if mode == Mode::Hybrid {
+        result["search_mode"] = json!(search_mode.as_str());
+        result["weights"] = json!({
+            "keyword": semantic.keyword_weight,
+            "semantic": semantic.semantic_weight,
+            "rrf_k": semantic.rrf_k
+        });
+    }
+    Ok(utils::json_content(result))
+}
@@ -0,0 +1,135 @@
+//! Response shaping
+//!
+//! Results can be trimmed to the fields a client needs, saving tokens:
+//!
+//! - `response_fields.exclude` removes fields wherever they occur in a
+//!   result, e.g. `description` of every product
+//! - `response_fields.include` and the `fields` argument of every tool
+//!   keep only the named fields of each row, the objects in the largest
+//!   array of a result such as `products`, or of the result itself if it
+//!   has no rows; fields outside the rows, like `count`, stay. With both,
+//!   a field must be in each list.
+//!
+//! Shaping is a convenience, not access control: fields that must never
+//! leave the plugin belong in `access_policy.redacted_fields`. Results in
+//! text blocks, such as inline exports, are not shaped.
+
+use crate::access::remove_fields;
+use crate::error::PluginError;
+use crate::get_config;
+use crate::truncation::rows_field;
+use crate::PluginConfig;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+/// The `response_fields` config field
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+pub(crate) struct ResponseFields {
+    /// Fields every row is limited to, all of them if absent
+    #[serde(default)]
+    include: Option<Vec<String>>,
+
+    /// Fields removed from every result
+    #[serde(default)]
+    exclude: Vec<String>,
+}
+
+/// Validate the response fields, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if config.response_fields.include.as_ref().is_some_and(Vec::is_empty) {
+        return Err("response_fields.include must name at least one field".to_string());
+    }
+    Ok(())
+}
+
+/// Add the `fields` argument to a tool's input schema
+pub(crate) fn extend_input_schema(input_schema: &mut Value) {
+    input_schema["properties"]["fields"] = json!({
+        "type": "array",
+        "items": {"type": "string"},
+        "description": "Only return these fields of each result row (or of the result without rows), e.g. [\"id\", \"name\", \"price\"]"
+    });
+}
+
+/// Fields requested with the `fields` argument
+fn parse_fields(args: &Value) -> Result<Option<Vec<String>>, PluginError> {
+    match &args["fields"] {
+        Value::Null => Ok(None),
+        Value::Array(fields) if !fields.is_empty() => fields
+            .iter()
+            .map(|field| field.as_str().map(str::to_string))
+            .collect::<Option<Vec<String>>>()
+            .map(Some)
+            .ok_or_else(|| PluginError::invalid_argument("Invalid fields parameter, expected field names")),
+        _ => Err(PluginError::invalid_argument(
+            "Invalid fields parameter, expected a non-empty array of field names",
+        )),
+    }
+}
+
+/// Check the `fields` argument before a tool runs, so that a malformed
+/// one does not fail a call only after its work is done
+pub(crate) fn check(args: &Value) -> Result<(), PluginError> {
+    parse_fields(args).map(|_| ())
+}
+
+/// Keep the fields of `object` allowed by every list in `includes`
+fn include(object: &mut Map<String, Value>, includes: &[&Vec<String>]) {
+    object.retain(|name, _| includes.iter().all(|fields| fields.contains(name)));
+}
+
+fn shape_object(object: &mut Map<String, Value>, includes: &[&Vec<String>], excludes: &[String]) {
+    if !includes.is_empty() {
+        match rows_field(object) {
+            Some(field) => {
+                for row in object[&field].as_array_mut().into_iter().flatten() {
+                    if let Some(row) = row.as_object_mut() {
+                        include(row, includes);
+                    }
+                }
+            }
+            None => include(object, includes),
+        }
+    }
+    if !excludes.is_empty() {
+        object.retain(|name, _| !excludes.contains(name));
+        object.values_mut().for_each(|value| remove_fields(value, excludes));
+    }
+}
+
+/// Apply the configured and requested fields to a tool result
+pub(crate) fn shape_response(mut result: Value, args: &Value) -> Result<Value, PluginError> {
+    let fields = parse_fields(args)?;
+    let config = get_config();
+    let includes: Vec<&Vec<String>> = config.response_fields.include.iter().chain(&fields).collect();
+    let excludes = &config.response_fields.exclude;
+    if includes.is_empty() && excludes.is_empty() {
+        return Ok(result);
+    }
+
+    if let Some(content) = result["content"].as_array_mut() {
+        for block in content.iter_mut().filter(|block| block["type"] == "json") {
+            if let Some(object) = block["json"].as_object_mut() {
+                shape_object(object, &includes, excludes);
+            }
+        }
+    }
+    if let Some(Value::Object(object)) = result.get_mut("structuredContent") {
+        shape_object(object, &includes, excludes);
+    }
+    Ok(result)
+}
+
+/// Drop the `required` lists of an output schema, whose fields a client
+/// may have shaped away
+pub(crate) fn relax_schema(schema: &mut Value) {
+    match schema {
+        Value::Object(object) => {
+            object.remove("required");
+            object.values_mut().for_each(relax_schema);
+        }
+        Value::Array(items) => items.iter_mut().for_each(relax_schema),
+        _ => {}
+    }
+}
@@ -0,0 +1,146 @@
+//! Similar products
+//!
+//! Alternatives to a product are other products in its category whose
+//! price lies within a band around its price, closest price first. Products
+//! without a category are matched by name instead, through `pg_trgm`
+//! similarity, within the same price band.
+
+use crate::args::{self, DecimalArg};
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::mapping::{self, PRODUCT_COLUMNS};
+use crate::search::SearchHit;
+use crate::{currency, get_config, locale, product_json, PluginConfig};
+use mcp_plugin_api::utils;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Number of alternatives when the caller does not pass `limit`
+const DEFAULT_SIMILAR_LIMIT: i64 = 10;
+
+/// Upper bound of the `limit` argument
+const MAX_SIMILAR_LIMIT: i64 = 50;
+
+/// Validate the similarity settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if config.similar_price_band_percent < Decimal::ZERO {
+        return Err("similar_price_band_percent must not be negative".to_string());
+    }
+    Ok(())
+}
+
+/// Arguments of get_similar_products
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SimilarArgs {
+    product_id: i32,
+    price_band_percent: Option<DecimalArg>,
+    #[schemars(range(min = 1, max = "MAX_SIMILAR_LIMIT"))]
+    limit: Option<i64>,
+    currency: Option<String>,
+    locale: Option<String>,
+}
+
+pub(crate) async fn handle_get_similar_products(
+    db: &dyn DatabaseBackend,
+    args: &Value,
+) -> Result<Value, PluginError> {
+    let pool = db.postgres()?;
+
+    let SimilarArgs {
+        product_id,
+        price_band_percent,
+        limit,
+        currency,
+        locale,
+    } = args::parse(args)?;
+
+    let config = get_config();
+    let price_band_percent = price_band_percent.map(|percent| percent.to_decimal("price_band_percent"));
+    let band_percent = match price_band_percent.transpose()? {
+        Some(percent) if percent < Decimal::ZERO => {
+            return Err(PluginError::invalid_argument(
+                "Invalid price_band_percent parameter, must not be negative",
+            ))
+        }
+        Some(percent) => percent,
+        None => config.similar_price_band_percent,
+    };
+
+    let limit = limit.unwrap_or(DEFAULT_SIMILAR_LIMIT);
+    let currency = currency::parse_currency(currency.as_deref())?;
+    let locale = locale::parse_locale(locale.as_deref())?;
+
+    let product = db
+        .fetch_product(product_id)
+        .await?
+        .ok_or_else(|| PluginError::not_found(format!("Product {product_id} not found")))?;
+
+    let band = product.price * band_percent / Decimal::ONE_HUNDRED;
+    let (min_price, max_price) = (product.price - band, product.price + band);
+
+    let products = mapping::products();
+    let (matched_by, hits) = match &product.category {
+        Some(category) => {
+            let hits = sqlx::query_as::<_, SearchHit>(&format!(
+                "SELECT {PRODUCT_COLUMNS}, NULL::real AS rank \
+                 FROM {products} \
+                 WHERE category = $1 AND id <> $2 AND price BETWEEN $3 AND $4 \
+                 ORDER BY abs(price - $5), id \
+                 LIMIT $6"
+            ))
+            .bind(category)
+            .bind(product_id)
+            .bind(min_price)
+            .bind(max_price)
+            .bind(product.price)
+            .bind(limit)
+            .fetch_all(pool)
+            .await?;
+            ("category", hits)
+        }
+        None => {
+            let hits = sqlx::query_as::<_, SearchHit>(&format!(
+                "SELECT {PRODUCT_COLUMNS}, similarity(name, $1) AS rank \
+                 FROM {products} \
+                 WHERE name % $1 AND id <> $2 AND price BETWEEN $3 AND $4 \
+                 ORDER BY rank DESC, id \
+                 LIMIT $5"
+            ))
+            .bind(&product.name)
+            .bind(product_id)
+            .bind(min_price)
+            .bind(max_price)
+            .bind(limit)
+            .fetch_all(pool)
+            .await?;
+            ("name", hits)
+        }
+    };
+
+    let exchange_rate = match &currency {
+        Some(currency) => Some(currency::exchange_rate(db, currency).await?),
+        None => None,
+    };
+
+    let similar: Vec<Value> = hits
+        .iter()
+        .map(|hit| {
+            let mut value = product_json(&hit.product, exchange_rate.as_ref(), locale.as_ref());
+            if let Some(rank) = hit.rank {
+                value["similarity"] = json!(rank);
+            }
+            value
+        })
+        .collect();
+
+    Ok(utils::json_content(json!({
+        "product": product_json(&product, exchange_rate.as_ref(), locale.as_ref()),
+        "matched_by": matched_by,
+        "price_band_percent": band_percent.normalize().to_string(),
+        "similar_products": similar,
+        "count": similar.len(),
+        "base_currency": config.base_currency
+    })))
+}
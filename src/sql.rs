@@ -0,0 +1,56 @@
+//! SQL building helpers
+//!
+//! Table and column names from the configuration end up inside query text,
+//! where they cannot be passed as bind parameters. They are validated and
+//! quoted here before use.
+
+/// Validate and quote a possibly schema-qualified identifier
+///
+/// Accepts `name` or `schema.name` where each part starts with a letter or
+/// underscore followed by letters, digits or underscores, and returns the
+/// double-quoted form, e.g. `"pricing"."currency_rates"`.
+pub(crate) fn quote_identifier(name: &str) -> Result<String, String> {
+    let parts: Vec<&str> = name.split('.').collect();
+    if parts.len() > 2 {
+        return Err(format!("Invalid SQL identifier '{name}': too many '.' separators"));
+    }
+
+    let mut quoted = Vec::with_capacity(parts.len());
+    for part in parts {
+        let mut chars = part.chars();
+        let valid_start = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+        let valid_rest = chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_start || !valid_rest || part.len() > 63 {
+            return Err(format!(
+                "Invalid SQL identifier '{name}': only letters, digits and '_' are allowed"
+            ));
+        }
+        quoted.push(format!("\"{part}\""));
+    }
+
+    Ok(quoted.join("."))
+}
+
+/// Escape LIKE metacharacters so the text matches literally
+///
+/// `%`, `_` and the escape character `\` itself are prefixed with `\`. The
+/// result must be used with `LIKE ... ESCAPE '\'`.
+pub(crate) fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Whether a query failed because the table does not exist (SQLSTATE 42P01)
+///
+/// Lets optional tables such as the inventory degrade gracefully.
+pub(crate) fn is_undefined_table(err: &sqlx::Error) -> bool {
+    matches!(err, sqlx::Error::Database(err) if err.code().as_deref() == Some("42P01"))
+}
@@ -0,0 +1,465 @@
+//! Read-only SQL queries
+//!
+//! `query_products_sql` runs a SELECT statement written by the caller. The
+//! statement is parsed with `sqlparser` and rejected unless it is a single
+//! query that reads only from `sql_allowed_tables` (and the CTEs in scope),
+//! uses no table functions and calls only the aggregate, window, string,
+//! math, date, JSON and text search functions of an allowlist. Functions
+//! reaching outside the catalogue, such as `pg_read_file`, or running a
+//! query passed as a string, such as `query_to_xml` or `ts_stat`, would
+//! bypass the table whitelist and are never allowed. A LIMIT above
+//! `max_results` is lowered, a missing one added. The statement then runs in
+//! a read-only transaction with a statement timeout and is rolled back.
+//!
+//! Rows are returned as JSON objects keyed by column name, together with
+//! the column names and their Postgres types.
+
+use crate::access;
+use crate::args;
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::sql::quote_identifier;
+use crate::{get_config, PluginConfig};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use mcp_plugin_api::utils;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sqlparser::ast::{
+    Expr, Ident, ObjectName, Query, SetExpr, Statement, TableFactor, Visit, Visitor,
+};
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+use sqlx::postgres::PgRow;
+use sqlx::{Column, Executor, Row, Statement as _, TypeInfo};
+use std::collections::HashSet;
+use std::ops::ControlFlow;
+
+/// Validate the SQL tool settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    for table in &config.sql_allowed_tables {
+        quote_identifier(table)?;
+    }
+    Ok(())
+}
+
+/// Functions a caller's query may call, unqualified or in `pg_catalog`
+///
+/// Anything else, server administration, large objects, remote connections,
+/// functions running a query passed as a string and the database's own
+/// functions alike, is refused.
+const ALLOWED_FUNCTIONS: &[&str] = &[
+    // Aggregates
+    "count", "sum", "avg", "min", "max", "bool_and", "bool_or", "every", "string_agg", "array_agg", "json_agg",
+    "jsonb_agg", "json_object_agg", "jsonb_object_agg", "stddev", "stddev_pop", "stddev_samp", "variance",
+    "var_pop", "var_samp", "percentile_cont", "percentile_disc", "mode", "corr", "covar_pop", "covar_samp",
+    // Window functions
+    "row_number", "rank", "dense_rank", "percent_rank", "cume_dist", "ntile", "lag", "lead", "first_value",
+    "last_value", "nth_value",
+    // Conditionals
+    "coalesce", "nullif", "greatest", "least",
+    // Math
+    "abs", "ceil", "ceiling", "floor", "round", "trunc", "sign", "sqrt", "cbrt", "power", "exp", "ln", "log",
+    "log10", "mod", "div", "width_bucket",
+    // Strings
+    "lower", "upper", "initcap", "length", "char_length", "character_length", "octet_length", "concat",
+    "concat_ws", "substr", "substring", "left", "right", "lpad", "rpad", "ltrim", "rtrim", "btrim", "trim",
+    "replace", "translate", "split_part", "strpos", "position", "reverse", "repeat", "starts_with", "format",
+    "regexp_replace", "regexp_match", "regexp_matches", "regexp_split_to_array", "to_char", "to_number", "md5",
+    // Dates
+    "now", "current_date", "current_timestamp", "localtimestamp", "date_trunc", "date_part", "date_bin",
+    "extract", "age", "make_date", "make_interval", "to_date", "to_timestamp",
+    // JSON and arrays
+    "to_json", "to_jsonb", "json_build_object", "jsonb_build_object", "json_build_array", "jsonb_build_array",
+    "json_typeof", "jsonb_typeof", "jsonb_array_length", "jsonb_object_keys", "jsonb_extract_path_text",
+    "array_length", "array_position", "array_to_string", "cardinality", "unnest",
+    // Text search and trigrams
+    "to_tsvector", "to_tsquery", "plainto_tsquery", "phraseto_tsquery", "websearch_to_tsquery", "ts_rank",
+    "ts_rank_cd", "ts_headline", "similarity", "word_similarity",
+];
+
+/// Whether a function may be called from a caller's query
+fn is_allowed_function(name: &ObjectName) -> bool {
+    match name.0.as_slice() {
+        [function] => ALLOWED_FUNCTIONS.contains(&normalize_ident(function).as_str()),
+        [schema, function] => {
+            normalize_ident(schema) == "pg_catalog" && ALLOWED_FUNCTIONS.contains(&normalize_ident(function).as_str())
+        }
+        _ => false,
+    }
+}
+
+/// Lowercase an unquoted identifier, as Postgres does
+fn normalize_ident(ident: &Ident) -> String {
+    match ident.quote_style {
+        Some(_) => ident.value.clone(),
+        None => ident.value.to_lowercase(),
+    }
+}
+
+fn normalize_name(name: &ObjectName) -> String {
+    name.0.iter().map(normalize_ident).collect::<Vec<_>>().join(".")
+}
+
+/// CTEs of one query
+struct Scope {
+    /// Names of the CTEs, each with its query
+    ctes: Vec<(String, *const Query)>,
+
+    /// Number of leading CTEs in scope: while a CTE's own query is visited
+    /// only the ones before it, or all of them in a RECURSIVE WITH
+    visible: usize,
+
+    recursive: bool,
+}
+
+impl Scope {
+    /// Index of the CTE whose query `query` is
+    fn position(&self, query: &Query) -> Option<usize> {
+        self.ctes.iter().position(|(_, cte)| std::ptr::eq(*cte, query))
+    }
+}
+
+/// Collects the relations a query reads and rejects forbidden constructs
+///
+/// A relation naming a CTE is not read from a table if a query enclosing
+/// it defines that CTE, so the queries being visited are kept on a stack
+/// with their CTEs.
+#[derive(Default)]
+struct Guard {
+    relations: HashSet<String>,
+    scopes: Vec<Scope>,
+}
+
+impl Guard {
+    fn is_cte(&self, relation: &ObjectName) -> bool {
+        let [name] = relation.0.as_slice() else {
+            return false;
+        };
+        let name = normalize_ident(name);
+        self.scopes
+            .iter()
+            .any(|scope| scope.ctes[..scope.visible].iter().any(|(cte, _)| *cte == name))
+    }
+}
+
+impl Visitor for Guard {
+    type Break = PluginError;
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<PluginError> {
+        if !query.locks.is_empty() {
+            return ControlFlow::Break(PluginError::invalid_argument("Locking clauses are not allowed"));
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            if let Some(index) = scope.position(query) {
+                scope.visible = if scope.recursive { scope.ctes.len() } else { index };
+            }
+        }
+        let (ctes, recursive) = match &query.with {
+            Some(with) => (
+                with.cte_tables
+                    .iter()
+                    .map(|cte| (normalize_ident(&cte.alias.name), &*cte.query as *const Query))
+                    .collect::<Vec<_>>(),
+                with.recursive,
+            ),
+            None => (Vec::new(), false),
+        };
+        self.scopes.push(Scope { visible: ctes.len(), ctes, recursive });
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_query(&mut self, query: &Query) -> ControlFlow<PluginError> {
+        self.scopes.pop();
+        // Past a CTE's query all CTEs of the WITH are in scope
+        if let Some(scope) = self.scopes.last_mut() {
+            if scope.position(query).is_some() {
+                scope.visible = scope.ctes.len();
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_relation(&mut self, relation: &ObjectName) -> ControlFlow<PluginError> {
+        if !self.is_cte(relation) {
+            self.relations.insert(normalize_name(relation));
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_table_factor(&mut self, table_factor: &TableFactor) -> ControlFlow<PluginError> {
+        match table_factor {
+            TableFactor::Table { args: None, .. }
+            | TableFactor::Derived { .. }
+            | TableFactor::NestedJoin { .. } => ControlFlow::Continue(()),
+            _ => ControlFlow::Break(PluginError::invalid_argument(
+                "Only tables and subqueries are allowed in FROM",
+            )),
+        }
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<PluginError> {
+        if let Expr::Function(function) = expr {
+            if !is_allowed_function(&function.name) {
+                return ControlFlow::Break(PluginError::invalid_argument(format!(
+                    "Function {} is not allowed",
+                    normalize_name(&function.name)
+                )));
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Parse and check the statement, returning it with the LIMIT enforced
+///
+/// The limit is set one above `max_rows` so truncation can be reported.
+fn prepare_statement(sql: &str, allowed_tables: &[String], max_rows: i64) -> Result<String, PluginError> {
+    let mut statements = Parser::parse_sql(&PostgreSqlDialect {}, sql)
+        .map_err(|e| PluginError::invalid_argument(format!("Invalid SQL: {e}")))?;
+    if statements.len() != 1 {
+        return Err(PluginError::invalid_argument("Expected exactly one SQL statement"));
+    }
+    let Statement::Query(mut query) = statements.remove(0) else {
+        return Err(PluginError::invalid_argument("Only SELECT statements are allowed"));
+    };
+    if let SetExpr::Select(select) = query.body.as_ref() {
+        if select.into.is_some() {
+            return Err(PluginError::invalid_argument("SELECT INTO is not allowed"));
+        }
+    }
+
+    let mut guard = Guard::default();
+    if let ControlFlow::Break(error) = query.visit(&mut guard) {
+        return Err(error);
+    }
+    let allowed: HashSet<String> = allowed_tables.iter().map(|table| table.to_lowercase()).collect();
+    let mut relations: Vec<&String> = guard
+        .relations
+        .iter()
+        .filter(|relation| !allowed.contains(*relation))
+        .collect();
+    if !relations.is_empty() {
+        relations.sort();
+        let relations: Vec<&str> = relations.into_iter().map(String::as_str).collect();
+        return Err(PluginError::PermissionDenied(format!(
+            "Table(s) not allowed: {}",
+            relations.join(", ")
+        )));
+    }
+
+    if query.fetch.is_some() {
+        return Err(PluginError::invalid_argument("Use LIMIT instead of FETCH"));
+    }
+    let limit = match &query.limit {
+        None => None,
+        Some(Expr::Value(sqlparser::ast::Value::Number(limit, _))) => Some(
+            limit
+                .parse::<i64>()
+                .map_err(|_| PluginError::invalid_argument(format!("Invalid LIMIT {limit}")))?,
+        ),
+        Some(_) => return Err(PluginError::invalid_argument("LIMIT must be a number")),
+    };
+    let limit = match limit {
+        Some(limit) if limit <= max_rows => limit,
+        _ => max_rows + 1,
+    };
+    query.limit = Some(Expr::Value(sqlparser::ast::Value::Number(limit.to_string(), false)));
+    Ok(query.to_string())
+}
+
+/// Decode one column of a row into JSON
+///
+/// NUMERIC values are returned as strings to keep them exact.
+fn column_value(row: &PgRow, index: usize, type_name: &str, column: &str) -> Result<Value, PluginError> {
+    let value = match type_name {
+        "BOOL" => json!(row.try_get::<Option<bool>, _>(index)?),
+        "INT2" => json!(row.try_get::<Option<i16>, _>(index)?),
+        "INT4" => json!(row.try_get::<Option<i32>, _>(index)?),
+        "INT8" => json!(row.try_get::<Option<i64>, _>(index)?),
+        "FLOAT4" => json!(row.try_get::<Option<f32>, _>(index)?),
+        "FLOAT8" => json!(row.try_get::<Option<f64>, _>(index)?),
+        "NUMERIC" => json!(row.try_get::<Option<Decimal>, _>(index)?.map(|value| value.to_string())),
+        "TEXT" | "VARCHAR" | "BPCHAR" | "NAME" => json!(row.try_get::<Option<String>, _>(index)?),
+        "JSON" | "JSONB" => row.try_get::<Option<Value>, _>(index)?.unwrap_or(Value::Null),
+        "TIMESTAMPTZ" => json!(row.try_get::<Option<DateTime<Utc>>, _>(index)?),
+        "TIMESTAMP" => json!(row.try_get::<Option<NaiveDateTime>, _>(index)?),
+        "DATE" => json!(row.try_get::<Option<NaiveDate>, _>(index)?),
+        _ => {
+            return Err(PluginError::invalid_argument(format!(
+                "Column {column} has the unsupported type {type_name}, cast it to text"
+            )))
+        }
+    };
+    Ok(value)
+}
+
+/// Arguments of query_products_sql
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SqlArgs {
+    sql: String,
+}
+
+pub(crate) async fn handle_query_products_sql(
+    db: &dyn DatabaseBackend,
+    args: &Value,
+) -> Result<Value, PluginError> {
+    let config = get_config();
+    if !config.enable_sql_tool {
+        return Err(PluginError::PermissionDenied(
+            "The SQL tool is disabled, set enable_sql_tool to allow it".to_string(),
+        ));
+    }
+    access::ensure_unrestricted("query_products_sql")?;
+    let pool = db.postgres()?;
+
+    let SqlArgs { sql } = args::parse(args)?;
+    if sql.trim().is_empty() {
+        return Err(PluginError::invalid_argument("Missing or invalid sql parameter"));
+    }
+    let max_rows = config.max_results;
+    let statement = prepare_statement(&sql, &config.sql_allowed_tables, max_rows)?;
+    tracing::debug!(%statement, "Running SQL query");
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("SET TRANSACTION READ ONLY").execute(&mut *tx).await?;
+    sqlx::query(&format!(
+        "SET LOCAL statement_timeout = {}",
+        config.request_timeout_seconds.saturating_mul(1000)
+    ))
+    .execute(&mut *tx)
+    .await?;
+
+    let prepared = (&mut *tx).prepare(statement.as_str()).await?;
+    let columns: Vec<(String, String)> = prepared
+        .columns()
+        .iter()
+        .map(|column| (column.name().to_string(), column.type_info().name().to_string()))
+        .collect();
+    let mut names = HashSet::new();
+    if let Some((name, _)) = columns.iter().find(|(name, _)| !names.insert(name)) {
+        return Err(PluginError::invalid_argument(format!(
+            "Duplicate column name {name}, give the columns distinct aliases"
+        )));
+    }
+
+    let mut rows = prepared.query().fetch_all(&mut *tx).await?;
+    tx.rollback().await?;
+
+    let truncated = rows.len() as i64 > max_rows;
+    rows.truncate(max_rows as usize);
+    let rows = rows
+        .iter()
+        .map(|row| {
+            columns
+                .iter()
+                .enumerate()
+                .map(|(index, (name, type_name))| {
+                    Ok((name.clone(), column_value(row, index, type_name, name)?))
+                })
+                .collect::<Result<Map<String, Value>, PluginError>>()
+                .map(Value::Object)
+        })
+        .collect::<Result<Vec<Value>, PluginError>>()?;
+
+    let columns: Vec<Value> = columns
+        .iter()
+        .map(|(name, type_name)| json!({"name": name, "type": type_name.to_lowercase()}))
+        .collect();
+    Ok(utils::json_content(json!({
+        "columns": columns,
+        "rows": rows,
+        "count": rows.len(),
+        "truncated": truncated
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prepare(sql: &str) -> Result<String, PluginError> {
+        prepare_statement(sql, &["products".to_string(), "price_history".to_string()], 100)
+    }
+
+    #[test]
+    fn accepts_reads_of_allowed_tables() {
+        for sql in [
+            "SELECT id, name FROM products WHERE price > 10",
+            "SELECT category, count(*), avg(price) FROM products GROUP BY category",
+            "SELECT p.name, h.price FROM products p JOIN price_history h ON h.product_id = p.id",
+            "SELECT * FROM (SELECT id FROM products) AS recent",
+            "SELECT id, row_number() OVER (ORDER BY price DESC) FROM products",
+            "SELECT lower(name), round(price, 2), pg_catalog.upper(name), now() FROM products",
+            "SELECT id FROM products WHERE to_tsvector(name) @@ plainto_tsquery('widget')",
+            "WITH cheap AS (SELECT id FROM products WHERE price < 5) SELECT * FROM cheap",
+            "WITH a AS (SELECT id FROM products), b AS (SELECT id FROM a) SELECT * FROM b",
+            "WITH RECURSIVE n AS (SELECT 1 AS i UNION ALL SELECT i + 1 FROM n WHERE i < 5) SELECT * FROM n",
+            "SELECT * FROM products WHERE EXISTS (WITH c AS (SELECT 1) SELECT * FROM c)",
+            "WITH c AS (SELECT id FROM products) SELECT * FROM products WHERE id IN (SELECT id FROM c)",
+        ] {
+            assert!(prepare(sql).is_ok(), "{sql}: {:?}", prepare(sql).unwrap_err().message());
+        }
+    }
+
+    #[test]
+    fn rejects_other_tables() {
+        for sql in [
+            "SELECT * FROM secrets",
+            "SELECT * FROM public.products",
+            "SELECT * FROM products WHERE id IN (SELECT product_id FROM secrets)",
+            // CTEs only cover the query defining them and the ones inside it
+            "SELECT * FROM secrets WHERE EXISTS (WITH secrets AS (SELECT 1) SELECT 1)",
+            "SELECT * FROM (WITH secrets AS (SELECT 1) SELECT * FROM secrets) AS s, secrets",
+            // A CTE is not in scope of its own query without RECURSIVE
+            "WITH secrets AS (SELECT * FROM secrets) SELECT * FROM secrets",
+            "WITH a AS (SELECT * FROM b), b AS (SELECT 1) SELECT * FROM a",
+            "WITH \"Secrets\" AS (SELECT 1) SELECT * FROM secrets",
+        ] {
+            let err = prepare(sql).unwrap_err();
+            assert!(matches!(err, PluginError::PermissionDenied(_)), "{sql}: {}", err.message());
+        }
+    }
+
+    #[test]
+    fn rejects_other_functions() {
+        for sql in [
+            "SELECT pg_read_file('/etc/passwd')",
+            "SELECT query_to_xml('SELECT * FROM secrets', true, true, '')",
+            "SELECT * FROM products WHERE EXISTS (SELECT ts_stat('SELECT to_tsvector(secret) FROM secrets'))",
+            "SELECT ts_rewrite('a'::tsquery, 'SELECT * FROM secrets')",
+            "SELECT set_config('statement_timeout', '0', false)",
+            "SELECT dblink('host=elsewhere', 'SELECT 1')",
+            "SELECT public.lower(name) FROM products",
+            "SELECT my_function(id) FROM products",
+        ] {
+            let err = prepare(sql).unwrap_err();
+            assert!(err.message().contains("is not allowed"), "{sql}: {}", err.message());
+        }
+    }
+
+    #[test]
+    fn rejects_other_statements_and_clauses() {
+        for sql in [
+            "DELETE FROM products",
+            "UPDATE products SET price = 0",
+            "SELECT 1; SELECT 2",
+            "SELECT * INTO copy FROM products",
+            "SELECT * FROM products FOR UPDATE",
+            "SELECT * FROM generate_series(1, 10)",
+            "SELECT * FROM products FETCH FIRST 5 ROWS ONLY",
+            "SELECT * FROM",
+        ] {
+            assert!(prepare(sql).is_err(), "{sql}");
+        }
+    }
+
+    #[test]
+    fn enforces_the_limit() {
+        assert_eq!(prepare("SELECT id FROM products").unwrap(), "SELECT id FROM products LIMIT 101");
+        assert_eq!(prepare("SELECT id FROM products LIMIT 5").unwrap(), "SELECT id FROM products LIMIT 5");
+        assert_eq!(prepare("SELECT id FROM products LIMIT 500").unwrap(), "SELECT id FROM products LIMIT 101");
+        assert_eq!(prepare("SELECT id FROM products LIMIT ALL").unwrap(), "SELECT id FROM products LIMIT 101");
+    }
+}
@@ -0,0 +1,180 @@
+//! Price statistics
+//!
+//! Aggregates prices in SQL instead of paging through search results:
+//! count, minimum, maximum, average and median (`percentile_cont`), plus a
+//! histogram of equal-width price buckets between minimum and maximum.
+//! Products can be narrowed down by category and by a name substring.
+//! With `summary_views` the statistics come from materialized views where
+//! possible, see the summaries module; the result's `freshness` tells
+//! where they came from.
+
+use crate::args;
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::mapping;
+use crate::search::like_pattern;
+use crate::summaries::{self, SummarySource};
+use crate::{format_price, get_config};
+use mcp_plugin_api::utils;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::{Postgres, QueryBuilder};
+
+/// Histogram buckets when the caller does not pass `buckets`
+const DEFAULT_BUCKETS: i64 = 10;
+
+/// Upper bound of the `buckets` argument
+const MAX_BUCKETS: i64 = 50;
+
+/// Arguments of get_price_statistics
+#[derive(Debug, Deserialize, JsonSchema)]
+struct StatisticsArgs {
+    category: Option<String>,
+    query: Option<String>,
+    #[schemars(range(min = 1, max = "MAX_BUCKETS"))]
+    buckets: Option<i64>,
+}
+
+#[derive(Default, sqlx::FromRow)]
+struct Summary {
+    product_count: i64,
+    min_price: Option<Decimal>,
+    max_price: Option<Decimal>,
+    avg_price: Option<Decimal>,
+    median_price: Option<Decimal>,
+}
+
+#[derive(sqlx::FromRow)]
+struct BucketCount {
+    bucket: i32,
+    product_count: i64,
+}
+
+/// Products the statistics are computed over
+struct Filter<'a> {
+    category: Option<&'a str>,
+    query: Option<&'a str>,
+}
+
+impl Filter<'_> {
+    /// Append `FROM <relation> WHERE ...` for the filter
+    fn push_from(&self, sql: &mut QueryBuilder<'_, Postgres>, relation: &str) {
+        sql.push(format!(" FROM {relation} WHERE TRUE"));
+        if let Some(category) = self.category {
+            sql.push(" AND category = ").push_bind(category.to_string());
+        }
+        if let Some(query) = self.query {
+            sql.push(" AND name ILIKE ")
+                .push_bind(like_pattern(query, false))
+                .push(" ESCAPE '\\'");
+        }
+    }
+}
+
+pub(crate) async fn handle_get_price_statistics(
+    db: &dyn DatabaseBackend,
+    args: &Value,
+) -> Result<Value, PluginError> {
+    let pool = db.postgres()?;
+
+    let StatisticsArgs {
+        category,
+        query,
+        buckets,
+    } = args::parse(args)?;
+    let filter = Filter {
+        category: category.as_deref(),
+        query: query.as_deref(),
+    };
+    let buckets = buckets.unwrap_or(DEFAULT_BUCKETS);
+
+    let source = summaries::source(pool, args, filter.query.is_some()).await;
+    let products = mapping::products();
+    let summary = match &source {
+        None => {
+            let mut sql = QueryBuilder::<Postgres>::new(
+                "SELECT count(*) AS product_count, min(price) AS min_price, max(price) AS max_price, \
+                 avg(price) AS avg_price, \
+                 (percentile_cont(0.5) WITHIN GROUP (ORDER BY price))::numeric AS median_price",
+            );
+            filter.push_from(&mut sql, &products);
+            sql.build_query_as::<Summary>().fetch_one(pool).await?
+        }
+        // The view has one row per category and a total row
+        Some(SummarySource { category_view, .. }) => {
+            let mut sql = QueryBuilder::<Postgres>::new(
+                "SELECT product_count, min_price, max_price, avg_price, median_price",
+            );
+            filter.push_from(&mut sql, category_view);
+            sql.push(if filter.category.is_some() { " AND NOT is_total" } else { " AND is_total" });
+            sql.build_query_as::<Summary>().fetch_optional(pool).await?.unwrap_or_default()
+        }
+    };
+
+    // width_bucket() puts the maximum itself into bucket n + 1, fold it into
+    // the last bucket. With a single distinct price there is one bucket.
+    let histogram = match (summary.min_price, summary.max_price) {
+        (Some(min), Some(max)) if min < max => {
+            let mut sql = QueryBuilder::<Postgres>::new("SELECT least(width_bucket(price, ");
+            sql.push_bind(min)
+                .push(", ")
+                .push_bind(max)
+                .push(", ")
+                .push_bind(buckets as i32)
+                .push("), ")
+                .push_bind(buckets as i32)
+                .push(") AS bucket, ");
+            match &source {
+                None => {
+                    sql.push("count(*) AS product_count");
+                    filter.push_from(&mut sql, &products);
+                }
+                Some(SummarySource { distribution_view, .. }) => {
+                    sql.push("sum(product_count)::bigint AS product_count");
+                    filter.push_from(&mut sql, distribution_view);
+                }
+            }
+            sql.push(" GROUP BY 1");
+            let counts = sql.build_query_as::<BucketCount>().fetch_all(pool).await?;
+
+            let width = (max - min) / Decimal::from(buckets);
+            (1..=buckets)
+                .map(|bucket| {
+                    let count = counts
+                        .iter()
+                        .find(|count| i64::from(count.bucket) == bucket)
+                        .map_or(0, |count| count.product_count);
+                    let from = min + width * Decimal::from(bucket - 1);
+                    let to = if bucket == buckets { max } else { min + width * Decimal::from(bucket) };
+                    json!({
+                        "from": format_price(&from),
+                        "to": format_price(&to),
+                        "count": count
+                    })
+                })
+                .collect()
+        }
+        (Some(min), Some(max)) => vec![json!({
+            "from": format_price(&min),
+            "to": format_price(&max),
+            "count": summary.product_count
+        })],
+        _ => Vec::new(),
+    };
+
+    let price = |price: Option<Decimal>| price.map(|price| format_price(&price));
+    Ok(utils::json_content(json!({
+        "category": filter.category,
+        "query": filter.query,
+        "product_count": summary.product_count,
+        "min_price": price(summary.min_price),
+        "max_price": price(summary.max_price),
+        "avg_price": price(summary.avg_price),
+        "median_price": price(summary.median_price),
+        "buckets": histogram,
+        "base_currency": get_config().base_currency,
+        "freshness": source.map_or_else(|| json!({"source": "live"}), |source| source.freshness())
+    })))
+}
@@ -0,0 +1,97 @@
+//! Product name suggestions
+//!
+//! `suggest_products` completes what a user is typing: it returns the ID
+//! and name of the active products whose name starts with the query,
+//! alphabetically, and nothing else, so an interactive client can call it
+//! on every keystroke. The match is `lower(name) LIKE 'query%'`, which a
+//! `text_pattern_ops` index on `lower(name)` answers with a short index
+//! range scan:
+//!
+//! ```sql
+//! CREATE INDEX products_name_prefix_idx ON products (lower(name) text_pattern_ops);
+//! ```
+//!
+//! Large or mapped catalogues can point `suggestions_view` at a relation
+//! with just the columns `id` and `name`, e.g. a materialized view of the
+//! names worth suggesting with the index above, refreshed along with the
+//! catalogue. The view is read as it is, without the status filter.
+
+use crate::args;
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::mapping;
+use crate::sql::{escape_like, quote_identifier};
+use crate::{get_config, PluginConfig};
+use mcp_plugin_api::utils;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Number of suggestions when the caller does not pass `limit`
+const DEFAULT_SUGGEST_LIMIT: i64 = 8;
+
+/// Upper bound of the `limit` argument
+const MAX_SUGGEST_LIMIT: i64 = 20;
+
+/// Upper bound of the typed prefix
+const MAX_PREFIX_LENGTH: u32 = 100;
+
+/// Validate the suggestion settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if let Some(view) = &config.suggestions_view {
+        quote_identifier(view)?;
+        if config.access_policy.restricts_rows() {
+            return Err("suggestions_view cannot be used while access_policy restricts the visible products".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Arguments of suggest_products
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SuggestArgs {
+    #[schemars(length(min = 1, max = "MAX_PREFIX_LENGTH"))]
+    query: String,
+    #[schemars(range(min = 1, max = "MAX_SUGGEST_LIMIT"))]
+    limit: Option<i64>,
+}
+
+#[derive(sqlx::FromRow)]
+struct Suggestion {
+    id: i32,
+    name: String,
+}
+
+pub(crate) async fn handle_suggest_products(db: &dyn DatabaseBackend, args: &Value) -> Result<Value, PluginError> {
+    let pool = db.postgres()?;
+    let SuggestArgs { query, limit } = args::parse(args)?;
+    let limit = limit.unwrap_or(DEFAULT_SUGGEST_LIMIT);
+    let config = get_config();
+
+    let (relation, status_filter) = match &config.suggestions_view {
+        Some(view) => (quote_identifier(view).map_err(PluginError::internal)?, ""),
+        None => (mapping::products(), " AND (status IS NULL OR status = ANY($3))"),
+    };
+    // Ordered like the index, so the scan stops after `limit` entries
+    let statement = format!(
+        "SELECT id, name FROM {relation} WHERE lower(name) LIKE $1 ESCAPE '\\'{status_filter} \
+         ORDER BY lower(name), id LIMIT $2"
+    );
+    let mut sql = sqlx::query_as::<_, Suggestion>(&statement)
+        .bind(format!("{}%", escape_like(&query.trim_start().to_lowercase())))
+        .bind(limit);
+    if config.suggestions_view.is_none() {
+        sql = sql.bind(&config.schema_mapping.active_statuses);
+    }
+    let suggestions = sql.fetch_all(pool).await?;
+
+    let suggestions: Vec<Value> = suggestions
+        .into_iter()
+        .map(|suggestion| json!({"id": suggestion.id, "name": suggestion.name}))
+        .collect();
+    Ok(utils::json_content(json!({
+        "query": query,
+        "suggestions": suggestions,
+        "count": suggestions.len()
+    })))
+}
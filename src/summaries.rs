@@ -0,0 +1,356 @@
+//! Materialized price summaries
+//!
+//! Aggregating prices over millions of rows takes a while, so
+//! `get_price_statistics` can read from two materialized views the plugin
+//! manages instead, when `summary_views.enabled` is set:
+//!
+//! - `category_view`: count, minimum, maximum, average and median price
+//!   per category, plus a total row over all products
+//! - `distribution_view`: the number of products per category and price,
+//!   which the histogram buckets are summed from
+//!
+//! Both are defined over the products relation, the `schema_mapping` and
+//! `access_policy` included, and carry the time of their last refresh. A
+//! background task creates them when missing or when their definition
+//! changed, e.g. after a new mapping, and refreshes them concurrently,
+//! without blocking readers, once they are `refresh_seconds` old. Several
+//! plugin instances share the views: refreshes of a view take an advisory
+//! lock on its name, so an instance that waited for another's refresh
+//! finds the view fresh and does not refresh it again.
+//!
+//! Statistics from a view report when it was refreshed. Calls with a
+//! `query`, calls for a tenant, and calls finding a view missing or older
+//! than `max_staleness_seconds` compute the statistics live.
+
+use crate::backend::{BackendKind, Database, DatabaseBackend};
+use crate::error::PluginError;
+use crate::mapping;
+use crate::sql::quote_identifier;
+use crate::{get_config, PluginConfig};
+use arc_swap::ArcSwap;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, PgPool};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Shortest refresh interval
+const MIN_REFRESH_SECONDS: u64 = 60;
+
+/// Delay before retrying after a failed creation or refresh
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Prefix of the comment marking a view as managed, followed by the hash
+/// of its definition
+const COMMENT_PREFIX: &str = "plug_pricing summary";
+
+/// Wakes the refresh task once a configuration is applied
+static RECONFIGURED: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// The `summary_views` config field
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub(crate) struct SummaryViews {
+    /// Create, refresh and read the summary views
+    #[serde(default)]
+    enabled: bool,
+
+    /// Materialized view with the price summary per category
+    #[serde(default = "default_category_view")]
+    category_view: String,
+
+    /// Materialized view with the product count per category and price
+    #[serde(default = "default_distribution_view")]
+    distribution_view: String,
+
+    /// Age in seconds from which a view is refreshed
+    #[serde(default = "default_refresh_seconds")]
+    refresh_seconds: u64,
+
+    /// Age in seconds from which statistics are computed live instead of
+    /// read from a view, never if absent
+    #[serde(default)]
+    max_staleness_seconds: Option<u64>,
+}
+
+impl Default for SummaryViews {
+    fn default() -> Self {
+        SummaryViews {
+            enabled: false,
+            category_view: default_category_view(),
+            distribution_view: default_distribution_view(),
+            refresh_seconds: default_refresh_seconds(),
+            max_staleness_seconds: None,
+        }
+    }
+}
+
+fn default_category_view() -> String {
+    "price_category_summary".to_string()
+}
+
+fn default_distribution_view() -> String {
+    "price_distribution".to_string()
+}
+
+fn default_refresh_seconds() -> u64 {
+    3600
+}
+
+/// Validate the summary view settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    let views = &config.summary_views;
+    quote_identifier(&views.category_view)?;
+    quote_identifier(&views.distribution_view)?;
+    if !views.enabled {
+        return Ok(());
+    }
+    if views.category_view == views.distribution_view {
+        return Err("summary_views must name two different views".to_string());
+    }
+    if BackendKind::parse(&config.backend)? != BackendKind::Postgres {
+        return Err("summary_views requires the postgres backend".to_string());
+    }
+    if config.read_only {
+        return Err("summary_views creates and refreshes views and requires read_only to be set to false".to_string());
+    }
+    if views.refresh_seconds < MIN_REFRESH_SECONDS {
+        return Err(format!("summary_views.refresh_seconds must be at least {MIN_REFRESH_SECONDS}"));
+    }
+    Ok(())
+}
+
+/// A managed view: its name, the query it materializes and the columns
+/// of its unique index, which concurrent refreshes need
+struct View {
+    name: String,
+    query: String,
+    key: &'static str,
+}
+
+impl View {
+    /// Comment identifying the current definition
+    fn comment(&self) -> String {
+        let digest = Sha256::digest(format!("{}\n{}", self.query, self.key).as_bytes());
+        format!("{COMMENT_PREFIX} {}", hex::encode(digest))
+    }
+}
+
+fn views(config: &PluginConfig) -> Result<[View; 2], PluginError> {
+    let views = &config.summary_views;
+    let products = mapping::products();
+    Ok([
+        View {
+            name: quote_identifier(&views.category_view).map_err(PluginError::internal)?,
+            query: format!(
+                "SELECT grouping(category) = 1 AS is_total, category, count(*) AS product_count, \
+                        min(price) AS min_price, max(price) AS max_price, avg(price) AS avg_price, \
+                        (percentile_cont(0.5) WITHIN GROUP (ORDER BY price))::numeric AS median_price, \
+                        now() AS refreshed_at \
+                 FROM {products} GROUP BY GROUPING SETS ((category), ())"
+            ),
+            key: "is_total, category",
+        },
+        View {
+            name: quote_identifier(&views.distribution_view).map_err(PluginError::internal)?,
+            query: format!(
+                "SELECT category, price, count(*) AS product_count, now() AS refreshed_at \
+                 FROM {products} GROUP BY category, price"
+            ),
+            key: "category, price",
+        },
+    ])
+}
+
+/// Views a statistics call reads from
+pub(crate) struct SummarySource {
+    /// Quoted name of the category view
+    pub(crate) category_view: String,
+    /// Quoted name of the distribution view
+    pub(crate) distribution_view: String,
+    /// Refresh time of the older view
+    refreshed_at: DateTime<Utc>,
+}
+
+impl SummarySource {
+    /// Staleness indicator of the statistics
+    pub(crate) fn freshness(&self) -> Value {
+        let age = (Utc::now() - self.refreshed_at).num_seconds().max(0);
+        json!({
+            "source": "summary_view",
+            "refreshed_at": self.refreshed_at.to_rfc3339(),
+            "age_seconds": age,
+            // The refresh is overdue, e.g. because it fails
+            "stale": age as u64 > 2 * get_config().summary_views.refresh_seconds
+        })
+    }
+}
+
+/// Refresh time of a view, `None` if it is missing or unreadable
+async fn refreshed_at(pool: &PgPool, view: &str) -> Option<DateTime<Utc>> {
+    sqlx::query_scalar::<_, Option<DateTime<Utc>>>(&format!("SELECT max(refreshed_at) FROM {view}"))
+        .fetch_one(pool)
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Views to read the statistics of a call from, `None` to compute them live
+pub(crate) async fn source(pool: &PgPool, args: &Value, has_query: bool) -> Option<SummarySource> {
+    let config = get_config();
+    let views = &config.summary_views;
+    if !views.enabled || has_query || !args["tenant"].is_null() {
+        return None;
+    }
+    let category_view = quote_identifier(&views.category_view).ok()?;
+    let distribution_view = quote_identifier(&views.distribution_view).ok()?;
+    let refreshed_at = refreshed_at(pool, &category_view)
+        .await?
+        .min(refreshed_at(pool, &distribution_view).await?);
+    let age = (Utc::now() - refreshed_at).num_seconds();
+    if views.max_staleness_seconds.is_some_and(|max| age > max as i64) {
+        return None;
+    }
+    Some(SummarySource {
+        category_view,
+        distribution_view,
+        refreshed_at,
+    })
+}
+
+/// Create a view unless it exists with the current definition
+async fn ensure_view(pool: &PgPool, view: &View) -> Result<(), PluginError> {
+    let comment = view.comment();
+    let current: Option<String> = sqlx::query_scalar("SELECT obj_description(to_regclass($1), 'pg_class')")
+        .bind(&view.name)
+        .fetch_one(pool)
+        .await?;
+    if current.as_deref() == Some(comment.as_str()) {
+        return Ok(());
+    }
+
+    // Another instance may be recreating it at the same time
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind(&view.name)
+        .execute(&mut *tx)
+        .await?;
+    let current: Option<String> = sqlx::query_scalar("SELECT obj_description(to_regclass($1), 'pg_class')")
+        .bind(&view.name)
+        .fetch_one(&mut *tx)
+        .await?;
+    if current.as_deref() == Some(comment.as_str()) {
+        return Ok(());
+    }
+    // Building the view may take longer than statement_timeout_ms allows tool queries
+    sqlx::query("SET LOCAL statement_timeout = 0").execute(&mut *tx).await?;
+    if current.is_none_or(|current| !current.starts_with(COMMENT_PREFIX))
+        && sqlx::query_scalar::<_, Option<String>>("SELECT to_regclass($1)::text")
+            .bind(&view.name)
+            .fetch_one(&mut *tx)
+            .await?
+            .is_some()
+    {
+        return Err(PluginError::Conflict(format!(
+            "{} exists and is not a summary view managed by the plugin",
+            view.name
+        )));
+    }
+    tracing::info!("Creating summary view {}", view.name);
+    sqlx::query(&format!("DROP MATERIALIZED VIEW IF EXISTS {}", view.name))
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(&format!("CREATE MATERIALIZED VIEW {} AS {}", view.name, view.query))
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(&format!("CREATE UNIQUE INDEX ON {} ({})", view.name, view.key))
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(&format!("COMMENT ON MATERIALIZED VIEW {} IS '{comment}'", view.name))
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Time since a view was last refreshed, `Duration::MAX` if never
+async fn view_age<'e>(executor: impl PgExecutor<'e>, view: &str) -> Result<Duration, PluginError> {
+    let age: f64 = sqlx::query_scalar(&format!(
+        "SELECT coalesce(extract(epoch FROM now() - max(refreshed_at))::float8, 'Infinity') FROM {view}"
+    ))
+    .fetch_one(executor)
+    .await?;
+    Ok(Duration::try_from_secs_f64(age.max(0.0)).unwrap_or(Duration::MAX))
+}
+
+/// Create missing views and refresh those due, returning the time until
+/// the next refresh is due
+async fn maintain(db: &dyn DatabaseBackend, config: &PluginConfig) -> Result<Duration, PluginError> {
+    let pool = db.primary()?;
+    let refresh = Duration::from_secs(config.summary_views.refresh_seconds);
+    let mut next = refresh;
+    for view in views(config)? {
+        ensure_view(pool, &view).await?;
+        let age = view_age(pool, &view.name).await?;
+        if age < refresh {
+            next = next.min(refresh - age);
+            continue;
+        }
+
+        // Another instance may be refreshing it at the same time
+        let mut tx = pool.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind(&view.name)
+            .execute(&mut *tx)
+            .await?;
+        let age = view_age(&mut *tx, &view.name).await?;
+        if age < refresh {
+            next = next.min(refresh - age);
+            continue;
+        }
+        tracing::debug!("Refreshing summary view {}", view.name);
+        sqlx::query("SET LOCAL statement_timeout = 0").execute(&mut *tx).await?;
+        sqlx::query(&format!("REFRESH MATERIALIZED VIEW CONCURRENTLY {}", view.name))
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+    }
+    Ok(next.max(Duration::from_secs(1)))
+}
+
+/// Spawn the refresh task on the current runtime
+pub(crate) fn start(db: Arc<ArcSwap<Database>>) -> JoinHandle<()> {
+    tokio::spawn(run(db))
+}
+
+/// Check the views of the current configuration, called once it is applied
+pub(crate) fn reconfigured() {
+    RECONFIGURED.notify_one();
+}
+
+async fn run(db: Arc<ArcSwap<Database>>) {
+    loop {
+        let config = get_config();
+        if !config.summary_views.enabled {
+            RECONFIGURED.notified().await;
+            continue;
+        }
+
+        let wait = match maintain(&**db.load_full(), &config).await {
+            Ok(next) => next,
+            Err(err) => {
+                tracing::warn!("Maintaining the summary views failed: {}", err.message());
+                RETRY_DELAY
+            }
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = RECONFIGURED.notified() => {}
+        }
+    }
+}
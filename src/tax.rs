@@ -0,0 +1,90 @@
+//! Tax calculation
+//!
+//! Tax rates are percentages per region (e.g. "DE" or "US-CA"), looked up
+//! in the `tax_rates` config map first and then in the optional
+//! `tax_rates_table` with the columns (region, rate). Stored prices are
+//! net unless `prices_include_tax` is set, in which case they are gross and
+//! the net price is derived from them.
+
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::sql::quote_identifier;
+use crate::{format_price, get_config, round_price, PluginConfig};
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+
+/// Validate the tax settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    for (region, rate) in &config.tax_rates {
+        if *rate < Decimal::ZERO || *rate > Decimal::ONE_HUNDRED {
+            return Err(format!("Tax rate for {region} must be between 0 and 100"));
+        }
+    }
+    if let Some(table) = &config.tax_rates_table {
+        quote_identifier(table)?;
+    }
+    Ok(())
+}
+
+/// Normalize an optional typed `region` argument
+pub(crate) fn parse_region(region: Option<&str>) -> Result<Option<String>, PluginError> {
+    match region {
+        None => Ok(None),
+        Some(region) if !region.trim().is_empty() => Ok(Some(region.trim().to_ascii_uppercase())),
+        Some(_) => Err(PluginError::invalid_argument("Invalid region parameter")),
+    }
+}
+
+/// Tax rate in percent for `region`
+///
+/// The `tax_rates_table` lookup needs the postgres backend.
+pub(crate) async fn tax_rate(db: &dyn DatabaseBackend, region: &str) -> Result<Decimal, PluginError> {
+    let config = get_config();
+    if let Some(rate) = config
+        .tax_rates
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(region))
+        .map(|(_, rate)| *rate)
+    {
+        return Ok(rate);
+    }
+
+    if let Some(table) = &config.tax_rates_table {
+        let table = quote_identifier(table).map_err(PluginError::internal)?;
+        let rate = sqlx::query_scalar::<_, Decimal>(&format!(
+            "SELECT rate FROM {table} WHERE upper(region) = $1"
+        ))
+        .bind(region)
+        .fetch_optional(db.postgres()?)
+        .await?;
+        if let Some(rate) = rate {
+            return Ok(rate);
+        }
+    }
+
+    Err(PluginError::invalid_argument(format!(
+        "No tax rate configured for region {region}"
+    )))
+}
+
+/// Net, tax and gross amounts for a stored price
+///
+/// Net and gross are rounded first and the tax is their difference, so the
+/// three amounts always add up as displayed.
+pub(crate) fn tax_breakdown(price: &Decimal, region: &str, rate: Decimal) -> Value {
+    let factor = Decimal::ONE + rate / Decimal::ONE_HUNDRED;
+    let (net, gross) = if get_config().prices_include_tax {
+        (round_price(&(price / factor)), round_price(price))
+    } else {
+        (round_price(price), round_price(&(price * factor)))
+    };
+
+    json!({
+        "region": region,
+        "rate_percent": rate.normalize().to_string(),
+        "prices_include_tax": get_config().prices_include_tax,
+        "net_price": format_price(&net),
+        "tax_amount": format_price(&(gross - net)),
+        "gross_price": format_price(&gross)
+    })
+}
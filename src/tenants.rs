@@ -0,0 +1,92 @@
+//! Multi-tenant schemas
+//!
+//! One database can hold a Postgres schema per tenant. Tool calls select
+//! their tenant with the `tenant` argument, which must be a key of the
+//! `tenants` config map; the schema it maps to is never taken from the
+//! caller. Every tenant gets its own lazily connected pool whose
+//! connections set `search_path` to the tenant schema when they are
+//! opened, so the handlers run unchanged against the tenant's tables.
+//!
+//! Calls without `tenant` use the main pool unless `tenant_required` is set.
+
+use crate::backend::{postgres, BackendKind, Database};
+use crate::error::PluginError;
+use crate::sql::quote_identifier;
+use crate::PluginConfig;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Validate the tenant settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if config.tenants.is_empty() {
+        if config.tenant_required {
+            return Err("tenant_required is set but no tenants are configured".to_string());
+        }
+        return Ok(());
+    }
+    if BackendKind::parse(&config.backend)? != BackendKind::Postgres {
+        return Err("tenants require the postgres backend".to_string());
+    }
+    for (tenant, schema) in &config.tenants {
+        if schema.contains('.') {
+            return Err(format!("Invalid schema '{schema}' for tenant {tenant}: must not be qualified"));
+        }
+        quote_identifier(schema)?;
+    }
+    Ok(())
+}
+
+/// Pools of the tenants used so far, for one configuration
+///
+/// Replaced as a whole on reconfigure, like the main pool.
+pub(crate) struct Tenants {
+    config: Arc<PluginConfig>,
+    pools: Mutex<HashMap<String, Arc<Database>>>,
+}
+
+impl Tenants {
+    pub(crate) fn new(config: Arc<PluginConfig>) -> Self {
+        Tenants {
+            config,
+            pools: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Database of the tenant named in the call arguments
+    ///
+    /// `None` means the call is not tenant specific and uses the main pool.
+    pub(crate) fn database(&self, args: &Value) -> Result<Option<Arc<Database>>, PluginError> {
+        let tenant = match &args["tenant"] {
+            Value::Null if self.config.tenant_required => {
+                return Err(PluginError::invalid_argument("Missing tenant parameter"))
+            }
+            Value::Null => return Ok(None),
+            Value::String(tenant) => tenant,
+            _ => return Err(PluginError::invalid_argument("Invalid tenant parameter")),
+        };
+
+        let schema = self
+            .config
+            .tenants
+            .get(tenant)
+            .ok_or_else(|| PluginError::invalid_argument(format!("Unknown tenant '{tenant}'")))?;
+
+        let mut pools = self.pools.lock().unwrap();
+        if let Some(db) = pools.get(tenant) {
+            return Ok(Some(db.clone()));
+        }
+        let search_path = quote_identifier(schema).map_err(PluginError::internal)?;
+        let db = Arc::new(postgres::connect_with_search_path(&self.config, search_path)?);
+        pools.insert(tenant.clone(), db.clone());
+        Ok(Some(db))
+    }
+
+    /// Close the pools of all tenants
+    pub(crate) async fn close(&self) {
+        let pools: Vec<_> = self.pools.lock().unwrap().drain().map(|(_, db)| db).collect();
+        for db in pools {
+            db.close().await;
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Tiered (volume) pricing
+//!
+//! Reads quantity breaks from the `price_tiers` table (name configurable
+//! through `price_tiers_table`):
+//!
+//! ```sql
+//! CREATE TABLE price_tiers (
+//!     product_id   INTEGER NOT NULL REFERENCES products(id),
+//!     min_quantity INTEGER NOT NULL,
+//!     price        NUMERIC(10,2) NOT NULL,
+//!     PRIMARY KEY (product_id, min_quantity)
+//! );
+//! ```
+//!
+//! A tier applies from its `min_quantity` up to the next tier's minimum.
+//! Quantities below the first tier pay the product's list price. Without
+//! the table every quantity pays the list price.
+
+use crate::args;
+use crate::backend::DatabaseBackend;
+use crate::currency::{self, ExchangeRate};
+use crate::error::PluginError;
+use crate::locale;
+use crate::sql::{is_undefined_table, quote_identifier};
+use crate::{format_price, get_config, product_json, PluginConfig, Product};
+use mcp_plugin_api::utils;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Arguments of get_price_tiers
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ProductArgs {
+    product_id: i32,
+    currency: Option<String>,
+    locale: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct PriceTier {
+    min_quantity: i32,
+    price: Decimal,
+}
+
+#[derive(sqlx::FromRow)]
+struct ProductTier {
+    product_id: i32,
+    #[sqlx(flatten)]
+    tier: PriceTier,
+}
+
+/// Validate the tier settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    quote_identifier(&config.price_tiers_table).map(|_| ())
+}
+
+/// Tiers of a product ordered by `min_quantity`, empty without a tier table
+pub(crate) async fn price_tiers(
+    db: &dyn DatabaseBackend,
+    product_id: i32,
+) -> Result<Vec<PriceTier>, PluginError> {
+    let mut tiers = price_tiers_of(db, &[product_id]).await?;
+    Ok(tiers.remove(&product_id).unwrap_or_default())
+}
+
+/// Tiers of several products in one query, ordered by `min_quantity`;
+/// products without tiers are left out
+pub(crate) async fn price_tiers_of(
+    db: &dyn DatabaseBackend,
+    product_ids: &[i32],
+) -> Result<HashMap<i32, Vec<PriceTier>>, PluginError> {
+    let table = quote_identifier(&get_config().price_tiers_table).map_err(PluginError::internal)?;
+    let result = sqlx::query_as::<_, ProductTier>(&format!(
+        "SELECT product_id, min_quantity, price FROM {table} \
+         WHERE product_id = ANY($1) ORDER BY product_id, min_quantity"
+    ))
+    .bind(product_ids)
+    .fetch_all(db.postgres()?)
+    .await;
+
+    let rows = match result {
+        Ok(rows) => rows,
+        Err(err) if is_undefined_table(&err) => Vec::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let mut tiers: HashMap<i32, Vec<PriceTier>> = HashMap::new();
+    for row in rows {
+        tiers.entry(row.product_id).or_default().push(row.tier);
+    }
+    Ok(tiers)
+}
+
+/// Tier table as JSON, with the quantity range each tier covers
+pub(crate) fn tiers_json(tiers: &[PriceTier]) -> Vec<Value> {
+    tiers
+        .iter()
+        .enumerate()
+        .map(|(i, tier)| {
+            let max_quantity = tiers.get(i + 1).map(|next| next.min_quantity - 1);
+            json!({
+                "min_quantity": tier.min_quantity,
+                "max_quantity": max_quantity,
+                "price": format_price(&tier.price)
+            })
+        })
+        .collect()
+}
+
+/// Unit and total price for buying `quantity` of a product
+pub(crate) fn quantity_pricing(
+    product: &Product,
+    tiers: &[PriceTier],
+    quantity: i32,
+    exchange_rate: Option<&ExchangeRate>,
+) -> Value {
+    let tier = tiers.iter().rev().find(|tier| tier.min_quantity <= quantity);
+    let unit_price = tier.map_or(product.price, |tier| tier.price);
+    let total_price = unit_price * Decimal::from(quantity);
+
+    let mut value = json!({
+        "quantity": quantity,
+        "tier_min_quantity": tier.map(|tier| tier.min_quantity),
+        "unit_price": format_price(&unit_price),
+        "total_price": format_price(&total_price),
+        "tiers": tiers_json(tiers)
+    });
+    if let Some(exchange_rate) = exchange_rate {
+        value["converted_unit_price"] = exchange_rate.convert(&unit_price);
+        value["converted_total_price"] = exchange_rate.convert(&total_price);
+    }
+    value
+}
+
+pub(crate) async fn handle_get_price_tiers(db: &dyn DatabaseBackend, args: &Value) -> Result<Value, PluginError> {
+    let ProductArgs {
+        product_id,
+        currency,
+        locale,
+    } = args::parse(args)?;
+    let currency = currency::parse_currency(currency.as_deref())?;
+    let locale = locale::parse_locale(locale.as_deref())?;
+
+    let product = db
+        .fetch_product(product_id)
+        .await?
+        .ok_or_else(|| PluginError::not_found(format!("Product {product_id} not found")))?;
+    let tiers = price_tiers(db, product_id).await?;
+
+    let exchange_rate = match &currency {
+        Some(currency) => Some(currency::exchange_rate(db, currency).await?),
+        None => None,
+    };
+    let tiers_value: Vec<Value> = tiers_json(&tiers)
+        .into_iter()
+        .zip(&tiers)
+        .map(|(mut value, tier)| {
+            if let Some(exchange_rate) = &exchange_rate {
+                value["converted_price"] = exchange_rate.convert(&tier.price);
+            }
+            value
+        })
+        .collect();
+
+    // Return structured JSON data for programmatic clients
+    Ok(utils::json_content(json!({
+        "product": product_json(&product, exchange_rate.as_ref(), locale.as_ref()),
+        "tiers": tiers_value,
+        "base_currency": get_config().base_currency
+    })))
+}
@@ -0,0 +1,60 @@
+//! Tool selection
+//!
+//! `tools.enabled` lists the only tools a deployment offers, `tools.disabled`
+//! tools it withholds; both default to none, which offers every tool. A
+//! tool left out is missing from `tools/list`, and calls to it fail with a
+//! `permission_denied` error naming the setting. Tools that need further
+//! settings, such as the write tools, still need those when enabled here.
+
+use crate::error::PluginError;
+use crate::{get_config, get_tools, try_get_config, PluginConfig};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// The `tools` config field
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+pub(crate) struct ToolsConfig {
+    /// Tools to offer, all of them if absent
+    #[serde(default)]
+    enabled: Option<Vec<String>>,
+
+    /// Tools to withhold, applied after `enabled`
+    #[serde(default)]
+    disabled: Vec<String>,
+}
+
+impl ToolsConfig {
+    fn is_enabled(&self, tool: &str) -> bool {
+        self.enabled.as_ref().is_none_or(|enabled| enabled.iter().any(|name| name == tool))
+            && !self.disabled.iter().any(|name| name == tool)
+    }
+}
+
+/// Validate the tool selection, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    let tools = get_tools();
+    let names = config.tools.enabled.iter().flatten().chain(&config.tools.disabled);
+    for name in names {
+        if !tools.contains_key(name) {
+            return Err(format!("Unknown tool '{name}' in tools"));
+        }
+    }
+    Ok(())
+}
+
+/// Whether the configuration offers `tool`; every tool before the plugin
+/// is configured
+pub(crate) fn is_enabled(tool: &str) -> bool {
+    try_get_config().is_none_or(|config| config.tools.is_enabled(tool))
+}
+
+/// Fail unless the configuration offers `tool`
+pub(crate) fn ensure_enabled(tool: &str) -> Result<(), PluginError> {
+    if get_config().tools.is_enabled(tool) {
+        Ok(())
+    } else {
+        Err(PluginError::PermissionDenied(format!(
+            "Tool {tool} is disabled by the tools setting of this deployment"
+        )))
+    }
+}
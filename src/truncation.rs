@@ -0,0 +1,146 @@
+//! Response size limits
+//!
+//! Every JSON tool result is kept within `max_response_rows` rows and
+//! `max_response_bytes` bytes (0 disables either), so a large result cannot
+//! exceed the host's MCP message limit. The rows are the largest array of
+//! the result object, e.g. `products`. A result over a limit keeps as many
+//! leading rows as fit and gains
+//!
+//! - `truncated`: true
+//! - `total_count`: the number of rows the tool returned
+//! - `next_cursor`: pass it as `response_cursor` with otherwise unchanged
+//!   arguments to get the rows that follow
+//!
+//! The cursor counts rows of the tool's result, so the call runs again
+//! and its first rows are skipped; tools with their own paging are better
+//! paged with `offset` or `cursor`. Results in text blocks, such as inline
+//! exports, are limited by their tools only.
+
+use crate::error::PluginError;
+use crate::{get_config, PluginConfig};
+use serde_json::{json, Value};
+
+/// Prefix of the `next_cursor` values
+const CURSOR_PREFIX: &str = "rows:";
+
+/// Overhead of the result around the object and the truncation fields
+const ENVELOPE_BYTES: usize = 128;
+
+/// Validate the response limits, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if config.max_response_bytes != 0 && config.max_response_bytes < 1024 {
+        return Err("max_response_bytes must be 0 (unlimited) or at least 1024".to_string());
+    }
+    Ok(())
+}
+
+/// Add the `response_cursor` argument to a tool's input schema
+pub(crate) fn extend_input_schema(input_schema: &mut Value) {
+    input_schema["properties"]["response_cursor"] = json!({
+        "type": "string",
+        "description": "The next_cursor of a truncated result, to continue it with otherwise unchanged arguments"
+    });
+}
+
+/// Rows to skip from the `response_cursor` argument
+fn parse_cursor(args: &Value) -> Result<usize, PluginError> {
+    match &args["response_cursor"] {
+        Value::Null => Ok(0),
+        Value::String(cursor) => cursor
+            .strip_prefix(CURSOR_PREFIX)
+            .and_then(|rows| rows.parse().ok())
+            .ok_or_else(|| PluginError::invalid_argument(format!("Invalid response_cursor '{cursor}'"))),
+        _ => Err(PluginError::invalid_argument("Invalid response_cursor parameter")),
+    }
+}
+
+/// Name of the largest array in a result object
+pub(crate) fn rows_field(object: &serde_json::Map<String, Value>) -> Option<String> {
+    object
+        .iter()
+        .filter_map(|(name, value)| value.as_array().map(|rows| (name, rows.len())))
+        .max_by_key(|(_, rows)| *rows)
+        .map(|(name, _)| name.clone())
+}
+
+/// Serialized size of a value in bytes
+fn size_of(value: &Value) -> usize {
+    serde_json::to_vec(value).map_or(0, |bytes| bytes.len())
+}
+
+/// Limit the rows of one result object, returning whether it changed
+///
+/// `copies` is the number of times the object appears in the result.
+fn limit_object(object: &mut serde_json::Map<String, Value>, skip: usize, copies: usize) -> bool {
+    let config = get_config();
+    let Some(field) = rows_field(object) else {
+        return false;
+    };
+    let Some(Value::Array(rows)) = object.remove(&field) else {
+        return false;
+    };
+    let total = rows.len();
+    let mut rows: Vec<Value> = rows.into_iter().skip(skip).collect();
+    if config.max_response_rows > 0 {
+        rows.truncate(config.max_response_rows);
+    }
+
+    // Keep the leading rows that fit next to the rest of the object
+    if config.max_response_bytes > 0 {
+        let mut used = (size_of(&Value::Object(object.clone())) + field.len() + ENVELOPE_BYTES) * copies;
+        let fitting = rows
+            .iter()
+            .take_while(|row| {
+                used += (size_of(row) + 1) * copies;
+                used <= config.max_response_bytes
+            })
+            .count();
+        rows.truncate(fitting);
+    }
+
+    let end = skip + rows.len();
+    let truncated = end < total;
+    // Keep a truncation the tool reported itself, e.g. at max_results
+    let truncated_by_tool = object.get("truncated") == Some(&Value::Bool(true));
+    if let Some(count) = object.get_mut("count").filter(|count| count.is_u64()) {
+        *count = json!(rows.len());
+    }
+    object.insert(field, Value::Array(rows));
+    if !truncated && skip == 0 {
+        return false;
+    }
+    object.insert("truncated".to_string(), json!(truncated || truncated_by_tool));
+    object.insert("total_count".to_string(), json!(total));
+    object.insert(
+        "next_cursor".to_string(),
+        if truncated { json!(format!("{CURSOR_PREFIX}{end}")) } else { Value::Null },
+    );
+    true
+}
+
+/// Apply the response limits to a tool result
+pub(crate) fn limit_response(mut result: Value, args: &Value) -> Result<Value, PluginError> {
+    let skip = parse_cursor(args)?;
+    let config = get_config();
+    if config.max_response_rows == 0 && config.max_response_bytes == 0 && skip == 0 {
+        return Ok(result);
+    }
+
+    // Typed results carry the object twice, as block and structuredContent
+    let structured = result.get("structuredContent").is_some();
+    let Some(block) = result["content"]
+        .as_array_mut()
+        .and_then(|content| content.iter_mut().find(|block| block["type"] == "json"))
+    else {
+        return Ok(result);
+    };
+    let Some(object) = block["json"].as_object_mut() else {
+        return Ok(result);
+    };
+    let changed = limit_object(object, skip, if structured { 2 } else { 1 });
+    if changed && structured {
+        let limited = block["json"].clone();
+        result["structuredContent"] = limited;
+    }
+    Ok(result)
+}
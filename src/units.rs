@@ -0,0 +1,169 @@
+//! Unit prices
+//!
+//! With `schema_mapping.unit_quantity_column` and `unit_of_measure_column`
+//! mapped, a product's price can be normalized to a price per base unit,
+//! e.g. 2.49 for 500 g becomes 4.98 per kg. Weights are normalized to kg,
+//! volumes to l, lengths to m and counts (pc, piece, each) to unit; other
+//! units are kept as they are, so products sharing them still compare.
+//!
+//! The priced product responses carry the `unit_price` of every product
+//! with a positive quantity and a unit. `compare_unit_prices` ranks the
+//! products matching a name and category by unit price, separately for
+//! every base unit.
+
+use crate::args;
+use crate::backend::DatabaseBackend;
+use crate::error::PluginError;
+use crate::mapping::{self, PRODUCT_COLUMNS};
+use crate::search::like_pattern;
+use crate::{format_price, get_config, product_json, Product};
+use mcp_plugin_api::utils;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{Postgres, QueryBuilder};
+use std::collections::BTreeMap;
+
+/// Page size of compare_unit_prices without `limit`
+const DEFAULT_COMPARE_LIMIT: i64 = 20;
+
+/// Upper bound of the `limit` argument
+const MAX_COMPARE_LIMIT: i64 = 200;
+
+/// Base unit and factor converting one `unit` into it
+fn base_unit(unit: &str) -> (String, Decimal) {
+    let unit = unit.trim().to_ascii_lowercase();
+    let (base, factor) = match unit.as_str() {
+        "kg" | "kilogram" | "kilograms" => ("kg", Decimal::ONE),
+        "g" | "gram" | "grams" => ("kg", Decimal::new(1, 3)),
+        "mg" | "milligram" | "milligrams" => ("kg", Decimal::new(1, 6)),
+        "lb" | "lbs" | "pound" | "pounds" => ("kg", Decimal::new(45359237, 8)),
+        "oz" | "ounce" | "ounces" => ("kg", Decimal::new(28349523125, 12)),
+        "l" | "liter" | "liters" | "litre" | "litres" => ("l", Decimal::ONE),
+        "dl" => ("l", Decimal::new(1, 1)),
+        "cl" => ("l", Decimal::new(1, 2)),
+        "ml" | "milliliter" | "milliliters" | "millilitre" | "millilitres" => ("l", Decimal::new(1, 3)),
+        "m" | "meter" | "meters" | "metre" | "metres" => ("m", Decimal::ONE),
+        "cm" => ("m", Decimal::new(1, 2)),
+        "mm" => ("m", Decimal::new(1, 3)),
+        "unit" | "units" | "pc" | "pcs" | "piece" | "pieces" | "each" | "ea" => ("unit", Decimal::ONE),
+        _ => return (unit, Decimal::ONE),
+    };
+    (base.to_string(), factor)
+}
+
+/// Price per base unit of a product
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub(crate) struct UnitPrice {
+    /// Formatted like every other price, in the base currency
+    pub(crate) price: String,
+    /// Base unit the price is for: kg, l, m, unit or the product's own unit
+    pub(crate) unit: String,
+    #[serde(skip)]
+    value: Decimal,
+}
+
+/// Unit price of a product, `None` without a positive quantity and a unit
+pub(crate) fn unit_price(product: &Product) -> Option<UnitPrice> {
+    let quantity = product.unit_quantity.filter(|quantity| *quantity > Decimal::ZERO)?;
+    let unit = product.unit_of_measure.as_deref().filter(|unit| !unit.trim().is_empty())?;
+    let (unit, factor) = base_unit(unit);
+    let value = product.price / (quantity * factor);
+    Some(UnitPrice {
+        price: format_price(&value),
+        unit,
+        value,
+    })
+}
+
+/// Arguments of compare_unit_prices
+#[derive(Debug, Deserialize, JsonSchema)]
+struct CompareUnitArgs {
+    query: Option<String>,
+    category: Option<String>,
+    #[schemars(length(min = 1, max = 32))]
+    unit: Option<String>,
+    #[schemars(range(min = 1, max = "MAX_COMPARE_LIMIT"))]
+    limit: Option<i64>,
+}
+
+pub(crate) async fn handle_compare_unit_prices(db: &dyn DatabaseBackend, args: &Value) -> Result<Value, PluginError> {
+    let pool = db.postgres()?;
+    let CompareUnitArgs {
+        query,
+        category,
+        unit,
+        limit,
+    } = args::parse(args)?;
+    let config = get_config();
+    let mapping = &config.schema_mapping;
+    if mapping.unit_quantity_column.is_none() || mapping.unit_of_measure_column.is_none() {
+        return Err(PluginError::Unsupported(
+            "Unit prices need schema_mapping.unit_quantity_column and unit_of_measure_column".to_string(),
+        ));
+    }
+    let limit = limit.unwrap_or(DEFAULT_COMPARE_LIMIT) as usize;
+    let unit = unit.map(|unit| base_unit(&unit).0);
+
+    // Normalized in Rust, so the candidates are capped at max_results
+    let mut sql = QueryBuilder::<Postgres>::new(format!(
+        "SELECT {PRODUCT_COLUMNS} FROM {} WHERE unit_quantity > 0 AND unit_of_measure IS NOT NULL",
+        mapping::products()
+    ));
+    if let Some(query) = &query {
+        sql.push(" AND name ILIKE ")
+            .push_bind(like_pattern(query, false))
+            .push(" ESCAPE '\\'");
+    }
+    if let Some(category) = &category {
+        sql.push(" AND category = ").push_bind(category);
+    }
+    sql.push(" ORDER BY id LIMIT ").push_bind(config.max_results + 1);
+    let mut products = sql.build_query_as::<Product>().fetch_all(pool).await?;
+    let truncated = products.len() as i64 > config.max_results;
+    products.truncate(config.max_results as usize);
+
+    let mut groups: BTreeMap<String, Vec<(UnitPrice, Product)>> = BTreeMap::new();
+    for product in products {
+        if let Some(unit_price) = unit_price(&product) {
+            if unit.as_ref().is_none_or(|unit| *unit == unit_price.unit) {
+                groups.entry(unit_price.unit.clone()).or_default().push((unit_price, product));
+            }
+        }
+    }
+
+    // Largest group first, cheapest per unit first within a group
+    let mut groups: Vec<(String, Vec<(UnitPrice, Product)>)> = groups.into_iter().collect();
+    groups.sort_by_key(|(_, products)| std::cmp::Reverse(products.len()));
+    let groups: Vec<Value> = groups
+        .into_iter()
+        .map(|(unit, mut products)| {
+            products.sort_by_key(|(unit_price, product)| (unit_price.value, product.id));
+            let matched = products.len();
+            let ranked: Vec<Value> = products
+                .iter()
+                .take(limit)
+                .enumerate()
+                .map(|(index, (_, product))| {
+                    let mut value = product_json(product, None, None);
+                    value["rank"] = json!(index + 1);
+                    value
+                })
+                .collect();
+            json!({
+                "unit": unit,
+                "products": ranked,
+                "count": ranked.len(),
+                "matched": matched
+            })
+        })
+        .collect();
+
+    // Return structured JSON data for programmatic clients
+    Ok(utils::json_content(json!({
+        "groups": groups,
+        "truncated": truncated,
+        "base_currency": config.base_currency
+    })))
+}
@@ -0,0 +1,113 @@
+//! Pool warm-up
+//!
+//! A new pool holds a single connection and no prepared statements, so the
+//! first tool calls pay for connecting and planning. Before a pool is put
+//! to use, the core catalogue queries run once with dummy parameters on
+//! `min_connections` connections at the same time, which opens those
+//! connections and leaves the statements prepared on each of them.
+//!
+//! With `warmup_check_indexes`, Postgres is also asked through `EXPLAIN`
+//! whether the product lookups can use an index; a lookup that could only
+//! scan the whole table is logged as a warning. Warm-up never fails the
+//! connection, problems are logged and the pool is used as it is.
+
+use crate::backend::{DatabaseBackend, ProductSearch};
+use crate::error::PluginError;
+use crate::mapping;
+use crate::progress::Progress;
+use crate::search::{self, SearchMode, SearchSort};
+use crate::PluginConfig;
+use serde_json::Value;
+use sqlx::{Postgres, QueryBuilder};
+use std::time::Instant;
+
+/// Run the queries every tool call depends on, once
+async fn run_core_queries(db: &dyn DatabaseBackend) -> Result<(), PluginError> {
+    let progress = Progress::from_args(&Value::Null);
+    let search = ProductSearch {
+        mode: SearchMode::Ilike,
+        query: "",
+        raw_pattern: false,
+        category: None,
+        brand: None,
+        attributes: None,
+        min_price: None,
+        max_price: None,
+        sort: SearchSort::Relevance,
+        limit: Some(1),
+        offset: 0,
+        include_inactive: false,
+        progress: &progress,
+    };
+    db.fetch_product(0).await?;
+    db.fetch_products(&[0]).await?;
+    db.search_products(&search).await?;
+    db.list_categories().await?;
+    Ok(())
+}
+
+/// Whether the plan of `sql` reads the products table without an index
+///
+/// Sequential scans are disabled for the check, so a plan that still
+/// contains one has no index to fall back on.
+async fn needs_seq_scan(db: &dyn DatabaseBackend, mut sql: QueryBuilder<'_, Postgres>) -> Result<bool, PluginError> {
+    let mut tx = db.postgres()?.begin().await?;
+    sqlx::query("SET LOCAL enable_seqscan = off").execute(&mut *tx).await?;
+    let plan: Vec<String> = sql.build_query_scalar().fetch_all(&mut *tx).await?;
+    tx.rollback().await?;
+    Ok(plan.iter().any(|line| line.contains("Seq Scan")))
+}
+
+/// Log a warning for every product lookup that cannot use an index
+async fn check_indexes(db: &dyn DatabaseBackend, config: &PluginConfig) {
+    let mapping = &config.schema_mapping;
+    let table = mapping.table();
+    let mut checks: Vec<(String, QueryBuilder<'_, Postgres>)> = Vec::new();
+
+    let mut sql = QueryBuilder::new(format!("EXPLAIN SELECT id FROM {} WHERE id = ", mapping::products()));
+    sql.push_bind(0);
+    checks.push((format!("{table} ({})", mapping.id_column), sql));
+
+    for column in [&config.sku_column, &config.barcode_column] {
+        let mut sql = QueryBuilder::new(format!(
+            "EXPLAIN SELECT 1 FROM {table} WHERE {}::text = ",
+            mapping.column(column)
+        ));
+        sql.push_bind("");
+        checks.push((format!("{table} ({column})"), sql));
+    }
+
+    if let Ok(mode @ (SearchMode::Fulltext | SearchMode::Trigram)) = SearchMode::parse(&config.search_mode) {
+        let mut sql = QueryBuilder::new("EXPLAIN ");
+        search::push_select(&mut sql, mode, "warmup", false);
+        checks.push((format!("{table} {} search", mode.as_str()), sql));
+    }
+
+    for (index, sql) in checks {
+        match needs_seq_scan(db, sql).await {
+            Ok(false) => {}
+            Ok(true) => tracing::warn!("No index for {index}, lookups scan the whole table"),
+            Err(err) => tracing::warn!("Index check for {index} failed: {err}"),
+        }
+    }
+}
+
+/// Open `min_connections` connections and prepare the core queries on them
+pub(crate) async fn warm_up(db: &dyn DatabaseBackend, config: &PluginConfig) {
+    let started = Instant::now();
+    let connections = config.min_connections.max(1);
+    // Running concurrently makes every run take a connection of its own
+    let runs = (0..connections).map(|_| run_core_queries(db));
+    if let Err(err) = futures::future::try_join_all(runs).await {
+        tracing::warn!("Warm-up query failed: {err}");
+    }
+
+    if config.warmup_check_indexes && db.postgres().is_ok() {
+        check_indexes(db, config).await;
+    }
+    tracing::info!(
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        connections,
+        "Warm-up finished"
+    );
+}
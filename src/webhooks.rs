@@ -0,0 +1,125 @@
+//! Webhook notifications of price changes
+//!
+//! Every committed `update_product_price` is POSTed as a JSON event to each
+//! of `webhook_urls`. With `webhook_secret` set, the request carries the
+//! header `X-Signature-256: sha256=<hex>`, the HMAC-SHA256 of the body under
+//! the secret, so receivers can verify its origin. Triggered price alerts
+//! are posted the same way with `price_alert_webhooks`.
+//!
+//! Deliveries run in background tasks on the plugin runtime and never delay
+//! or fail the tool call. A delivery failing with a network error, a 429 or
+//! a 5xx response is retried up to `webhook_max_retries` times with
+//! exponential backoff starting at one second; deliveries still pending at
+//! shutdown are dropped.
+
+use crate::{get_config, PluginConfig};
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use reqwest::Url;
+use serde_json::Value;
+use sha2::Sha256;
+use std::time::Duration;
+
+/// Header carrying the HMAC signature of the body
+const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+/// Header naming the event type
+const EVENT_HEADER: &str = "X-Event-Type";
+
+/// Delay before the first retry, doubled for each further one
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+/// Validate the webhook settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    // The messages leave out the URL, which may embed a token
+    for (index, url) in config.webhook_urls.iter().enumerate() {
+        let parsed = Url::parse(url).map_err(|err| format!("Invalid webhook_urls[{index}]: {err}"))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(format!("Invalid webhook_urls[{index}]: expected an http or https URL"));
+        }
+    }
+    if config.webhook_secret.as_deref() == Some("") {
+        return Err("webhook_secret must not be empty".to_string());
+    }
+    if config.webhook_timeout_seconds == 0 {
+        return Err("webhook_timeout_seconds must be at least 1".to_string());
+    }
+    Ok(())
+}
+
+/// `sha256=<hex>` signature of `body`
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Queue delivery of `event` to every configured webhook
+pub(crate) fn notify(event_type: &'static str, event: Value) {
+    let config = get_config();
+    if config.webhook_urls.is_empty() {
+        return;
+    }
+
+    let body = event.to_string();
+    let signature = config.webhook_secret.as_deref().map(|secret| sign(secret, body.as_bytes()));
+    for url in &config.webhook_urls {
+        tokio::spawn(deliver(
+            url.clone(),
+            event_type,
+            body.clone(),
+            signature.clone(),
+            config.webhook_max_retries,
+            Duration::from_secs(config.webhook_timeout_seconds),
+        ));
+    }
+}
+
+async fn deliver(
+    url: String,
+    event_type: &'static str,
+    body: String,
+    signature: Option<String>,
+    max_retries: u32,
+    timeout: Duration,
+) {
+    // Webhook URLs often embed a token, only the host is logged
+    let host = Url::parse(&url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default();
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 0..=max_retries {
+        if attempt > 0 {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+
+        let mut request = CLIENT
+            .post(&url)
+            .timeout(timeout)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, event_type)
+            .body(body.clone());
+        if let Some(signature) = &signature {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+
+        let error = match request.send().await {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response) => {
+                let status = response.status();
+                if !(status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS) {
+                    tracing::warn!(host, event_type, "Webhook rejected with {status}, not retrying");
+                    return;
+                }
+                format!("status {status}")
+            }
+            Err(err) => err.without_url().to_string(),
+        };
+        tracing::warn!(host, event_type, attempt, "Webhook delivery failed: {error}");
+    }
+    tracing::error!(host, event_type, "Webhook delivery given up after {} attempts", max_retries + 1);
+}
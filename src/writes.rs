@@ -0,0 +1,199 @@
+//! Write tools
+//!
+//! Price changes are opt-in: the tools refuse to run unless `enable_writes`
+//! is set, which in turn requires `read_only` to be off. Every change is
+//! recorded in the price audit table (name configurable through
+//! `price_audit_table`) within the same transaction:
+//!
+//! ```sql
+//! CREATE TABLE price_audit (
+//!     id         BIGSERIAL PRIMARY KEY,
+//!     product_id INTEGER NOT NULL REFERENCES products(id),
+//!     old_price  NUMERIC NOT NULL,
+//!     new_price  NUMERIC NOT NULL,
+//!     changed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+//!     changed_by TEXT,
+//!     reason     TEXT
+//! );
+//! ```
+//!
+//! With the `dry_run` argument, or for every call with the `dry_run` config
+//! flag, a write tool runs its statements in a transaction as usual,
+//! reports the changes and the rows affected, and rolls back. Dry runs
+//! still need `enable_writes`, but skip webhooks and idempotency keys.
+
+use crate::alerts;
+use crate::args::{self, DecimalArg};
+use crate::auth;
+use crate::backend::DatabaseBackend;
+use crate::cache::CacheBackend;
+use crate::error::PluginError;
+use crate::idempotency::{self, MAX_KEY_LENGTH};
+use crate::mapping;
+use crate::sql::quote_identifier;
+use crate::webhooks;
+use crate::{format_price, get_config, PluginConfig};
+use chrono::Utc;
+use mcp_plugin_api::utils;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Validate the write settings, called before a configuration is applied
+pub(crate) fn validate_config(config: &PluginConfig) -> Result<(), String> {
+    if config.enable_writes && config.read_only {
+        return Err("enable_writes requires read_only to be set to false".to_string());
+    }
+    quote_identifier(&config.price_audit_table).map(|_| ())
+}
+
+/// Whether a write runs dry, on request or by the `dry_run` config flag
+pub(crate) fn is_dry_run(requested: bool) -> bool {
+    requested || get_config().dry_run
+}
+
+pub(crate) fn ensure_writes_enabled() -> Result<(), PluginError> {
+    if get_config().enable_writes {
+        Ok(())
+    } else {
+        Err(PluginError::PermissionDenied(
+            "Write tools are disabled, set enable_writes to allow them".to_string(),
+        ))
+    }
+}
+
+/// Arguments of update_product_price
+#[derive(Debug, Deserialize, JsonSchema)]
+struct UpdatePriceArgs {
+    product_id: i32,
+    new_price: DecimalArg,
+    expected_current_price: DecimalArg,
+    changed_by: Option<String>,
+    reason: Option<String>,
+    #[serde(default)]
+    dry_run: bool,
+    #[schemars(length(min = 1, max = "MAX_KEY_LENGTH"))]
+    idempotency_key: Option<String>,
+}
+
+pub(crate) async fn handle_update_product_price(
+    db: &dyn DatabaseBackend,
+    cache: &dyn CacheBackend,
+    args: &Value,
+) -> Result<Value, PluginError> {
+    ensure_writes_enabled()?;
+
+    let UpdatePriceArgs {
+        product_id,
+        new_price,
+        expected_current_price,
+        changed_by,
+        reason,
+        dry_run,
+        idempotency_key,
+    } = args::parse(args)?;
+    let new_price = new_price.to_decimal("new_price")?;
+    if new_price < Decimal::ZERO {
+        return Err(PluginError::invalid_argument("new_price must not be negative"));
+    }
+    let expected_price = expected_current_price.to_decimal("expected_current_price")?;
+    let changed_by = auth::changed_by(changed_by, args)?;
+    let dry_run = is_dry_run(dry_run);
+    // Dry runs change nothing a retry could repeat
+    let idempotency_key = idempotency_key.filter(|_| !dry_run);
+
+    let config = get_config();
+    let audit_table = quote_identifier(&config.price_audit_table).map_err(PluginError::internal)?;
+    let mapping = &config.schema_mapping;
+    let table = mapping.table();
+    let (id, price) = (mapping.column(&mapping.id_column), mapping.column(&mapping.price_column));
+    let mut tx = db.primary()?.begin().await?;
+
+    // A retry of a call that went through gets the original result
+    if let Some(key) = &idempotency_key {
+        if let Some(response) = idempotency::claim(&mut tx, "update_product_price", key, args).await? {
+            tx.rollback().await?;
+            return Ok(utils::json_content(response));
+        }
+    }
+
+    // Lock the row so the comparison and the update see the same price
+    let current_price = sqlx::query_scalar::<_, Decimal>(&format!(
+        "SELECT {price} FROM {table} WHERE {id} = $1 AND {} FOR UPDATE",
+        mapping::visible_rows()
+    ))
+    .bind(product_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| PluginError::not_found(format!("Product {product_id} not found")))?;
+
+    if current_price != expected_price {
+        return Err(PluginError::Conflict(format!(
+            "Price of product {product_id} is {}, not the expected {}",
+            format_price(&current_price),
+            format_price(&expected_price)
+        )));
+    }
+
+    let affected_rows = sqlx::query(&format!("UPDATE {table} SET {price} = $1 WHERE {id} = $2"))
+        .bind(new_price)
+        .bind(product_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+    let audit_id = sqlx::query_scalar::<_, i64>(&format!(
+        "INSERT INTO {audit_table} (product_id, old_price, new_price, changed_by, reason) \
+         VALUES ($1, $2, $3, $4, $5) RETURNING id"
+    ))
+    .bind(product_id)
+    .bind(current_price)
+    .bind(new_price)
+    .bind(&changed_by)
+    .bind(&reason)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if dry_run {
+        tx.rollback().await?;
+        return Ok(utils::json_content(json!({
+            "product_id": product_id,
+            "old_price": format_price(&current_price),
+            "new_price": format_price(&new_price),
+            "dry_run": true,
+            "affected_rows": affected_rows
+        })));
+    }
+
+    let response = json!({
+        "product_id": product_id,
+        "old_price": format_price(&current_price),
+        "new_price": format_price(&new_price),
+        "audit_id": audit_id
+    });
+    if let Some(key) = &idempotency_key {
+        idempotency::complete(&mut tx, "update_product_price", key, &response).await?;
+    }
+
+    tx.commit().await?;
+    cache.invalidate_product(product_id).await;
+    alerts::wake();
+
+    webhooks::notify(
+        "price.updated",
+        json!({
+            "event": "price.updated",
+            "product_id": product_id,
+            "old_price": format_price(&current_price),
+            "new_price": format_price(&new_price),
+            "changed_by": changed_by,
+            "reason": reason,
+            "audit_id": audit_id,
+            "changed_at": Utc::now().to_rfc3339()
+        }),
+    );
+
+    // Return structured JSON data for programmatic clients
+    Ok(utils::json_content(response))
+}